@@ -17,7 +17,7 @@ struct Input {
         long = "out-path",
         short = 'o',
         default_value = "./peaks.parquet",
-        help = "Path to a results file (WARNING: overwrites existing files). Supported formats: .parquet, .mgf, .spec.parquet",
+        help = "Path to a results file (WARNING: overwrites existing files). Supported formats: .parquet, .mgf, .mzML, .spec.parquet, .features.parquet",
         value_hint = ValueHint::FilePath,
         value_parser = validate_output_path,
     )]
@@ -40,7 +40,7 @@ struct Input {
         long = "min-spectrum-size",
         short = 's',
         default_value_t = 5,
-        help = "Minimum number of peaks required for a spectrum to be written (only for .mgf output)"
+        help = "Minimum number of peaks required for a spectrum to be written (only for .mgf and .mzML output)"
     )]
     min_spectrum_size: usize,
     #[arg(
@@ -59,11 +59,11 @@ fn validate_output_path(s: &str) -> Result<String, String> {
         .and_then(|e| e.to_str())
         .map(|ext| ext.to_lowercase())
     {
-        Some(ref ext) if ext == "parquet" || ext == "mgf" => {
+        Some(ref ext) if ext == "parquet" || ext == "mgf" || ext == "mzml" => {
             Ok(path.to_string_lossy().to_string())
         },
         _ => Err(String::from(
-            "Invalid file extension. Must be .parquet or .mgf",
+            "Invalid file extension. Must be .parquet, .mgf or .mzML",
         )),
     }
 }
@@ -22,6 +22,12 @@
 //! let result = run("raw_data.d", "output.mgf", 5.0, 2.0, 5, false);
 //! ```
 //!
+//! ```no_run
+//! use timsrust_centroid_cli::run;
+//! // Link MS1 peaks into LC-IMS-MS features for label-free quantification
+//! let result = run("raw_data.d", "output.features.parquet", 0.5, 2.0, 5, false);
+//! ```
+//!
 //! ## CLI Usage (with `cli` feature, enabled by default)
 //! Run `timsrust_centroid` from the command line for processing.
 //!
@@ -37,6 +43,10 @@
 //! timsrust_centroid input.d output.mgf --min-ion-count_ms2 2 --min-spectrum-size 5
 //! ```
 //!
+//! ```sh
+//! timsrust_centroid input.d output.features.parquet --min-ion-count_ms1 0.5
+//! ```
+//!
 //! ## Features enabled by default
 //! - `cli`: Enables the command-line interface
 //! - `writer`: Enables Parquet output
@@ -49,6 +59,7 @@ mod runner;
 pub use cli::CLI;
 pub use runner::run;
 use serde::{Deserialize, Serialize};
+use timsrust_centroid::features::Feature;
 // use timsrust_centroid::Peak;
 // use timsrust_core::io::impl_parquet_scheme;
 
@@ -68,6 +79,12 @@ pub struct CoordinatePeak {
     pub scan: u32,
     pub tof: u32,
     pub apex_intensity: u64,
+    pub area: u64,
+    pub ion_count: u32,
+    pub tof_fwhm: f32,
+    pub scan_fwhm: f32,
+    pub tof_apex: f64,
+    pub scan_apex: f64,
     pub rt: f64,
     pub im: f64,
     pub mz: f64,
@@ -80,6 +97,12 @@ pub struct CoordinatePeak {
 //         (scan, arrow::datatypes::UInt32Type, false),
 //         (tof, arrow::datatypes::UInt32Type, false),
 //         (apex_intensity, arrow::datatypes::UInt64Type, false),
+//         (area, arrow::datatypes::UInt64Type, false),
+//         (ion_count, arrow::datatypes::UInt32Type, false),
+//         (tof_fwhm, arrow::datatypes::Float32Type, false),
+//         (scan_fwhm, arrow::datatypes::Float32Type, false),
+//         (tof_apex, arrow::datatypes::Float64Type, false),
+//         (scan_apex, arrow::datatypes::Float64Type, false),
 //         (rt, arrow::datatypes::Float64Type, false),
 //         (im, arrow::datatypes::Float64Type, false),
 //         (mz, arrow::datatypes::Float64Type, false),
@@ -92,6 +115,12 @@ pub struct FullPeak {
     pub scan: u32,
     pub tof: u32,
     pub apex_intensity: u64,
+    pub area: u64,
+    pub ion_count: u32,
+    pub tof_fwhm: f32,
+    pub scan_fwhm: f32,
+    pub tof_apex: f64,
+    pub scan_apex: f64,
     pub rt: f64,
     pub im: f64,
     pub mz: f64,
@@ -106,6 +135,12 @@ pub struct FullPeak {
 //         (scan, arrow::datatypes::UInt32Type, false),
 //         (tof, arrow::datatypes::UInt32Type, false),
 //         (apex_intensity, arrow::datatypes::UInt64Type, false),
+//         (area, arrow::datatypes::UInt64Type, false),
+//         (ion_count, arrow::datatypes::UInt32Type, false),
+//         (tof_fwhm, arrow::datatypes::Float32Type, false),
+//         (scan_fwhm, arrow::datatypes::Float32Type, false),
+//         (tof_apex, arrow::datatypes::Float64Type, false),
+//         (scan_apex, arrow::datatypes::Float64Type, false),
 //         (rt, arrow::datatypes::Float64Type, false),
 //         (im, arrow::datatypes::Float64Type, false),
 //         (mz, arrow::datatypes::Float64Type, false),
@@ -151,3 +186,48 @@ pub struct Precursor {
 //         (ce, arrow::datatypes::Float64Type, false),
 //     ]
 // );
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeatureRecord {
+    pub id: u64,
+    pub rt_apex: f64,
+    pub rt_start: f64,
+    pub rt_end: f64,
+    pub im_apex: f64,
+    pub mz_apex: f64,
+    pub frame_apex: u32,
+    pub scan_apex: f64,
+    pub tof_apex: f64,
+    pub apex_intensity: u64,
+    pub area: u64,
+    pub ion_count: u32,
+    pub elution_rts: Vec<f64>,
+    pub elution_profile: Vec<u64>,
+    pub isotope_group: Option<u64>,
+    pub charge: Option<u8>,
+    pub isotope: Option<u8>,
+}
+
+impl From<Feature> for FeatureRecord {
+    fn from(feature: Feature) -> Self {
+        Self {
+            id: feature.id as u64,
+            rt_apex: feature.rt_apex,
+            rt_start: feature.rt_start,
+            rt_end: feature.rt_end,
+            im_apex: feature.im_apex,
+            mz_apex: feature.mz_apex,
+            frame_apex: feature.frame_apex,
+            scan_apex: feature.scan_apex,
+            tof_apex: feature.tof_apex,
+            apex_intensity: feature.apex_intensity,
+            area: feature.area,
+            ion_count: feature.ion_count,
+            elution_rts: feature.elution_rts,
+            elution_profile: feature.elution_profile,
+            isotope_group: feature.isotope_group.map(|group| group as u64),
+            charge: feature.charge,
+            isotope: feature.isotope,
+        }
+    }
+}
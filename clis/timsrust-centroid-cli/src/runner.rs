@@ -1,10 +1,11 @@
 use std::sync::Arc;
 
-use indicatif::{ParallelProgressIterator, ProgressStyle};
+use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use timsrust::{ImConverter, MzConverter, RtConverter};
 use timsrust_centroid::{
     PeakReader, TimsError, TimsResult,
+    features::{FeatureFinder, FeatureFinderConfig},
     spectrum_reader::{
         NarrowSpectrumReader, SpectrumReader,
         narrow_spectrum_reader::{QuadInfo, SpectralChunk, split_peaks},
@@ -12,15 +13,23 @@ use timsrust_centroid::{
 };
 use timsrust_tdf::{FrameInfoReader, TdfFrameReader, TdfIonReader};
 
-use timsrust_core::{Converter, utils::thread::Synced};
 use timsrust_core::{
-    ConvertibleTo, FrameIndex, Im, Mz, Rt, ScanIndex, TofIndex,
-    io::formats::parquet::ParquetWriter,
+    Converter,
+    utils::{ordered::par_for_each_ordered, thread::Synced},
+};
+use timsrust_core::{
+    ConvertibleTo, FrameIndex, Im, MSLevel, Mz, Rt, ScanIndex, Spectrum,
+    TofIndex, io::formats::parquet::ParquetWriter,
 };
 use timsrust_mgf::MGFWriter;
+use timsrust_mzml::MzMLWriter;
 // use timsrust_tdf::{Metadata, Scan2ImConverter, Tof2MzConverter};
 
-use crate::{CoordinatePeak, FullPeak, Precursor};
+use crate::{CoordinatePeak, FeatureRecord, FullPeak, Precursor};
+
+/// Number of frames that are processed in parallel before their spectra are
+/// written, in frame order.
+const EXPORT_WINDOW: usize = 256;
 
 type TdfPeakReader = PeakReader<TdfIonReader, FrameInfoReader>;
 type TdfNarrowReader = NarrowSpectrumReader<
@@ -44,10 +53,11 @@ fn make_tdf_frame_reader(
 ///
 /// # Arguments
 /// * `in_path` - Path to the input file containing a .d folder.
-/// * `out_path` - Path to the output (WARNING: will be overwritten). Needs to be .parquet or .mgf.
+/// * `out_path` - Path to the output (WARNING: will be overwritten). Needs to be .parquet, .mgf or .mzML.
+///   A `.features.parquet` output contains LC-IMS-MS features instead of peaks.
 /// * `min_ion_count_ms1` - Minimum number of ions required for a peak to be considered in MS1.
 /// * `min_ion_count_ms2` - Minimum number of ions required for a peak to be considered in MS2.
-/// * `min_spectrum_size` - Minimum number of peaks required for a spectrum to be written (only for .mgf output).
+/// * `min_spectrum_size` - Minimum number of peaks required for a spectrum to be written (only for .mgf and .mzML output).
 ///
 /// # Returns
 /// * `TimsResult<()>` - Returns `Ok(())` if successful, or an error otherwise.
@@ -83,6 +93,14 @@ pub fn run(
             im_converter,
             rt_converter,
         ),
+        out_path if out_path.ends_with(".features.parquet") => run_features(
+            in_path,
+            out_path,
+            min_ion_count_ms1,
+            min_ion_count_ms2,
+            mz_converter,
+            im_converter,
+        ),
         out_path if out_path.ends_with(".parquet") => run_parquet(
             in_path,
             out_path,
@@ -102,12 +120,63 @@ pub fn run(
             mz_converter,
             im_converter,
         ),
+        out_path if out_path.to_lowercase().ends_with(".mzml") => run_mzml(
+            in_path,
+            out_path,
+            min_ion_count_ms1,
+            min_ion_count_ms2,
+            min_spectrum_size,
+            use_precursors,
+            mz_converter,
+            im_converter,
+            rt_converter,
+        ),
         _ => Err(TimsError::new(
-            "Output file must end with .parquet or .mgf".to_string(),
+            "Output file must end with .parquet, .mgf or .mzML".to_string(),
         )),
     }
 }
 
+fn run_features(
+    in_path: impl AsRef<str>,
+    out_path: impl AsRef<str>,
+    min_ion_count_ms1: f64,
+    min_ion_count_ms2: f64,
+    mz_converter: MzConverter,
+    im_converter: ImConverter,
+) -> TimsResult<()> {
+    let time = std::time::Instant::now();
+    log::info!("Running 3D feature finding on {}", in_path.as_ref());
+    let peak_reader = {
+        let fr = make_tdf_frame_reader(&in_path)?;
+        PeakReader::new(fr, min_ion_count_ms1, min_ion_count_ms2)?
+    };
+    log::info!("Found {} frames", peak_reader.frame_count());
+    log::info!("Calculated TOF FWHM: {}", peak_reader.tof_fwhm());
+    log::info!("Calculated scan FWHM: {}", peak_reader.scan_fwhm());
+    log::info!("Using min_ion_count_ms1: {}", min_ion_count_ms1);
+    let feature_finder = FeatureFinder::new(
+        peak_reader,
+        Arc::new(im_converter),
+        Arc::new(mz_converter),
+        FeatureFinderConfig::default(),
+    );
+    let features = feature_finder.find_features()?;
+    let feature_count = features.len();
+    let mut writer = ParquetWriter::new(out_path.as_ref())
+        .map_err(|e| TimsError::new(e.to_string()))?;
+    writer
+        .write_batch(features.into_iter().map(FeatureRecord::from).collect())
+        .map_err(|e| TimsError::new(e.to_string()))?;
+    log::info!(
+        "Wrote {} features to {} in {:?}",
+        feature_count,
+        out_path.as_ref(),
+        time.elapsed()
+    );
+    Ok(())
+}
+
 fn run_parquet(
     in_path: impl AsRef<str>,
     out_path: impl AsRef<str>,
@@ -155,14 +224,15 @@ fn centroid_all_frames(
     im_converter: &ImConverter,
     rt_converter: &RtConverter,
 ) -> TimsResult<usize> {
-    let peak_count = (0..peak_reader.frame_count())
-        .into_par_iter()
+    let peak_count = peak_reader
+        .frame_indices()
+        .par_iter()
         .progress_with_style(
             ProgressStyle::default_bar()
                 .template(" [{elapsed_precise}] {bar} {pos:>7}/{len:7} ({eta}, {per_sec} frames/s)")
                 .expect("Failed to set progress style")
         )
-        .map(|index| {
+        .map(|&index| {
             if let Ok(peaks) = peak_reader.get_peaks_from_frame(index) {
                 let frame = peak_reader.frame_reader().get_frame(index).unwrap();
                 let quad_info = frame.info().quadrupole_settings().clone();
@@ -175,6 +245,12 @@ fn centroid_all_frames(
                             scan: p.scan,
                             tof: p.tof,
                             apex_intensity: p.apex_intensity,
+                            area: p.area,
+                            ion_count: p.ion_count,
+                            tof_fwhm: p.tof_fwhm,
+                            scan_fwhm: p.scan_fwhm,
+                            tof_apex: p.tof_apex,
+                            scan_apex: p.scan_apex,
                             rt: f64::from(
                                 FrameIndex::try_from(p.frame)
                                     .unwrap()
@@ -254,8 +330,9 @@ fn run_parquet_mgf(
         Synced::from(ParquetWriter::new(frag_path).unwrap());
     let synced_precursor_writer =
         Synced::from(ParquetWriter::new(prec_path).unwrap());
-    (0..spectrum_reader.frame_count())
-        .into_par_iter()
+    spectrum_reader
+        .frame_indices()
+        .par_iter()
         .progress_with_style(
             ProgressStyle::default_bar()
                 .template(
@@ -263,7 +340,7 @@ fn run_parquet_mgf(
                 )
                 .expect("Failed to set progress style"),
         )
-        .for_each(|index| {
+        .for_each(|&index| {
             if let Some(chunk) = spectrum_reader.get_spectral_chunk_from_frame(index) {
                 let count = write_spectrum_parquet_chunk(
                     &chunk,
@@ -314,6 +391,12 @@ fn write_spectrum_parquet_chunk(
                     scan: p.scan,
                     tof: p.tof,
                     apex_intensity: p.apex_intensity,
+                    area: p.area,
+                    ion_count: p.ion_count,
+                    tof_fwhm: p.tof_fwhm,
+                    scan_fwhm: p.scan_fwhm,
+                    tof_apex: p.tof_apex,
+                    scan_apex: p.scan_apex,
                     rt: f64::from(
                         FrameIndex::try_from(p.frame)
                             .unwrap()
@@ -411,7 +494,8 @@ fn run_mgf(
         )?
     };
     let mz_converter = Arc::new(mz_converter);
-    let mgf_writer = MGFWriter::new(out_path.as_ref());
+    let mut mgf_writer = MGFWriter::new(out_path.as_ref())
+        .map_err(|e| TimsError::new(e.to_string()))?;
     log::info!("Found {} frames", spectrum_reader.frame_count());
     log::info!("Calculated TOF FWHM: {}", spectrum_reader.tof_fwhm());
     log::info!("Calculated scan FWHM: {}", spectrum_reader.scan_fwhm());
@@ -419,25 +503,29 @@ fn run_mgf(
     log::info!("Using min_ion_count_ms2: {}", min_ion_count_ms2);
     log::info!("Using min_spectrum_size: {}", min_spectrum_size);
     log::info!("Using precursors: {}", use_precursors);
-    let synced_mgf_writer = Synced::from(mgf_writer);
-    (0..spectrum_reader.frame_count())
-        .into_par_iter()
-        .progress_with_style(
-            ProgressStyle::default_bar()
-                .template(
-                    " [{elapsed_precise}] {bar} {pos:>7}/{len:7} ({eta}, {per_sec} frames/s)",
-                )
-                .expect("Failed to set progress style"),
-        )
-        .for_each(|index| {
+    let progress = frame_progress_bar(spectrum_reader.frame_count());
+    let frame_indices = spectrum_reader.frame_indices();
+    par_for_each_ordered(
+        frame_indices.len(),
+        EXPORT_WINDOW,
+        |position| {
+            let index = frame_indices[position];
             let spectra = spectrum_reader.get_spectra_from_frame(index);
-            _ = synced_mgf_writer.with_lock(|mgf_writer| {
-                for spectrum in spectra.into_iter() {
-                    let spectrum = spectrum.to_mz_spectrum(mz_converter.as_ref());
-                    mgf_writer.write(&spectrum);
-                }
-            });
-        });
+            progress.inc(1);
+            spectra
+        },
+        |spectra| {
+            spectra.into_iter().try_for_each(|spectrum| {
+                let spectrum = spectrum.to_mz_spectrum(mz_converter.as_ref());
+                mgf_writer.write(&spectrum)
+            })
+        },
+    )
+    .map_err(|e| TimsError::new(e.to_string()))?;
+    progress.finish();
+    mgf_writer
+        .finalize()
+        .map_err(|e| TimsError::new(e.to_string()))?;
     log::info!(
         "Wrote {} spectra to {} in {:?}",
         spectrum_reader.len(),
@@ -446,3 +534,413 @@ fn run_mgf(
     );
     Ok(())
 }
+
+#[allow(clippy::too_many_arguments)]
+fn run_mzml(
+    in_path: impl AsRef<str>,
+    out_path: impl AsRef<str>,
+    min_ion_count_ms1: f64,
+    min_ion_count_ms2: f64,
+    min_spectrum_size: usize,
+    use_precursors: bool,
+    mz_converter: MzConverter,
+    im_converter: ImConverter,
+    rt_converter: RtConverter,
+) -> TimsResult<()> {
+    let time = std::time::Instant::now();
+    log::info!("Running 2D centroiding on {}", in_path.as_ref());
+    let peak_reader = {
+        let fr = make_tdf_frame_reader(&in_path)?;
+        PeakReader::new(fr, min_ion_count_ms1, min_ion_count_ms2)?
+    };
+    let spectrum_reader: TdfSpectrumReader = {
+        let fr = make_tdf_frame_reader(in_path.as_ref())?;
+        SpectrumReader::new(
+            fr,
+            min_ion_count_ms1,
+            min_ion_count_ms2,
+            min_spectrum_size,
+            use_precursors,
+            im_converter.clone(),
+            mz_converter.clone(),
+        )?
+    };
+    let metadata = timsrust_tdf::Metadata::new(in_path.as_ref())
+        .map_err(|e| TimsError::new(e.to_string()))?;
+    let mut mzml_writer = MzMLWriter::new(out_path.as_ref())
+        .map_err(|e| TimsError::new(e.to_string()))?;
+    mzml_writer.set_metadata(&metadata);
+    log::info!("Found {} frames", spectrum_reader.frame_count());
+    log::info!("Calculated TOF FWHM: {}", spectrum_reader.tof_fwhm());
+    log::info!("Calculated scan FWHM: {}", spectrum_reader.scan_fwhm());
+    log::info!("Using min_ion_count_ms1: {}", min_ion_count_ms1);
+    log::info!("Using min_ion_count_ms2: {}", min_ion_count_ms2);
+    log::info!("Using min_spectrum_size: {}", min_spectrum_size);
+    log::info!("Using precursors: {}", use_precursors);
+    let progress = frame_progress_bar(spectrum_reader.frame_count());
+    let frame_indices = spectrum_reader.frame_indices();
+    par_for_each_ordered(
+        frame_indices.len(),
+        EXPORT_WINDOW,
+        |position| {
+            let index = frame_indices[position];
+            let spectra = frame_spectra(
+                index,
+                &peak_reader,
+                &spectrum_reader,
+                min_spectrum_size,
+                &mz_converter,
+                &im_converter,
+                &rt_converter,
+            );
+            progress.inc(1);
+            spectra
+        },
+        |spectra| {
+            let spectra = spectra?;
+            if let Some((spectrum, rt, ion_mobilities)) = &spectra.ms1 {
+                mzml_writer
+                    .write_ms1(spectrum, *rt, Some(ion_mobilities))
+                    .map_err(|e| TimsError::new(e.to_string()))?;
+            }
+            spectra
+                .ms2
+                .iter()
+                .try_for_each(|spectrum| mzml_writer.write(spectrum))
+                .map_err(|e| TimsError::new(e.to_string()))
+        },
+    )?;
+    progress.finish();
+    let spectrum_count = mzml_writer.len();
+    mzml_writer
+        .finalize()
+        .map_err(|e| TimsError::new(e.to_string()))?;
+    log::info!(
+        "Wrote {} spectra to {} in {:?}",
+        spectrum_count,
+        out_path.as_ref(),
+        time.elapsed()
+    );
+    Ok(())
+}
+
+/// A progress bar over all frames of a run.
+fn frame_progress_bar(frame_count: usize) -> ProgressBar {
+    ProgressBar::new(frame_count as u64).with_style(
+        ProgressStyle::default_bar()
+            .template(
+                " [{elapsed_precise}] {bar} {pos:>7}/{len:7} ({eta}, {per_sec} frames/s)",
+            )
+            .expect("Failed to set progress style"),
+    )
+}
+
+/// The spectra of a single frame, ready to be written to mzML.
+struct FrameSpectra {
+    /// The centroided MS1 spectrum of an MS1 frame, unless it has fewer
+    /// than `min_spectrum_size` peaks.
+    ms1: Option<(Spectrum<Mz>, Rt, Vec<Im>)>,
+    /// The MS2 spectra the spectrum reader assigns to this frame: the
+    /// spectra of all MS2 frames of the cycle for a narrow reader, which
+    /// groups them by MS1 frame, or those of the frame itself for a wide
+    /// reader.
+    ms2: Vec<Spectrum<Mz>>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn frame_spectra(
+    index: usize,
+    peak_reader: &TdfPeakReader,
+    spectrum_reader: &TdfSpectrumReader,
+    min_spectrum_size: usize,
+    mz_converter: &MzConverter,
+    im_converter: &ImConverter,
+    rt_converter: &RtConverter,
+) -> TimsResult<FrameSpectra> {
+    let info = peak_reader
+        .frame_reader()
+        .get_info(index)
+        .map_err(|e| TimsError::new(e.to_string()))?;
+    let ms_level = info.ms_level();
+    let has_ms2_spectra = match spectrum_reader {
+        SpectrumReader::Narrow(_) => ms_level == MSLevel::MS1,
+        SpectrumReader::Wide(_) => ms_level == MSLevel::MS2,
+    };
+    let ms2 = if has_ms2_spectra {
+        spectrum_reader
+            .get_spectra_from_frame(index)
+            .into_iter()
+            .map(|spectrum| spectrum.to_mz_spectrum(mz_converter))
+            .collect()
+    } else {
+        vec![]
+    };
+    if ms_level != MSLevel::MS1 {
+        return Ok(FrameSpectra { ms1: None, ms2 });
+    }
+    let peaks = peak_reader.get_peaks_from_frame(index)?;
+    let ms1 = (peaks.len() >= min_spectrum_size).then(|| {
+        let (spectrum, ion_mobilities) =
+            to_ms1_spectrum(index, &peaks, mz_converter, im_converter);
+        let rt = FrameIndex::try_from(index).unwrap().convert(rt_converter);
+        (spectrum, rt, ion_mobilities)
+    });
+    Ok(FrameSpectra { ms1, ms2 })
+}
+
+/// An MS1 spectrum of centroided peaks, sorted by m/z, with the ion
+/// mobility of every peak.
+fn to_ms1_spectrum(
+    frame_index: usize,
+    peaks: &[timsrust_centroid::Peak],
+    mz_converter: &MzConverter,
+    im_converter: &ImConverter,
+) -> (timsrust_core::Spectrum<Mz>, Vec<Im>) {
+    let mut peaks = peaks.to_vec();
+    peaks.sort_by_key(|p| p.tof);
+    let spectrum = timsrust_core::Spectrum::new(
+        peaks.iter().map(|p| p.apex_intensity as f64).collect(),
+        frame_index,
+        None,
+        peaks
+            .iter()
+            .map(|p| TofIndex::try_from(p.tof).unwrap().convert(mz_converter))
+            .collect(),
+        Default::default(),
+    );
+    let ion_mobilities = peaks
+        .iter()
+        .map(|p| ScanIndex::try_from(p.scan).unwrap().convert(im_converter))
+        .collect();
+    (spectrum, ion_mobilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::Path};
+
+    use timsrust_core::io::formats::parquet::ParquetReader;
+
+    use super::*;
+
+    const SCAN_COUNT: usize = 40;
+    const DIGITIZER_SAMPLES: f64 = 400000.0;
+    /// The precursors as `(mz, scan)`, one per window group.
+    const PRECURSORS: [(f64, usize); 2] = [(500.0, 15), (700.0, 25)];
+    const FRAGMENT_MZS: [f64; 5] = [420.0, 450.0, 480.0, 520.0, 560.0];
+
+    /// The TOF index of `mz` for an m/z range of 100 to 1000.
+    fn mz_to_tof(mz: f64) -> u32 {
+        let slope = (1000_f64.sqrt() - 100_f64.sqrt()) / DIGITIZER_SAMPLES;
+        ((mz.sqrt() - 100_f64.sqrt()) / slope).round() as u32
+    }
+
+    /// A compression type 2 frame blob with its 8 byte header, from peaks
+    /// `(scan, mz, intensity)` spread over the neighbouring scans and TOF
+    /// indices.
+    fn blob(peaks: &[(usize, f64, f64)]) -> Vec<u8> {
+        let mut scans = vec![BTreeMap::new(); SCAN_COUNT];
+        for &(scan, mz, intensity) in peaks {
+            for scan_offset in [-1, 0, 1_isize] {
+                for tof_offset in [-1, 0, 1_isize] {
+                    let weight = 0.5_f64
+                        .powi((scan_offset.abs() + tof_offset.abs()) as i32);
+                    let tof = mz_to_tof(mz)
+                        .checked_add_signed(tof_offset as i32)
+                        .unwrap();
+                    *scans[scan.checked_add_signed(scan_offset).unwrap()]
+                        .entry(tof)
+                        .or_insert(0) += (intensity * weight) as u32;
+                }
+            }
+        }
+        let mut values = vec![SCAN_COUNT as u32];
+        values
+            .extend(scans[..SCAN_COUNT - 1].iter().map(|s| 2 * s.len() as u32));
+        for scan in &scans {
+            let mut previous = None;
+            for (&tof, &intensity) in scan {
+                values.push(previous.map_or(tof + 1, |p| tof - p));
+                values.push(intensity);
+                previous = Some(tof);
+            }
+        }
+        let bytes = (0..4)
+            .flat_map(|k| values.iter().map(move |v| (v >> (8 * k)) as u8))
+            .collect::<Vec<_>>();
+        let compressed = zstd::encode_all(bytes.as_slice(), 0).unwrap();
+        let mut blob = ((compressed.len() + 8) as u32).to_le_bytes().to_vec();
+        blob.extend((SCAN_COUNT as u32).to_le_bytes());
+        blob.extend(compressed);
+        blob
+    }
+
+    /// Writes a DIA run of `cycles` cycles of an MS1 frame with the isotope
+    /// envelopes of [`PRECURSORS`], followed by one MS2 frame per precursor
+    /// with its [`FRAGMENT_MZS`].
+    fn write_dia_run(dir: &Path, cycles: usize) -> String {
+        let mut bin = vec![];
+        let mut frames = vec![];
+        let mut window_groups = vec![];
+        for cycle in 0..cycles {
+            let abundance = 1.0 + cycle.min(cycles - cycle - 1) as f64;
+            let peaks = PRECURSORS
+                .iter()
+                .flat_map(|&(mz, scan)| {
+                    [1.0, 0.55, 0.15].into_iter().enumerate().map(
+                        move |(isotope, ratio)| {
+                            let mz = mz + isotope as f64 * 1.0033548378 / 2.0;
+                            (scan, mz, 2000.0 * abundance * ratio)
+                        },
+                    )
+                })
+                .collect::<Vec<_>>();
+            frames.push((0, bin.len(), 9 * peaks.len()));
+            bin.extend(blob(&peaks));
+            for (group, &(_, scan)) in PRECURSORS.iter().enumerate() {
+                let peaks = FRAGMENT_MZS
+                    .iter()
+                    .map(|&mz| (scan, mz, 500.0 * abundance))
+                    .collect::<Vec<_>>();
+                window_groups.push((frames.len() + 1, group + 1));
+                frames.push((9, bin.len(), 9 * peaks.len()));
+                bin.extend(blob(&peaks));
+            }
+        }
+        std::fs::write(dir.join("analysis.tdf_bin"), bin).unwrap();
+        let frames = frames
+            .iter()
+            .enumerate()
+            .map(|(index, (msms_type, offset, peak_count))| {
+                format!(
+                    "({}, {}, 9, {msms_type}, {offset}, {SCAN_COUNT}, \
+                    {peak_count}, 100)",
+                    index + 1,
+                    index as f64 * 0.1,
+                )
+            })
+            .collect::<Vec<_>>();
+        let window_groups = window_groups
+            .iter()
+            .map(|(frame, group)| format!("({frame}, {group})"))
+            .collect::<Vec<_>>();
+        let windows = PRECURSORS
+            .iter()
+            .enumerate()
+            .map(|(group, (mz, _))| {
+                format!("({}, 0, {SCAN_COUNT}, {mz}, 50, 30)", group + 1)
+            })
+            .collect::<Vec<_>>();
+        rusqlite::Connection::open(dir.join("analysis.tdf"))
+            .unwrap()
+            .execute_batch(&format!(
+                "CREATE TABLE GlobalMetadata (Key TEXT, Value TEXT);
+                INSERT INTO GlobalMetadata VALUES
+                    ('TimsCompressionType', '2'),
+                    ('MaxNumPeaksPerScan', '100'),
+                    ('DigitizerNumSamples', '{DIGITIZER_SAMPLES}'),
+                    ('MzAcqRangeLower', '100.0'),
+                    ('MzAcqRangeUpper', '1000.0'),
+                    ('OneOverK0AcqRangeLower', '0.5'),
+                    ('OneOverK0AcqRangeUpper', '1.5'),
+                    ('AcquisitionSoftware', 'timsTOF');
+                CREATE TABLE Frames (
+                    Id INTEGER, Time REAL, ScanMode INTEGER,
+                    MsMsType INTEGER, TimsId INTEGER, NumScans INTEGER,
+                    NumPeaks INTEGER, AccumulationTime REAL
+                );
+                INSERT INTO Frames VALUES {};
+                CREATE TABLE DiaFrameMsMsInfo (
+                    Frame INTEGER, WindowGroup INTEGER
+                );
+                INSERT INTO DiaFrameMsMsInfo VALUES {};
+                CREATE TABLE DiaFrameMsMsWindows (
+                    WindowGroup INTEGER, ScanNumBegin INTEGER,
+                    ScanNumEnd INTEGER, IsolationMz REAL,
+                    IsolationWidth REAL, CollisionEnergy REAL
+                );
+                INSERT INTO DiaFrameMsMsWindows VALUES {};",
+                frames.join(", "),
+                window_groups.join(", "),
+                windows.join(", "),
+            ))
+            .unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn mzml_contains_ms2_spectra_of_dia_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = dir.path().join("dia.d");
+        std::fs::create_dir(&run).unwrap();
+        let in_path = write_dia_run(&run, 5);
+        let out_path = dir.path().join("dia.mzML");
+        run_mzml(
+            &in_path,
+            out_path.to_str().unwrap(),
+            1.0,
+            1.0,
+            5,
+            true,
+            MzConverter::new(&in_path).unwrap(),
+            ImConverter::new(&in_path).unwrap(),
+            RtConverter::new(&in_path).unwrap(),
+        )
+        .unwrap();
+        let xml = std::fs::read_to_string(out_path).unwrap();
+        let ms1_count = xml.matches(r#"name="ms level" value="1""#).count();
+        let ms2_count = xml.matches(r#"name="ms level" value="2""#).count();
+        assert_eq!(ms1_count, 5);
+        assert_eq!(ms2_count, 2 * 5);
+    }
+
+    #[test]
+    fn features_of_planted_isotope_envelopes() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = dir.path().join("dia.d");
+        std::fs::create_dir(&run).unwrap();
+        let in_path = write_dia_run(&run, 5);
+        let out_path = dir.path().join("dia.features.parquet");
+        run_features(
+            &in_path,
+            out_path.to_str().unwrap(),
+            1.0,
+            1.0,
+            MzConverter::new(&in_path).unwrap(),
+            ImConverter::new(&in_path).unwrap(),
+        )
+        .unwrap();
+        let features = ParquetReader::<FeatureRecord>::from(&out_path)
+            .unwrap()
+            .read_all()
+            .unwrap();
+        // Three isotopes of two precursors, each over all five MS1 frames.
+        assert_eq!(features.len(), 3 * PRECURSORS.len());
+        let im_converter = ImConverter::new(&in_path).unwrap();
+        let monoisotopic = &features[0];
+        // MS1 frames are every third frame, 0.1 seconds apart.
+        let ms1_rts = (0..5).map(|cycle| (3 * cycle) as f64 * 0.1);
+        assert_eq!(monoisotopic.elution_rts, ms1_rts.collect::<Vec<_>>());
+        assert_eq!(monoisotopic.rt_apex, 6.0 * 0.1);
+        assert_eq!(monoisotopic.frame_apex, 7);
+        assert_eq!(monoisotopic.scan_apex, 15.0);
+        assert_eq!(
+            monoisotopic.im_apex,
+            f64::from(
+                ScanIndex::try_from(15_u32).unwrap().convert(&im_converter)
+            ),
+        );
+        assert!((monoisotopic.mz_apex - PRECURSORS[0].0).abs() < 0.01);
+        // Every planted peak has four times its apex intensity as area.
+        assert_eq!(
+            monoisotopic.elution_profile,
+            [8000, 16000, 24000, 16000, 8000]
+        );
+        assert_eq!(monoisotopic.area, 72000);
+        for (isotope, feature) in features[..3].iter().enumerate() {
+            assert_eq!(feature.isotope_group, Some(monoisotopic.id));
+            assert_eq!(feature.charge, Some(2));
+            assert_eq!(feature.isotope, Some(isotope as u8));
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+use timsrust::IonExportConfig;
+
+use crate::run;
+
+#[derive(Parser, Debug)]
+#[command(name = "timsions", version = clap::crate_version!(), author = "BrukerProteoScape", about = "Export all ions of TimsTof data as a Parquet table")]
+struct Input {
+    #[arg(
+        help = "Path to TimsTof data (i.e. (any file within) a .d folder)",
+        value_hint = ValueHint::FilePath
+    )]
+    in_path: String,
+    #[arg(
+        long = "out-path",
+        short = 'o',
+        default_value = "./ions.parquet",
+        help = "Path to a results file (WARNING: overwrites existing files). Supported formats: .parquet",
+        value_hint = ValueHint::FilePath,
+        value_parser = validate_output_path,
+    )]
+    out_path: String,
+    #[arg(long = "min-frame", help = "First frame index to export")]
+    min_frame: Option<usize>,
+    #[arg(long = "max-frame", help = "Last frame index to export")]
+    max_frame: Option<usize>,
+    #[arg(long = "min-rt", help = "Minimum retention time in seconds")]
+    min_rt: Option<f64>,
+    #[arg(long = "max-rt", help = "Maximum retention time in seconds")]
+    max_rt: Option<f64>,
+    #[arg(
+        long = "row-group-size",
+        default_value_t = IonExportConfig::default().row_group_size,
+        help = "Number of ions per Parquet row group"
+    )]
+    row_group_size: usize,
+    #[arg(long = "no-rt", help = "Leave the rt column empty")]
+    no_rt: bool,
+    #[arg(long = "no-im", help = "Leave the im (1/K0) column empty")]
+    no_im: bool,
+    #[arg(long = "no-mz", help = "Leave the mz column empty")]
+    no_mz: bool,
+}
+
+impl Input {
+    fn config(&self) -> IonExportConfig {
+        let frames = match (self.min_frame, self.max_frame) {
+            (None, None) => None,
+            (min, max) => {
+                Some(min.unwrap_or(0)..max.map_or(usize::MAX, |max| max + 1))
+            },
+        };
+        let rt_range = match (self.min_rt, self.max_rt) {
+            (None, None) => None,
+            (min, max) => Some(
+                min.unwrap_or(f64::NEG_INFINITY)..=max.unwrap_or(f64::INFINITY),
+            ),
+        };
+        IonExportConfig {
+            frames,
+            rt_range,
+            include_rt: !self.no_rt,
+            include_im: !self.no_im,
+            include_mz: !self.no_mz,
+            row_group_size: self.row_group_size,
+        }
+    }
+}
+
+fn validate_output_path(s: &str) -> Result<String, String> {
+    let path = PathBuf::from(s);
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| ext.to_lowercase())
+    {
+        Some(ref ext) if ext == "parquet" => {
+            Ok(path.to_string_lossy().to_string())
+        },
+        _ => Err(String::from("Invalid file extension. Must be .parquet")),
+    }
+}
+
+/// Command-line interface entry point for the ion table export.
+pub struct CLI {}
+
+impl CLI {
+    /// Run the CLI application.
+    ///
+    /// Parses arguments and invokes the main runner.
+    pub fn run() {
+        let input = Input::parse();
+        let config = input.config();
+        run(input.in_path, input.out_path, config);
+    }
+}
@@ -0,0 +1,5 @@
+mod cli;
+mod runner;
+
+pub use cli::CLI;
+pub use runner::run;
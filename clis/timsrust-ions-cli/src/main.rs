@@ -0,0 +1,3 @@
+fn main() {
+    timsrust_ions_cli::CLI::run();
+}
@@ -0,0 +1,22 @@
+use timsrust::{IonExportConfig, export_ions};
+
+pub fn run(
+    in_path: impl AsRef<str>,
+    out_path: impl AsRef<str>,
+    config: IonExportConfig,
+) {
+    env_logger::Builder::from_default_env()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+    let time = std::time::Instant::now();
+    log::info!("Running on file {}", in_path.as_ref());
+    log::info!("Using {:?}", config);
+    let count = export_ions(in_path, out_path.as_ref(), &config)
+        .expect("Failed to export ions");
+    log::info!(
+        "Wrote {} ions to {} in {:?}",
+        count,
+        out_path.as_ref(),
+        time.elapsed()
+    );
+}
@@ -1,8 +1,74 @@
 // use rayon::prelude::*;
-use timsrust::SpectrumReader;
-use timsrust::core::utils::thread::Synced;
+use timsrust::core::utils::ordered::par_for_each_ordered;
+use timsrust::core::{Mz, Spectrum};
+use timsrust::{SpectrumReader, SpectrumReaderError};
 use timsrust_cli_core::prelude::*;
 use timsrust_mgf::MGFWriter;
+use timsrust_mzml::MzMLWriter;
+use timsrust_mzpeak::MzPeakWriter;
+
+/// Number of chunks of spectra (single spectra or all spectra of a
+/// centroided frame) that are extracted in parallel before being written.
+const EXPORT_WINDOW: usize = 256;
+
+/// The output format, chosen by the extension of the output path.
+enum SpectrumWriter {
+    Mgf(MGFWriter),
+    MzML(MzMLWriter),
+    MzPeak(Box<MzPeakWriter>),
+}
+
+impl SpectrumWriter {
+    fn new(in_path: &str, out_path: &str) -> Self {
+        if out_path.to_lowercase().ends_with(".mzml") {
+            let mut writer =
+                MzMLWriter::new(out_path).expect("Failed to create mzML file");
+            if let Ok(metadata) = timsrust::tdf::Metadata::new(in_path) {
+                writer.set_metadata(&metadata);
+            }
+            Self::MzML(writer)
+        } else if out_path.to_lowercase().ends_with(".mzpeak") {
+            let mut writer = MzPeakWriter::new(out_path)
+                .expect("Failed to create mzPeak directory");
+            if let Ok(metadata) = timsrust::tdf::Metadata::new(in_path) {
+                writer.set_metadata(&metadata);
+            }
+            Self::MzPeak(Box::new(writer))
+        } else {
+            Self::Mgf(
+                MGFWriter::new(out_path).expect("Failed to create MGF file"),
+            )
+        }
+    }
+
+    fn write(&mut self, spectrum: &Spectrum<Mz>) -> Result<(), String> {
+        match self {
+            Self::Mgf(writer) => {
+                writer.write(spectrum).map_err(|e| e.to_string())
+            },
+            Self::MzML(writer) => {
+                writer.write(spectrum).map_err(|e| e.to_string())
+            },
+            Self::MzPeak(writer) => {
+                writer.write(spectrum).map_err(|e| e.to_string())
+            },
+        }
+    }
+
+    fn finalize(self) {
+        match self {
+            Self::Mgf(writer) => {
+                writer.finalize().expect("Failed to finalize MGF file")
+            },
+            Self::MzML(writer) => {
+                writer.finalize().expect("Failed to finalize mzML file")
+            },
+            Self::MzPeak(writer) => writer
+                .finalize()
+                .expect("Failed to finalize mzPeak directory"),
+        }
+    }
+}
 
 fn runner(
     in_path: impl AsRef<str>,
@@ -11,24 +77,30 @@ fn runner(
     top_n: usize,
 ) {
     let time = std::time::Instant::now();
-    let spectrum_reader = SpectrumReader::new(in_path).unwrap();
-    let mgf_writer = MGFWriter::new(out_path.as_ref());
-    let synced_mgf_writer = Synced::from(mgf_writer);
-    let spec_count = spectrum_reader
-        .par_iter()
-        .filter_map(|spectrum| {
-            #[allow(clippy::collapsible_if)]
-            if let Ok(spectrum) = spectrum {
-                let spectrum = spectrum.get_top_n(top_n);
-                if spectrum.len() >= min_spectrum_size {
-                    let _ = synced_mgf_writer
-                        .with_lock(|mgf_writer| mgf_writer.write(&spectrum));
-                    return Some(1);
-                }
+    let spectrum_reader = SpectrumReader::new(in_path.as_ref()).unwrap();
+    let mut writer = SpectrumWriter::new(in_path.as_ref(), out_path.as_ref());
+    let mut spec_count = 0;
+    par_for_each_ordered(
+        spectrum_reader.chunk_count(),
+        EXPORT_WINDOW,
+        |chunk| {
+            let spectra = spectrum_reader.get_chunk(chunk)?;
+            Ok(spectra
+                .into_iter()
+                .map(|spectrum| spectrum.get_top_n(top_n))
+                .filter(|spectrum| spectrum.len() >= min_spectrum_size)
+                .collect::<Vec<_>>())
+        },
+        |spectra: Result<_, SpectrumReaderError>| {
+            for spectrum in spectra.map_err(|e| e.to_string())? {
+                writer.write(&spectrum)?;
+                spec_count += 1;
             }
-            None
-        })
-        .sum::<usize>();
+            Ok::<(), String>(())
+        },
+    )
+    .expect("Failed to write spectrum");
+    writer.finalize();
     log::info!(
         "Wrote {} spectra to {} in {:?}",
         spec_count,
@@ -71,7 +143,7 @@ fn read_args() -> ArgMatches {
             Arg::new("output")
                 .required(true)
                 .index(2)
-                .help("Output path for filtered spectra"),
+                .help("Output path for filtered spectra (.mgf, .mzML or mzPeak-like .mzpeak)"),
         )
         .arg(
             Arg::new("min-spectrum-size")
@@ -1,9 +1,9 @@
-// use indicatif::{ParallelProgressIterator, ProgressStyle};
-use rayon::prelude::*;
-use timsrust_core::utils::thread::Synced;
+use timsrust_core::utils::ordered::par_for_each_ordered;
+use timsrust_parquet_spectra::spectrum_writer::ParquetSpectrumWriter;
 
-use crate::Fragment;
-use crate::{ParquetWriter, Precursor};
+/// Number of chunks of spectra (single spectra or all spectra of a
+/// centroided frame) that are extracted in parallel before being written.
+const EXPORT_WINDOW: usize = 256;
 
 pub fn run(
     in_path: impl AsRef<str>,
@@ -23,76 +23,32 @@ pub fn run(
     let prec_path = out_path
         .as_ref()
         .replace(".spec.parquet", ".precursors.parquet");
-    let synced_fragment_writer =
-        Synced::from(ParquetWriter::new(frag_path).unwrap());
-    let synced_precursor_writer =
-        Synced::from(ParquetWriter::new(prec_path).unwrap());
-    let precursors = spectrum_reader
-        .par_iter()
-        .filter_map(|x| {
-            if let Ok(spectrum) = x {
-                if spectrum.len() < min_spectrum_size {
-                    return None;
-                }
-                if let Some(precursor) = spectrum.precursor() {
-                    let fragments = spectrum
-                        .intensities()
-                        .iter()
-                        .zip(spectrum.mz_values())
-                        .map(|(intensity, mz)| Fragment {
-                            mz: f64::from(*mz),
-                            apex_intensity: *intensity as u64,
-                        })
-                        .collect();
-                    let current_frag_offset = synced_fragment_writer
-                        .with_lock(|w| {
-                            let offset = w.shape().0;
-                            let _ = w.write_batch(fragments);
-                            offset
-                        })
-                        .expect("Failed to write batch");
-                    let rt = f64::from(precursor.rt());
-                    let im = f64::from(precursor.im());
-                    let mz = f64::from(precursor.mz());
-                    let parquet_precursor = Precursor {
-                        frame: u32::from(precursor.frame_index()),
-                        scan: u32::from(precursor.scan_index()),
-                        tof: 0, //todo
-                        // apex_intensity: precursor.intensity().unwrap() as u64,
-                        apex_intensity: match precursor.intensity() {
-                            Some(i) => *i as u64,
-                            _ => 0,
-                        },
-                        rt,
-                        im,
-                        mz,
-                        start: current_frag_offset as u64,
-                        end: (current_frag_offset + spectrum.len()) as u64,
-                        charge: match precursor.charge() {
-                            Some(c) => i8::from(*c) as u8,
-                            _ => 0,
-                        },
-                        index: precursor.index() as u32,
-                        isolation_mz: f64::from(
-                            spectrum.isolation_window().center(),
-                        ),
-                        isolation_width: f64::from(
-                            spectrum.isolation_window().width(),
-                        ),
-                        ce: spectrum.isolation_window().collision_energy(),
-                    };
-                    return Some(parquet_precursor);
-                };
-            };
-            None
-        })
-        .collect::<Vec<_>>();
-    let len = precursors.len();
-    synced_precursor_writer
-        .with_lock(|w| {
-            let _ = w.write_batch(precursors);
-        })
-        .expect("Failed to write batch");
+    let mut writer = ParquetSpectrumWriter::new(prec_path, frag_path)
+        .expect("Failed to create parquet files");
+    par_for_each_ordered(
+        spectrum_reader.chunk_count(),
+        EXPORT_WINDOW,
+        |chunk| {
+            let spectra = spectrum_reader.get_chunk(chunk)?;
+            Ok(spectra
+                .into_iter()
+                .filter(|spectrum| {
+                    spectrum.len() >= min_spectrum_size
+                        && spectrum.precursor().is_some()
+                })
+                .collect::<Vec<_>>())
+        },
+        |spectra: Result<_, timsrust::SpectrumReaderError>| {
+            spectra
+                .map_err(|e| e.to_string())?
+                .iter()
+                .try_for_each(|spectrum| writer.write(spectrum))
+                .map_err(|e| e.to_string())
+        },
+    )
+    .expect("Failed to write spectrum");
+    let len = writer.len();
+    writer.finalize().expect("Failed to write precursors");
     log::info!(
         "Wrote {} precursors to {} in {:?}",
         len,
@@ -154,6 +154,30 @@ impl<T: for<'de> serde::Deserialize<'de>> JsonReader<T> {
     }
 }
 
+/// Reads and writes a value as a JSON or TOML file.
+///
+/// # Examples
+///
+/// ```
+/// use filemanager::formats::json::JsonFormat;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Config { window: u32, calibrate: bool }
+///
+/// impl JsonFormat for Config {}
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("config.toml");
+/// std::fs::write(&path, "window = 3\ncalibrate = true\n").unwrap();
+/// let config = Config::read_from_file(&path).unwrap();
+/// assert_eq!(config, Config { window: 3, calibrate: true });
+///
+/// let path = dir.path().join("config.json");
+/// config.write_to_json(&path).unwrap();
+/// let config = Config::read_from_file(&path).unwrap();
+/// assert_eq!(config, Config { window: 3, calibrate: true });
+/// ```
 pub trait JsonFormat: Sized {
     fn write_to_json(self, uri: impl Into<Uri>) -> Result<(), JsonError>
     where
@@ -169,4 +193,45 @@ pub trait JsonFormat: Sized {
     {
         JsonReader::from(uri)?.read_all()
     }
+
+    fn write_to_toml(self, uri: impl Into<Uri>) -> Result<(), JsonError>
+    where
+        Self: serde::Serialize,
+    {
+        let uri: Uri = uri.into();
+        let path = uri.as_path().ok_or_else(|| {
+            JsonError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "not a local path",
+            ))
+        })?;
+        let text = toml::to_string_pretty(&self)
+            .map_err(|e| JsonError::Serialize(Box::new(e)))?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    fn read_from_toml(uri: impl Into<Uri>) -> Result<Self, JsonError>
+    where
+        Self: for<'de> serde::Deserialize<'de>,
+    {
+        let data =
+            crate::formats::binary::BinaryReader::from(uri)?.read_range(..)?;
+        let text = std::str::from_utf8(&data)
+            .map_err(|e| JsonError::Deserialize(Box::new(e)))?;
+        toml::from_str(text).map_err(|e| JsonError::Deserialize(Box::new(e)))
+    }
+
+    /// Reads from TOML if the path ends in `.toml`, and from JSON otherwise.
+    fn read_from_file(uri: impl Into<Uri>) -> Result<Self, JsonError>
+    where
+        Self: for<'de> serde::Deserialize<'de>,
+    {
+        let uri: Uri = uri.into();
+        if uri.as_ref().to_lowercase().ends_with(".toml") {
+            Self::read_from_toml(uri)
+        } else {
+            Self::read_from_json(uri)
+        }
+    }
 }
@@ -1,16 +1,32 @@
+use std::io::Read;
 use std::marker::PhantomData;
-use std::path::PathBuf;
 use std::sync::Arc;
 
 use arrow::datatypes::{FieldRef, Schema};
+use bytes::Bytes;
 use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::{
+    ArrowReaderMetadata, ArrowReaderOptions, ParquetRecordBatchReader,
+    ParquetRecordBatchReaderBuilder,
+};
+use parquet::file::metadata::PageIndexPolicy;
 use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{ChunkReader, Length};
 use serde_arrow::schema::{SchemaLike, TracingOptions};
 
 use crate::Uri;
+use crate::formats::binary::BinaryReader;
+
+mod query;
+
+pub use query::{ParquetQuery, Predicate};
 
 const MAX_ROW_GROUPS: usize = 32000;
 
+/// Number of bytes fetched at once when parquet reads sequentially (e.g.
+/// page headers).
+const READ_BLOCK_SIZE: usize = 64 * 1024;
+
 /// Errors from Parquet read/write operations.
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
@@ -33,6 +49,9 @@ pub enum ParquetError {
     },
     #[error(transparent)]
     Cache(#[from] crate::CacheError),
+    /// A queried column does not exist.
+    #[error("unknown column: {0}")]
+    UnknownColumn(String),
 }
 
 /// Writes records to a Parquet file using Apache Arrow columnar format.
@@ -62,6 +81,7 @@ pub struct ParquetWriter<T> {
     batches: usize,
     max_batch_count: Option<usize>,
     max_row_count: Option<usize>,
+    row_group_size: Option<usize>,
     open_rows: usize,
     open_batches: usize,
     row_groups_written: usize,
@@ -111,6 +131,7 @@ impl<T: serde::Serialize + for<'de> serde::Deserialize<'de>> ParquetWriter<T> {
             batches: 0,
             max_batch_count: None,
             max_row_count: None,
+            row_group_size: None,
             open_rows: 0,
             open_batches: 0,
             row_groups_written: 0,
@@ -126,8 +147,42 @@ impl<T: serde::Serialize + for<'de> serde::Deserialize<'de>> ParquetWriter<T> {
         self.max_row_count = Some(count);
     }
 
+    /// Closes the open row group as soon as it holds at least `rows` rows.
+    ///
+    /// This takes precedence over the batch and row count limits. Batches
+    /// are never split, so writing batches of exactly `rows` rows yields
+    /// row groups of that size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use filemanager::formats::parquet::{ParquetReader, ParquetWriter};
+    /// use serde::{Serialize, Deserialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Row { id: u32 }
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("data.parquet");
+    /// let mut writer = ParquetWriter::<Row>::new(&path).unwrap();
+    /// writer.set_row_group_size(2);
+    /// for id in 0..5 {
+    ///     writer.write_batch(vec![Row { id }]).unwrap();
+    /// }
+    /// writer.close().unwrap();
+    /// let reader = ParquetReader::<Row>::from(&path).unwrap();
+    /// assert_eq!(reader.row_group_count(), 3);
+    /// ```
+    pub fn set_row_group_size(&mut self, rows: usize) {
+        self.row_group_size = Some(rows.max(1));
+    }
+
     fn flush(&mut self) -> Result<(), ParquetError> {
-        if let Some(max_batches) = self.max_batch_count {
+        if let Some(rows) = self.row_group_size {
+            if self.open_rows >= rows {
+                self.flush_and_close_row_group()?;
+            }
+        } else if let Some(max_batches) = self.max_batch_count {
             let batches_per_row_group = max_batches / MAX_ROW_GROUPS;
             if self.open_batches > batches_per_row_group {
                 self.flush_and_close_row_group()?;
@@ -218,6 +273,35 @@ impl<T: serde::Serialize + for<'de> serde::Deserialize<'de>> ParquetWriter<T> {
     pub fn shape(&self) -> (usize, usize) {
         (self.rows, self.cols)
     }
+
+    /// Writes the Parquet footer and closes the file.
+    ///
+    /// Dropping the writer closes it as well, but ignores any error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use filemanager::formats::parquet::{ParquetReader, ParquetWriter};
+    /// use serde::{Serialize, Deserialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Row { id: u32 }
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("data.parquet");
+    /// let mut writer = ParquetWriter::<Row>::new(&path).unwrap();
+    /// writer.write_batch(vec![Row { id: 1 }]).unwrap();
+    /// writer.close().unwrap();
+    /// assert_eq!(ParquetReader::<Row>::from(&path).unwrap().shape().0, 1);
+    /// ```
+    pub fn close(mut self) -> Result<(), ParquetError> {
+        if let Some(writer) = self.writer.take() {
+            writer
+                .close()
+                .map_err(|e| ParquetError::Write(Box::new(e)))?;
+        }
+        Ok(())
+    }
 }
 
 impl<T> Drop for ParquetWriter<T> {
@@ -249,9 +333,15 @@ impl<T> Drop for ParquetWriter<T> {
 /// let rows = reader.read_all().unwrap();
 /// assert_eq!(rows.len(), 2);
 /// ```
+///
+/// Only the footer and page index are read on creation. Local files are
+/// memory-mapped and cloud files are read with range requests (see
+/// [`BinaryReader`]), so [`read_range`](ParquetReader::read_range) and
+/// [`query`](ParquetReader::query) only fetch the row groups they need.
 #[derive(Debug)]
 pub struct ParquetReader<T> {
-    path: PathBuf,
+    source: ParquetSource,
+    metadata: ArrowReaderMetadata,
     rows: usize,
     cols: usize,
     _marker: PhantomData<T>,
@@ -277,30 +367,24 @@ impl<T: for<'de> serde::Deserialize<'de>> ParquetReader<T> {
     /// let _reader = ParquetReader::<Row>::from(&path).unwrap();
     /// ```
     pub fn from(uri: impl Into<Uri>) -> Result<Self, ParquetError> {
-        let uri = uri.into();
-        let uri = uri.force_cache()?;
-        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
-
-        let path = uri.as_path().ok_or_else(|| {
-            ParquetError::Io(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "not a local file",
-            ))
-        })?;
-        let f = std::fs::File::open(&path)?;
-        let builder = ParquetRecordBatchReaderBuilder::try_new(f)
+        let reader = BinaryReader::from(uri)
+            .map_err(|e| ParquetError::Read(Box::new(e)))?;
+        let source = ParquetSource(Arc::new(reader));
+        let options = ArrowReaderOptions::default()
+            .with_page_index_policy(PageIndexPolicy::Optional);
+        let metadata = ArrowReaderMetadata::load(&source, options)
             .map_err(|e| ParquetError::Read(Box::new(e)))?;
-        let metadata = builder.metadata();
         let rows: usize = metadata
+            .metadata()
             .row_groups()
             .iter()
             .map(|rg| rg.num_rows() as usize)
             .sum();
-        let schema = builder.schema();
-        let cols = schema.fields().len();
+        let cols = metadata.schema().fields().len();
 
         Ok(ParquetReader {
-            path,
+            source,
+            metadata,
             rows,
             cols,
             _marker: PhantomData,
@@ -374,23 +458,11 @@ impl<T: for<'de> serde::Deserialize<'de>> ParquetReader<T> {
     /// assert_eq!(reader.read_all().unwrap(), vec![Row { n: 42 }]);
     /// ```
     pub fn read_all(&self) -> Result<Vec<T>, ParquetError> {
-        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
-
-        let f = std::fs::File::open(&self.path)?;
-        let builder = ParquetRecordBatchReaderBuilder::try_new(f)
-            .map_err(|e| ParquetError::Read(Box::new(e)))?;
-        let reader = builder
+        let reader = self
+            .builder()
             .build()
             .map_err(|e| ParquetError::Read(Box::new(e)))?;
-
-        let mut result = Vec::with_capacity(self.rows);
-        for batch in reader {
-            let batch = batch.map_err(|e| ParquetError::Read(Box::new(e)))?;
-            let mut items: Vec<T> = serde_arrow::from_record_batch(&batch)
-                .map_err(|e| ParquetError::Read(Box::new(e)))?;
-            result.append(&mut items);
-        }
-        Ok(result)
+        Self::decode(reader, self.rows)
     }
 
     /// Reads a row range from the Parquet file.
@@ -412,22 +484,162 @@ impl<T: for<'de> serde::Deserialize<'de>> ParquetReader<T> {
     /// let reader = ParquetReader::<Row>::from(&path).unwrap();
     /// assert_eq!(reader.read_range(1..3).unwrap(), vec![Row { n: 2 }, Row { n: 3 }]);
     /// ```
+    ///
+    /// Only the row groups that overlap with the range are read:
+    ///
+    /// ```
+    /// use filemanager::formats::parquet::{ParquetWriter, ParquetReader};
+    /// use serde::{Serialize, Deserialize};
+    ///
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Row { n: u32 }
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("data.parquet");
+    /// let mut w = ParquetWriter::<Row>::new(&path).unwrap();
+    /// // Every batch becomes a separate row group.
+    /// w.set_max_row_count(1);
+    /// for n in 0..4 {
+    ///     w.write_batch(vec![Row { n: 2 * n }, Row { n: 2 * n + 1 }]).unwrap();
+    /// }
+    /// drop(w);
+    /// let reader = ParquetReader::<Row>::from(&path).unwrap();
+    /// assert_eq!(reader.row_group_count(), 4);
+    /// let rows = reader.read_range(3..6).unwrap();
+    /// assert_eq!(rows, vec![Row { n: 3 }, Row { n: 4 }, Row { n: 5 }]);
+    /// assert!(reader.read_range(3..3).unwrap().is_empty());
+    /// assert!(reader.read_range(7..9).is_err());
+    /// ```
     pub fn read_range(
         &self,
         range: std::ops::Range<usize>,
     ) -> Result<Vec<T>, ParquetError> {
-        if range.end > self.rows {
+        if range.end > self.rows || range.start > range.end {
             return Err(ParquetError::OutOfBounds {
                 start: range.start,
                 end: range.end,
                 rows: self.rows,
             });
         }
-        let all = self.read_all()?;
-        Ok(all
-            .into_iter()
-            .skip(range.start)
-            .take(range.end - range.start)
-            .collect())
+        if range.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut row_groups = vec![];
+        let mut offset = 0;
+        let mut group_start = 0;
+        for (index, row_group) in
+            self.metadata.metadata().row_groups().iter().enumerate()
+        {
+            let group_end = group_start + row_group.num_rows() as usize;
+            if group_start < range.end && range.start < group_end {
+                if row_groups.is_empty() {
+                    offset = range.start - group_start;
+                }
+                row_groups.push(index);
+            }
+            group_start = group_end;
+        }
+        let reader = self
+            .builder()
+            .with_row_groups(row_groups)
+            .with_offset(offset)
+            .with_limit(range.len())
+            .build()
+            .map_err(|e| ParquetError::Read(Box::new(e)))?;
+        Self::decode(reader, range.len())
+    }
+
+    /// Starts a selective read with column projection and predicates.
+    pub fn query(&self) -> ParquetQuery<'_, T> {
+        ParquetQuery::new(self)
+    }
+
+    /// Returns the number of row groups in the Parquet file.
+    #[must_use]
+    pub fn row_group_count(&self) -> usize {
+        self.metadata.metadata().num_row_groups()
+    }
+
+    fn builder(&self) -> ParquetRecordBatchReaderBuilder<ParquetSource> {
+        ParquetRecordBatchReaderBuilder::new_with_metadata(
+            self.source.clone(),
+            self.metadata.clone(),
+        )
+    }
+
+    fn decode(
+        reader: ParquetRecordBatchReader,
+        capacity: usize,
+    ) -> Result<Vec<T>, ParquetError> {
+        let mut result = Vec::with_capacity(capacity);
+        for batch in reader {
+            let batch = batch.map_err(|e| ParquetError::Read(Box::new(e)))?;
+            let mut items: Vec<T> = serde_arrow::from_record_batch(&batch)
+                .map_err(|e| ParquetError::Read(Box::new(e)))?;
+            result.append(&mut items);
+        }
+        Ok(result)
+    }
+}
+
+/// Gives parquet access to byte ranges of a local or cloud file.
+#[derive(Debug, Clone)]
+struct ParquetSource(Arc<BinaryReader>);
+
+impl Length for ParquetSource {
+    fn len(&self) -> u64 {
+        self.0.len() as u64
+    }
+}
+
+impl ChunkReader for ParquetSource {
+    type T = ParquetSourceRead;
+
+    fn get_read(&self, start: u64) -> parquet::errors::Result<Self::T> {
+        Ok(ParquetSourceRead {
+            source: self.clone(),
+            position: start,
+            buffer: Bytes::new(),
+        })
+    }
+
+    fn get_bytes(
+        &self,
+        start: u64,
+        length: usize,
+    ) -> parquet::errors::Result<Bytes> {
+        let start = start as usize;
+        self.0
+            .read_range(start..start + length)
+            .map(Bytes::from)
+            .map_err(|e| parquet::errors::ParquetError::External(Box::new(e)))
+    }
+}
+
+/// Reads a [`ParquetSource`] sequentially, fetching blocks of
+/// [`READ_BLOCK_SIZE`] bytes on demand.
+struct ParquetSourceRead {
+    source: ParquetSource,
+    position: u64,
+    buffer: Bytes,
+}
+
+impl Read for ParquetSourceRead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buffer.is_empty() {
+            let remaining = self.source.len().saturating_sub(self.position);
+            let length = remaining.min(READ_BLOCK_SIZE as u64) as usize;
+            if length == 0 {
+                return Ok(0);
+            }
+            self.buffer = self
+                .source
+                .get_bytes(self.position, length)
+                .map_err(std::io::Error::other)?;
+            self.position += length as u64;
+        }
+        let length = buf.len().min(self.buffer.len());
+        buf[..length].copy_from_slice(&self.buffer.split_to(length));
+        Ok(length)
     }
 }
@@ -0,0 +1,421 @@
+use std::ops::Bound;
+
+use arrow::array::{ArrayRef, AsArray, BooleanArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Float64Type};
+use arrow::error::ArrowError;
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::statistics::StatisticsConverter;
+use parquet::arrow::arrow_reader::{
+    ArrowPredicateFn, RowFilter, RowSelection, RowSelector,
+};
+
+use super::{ParquetError, ParquetReader};
+
+/// A range condition on a numeric column.
+///
+/// Values of any integer or floating point column are compared as `f64`.
+/// Null values never match.
+///
+/// # Examples
+///
+/// ```
+/// use filemanager::formats::parquet::Predicate;
+///
+/// let window = Predicate::between("mz", 400.0, 500.0);
+/// assert!(window.matches(400.0));
+/// assert!(!window.matches(500.5));
+/// assert!(!Predicate::lt("rt", 60.0).matches(60.0));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    column: String,
+    lower: Bound<f64>,
+    upper: Bound<f64>,
+}
+
+impl Predicate {
+    /// `column` lies within `lower..=upper`.
+    pub fn between(column: impl Into<String>, lower: f64, upper: f64) -> Self {
+        Self::new(column, Bound::Included(lower), Bound::Included(upper))
+    }
+
+    /// `column` is smaller than `value`.
+    pub fn lt(column: impl Into<String>, value: f64) -> Self {
+        Self::new(column, Bound::Unbounded, Bound::Excluded(value))
+    }
+
+    /// `column` is smaller than or equal to `value`.
+    pub fn le(column: impl Into<String>, value: f64) -> Self {
+        Self::new(column, Bound::Unbounded, Bound::Included(value))
+    }
+
+    /// `column` is larger than `value`.
+    pub fn gt(column: impl Into<String>, value: f64) -> Self {
+        Self::new(column, Bound::Excluded(value), Bound::Unbounded)
+    }
+
+    /// `column` is larger than or equal to `value`.
+    pub fn ge(column: impl Into<String>, value: f64) -> Self {
+        Self::new(column, Bound::Included(value), Bound::Unbounded)
+    }
+
+    /// `column` equals `value`.
+    pub fn eq(column: impl Into<String>, value: f64) -> Self {
+        Self::between(column, value, value)
+    }
+
+    fn new(
+        column: impl Into<String>,
+        lower: Bound<f64>,
+        upper: Bound<f64>,
+    ) -> Self {
+        Self {
+            column: column.into(),
+            lower,
+            upper,
+        }
+    }
+
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Returns `true` if `value` satisfies the predicate.
+    pub fn matches(&self, value: f64) -> bool {
+        let above = match self.lower {
+            Bound::Included(lower) => value >= lower,
+            Bound::Excluded(lower) => value > lower,
+            Bound::Unbounded => true,
+        };
+        let below = match self.upper {
+            Bound::Included(upper) => value <= upper,
+            Bound::Excluded(upper) => value < upper,
+            Bound::Unbounded => true,
+        };
+        above && below
+    }
+
+    /// Returns `false` if no value within `min..=max` can match.
+    fn may_match(&self, min: f64, max: f64) -> bool {
+        let above = match self.lower {
+            Bound::Included(lower) => max >= lower,
+            Bound::Excluded(lower) => max > lower,
+            Bound::Unbounded => true,
+        };
+        let below = match self.upper {
+            Bound::Included(upper) => min <= upper,
+            Bound::Excluded(upper) => min < upper,
+            Bound::Unbounded => true,
+        };
+        above && below
+    }
+}
+
+/// A selective read of a Parquet file, created with
+/// [`ParquetReader::query`].
+///
+/// Row groups and pages whose statistics cannot match all predicates are
+/// not read at all. The remaining rows are filtered exactly, and only the
+/// projected columns are decoded.
+///
+/// # Examples
+///
+/// ```
+/// use filemanager::formats::parquet::{ParquetReader, ParquetWriter, Predicate};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Precursor { mz: f64, rt: f32, charge: u8 }
+///
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// struct Mz { mz: f64 }
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("precursors.parquet");
+/// let mut writer = ParquetWriter::<Precursor>::new(&path).unwrap();
+/// // Every batch becomes a separate row group.
+/// writer.set_max_row_count(1);
+/// for i in 0..10 {
+///     let batch = (0..10)
+///         .map(|j| Precursor {
+///             mz: 100.0 * i as f64 + j as f64,
+///             rt: j as f32,
+///             charge: 2,
+///         })
+///         .collect();
+///     writer.write_batch(batch).unwrap();
+/// }
+/// writer.close().unwrap();
+///
+/// let reader = ParquetReader::<Precursor>::from(&path).unwrap();
+/// let query = reader
+///     .query()
+///     .columns(["mz"])
+///     .filter(Predicate::between("mz", 400.0, 599.0))
+///     .filter(Predicate::lt("rt", 2.0));
+/// assert_eq!(query.row_groups().unwrap(), vec![4, 5]);
+/// let rows: Vec<Mz> = query.read_as().unwrap();
+/// let mz: Vec<f64> = rows.iter().map(|row| row.mz).collect();
+/// assert_eq!(mz, vec![400.0, 401.0, 500.0, 501.0]);
+/// assert!(reader.query().filter(Predicate::gt("im", 1.0)).read().is_err());
+/// ```
+#[derive(Debug)]
+pub struct ParquetQuery<'a, T> {
+    reader: &'a ParquetReader<T>,
+    columns: Option<Vec<String>>,
+    predicates: Vec<Predicate>,
+}
+
+impl<'a, T: for<'de> serde::Deserialize<'de>> ParquetQuery<'a, T> {
+    pub(super) fn new(reader: &'a ParquetReader<T>) -> Self {
+        Self {
+            reader,
+            columns: None,
+            predicates: vec![],
+        }
+    }
+
+    /// Only reads `columns` instead of all columns.
+    pub fn columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only reads rows that satisfy `predicate` (and all other predicates).
+    pub fn filter(mut self, predicate: Predicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    /// Reads all matching rows.
+    pub fn read(self) -> Result<Vec<T>, ParquetError> {
+        self.read_as()
+    }
+
+    /// Reads all matching rows into `U`, e.g. a type with only the
+    /// projected columns.
+    pub fn read_as<U: for<'de> serde::Deserialize<'de>>(
+        self,
+    ) -> Result<Vec<U>, ParquetError> {
+        let metadata = &self.reader.metadata;
+        let parquet_schema = metadata.parquet_schema();
+        let projection = match &self.columns {
+            Some(columns) => columns
+                .iter()
+                .map(|column| self.column_index(column))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => (0..metadata.schema().fields().len()).collect(),
+        };
+        let row_groups = self.row_groups()?;
+        let selection = self.page_selection(&row_groups)?;
+        let mut builder = self
+            .reader
+            .builder()
+            .with_row_groups(row_groups)
+            .with_projection(ProjectionMask::roots(parquet_schema, projection));
+        if let Some(selection) = selection {
+            builder = builder.with_row_selection(selection);
+        }
+        if !self.predicates.is_empty() {
+            let mut columns = self
+                .predicates
+                .iter()
+                .map(|predicate| self.column_index(predicate.column()))
+                .collect::<Result<Vec<_>, _>>()?;
+            columns.sort_unstable();
+            columns.dedup();
+            let predicates = self.predicates.clone();
+            let filter = ArrowPredicateFn::new(
+                ProjectionMask::roots(parquet_schema, columns),
+                move |batch: RecordBatch| evaluate(&predicates, &batch),
+            );
+            builder =
+                builder.with_row_filter(RowFilter::new(vec![Box::new(filter)]));
+        }
+        let reader = builder
+            .build()
+            .map_err(|e| ParquetError::Read(Box::new(e)))?;
+        ParquetReader::<U>::decode(reader, 0)
+    }
+
+    /// The row groups whose statistics may match all predicates.
+    pub fn row_groups(&self) -> Result<Vec<usize>, ParquetError> {
+        let metadata = self.reader.metadata.metadata();
+        let mut keep = vec![true; metadata.num_row_groups()];
+        for predicate in &self.predicates {
+            let converter = self.statistics(predicate)?;
+            let mins = f64_values(
+                &converter
+                    .row_group_mins(metadata.row_groups())
+                    .map_err(|e| ParquetError::Read(Box::new(e)))?,
+            )?;
+            let maxes = f64_values(
+                &converter
+                    .row_group_maxes(metadata.row_groups())
+                    .map_err(|e| ParquetError::Read(Box::new(e)))?,
+            )?;
+            for ((keep, min), max) in keep.iter_mut().zip(mins).zip(maxes) {
+                if let (Some(min), Some(max)) = (min, max) {
+                    *keep &= predicate.may_match(min, max);
+                }
+            }
+        }
+        Ok((0..keep.len()).filter(|&index| keep[index]).collect())
+    }
+
+    /// The number of rows that are read from the selected row groups and
+    /// pages, before the predicates are applied to individual rows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use filemanager::formats::parquet::{ParquetReader, ParquetWriter, Predicate};
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Row { rt: f64 }
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("data.parquet");
+    /// let mut writer = ParquetWriter::<Row>::new(&path).unwrap();
+    /// let rows = (0..100_000).map(|i| Row { rt: i as f64 }).collect();
+    /// writer.write_batch(rows).unwrap();
+    /// writer.close().unwrap();
+    ///
+    /// let reader = ParquetReader::<Row>::from(&path).unwrap();
+    /// assert_eq!(reader.row_group_count(), 1);
+    /// let query = reader.query().filter(Predicate::lt("rt", 10.0));
+    /// // Pages without early retention times are skipped.
+    /// assert!(query.scanned_rows().unwrap() < 50_000);
+    /// assert_eq!(query.read().unwrap().len(), 10);
+    /// ```
+    pub fn scanned_rows(&self) -> Result<usize, ParquetError> {
+        let row_groups = self.row_groups()?;
+        Ok(match self.page_selection(&row_groups)? {
+            Some(selection) => selection.row_count(),
+            None => {
+                let metadata = self.reader.metadata.metadata();
+                row_groups
+                    .iter()
+                    .map(|&index| metadata.row_group(index).num_rows() as usize)
+                    .sum()
+            },
+        })
+    }
+
+    /// Skips the pages of `row_groups` whose statistics cannot match, if
+    /// the file has a page index.
+    fn page_selection(
+        &self,
+        row_groups: &[usize],
+    ) -> Result<Option<RowSelection>, ParquetError> {
+        let metadata = self.reader.metadata.metadata();
+        let (Some(column_index), Some(offset_index)) =
+            (metadata.column_index(), metadata.offset_index())
+        else {
+            return Ok(None);
+        };
+        let mut selection: Option<RowSelection> = None;
+        for predicate in &self.predicates {
+            let converter = self.statistics(predicate)?;
+            let mins = f64_values(
+                &converter
+                    .data_page_mins(column_index, offset_index, row_groups)
+                    .map_err(|e| ParquetError::Read(Box::new(e)))?,
+            )?;
+            let maxes = f64_values(
+                &converter
+                    .data_page_maxes(column_index, offset_index, row_groups)
+                    .map_err(|e| ParquetError::Read(Box::new(e)))?,
+            )?;
+            let Some(row_counts) = converter
+                .data_page_row_counts(
+                    offset_index,
+                    metadata.row_groups(),
+                    row_groups,
+                )
+                .map_err(|e| ParquetError::Read(Box::new(e)))?
+            else {
+                continue;
+            };
+            let selectors = row_counts
+                .iter()
+                .zip(mins)
+                .zip(maxes)
+                .map(|((rows, min), max)| {
+                    let rows = rows.unwrap_or(0) as usize;
+                    match (min, max) {
+                        (Some(min), Some(max))
+                            if !predicate.may_match(min, max) =>
+                        {
+                            RowSelector::skip(rows)
+                        },
+                        _ => RowSelector::select(rows),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let pages = RowSelection::from(selectors);
+            selection = Some(match selection {
+                Some(selection) => selection.intersection(&pages),
+                None => pages,
+            });
+        }
+        Ok(selection)
+    }
+
+    fn statistics(
+        &self,
+        predicate: &Predicate,
+    ) -> Result<StatisticsConverter<'a>, ParquetError> {
+        let metadata = &self.reader.metadata;
+        self.column_index(predicate.column())?;
+        StatisticsConverter::try_new(
+            predicate.column(),
+            metadata.schema(),
+            metadata.parquet_schema(),
+        )
+        .map_err(|e| ParquetError::Read(Box::new(e)))
+    }
+
+    fn column_index(&self, column: &str) -> Result<usize, ParquetError> {
+        self.reader
+            .metadata
+            .schema()
+            .index_of(column)
+            .map_err(|_| ParquetError::UnknownColumn(column.to_string()))
+    }
+}
+
+/// The values of a numeric array as `f64`.
+fn f64_values(array: &ArrayRef) -> Result<Vec<Option<f64>>, ParquetError> {
+    let values = cast(array, &DataType::Float64)
+        .map_err(|e| ParquetError::Read(Box::new(e)))?;
+    Ok(values.as_primitive::<Float64Type>().iter().collect())
+}
+
+fn evaluate(
+    predicates: &[Predicate],
+    batch: &RecordBatch,
+) -> Result<BooleanArray, ArrowError> {
+    let mut keep = vec![true; batch.num_rows()];
+    for predicate in predicates {
+        let column =
+            batch.column_by_name(predicate.column()).ok_or_else(|| {
+                ArrowError::SchemaError(format!(
+                    "Unknown column {}",
+                    predicate.column()
+                ))
+            })?;
+        let values = cast(column, &DataType::Float64)?;
+        let values = values.as_primitive::<Float64Type>();
+        for (keep, value) in keep.iter_mut().zip(values.iter()) {
+            *keep &= value.is_some_and(|value| predicate.matches(value));
+        }
+    }
+    Ok(BooleanArray::from(keep))
+}
@@ -50,7 +50,11 @@ impl RollingSparseBuffer {
                             continue;
                         }
                         let ni = di;
-                        for nj in [i + dj as usize, i - dj as usize] {
+                        let dj = dj as usize;
+                        for nj in [Some(i + dj), i.checked_sub(dj)]
+                            .into_iter()
+                            .flatten()
+                        {
                             if let Some(&x) = self.candidates[ni].get(nj as u32)
                             {
                                 is_peak &= x < center;
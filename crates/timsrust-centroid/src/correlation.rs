@@ -0,0 +1,396 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use timsrust_core::{Converter, Mz, TofIndex};
+
+use crate::{Peak, peaks::TOFMap};
+
+/// Parameters of the precursor–fragment elution correlation of DIA
+/// pseudo-spectra.
+///
+/// Fragments are scored by the mean of the Pearson correlation of their
+/// retention time profile (over neighbouring cycles) and their mobility
+/// profile (within the cycle) with those of the precursor.
+///
+/// # Example
+/// ```
+/// use timsrust_centroid::correlation::ElutionCorrelationParams;
+/// let params = ElutionCorrelationParams {
+///     cycles: 5,
+///     ..Default::default()
+/// };
+/// assert_eq!(params.min_score, 0.5);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElutionCorrelationParams {
+    /// Number of neighbouring cycles on each side of a frame that make up
+    /// the retention time profiles.
+    pub cycles: usize,
+    /// Half-width in TOF bins over which raw intensities are summed.
+    pub tof_tolerance: u32,
+    /// Minimum score for a fragment to be kept.
+    pub min_score: f32,
+}
+
+impl Default for ElutionCorrelationParams {
+    fn default() -> Self {
+        Self {
+            cycles: 3,
+            tof_tolerance: 2,
+            min_score: 0.5,
+        }
+    }
+}
+
+/// The raw ions of the frames at the same position in neighbouring cycles.
+///
+/// Frames that do not exist (e.g. at the edges of the run) are `None`.
+pub(crate) struct CycleFrames {
+    frames: Vec<Option<Arc<TOFMap>>>,
+    center: usize,
+}
+
+impl CycleFrames {
+    pub(crate) fn new(frames: Vec<Option<Arc<TOFMap>>>, center: usize) -> Self {
+        assert!(center < frames.len());
+        Self { frames, center }
+    }
+
+    fn center(&self) -> Option<&TOFMap> {
+        self.frames[self.center].as_deref()
+    }
+
+    /// The largest TOF index of the central frame.
+    pub(crate) fn max_tof(&self) -> u32 {
+        self.center()
+            .map_or(0, |tofs| tofs.len().saturating_sub(1) as u32)
+    }
+
+    /// Summed intensity around `tof` and `scan` in every cycle.
+    fn rt_profile(
+        &self,
+        tof: u32,
+        scan: u32,
+        scan_tolerance: u32,
+        tof_tolerance: u32,
+    ) -> Vec<f64> {
+        let scans = scan.saturating_sub(scan_tolerance)..=scan + scan_tolerance;
+        self.frames
+            .iter()
+            .map(|frame| {
+                frame.as_ref().map_or(0.0, |tofs| {
+                    scans
+                        .clone()
+                        .map(|scan| {
+                            summed_intensity(tofs, tof, scan, tof_tolerance)
+                        })
+                        .sum()
+                })
+            })
+            .collect()
+    }
+
+    /// Summed intensity around `tof` for every scan around `scan` in the
+    /// central cycle.
+    fn im_profile(
+        &self,
+        tof: u32,
+        scan: u32,
+        scan_tolerance: u32,
+        tof_tolerance: u32,
+    ) -> Vec<f64> {
+        let scans = scan.saturating_sub(scan_tolerance)..=scan + scan_tolerance;
+        match self.center() {
+            Some(tofs) => scans
+                .map(|scan| summed_intensity(tofs, tof, scan, tof_tolerance))
+                .collect(),
+            None => vec![0.0; scans.count()],
+        }
+    }
+}
+
+/// The most recently read transposed frames, or other values computed per
+/// frame.
+///
+/// Neighbouring cycles share most of their frames, so that every frame is
+/// read and transposed about once instead of once per cycle it is part of.
+pub(crate) struct FrameCache<T = TOFMap> {
+    capacity: usize,
+    frames: Mutex<VecDeque<(usize, Arc<T>)>>,
+}
+
+impl<T> FrameCache<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// The frame at `index`, computed with `f` if it is not cached.
+    pub(crate) fn get_or_insert_with(
+        &self,
+        index: usize,
+        f: impl FnOnce() -> Option<T>,
+    ) -> Option<Arc<T>> {
+        if let Some(tofs) = self.get(index) {
+            return Some(tofs);
+        }
+        // Frames are computed without holding the lock, so that threads
+        // can read different frames in parallel.
+        let tofs = Arc::new(f()?);
+        let mut frames = self.frames.lock().expect("Lock is not poisoned");
+        if !frames.iter().any(|(i, _)| *i == index) {
+            frames.push_back((index, tofs.clone()));
+            while frames.len() > self.capacity {
+                frames.pop_front();
+            }
+        }
+        Some(tofs)
+    }
+
+    fn get(&self, index: usize) -> Option<Arc<T>> {
+        let frames = self.frames.lock().expect("Lock is not poisoned");
+        frames
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, tofs)| tofs.clone())
+    }
+}
+
+fn summed_intensity(
+    tofs: &TOFMap,
+    tof: u32,
+    scan: u32,
+    tof_tolerance: u32,
+) -> f64 {
+    let start = tof.saturating_sub(tof_tolerance) as usize;
+    let end = (tof + tof_tolerance + 1) as usize;
+    tofs[start.min(tofs.len())..end.min(tofs.len())]
+        .iter()
+        .filter_map(|scans| scans.get(&scan))
+        .sum::<u64>() as f64
+}
+
+/// The retention time and mobility profiles of a precursor.
+pub(crate) struct PrecursorProfiles {
+    scan: u32,
+    rt: Vec<f64>,
+    im: Vec<f64>,
+}
+
+/// Scores the co-elution of fragments from one MS2 frame with the
+/// precursors from the MS1 frame of the same cycle.
+pub(crate) struct ElutionCorrelator<'a> {
+    ms1: &'a CycleFrames,
+    ms2: CycleFrames,
+    scan_tolerance: u32,
+    params: &'a ElutionCorrelationParams,
+}
+
+impl<'a> ElutionCorrelator<'a> {
+    pub(crate) fn new(
+        ms1: &'a CycleFrames,
+        ms2: CycleFrames,
+        scan_tolerance: u32,
+        params: &'a ElutionCorrelationParams,
+    ) -> Self {
+        Self {
+            ms1,
+            ms2,
+            scan_tolerance,
+            params,
+        }
+    }
+
+    pub(crate) fn params(&self) -> &ElutionCorrelationParams {
+        self.params
+    }
+
+    pub(crate) fn precursor_profiles(
+        &self,
+        tof: u32,
+        scan: u32,
+    ) -> PrecursorProfiles {
+        PrecursorProfiles {
+            scan,
+            rt: self.ms1.rt_profile(
+                tof,
+                scan,
+                self.scan_tolerance / 2,
+                self.params.tof_tolerance,
+            ),
+            im: self.ms1.im_profile(
+                tof,
+                scan,
+                self.scan_tolerance,
+                self.params.tof_tolerance,
+            ),
+        }
+    }
+
+    /// The mean of the retention time and mobility correlation of
+    /// `fragment` with the precursor.
+    pub(crate) fn score(
+        &self,
+        precursor: &PrecursorProfiles,
+        fragment: &Peak,
+    ) -> f32 {
+        let rt = self.ms2.rt_profile(
+            fragment.tof,
+            fragment.scan,
+            self.scan_tolerance / 2,
+            self.params.tof_tolerance,
+        );
+        let im = self.ms2.im_profile(
+            fragment.tof,
+            precursor.scan,
+            self.scan_tolerance,
+            self.params.tof_tolerance,
+        );
+        ((pearson(&precursor.rt, &rt) + pearson(&precursor.im, &im)) / 2.0)
+            as f32
+    }
+}
+
+/// The Pearson correlation of two profiles, or 0 if either is constant or
+/// has fewer than three points.
+fn pearson(x: &[f64], y: &[f64]) -> f64 {
+    assert_eq!(x.len(), y.len());
+    let n = x.len();
+    if n < 3 {
+        return 0.0;
+    }
+    let mean_x = x.iter().sum::<f64>() / n as f64;
+    let mean_y = y.iter().sum::<f64>() / n as f64;
+    let mut covariance = 0.0;
+    let mut variance_x = 0.0;
+    let mut variance_y = 0.0;
+    for (a, b) in x.iter().zip(y) {
+        covariance += (a - mean_x) * (b - mean_y);
+        variance_x += (a - mean_x).powi(2);
+        variance_y += (b - mean_y).powi(2);
+    }
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return 0.0;
+    }
+    covariance / (variance_x * variance_y).sqrt()
+}
+
+/// The TOF index in `0..=max_tof` whose mz is closest to (but not below)
+/// `mz`, for a monotonically increasing converter.
+pub(crate) fn mz_to_tof(
+    mz_converter: impl Converter<TofIndex, Mz>,
+    mz: f64,
+    max_tof: u32,
+) -> u32 {
+    let mut lower = 0;
+    let mut upper = max_tof;
+    while lower < upper {
+        let middle = lower + (upper - lower) / 2;
+        let middle_mz =
+            mz_converter.convert(TofIndex::try_from(middle).unwrap());
+        if f64::from(middle_mz) < mz {
+            lower = middle + 1;
+        } else {
+            upper = middle;
+        }
+    }
+    lower
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+
+    use super::*;
+
+    /// A frame with a single peak at `tof` whose intensity per scan follows
+    /// `profile`, starting at scan 0.
+    fn frame(tof: u32, profile: &[u64]) -> TOFMap {
+        let mut tofs = vec![FxHashMap::default(); tof as usize + 1];
+        for (scan, &intensity) in profile.iter().enumerate() {
+            tofs[tof as usize].insert(scan as u32, intensity);
+        }
+        tofs
+    }
+
+    #[test]
+    fn test_pearson() {
+        assert!(
+            (pearson(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]) - 1.0).abs() < 1e-9
+        );
+        assert!(
+            (pearson(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]) + 1.0).abs() < 1e-9
+        );
+        assert_eq!(pearson(&[1.0, 1.0, 1.0], &[3.0, 2.0, 1.0]), 0.0);
+        assert_eq!(pearson(&[1.0, 2.0], &[1.0, 2.0]), 0.0);
+    }
+
+    #[test]
+    fn test_score() {
+        let params = ElutionCorrelationParams::default();
+        let ms1 = CycleFrames::new(
+            vec![
+                Some(Arc::new(frame(100, &[0, 1, 2, 1, 0]))),
+                Some(Arc::new(frame(100, &[1, 4, 8, 4, 1]))),
+                Some(Arc::new(frame(100, &[0, 2, 3, 2, 0]))),
+            ],
+            1,
+        );
+        let ms2 = CycleFrames::new(
+            vec![
+                Some(Arc::new(frame(50, &[0, 1, 2, 1, 0]))),
+                Some(Arc::new(frame(50, &[0, 2, 4, 2, 0]))),
+                Some(Arc::new(frame(50, &[0, 1, 3, 1, 0]))),
+            ],
+            1,
+        );
+        let correlator = ElutionCorrelator::new(&ms1, ms2, 2, &params);
+        let precursor = correlator.precursor_profiles(100, 2);
+        let coeluting = Peak {
+            scan: 2,
+            tof: 50,
+            ..Default::default()
+        };
+        let absent = Peak {
+            scan: 2,
+            tof: 70,
+            ..Default::default()
+        };
+        assert!(correlator.score(&precursor, &coeluting) > 0.9);
+        assert_eq!(correlator.score(&precursor, &absent), 0.0);
+    }
+
+    #[test]
+    fn test_frame_cache() {
+        let cache = FrameCache::new(2);
+        let reads = std::cell::Cell::new(0);
+        let read = |tof| {
+            reads.set(reads.get() + 1);
+            Some(frame(tof, &[1]))
+        };
+        for index in [1, 2, 1, 2, 3, 2, 1] {
+            let tofs = cache.get_or_insert_with(index, || read(index as u32));
+            assert_eq!(tofs.unwrap().len(), index + 1);
+        }
+        // Frame 1 is evicted by frame 3 and read again.
+        assert_eq!(reads.get(), 4);
+        assert!(cache.get_or_insert_with(4, || None).is_none());
+    }
+
+    #[test]
+    fn test_mz_to_tof() {
+        struct Linear;
+        impl Converter<TofIndex, Mz> for Linear {
+            fn convert(&self, value: TofIndex) -> Mz {
+                Mz::from(f64::from(u32::from(value)) * 2.0)
+            }
+        }
+        assert_eq!(mz_to_tof(&Linear, 20.0, 100), 10);
+        assert_eq!(mz_to_tof(&Linear, 21.0, 100), 11);
+        assert_eq!(mz_to_tof(&Linear, 1000.0, 100), 100);
+    }
+}
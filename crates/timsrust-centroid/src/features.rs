@@ -0,0 +1,590 @@
+//! LC-IMS-MS feature finding.
+//!
+//! Centroided MS1 peaks of consecutive MS1 frames are linked in RT into
+//! traces. Traces that span enough frames become [`Feature`]s, which are
+//! finally grouped into isotope patterns.
+
+use std::{collections::HashMap, sync::Arc};
+
+use rustc_hash::FxHashMap;
+use timsrust_core::{
+    Converter, FrameInfo, FrameIons, Im, MSLevel, Mz, ScanIndex, TofIndex,
+    utils::{
+        graph_buffer::{Graph, GraphProcessor},
+        reader::{IndexedReader, Reader},
+        thread::Synced,
+    },
+};
+
+use crate::{Peak, PeakReader, TimsResult, isotopes::ISOTOPE_MASS};
+
+/// Number of MS1 frames that are linked in parallel before their traces are
+/// assembled. Bounds the number of frames whose peaks are kept in memory.
+const BLOCK_SIZE: usize = 128;
+/// Maximum number of isotopes searched on either side of the most intense
+/// feature of an isotope group.
+const MAX_ISOTOPES: usize = 8;
+
+/// Parameters of the [`FeatureFinder`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureFinderConfig {
+    /// Minimum number of consecutive MS1 frames a feature spans.
+    pub min_frames: usize,
+    /// Maximum m/z difference between expected and observed isotopes.
+    pub isotope_mz_tolerance: f64,
+    /// Charge states considered for isotope grouping.
+    pub charges: Vec<u8>,
+}
+
+impl Default for FeatureFinderConfig {
+    fn default() -> Self {
+        Self {
+            min_frames: 3,
+            isotope_mz_tolerance: 0.01,
+            charges: (1..6).collect(),
+        }
+    }
+}
+
+/// A centroided MS1 peak traced over consecutive MS1 frames.
+///
+/// The scan, TOF, 1/K0 and m/z apex are the area-weighted average of the
+/// fractional apexes of all peaks in the trace. The RT apex is the RT of the
+/// frame with the largest peak area.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Feature {
+    /// Deterministic id, in order of the last frame of the feature.
+    pub id: usize,
+    pub rt_apex: f64,
+    pub rt_start: f64,
+    pub rt_end: f64,
+    pub im_apex: f64,
+    pub mz_apex: f64,
+    pub frame_apex: u32,
+    pub scan_apex: f64,
+    pub tof_apex: f64,
+    pub apex_intensity: u64,
+    /// Summed area of all peaks in the trace.
+    pub area: u64,
+    pub ion_count: u32,
+    /// RT (in seconds) of every frame in the trace.
+    pub elution_rts: Vec<f64>,
+    /// Peak area in every frame of the trace.
+    pub elution_profile: Vec<u64>,
+    /// Id of the lightest feature of the isotope group, if any.
+    pub isotope_group: Option<usize>,
+    pub charge: Option<u8>,
+    /// Position within the isotope group, starting at 0 for the lightest
+    /// feature.
+    pub isotope: Option<u8>,
+}
+
+/// Finds 3D (RT × 1/K0 × m/z) features in the MS1 frames of a
+/// [`PeakReader`].
+///
+/// # Example
+/// ```ignore
+/// use std::sync::Arc;
+/// use timsrust_centroid::{PeakReader, features::FeatureFinder};
+/// let frame_reader = /* e.g. TdfFrameReader::new("example.d").unwrap() */;
+/// let peak_reader = PeakReader::new(frame_reader, 0.5, 2.0).unwrap();
+/// let finder = FeatureFinder::new(
+///     peak_reader,
+///     Arc::new(im_converter),
+///     Arc::new(mz_converter),
+///     Default::default(),
+/// );
+/// let features = finder.find_features().unwrap();
+/// ```
+pub struct FeatureFinder<IonReader, InfoReader, ImConverter, MzConverter> {
+    peak_reader: PeakReader<IonReader, InfoReader>,
+    im_converter: Arc<ImConverter>,
+    mz_converter: Arc<MzConverter>,
+    config: FeatureFinderConfig,
+}
+
+impl<IonReader, InfoReader, ImConverter, MzConverter>
+    FeatureFinder<IonReader, InfoReader, ImConverter, MzConverter>
+where
+    IonReader: Reader<FrameIons> + Sync + Send,
+    InfoReader: Reader<FrameInfo> + IndexedReader<FrameInfo> + Sync + Send,
+    ImConverter: Converter<ScanIndex, Im> + Sync + Send,
+    MzConverter: Converter<TofIndex, Mz> + Sync + Send,
+{
+    pub fn new(
+        peak_reader: PeakReader<IonReader, InfoReader>,
+        im_converter: Arc<ImConverter>,
+        mz_converter: Arc<MzConverter>,
+        config: FeatureFinderConfig,
+    ) -> Self {
+        Self {
+            peak_reader,
+            im_converter,
+            mz_converter,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &FeatureFinderConfig {
+        &self.config
+    }
+
+    pub fn peak_reader(&self) -> &PeakReader<IonReader, InfoReader> {
+        &self.peak_reader
+    }
+
+    /// Link, assemble and isotope-group the features of all MS1 frames.
+    ///
+    /// Frames are linked in parallel in blocks of MS1 frames, each frame
+    /// together with its successor as neighbor in a [`Graph`].
+    pub fn find_features(&self) -> TimsResult<Vec<Feature>> {
+        let ms1_frames = self.ms1_frames();
+        let processor = LinkProcessor {
+            peak_reader: &self.peak_reader,
+            ms1_frames: &ms1_frames,
+            tof_tolerance: (self.peak_reader.tof_fwhm() as u32 / 2).max(1),
+            scan_tolerance: (self.peak_reader.scan_fwhm() as u32 / 2).max(1),
+            links: Synced::default(),
+        };
+        let graph = Graph::new(processor);
+        let mut assembler = TraceAssembler::default();
+        let mut features = vec![];
+        for block_start in (0..ms1_frames.len()).step_by(BLOCK_SIZE) {
+            let block_end = (block_start + BLOCK_SIZE).min(ms1_frames.len());
+            graph.process_all(block_start..block_end);
+            let mut block =
+                graph
+                    .processor()
+                    .links
+                    .with_lock(std::mem::take)
+                    .map_err(|e| crate::TimsError::new(e.to_string()))?;
+            for (position, &(rt, _)) in ms1_frames
+                .iter()
+                .enumerate()
+                .take(block_end)
+                .skip(block_start)
+            {
+                let finished = match block.remove(&position) {
+                    Some((frame_peaks, links)) => {
+                        assembler.add_frame(rt, &frame_peaks.peaks, &links)
+                    },
+                    None => assembler.add_frame(rt, &[], &[]),
+                };
+                self.collect_features(finished, &mut features);
+            }
+        }
+        self.collect_features(assembler.finish(), &mut features);
+        group_isotopes(
+            &mut features,
+            &self.config.charges,
+            self.config.isotope_mz_tolerance,
+            self.peak_reader.scan_fwhm() as f64 / 2.0,
+        );
+        Ok(features)
+    }
+
+    /// All MS1 frames as (RT, frame index), in acquisition order.
+    ///
+    /// Info readers need not iterate their indices in order.
+    fn ms1_frames(&self) -> Vec<(f64, usize)> {
+        let frame_reader = self.peak_reader.frame_reader();
+        let mut frames = frame_reader
+            .iter_indices()
+            .filter_map(|index| {
+                let info = frame_reader.get_info(index).ok()?;
+                (info.ms_level() == MSLevel::MS1)
+                    .then(|| (info.rt_in_seconds(), index))
+            })
+            .collect::<Vec<_>>();
+        frames.sort_unstable_by_key(|&(_, index)| index);
+        frames
+    }
+
+    fn collect_features(
+        &self,
+        traces: Vec<Trace>,
+        features: &mut Vec<Feature>,
+    ) {
+        for trace in traces {
+            if let Some(feature) = self.to_feature(&trace, features.len()) {
+                features.push(feature);
+            }
+        }
+    }
+
+    fn to_feature(&self, trace: &Trace, id: usize) -> Option<Feature> {
+        let peaks = &trace.peaks;
+        if peaks.is_empty() || peaks.len() < self.config.min_frames {
+            return None;
+        }
+        let area = peaks.iter().map(|(_, p)| p.area).sum::<u64>();
+        let weight = area.max(1) as f64;
+        let weighted_average = |value: fn(&Peak) -> f64| {
+            peaks
+                .iter()
+                .map(|(_, p)| value(p) * p.area as f64)
+                .sum::<f64>()
+                / weight
+        };
+        let tof_apex = weighted_average(|p| p.tof_apex);
+        let scan_apex = weighted_average(|p| p.scan_apex);
+        let (rt_apex, apex_peak) = peaks.iter().max_by_key(|(_, p)| p.area)?;
+        let im_apex =
+            interpolate(self.im_converter.as_ref(), scan_apex, |x| {
+                ScanIndex::try_from(x).ok()
+            })?;
+        let mz_apex = interpolate(self.mz_converter.as_ref(), tof_apex, |x| {
+            TofIndex::try_from(x).ok()
+        })?;
+        Some(Feature {
+            id,
+            rt_apex: *rt_apex,
+            rt_start: peaks.first()?.0,
+            rt_end: peaks.last()?.0,
+            im_apex,
+            mz_apex,
+            frame_apex: apex_peak.frame,
+            scan_apex,
+            tof_apex,
+            apex_intensity: apex_peak.apex_intensity,
+            area,
+            ion_count: peaks.iter().map(|(_, p)| p.ion_count).sum(),
+            elution_rts: peaks.iter().map(|(rt, _)| *rt).collect(),
+            elution_profile: peaks.iter().map(|(_, p)| p.area).collect(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Convert a fractional index by linear interpolation between its
+/// neighbouring integer indices.
+fn interpolate<Index, Value>(
+    converter: &impl Converter<Index, Value>,
+    value: f64,
+    to_index: impl Fn(u32) -> Option<Index>,
+) -> Option<f64>
+where
+    f64: From<Value>,
+{
+    let lower = value.max(0.0).floor();
+    let fraction = value.max(0.0) - lower;
+    let low = f64::from(converter.convert(to_index(lower as u32)?));
+    let high = f64::from(converter.convert(to_index(lower as u32 + 1)?));
+    Some(low + (high - low) * fraction)
+}
+
+/// The centroided peaks of an MS1 frame, sorted by TOF.
+struct FramePeaks {
+    position: usize,
+    peaks: Vec<Peak>,
+}
+
+/// For every peak of a frame, the index of its continuation in the next
+/// MS1 frame.
+type Links = Vec<Option<u32>>;
+
+struct LinkProcessor<'a, IonReader, InfoReader> {
+    peak_reader: &'a PeakReader<IonReader, InfoReader>,
+    ms1_frames: &'a [(f64, usize)],
+    tof_tolerance: u32,
+    scan_tolerance: u32,
+    links: Synced<FxHashMap<usize, (Arc<FramePeaks>, Links)>>,
+}
+
+impl<IonReader, InfoReader> GraphProcessor<FramePeaks>
+    for LinkProcessor<'_, IonReader, InfoReader>
+where
+    IonReader: Reader<FrameIons> + Sync + Send,
+    InfoReader: Reader<FrameInfo> + IndexedReader<FrameInfo> + Sync + Send,
+{
+    fn load(&self, position: usize) -> Option<FramePeaks> {
+        let &(_, frame_index) = self.ms1_frames.get(position)?;
+        let mut peaks =
+            self.peak_reader.get_peaks_from_frame(frame_index).ok()?;
+        peaks.sort_by_key(|p| (p.tof, p.scan));
+        Some(FramePeaks { position, peaks })
+    }
+
+    fn neighbor_indices(
+        &self,
+        position: usize,
+        _data: Arc<FramePeaks>,
+    ) -> Vec<usize> {
+        if position + 1 < self.ms1_frames.len() {
+            vec![position + 1]
+        } else {
+            vec![]
+        }
+    }
+
+    fn process(
+        &self,
+        data: &Arc<FramePeaks>,
+        neighbors: &HashMap<usize, Arc<FramePeaks>>,
+    ) {
+        let links = match neighbors.get(&(data.position + 1)) {
+            Some(next) => link_peaks(
+                &data.peaks,
+                &next.peaks,
+                self.tof_tolerance,
+                self.scan_tolerance,
+            ),
+            None => vec![None; data.peaks.len()],
+        };
+        self.links
+            .with_lock(|links_by_position| {
+                links_by_position.insert(data.position, (data.clone(), links))
+            })
+            .expect("Link lock is poisoned");
+    }
+}
+
+/// Link every peak to its closest peak in the next frame, within the given
+/// tolerances. Each peak in the next frame is claimed by at most one peak.
+///
+/// Both frames need to be sorted by TOF.
+fn link_peaks(
+    peaks: &[Peak],
+    next_peaks: &[Peak],
+    tof_tolerance: u32,
+    scan_tolerance: u32,
+) -> Links {
+    let mut claims: FxHashMap<u32, (f64, usize)> = FxHashMap::default();
+    for (index, peak) in peaks.iter().enumerate() {
+        let start = next_peaks.partition_point(|p| {
+            p.tof < peak.tof.saturating_sub(tof_tolerance)
+        });
+        let closest = next_peaks[start..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, p)| p.tof <= peak.tof + tof_tolerance)
+            .filter(|(_, p)| p.scan.abs_diff(peak.scan) <= scan_tolerance)
+            .map(|(offset, p)| {
+                let tof_distance =
+                    p.tof.abs_diff(peak.tof) as f64 / tof_tolerance as f64;
+                let scan_distance =
+                    p.scan.abs_diff(peak.scan) as f64 / scan_tolerance as f64;
+                let distance = tof_distance.powi(2) + scan_distance.powi(2);
+                ((start + offset) as u32, distance)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((next_index, distance)) = closest {
+            let claim = claims.entry(next_index).or_insert((distance, index));
+            if distance < claim.0 {
+                *claim = (distance, index);
+            }
+        }
+    }
+    let mut links = vec![None; peaks.len()];
+    for (next_index, (_, index)) in claims {
+        links[index] = Some(next_index);
+    }
+    links
+}
+
+#[derive(Debug, Default)]
+struct Trace {
+    peaks: Vec<(f64, Peak)>,
+}
+
+/// Chains linked peaks of consecutive frames into traces.
+#[derive(Debug, Default)]
+struct TraceAssembler {
+    /// Open traces, by the index of their continuation in the next frame.
+    open: FxHashMap<u32, Trace>,
+}
+
+impl TraceAssembler {
+    /// Extend the open traces with the peaks of the next frame and return
+    /// the traces that end in this frame.
+    fn add_frame(
+        &mut self,
+        rt: f64,
+        peaks: &[Peak],
+        links: &[Option<u32>],
+    ) -> Vec<Trace> {
+        let mut previous = std::mem::take(&mut self.open);
+        let mut finished = vec![];
+        for (index, peak) in peaks.iter().enumerate() {
+            let mut trace =
+                previous.remove(&(index as u32)).unwrap_or_default();
+            trace.peaks.push((rt, peak.clone()));
+            match links.get(index).copied().flatten() {
+                Some(next_index) => {
+                    self.open.insert(next_index, trace);
+                },
+                None => finished.push(trace),
+            }
+        }
+        finished.extend(Self::sorted(previous));
+        finished
+    }
+
+    /// Close all open traces.
+    fn finish(self) -> Vec<Trace> {
+        Self::sorted(self.open)
+    }
+
+    fn sorted(traces: FxHashMap<u32, Trace>) -> Vec<Trace> {
+        let mut traces = traces.into_iter().collect::<Vec<_>>();
+        traces.sort_by_key(|(index, _)| *index);
+        traces.into_iter().map(|(_, trace)| trace).collect()
+    }
+}
+
+/// Group features into isotope patterns, starting from the most intense
+/// feature. Isotopes need to have their RT apex within the RT range of that
+/// feature and their scan apex within `scan_tolerance` of it.
+fn group_isotopes(
+    features: &mut [Feature],
+    charges: &[u8],
+    mz_tolerance: f64,
+    scan_tolerance: f64,
+) {
+    let mut by_mz = (0..features.len()).collect::<Vec<_>>();
+    by_mz.sort_by(|&a, &b| features[a].mz_apex.total_cmp(&features[b].mz_apex));
+    let mut by_area = (0..features.len()).collect::<Vec<_>>();
+    by_area.sort_by_key(|&i| std::cmp::Reverse(features[i].area));
+    let mut grouped = vec![false; features.len()];
+    for seed in by_area {
+        if grouped[seed] {
+            continue;
+        }
+        let mut best: Option<(u8, Vec<usize>)> = None;
+        for &charge in charges.iter().filter(|&&charge| charge > 0) {
+            let step = ISOTOPE_MASS / charge as f64;
+            let find = |current: usize, direction: f64| {
+                let target = features[current].mz_apex + direction * step;
+                let start = by_mz.partition_point(|&i| {
+                    features[i].mz_apex < target - mz_tolerance
+                });
+                by_mz[start..]
+                    .iter()
+                    .copied()
+                    .take_while(|&i| {
+                        features[i].mz_apex <= target + mz_tolerance
+                    })
+                    .filter(|&i| !grouped[i] && i != seed)
+                    .filter(|&i| {
+                        let (isotope, seed) = (&features[i], &features[seed]);
+                        (seed.rt_start..=seed.rt_end).contains(&isotope.rt_apex)
+                            && (isotope.scan_apex - seed.scan_apex).abs()
+                                <= scan_tolerance
+                    })
+                    .max_by_key(|&i| features[i].area)
+            };
+            let mut lighter = vec![];
+            let mut current = seed;
+            while lighter.len() < MAX_ISOTOPES {
+                match find(current, -1.0) {
+                    Some(next) => {
+                        lighter.push(next);
+                        current = next;
+                    },
+                    None => break,
+                }
+            }
+            let mut chain = lighter.into_iter().rev().collect::<Vec<_>>();
+            chain.push(seed);
+            current = seed;
+            for _ in 0..MAX_ISOTOPES {
+                match find(current, 1.0) {
+                    Some(next) => {
+                        chain.push(next);
+                        current = next;
+                    },
+                    None => break,
+                }
+            }
+            if chain.len() > 1
+                && best
+                    .as_ref()
+                    .is_none_or(|(_, best)| chain.len() > best.len())
+            {
+                best = Some((charge, chain));
+            }
+        }
+        if let Some((charge, chain)) = best {
+            let group = features[chain[0]].id;
+            for (isotope, &index) in chain.iter().enumerate() {
+                grouped[index] = true;
+                features[index].isotope_group = Some(group);
+                features[index].charge = Some(charge);
+                features[index].isotope = Some(isotope as u8);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak(scan: u32, tof: u32, area: u64) -> Peak {
+        Peak {
+            scan,
+            tof,
+            area,
+            apex_intensity: area,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_link_peaks() {
+        let peaks = vec![peak(10, 100, 1), peak(12, 101, 1), peak(50, 500, 1)];
+        let next_peaks = vec![peak(11, 101, 1), peak(80, 500, 1)];
+        let links = link_peaks(&peaks, &next_peaks, 2, 2);
+        // Both of the first two peaks match the first next peak; the
+        // closest one claims it.
+        assert_eq!(links, vec![None, Some(0), None]);
+    }
+
+    #[test]
+    fn test_trace_assembler() {
+        let mut assembler = TraceAssembler::default();
+        let frame = vec![peak(10, 100, 5), peak(20, 200, 5)];
+        let finished = assembler.add_frame(1.0, &frame, &[Some(1), None]);
+        assert_eq!(finished.len(), 1);
+        let frame = vec![peak(30, 300, 5), peak(10, 100, 7)];
+        let finished = assembler.add_frame(2.0, &frame, &[None, Some(0)]);
+        assert_eq!(finished.len(), 1);
+        let finished = assembler.add_frame(3.0, &[peak(10, 100, 3)], &[None]);
+        assert_eq!(finished.len(), 1);
+        assert_eq!(
+            finished[0]
+                .peaks
+                .iter()
+                .map(|(rt, _)| *rt)
+                .collect::<Vec<_>>(),
+            vec![1.0, 2.0, 3.0]
+        );
+        assert!(assembler.finish().is_empty());
+    }
+
+    #[test]
+    fn test_group_isotopes() {
+        let feature = |id: usize, mz_apex: f64, area: u64| Feature {
+            id,
+            mz_apex,
+            area,
+            rt_start: 0.0,
+            rt_apex: 1.0,
+            rt_end: 2.0,
+            ..Default::default()
+        };
+        let mut features = vec![
+            feature(0, 500.0, 100),
+            feature(1, 500.0 + ISOTOPE_MASS / 2.0, 80),
+            feature(2, 500.0 + ISOTOPE_MASS, 40),
+            feature(3, 700.0, 10),
+        ];
+        group_isotopes(&mut features, &[1, 2, 3], 0.01, 5.0);
+        for (index, feature) in features.iter().take(3).enumerate() {
+            assert_eq!(feature.isotope_group, Some(0));
+            assert_eq!(feature.charge, Some(2));
+            assert_eq!(feature.isotope, Some(index as u8));
+        }
+        assert_eq!(features[3].isotope_group, None);
+    }
+}
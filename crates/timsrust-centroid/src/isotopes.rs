@@ -0,0 +1,397 @@
+//! Isotope envelope detection for centroided peaks.
+
+/// Mass difference between consecutive isotopes (13C - 12C).
+pub(crate) const ISOTOPE_MASS: f64 = 1.0033548378;
+const PROTON_MASS: f64 = 1.007276466812;
+/// Average mass of an averagine residue (C4.9384 H7.7583 N1.3577 O1.4773
+/// S0.0417).
+const AVERAGINE_MASS: f64 = 111.1254;
+/// Expected number of heavy isotope substitutions per Da of averagine,
+/// weighted by the natural abundance of 13C, 2H, 15N, 17O and 33S.
+const HEAVY_ATOMS_PER_DA: f64 = (4.9384 * 0.0107
+    + 7.7583 * 0.000115
+    + 1.3577 * 0.00364
+    + 1.4773 * 0.00038
+    + 0.0417 * 0.0075)
+    / AVERAGINE_MASS;
+
+/// How charge states and monoisotopic peaks are assigned to MS1 peaks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeisotopingStrategy {
+    /// Accept a charge if the next isotope is present within `max_delta_mz`,
+    /// and consider a peak monoisotopic if the previous isotope is absent.
+    Simple { max_delta_mz: f64 },
+    /// Score isotope envelopes against averagine-predicted intensities.
+    Averagine(AveragineParams),
+}
+
+impl Default for DeisotopingStrategy {
+    fn default() -> Self {
+        Self::simple()
+    }
+}
+
+impl DeisotopingStrategy {
+    /// The fixed-tolerance rule without intensity scoring.
+    pub fn simple() -> Self {
+        Self::Simple { max_delta_mz: 0.01 }
+    }
+}
+
+/// Parameters of averagine isotope envelope scoring.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AveragineParams {
+    /// Maximum m/z error of an isotope, in ppm of its expected m/z.
+    pub ppm_tolerance: f64,
+    /// Number of isotopes that are predicted and matched.
+    pub max_isotopes: usize,
+    /// Minimum number of consecutive isotopes, including the monoisotopic
+    /// peak, that need to be observed.
+    pub min_isotopes: usize,
+    /// Minimum cosine similarity between observed and predicted envelope.
+    pub min_score: f64,
+}
+
+impl Default for AveragineParams {
+    fn default() -> Self {
+        Self {
+            ppm_tolerance: 20.0,
+            max_isotopes: 4,
+            min_isotopes: 2,
+            min_score: 0.8,
+        }
+    }
+}
+
+/// A scored isotope envelope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsotopeCandidate {
+    /// Index of the monoisotopic peak.
+    pub monoisotopic_index: usize,
+    pub charge: u8,
+    /// Cosine similarity between the observed and predicted envelope.
+    pub score: f64,
+    /// Indices of the observed isotopes, starting with the monoisotopic
+    /// peak.
+    pub isotope_indices: Vec<usize>,
+}
+
+/// Relative isotope intensities of an averagine molecule of neutral `mass`.
+///
+/// Uses a Poisson approximation of the number of heavy isotopes.
+pub fn averagine_ratios(mass: f64, count: usize) -> Vec<f64> {
+    let lambda = mass.max(0.0) * HEAVY_ATOMS_PER_DA;
+    let mut ratios = Vec::with_capacity(count);
+    let mut probability = (-lambda).exp();
+    for k in 0..count {
+        ratios.push(probability);
+        probability *= lambda / (k + 1) as f64;
+    }
+    ratios
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+    let norm = a.iter().map(|x| x * x).sum::<f64>().sqrt()
+        * b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm == 0.0 { 0.0 } else { dot / norm }
+}
+
+/// Finds isotope envelopes among the centroided peaks of a single frame.
+///
+/// Isotopes of a peak need to be within `scan_tolerance` scans of it.
+#[derive(Debug)]
+pub struct IsotopeScorer {
+    mzs: Vec<f64>,
+    scans: Vec<u32>,
+    intensities: Vec<f64>,
+    by_mz: Vec<usize>,
+    scan_tolerance: u32,
+}
+
+impl IsotopeScorer {
+    pub fn new(
+        mzs: Vec<f64>,
+        scans: Vec<u32>,
+        intensities: Vec<f64>,
+        scan_tolerance: u32,
+    ) -> Self {
+        assert!(
+            mzs.len() == scans.len() && mzs.len() == intensities.len(),
+            "mzs, scans and intensities must have the same length"
+        );
+        let mut by_mz = (0..mzs.len()).collect::<Vec<_>>();
+        by_mz.sort_by(|&a, &b| mzs[a].total_cmp(&mzs[b]));
+        Self {
+            mzs,
+            scans,
+            intensities,
+            by_mz,
+            scan_tolerance,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.mzs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The most intense peak within `tolerance` of `mz` and within the scan
+    /// tolerance of peak `index`.
+    pub fn find(&self, index: usize, mz: f64, tolerance: f64) -> Option<usize> {
+        let scan = self.scans[index];
+        let lower_scan = scan.saturating_sub(self.scan_tolerance);
+        let upper_scan = scan + self.scan_tolerance;
+        let start = self
+            .by_mz
+            .partition_point(|&i| self.mzs[i] <= mz - tolerance);
+        self.by_mz[start..]
+            .iter()
+            .copied()
+            .take_while(|&i| self.mzs[i] < mz + tolerance)
+            .filter(|&i| (lower_scan..upper_scan).contains(&self.scans[i]))
+            .max_by(|&a, &b| {
+                self.intensities[a].total_cmp(&self.intensities[b])
+            })
+    }
+
+    /// Score the envelope of `charge` with peak `index` as monoisotopic peak.
+    pub fn score(
+        &self,
+        index: usize,
+        charge: u8,
+        params: &AveragineParams,
+    ) -> Option<IsotopeCandidate> {
+        if charge == 0 {
+            return None;
+        }
+        let step = ISOTOPE_MASS / charge as f64;
+        let mz = self.mzs[index];
+        let mut isotope_indices = vec![index];
+        for isotope in 1..params.max_isotopes {
+            let expected = mz + isotope as f64 * step;
+            match self.find(
+                index,
+                expected,
+                expected * params.ppm_tolerance * 1e-6,
+            ) {
+                Some(i) => isotope_indices.push(i),
+                None => break,
+            }
+        }
+        if isotope_indices.len() < params.min_isotopes.max(1) {
+            return None;
+        }
+        // Missing isotopes count as zero intensity, which penalizes missing
+        // isotopes in proportion to their predicted intensity.
+        let mut observed = isotope_indices
+            .iter()
+            .map(|&i| self.intensities[i])
+            .collect::<Vec<_>>();
+        observed.resize(params.max_isotopes.max(1), 0.0);
+        let mass = (mz - PROTON_MASS) * charge as f64;
+        let predicted = averagine_ratios(mass, observed.len());
+        let score = cosine_similarity(&observed, &predicted);
+        if score < params.min_score {
+            return None;
+        }
+        Some(IsotopeCandidate {
+            monoisotopic_index: index,
+            charge,
+            score,
+            isotope_indices,
+        })
+    }
+
+    /// All envelopes of `charges` that contain peak `index`, ranked by
+    /// descending score.
+    ///
+    /// Besides peak `index` itself, every lighter isotope that is observed
+    /// is tried as monoisotopic peak.
+    pub fn candidates(
+        &self,
+        index: usize,
+        charges: &[u8],
+        params: &AveragineParams,
+    ) -> Vec<IsotopeCandidate> {
+        let mut candidates = vec![];
+        for &charge in charges.iter().filter(|&&charge| charge > 0) {
+            let step = ISOTOPE_MASS / charge as f64;
+            let mut monoisotopic_index = index;
+            for _ in 0..params.max_isotopes.max(1) {
+                candidates.extend(self.score(
+                    monoisotopic_index,
+                    charge,
+                    params,
+                ));
+                let expected = self.mzs[monoisotopic_index] - step;
+                match self.find(
+                    monoisotopic_index,
+                    expected,
+                    expected * params.ppm_tolerance * 1e-6,
+                ) {
+                    Some(i) => monoisotopic_index = i,
+                    None => break,
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
+
+    /// The charges of `charges` for which peak `index` is monoisotopic,
+    /// following `strategy`.
+    ///
+    /// With `monoisotopic_only`, charges for which a lighter peak is a
+    /// better monoisotopic candidate are skipped. With
+    /// `highest_charge_state_only`, only the highest accepted charge is
+    /// returned. Otherwise charges are ordered from highest to lowest.
+    pub fn monoisotopic_charges(
+        &self,
+        index: usize,
+        charges: &[u8],
+        strategy: &DeisotopingStrategy,
+        monoisotopic_only: bool,
+        highest_charge_state_only: bool,
+    ) -> Vec<u8> {
+        let mut result = vec![];
+        match strategy {
+            DeisotopingStrategy::Simple { max_delta_mz } => {
+                let mz = self.mzs[index];
+                for &charge in charges.iter().rev() {
+                    let step = ISOTOPE_MASS / charge as f64;
+                    if monoisotopic_only
+                        && self.find(index, mz - step, *max_delta_mz).is_some()
+                    {
+                        continue;
+                    }
+                    if self.find(index, mz + step, *max_delta_mz).is_some() {
+                        result.push(charge);
+                    }
+                }
+            },
+            DeisotopingStrategy::Averagine(params) => {
+                let candidates = self.candidates(index, charges, params);
+                for candidate in candidates.iter() {
+                    let charge = candidate.charge;
+                    if result.contains(&charge) {
+                        continue;
+                    }
+                    // The best candidate of a charge comes first.
+                    let is_best = candidates
+                        .iter()
+                        .find(|c| c.charge == charge)
+                        .is_some_and(|best| best.monoisotopic_index == index);
+                    let is_own = candidate.monoisotopic_index == index;
+                    if is_own && (is_best || !monoisotopic_only) {
+                        result.push(charge);
+                    }
+                }
+            },
+        }
+        result.sort_unstable_by(|a, b| b.cmp(a));
+        if highest_charge_state_only {
+            result.truncate(1);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An averagine envelope of `charge` starting at `mz` in scan 10.
+    fn envelope(mz: f64, charge: u8, count: usize) -> (Vec<f64>, Vec<f64>) {
+        let mass = (mz - PROTON_MASS) * charge as f64;
+        let mzs = (0..count)
+            .map(|i| mz + i as f64 * ISOTOPE_MASS / charge as f64)
+            .collect();
+        let intensities = averagine_ratios(mass, count)
+            .into_iter()
+            .map(|ratio| ratio * 1000.0)
+            .collect();
+        (mzs, intensities)
+    }
+
+    #[test]
+    fn test_averagine_ratios() {
+        let light = averagine_ratios(1000.0, 3);
+        assert!(light[0] > light[1] && light[1] > light[2]);
+        let heavy = averagine_ratios(4000.0, 3);
+        assert!(heavy[1] > heavy[0]);
+        assert!(averagine_ratios(0.0, 2) == vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_candidates_rank_charge_and_monoisotope() {
+        let (mut mzs, mut intensities) = envelope(800.0, 2, 4);
+        // A small peak one z=2 isotope below the envelope.
+        mzs.push(800.0 - ISOTOPE_MASS / 2.0);
+        intensities.push(30.0);
+        let scans = vec![10; mzs.len()];
+        let scorer = IsotopeScorer::new(mzs, scans, intensities, 2);
+        let params = AveragineParams::default();
+        let candidates = scorer.candidates(1, &[1, 2, 3], &params);
+        let best = &candidates[0];
+        assert_eq!(best.charge, 2);
+        assert_eq!(best.monoisotopic_index, 0);
+        assert_eq!(best.isotope_indices, vec![0, 1, 2, 3]);
+        assert!(best.score > 0.99);
+        assert_eq!(
+            scorer.monoisotopic_charges(
+                0,
+                &[1, 2, 3],
+                &DeisotopingStrategy::Averagine(params.clone()),
+                true,
+                true
+            ),
+            vec![2]
+        );
+        // The simple rule skips charge 2 as the previous isotope exists, and
+        // mistakes the second isotope for the first isotope of charge 1.
+        assert_eq!(
+            scorer.monoisotopic_charges(
+                0,
+                &[1, 2, 3],
+                &DeisotopingStrategy::simple(),
+                true,
+                true
+            ),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_highest_charge_state_only() {
+        assert_eq!(
+            DeisotopingStrategy::default(),
+            DeisotopingStrategy::simple()
+        );
+        let (mzs, intensities) = envelope(800.0, 2, 4);
+        let scans = vec![10; mzs.len()];
+        let scorer = IsotopeScorer::new(mzs, scans, intensities, 2);
+        let strategy = DeisotopingStrategy::Averagine(AveragineParams {
+            min_score: 0.0,
+            ..Default::default()
+        });
+        let charges =
+            scorer.monoisotopic_charges(0, &[1, 2], &strategy, false, false);
+        assert_eq!(charges, vec![2, 1]);
+        assert_eq!(
+            scorer.monoisotopic_charges(0, &[2, 1], &strategy, false, true),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_scan_tolerance() {
+        let (mzs, intensities) = envelope(500.0, 1, 3);
+        let scorer = IsotopeScorer::new(mzs, vec![10, 20, 30], intensities, 2);
+        let params = AveragineParams::default();
+        assert!(scorer.score(0, 1, &params).is_none());
+    }
+}
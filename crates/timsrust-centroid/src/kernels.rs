@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use timsrust_core::io::{Uri, formats::json::JsonFormat};
+use timsrust_core::utils::{ndarray::NDArray, vec::extract_kernel};
+
+use crate::{TimsError, TimsResult};
+
+/// Full width at half maximum (FWHM) threshold for kernel extraction.
+const FWHM: f32 = 0.5;
+
+pub(crate) type Grid2D = NDArray<u64, 2>;
+
+/// Normalized TOF and scan kernels that smooth frames before peak picking.
+///
+/// Kernels can be saved with [`JsonFormat::write_to_json`] and shared
+/// between runs, so that all runs of a batch are centroided identically.
+///
+/// # Example
+/// ```
+/// use timsrust_centroid::kernels::CentroidingKernels;
+/// let kernels = CentroidingKernels {
+///     tof: vec![0.2, 1.0, 0.3],
+///     scan: vec![0.1, 0.6, 1.0, 0.5, 0.1],
+/// };
+/// assert_eq!(kernels.tof.len(), 3);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CentroidingKernels {
+    pub tof: Vec<f32>,
+    pub scan: Vec<f32>,
+}
+
+impl JsonFormat for CentroidingKernels {}
+
+impl CentroidingKernels {
+    /// Extract the kernels at half maximum of the TOF and scan projections
+    /// of an average peak.
+    ///
+    /// # Errors
+    /// Returns an error if the average peak is empty or too narrow to give
+    /// valid kernels.
+    pub fn from_average_peak(average_peak: &Grid2D) -> TimsResult<Self> {
+        let tof = extract_kernel(&average_peak.project_axis(0), FWHM)
+            .ok_or(TimsError::new("Failed to extract kernel"))?;
+        let scan = extract_kernel(&average_peak.project_axis(1), FWHM)
+            .ok_or(TimsError::new("Failed to extract kernel"))?;
+        let kernels = Self { tof, scan };
+        kernels.validate()?;
+        Ok(kernels)
+    }
+
+    /// Check that the kernels can be used for centroiding.
+    ///
+    /// # Errors
+    /// Returns an error if a kernel has negative or non-finite values or no
+    /// positive value, or if the TOF kernel is shorter than 3.
+    pub fn validate(&self) -> TimsResult<()> {
+        for (name, kernel) in [("TOF", &self.tof), ("Scan", &self.scan)] {
+            if kernel.iter().any(|x| !x.is_finite() || *x < 0.0) {
+                return Err(TimsError::new(format!(
+                    "{name} kernel has negative or non-finite values"
+                )));
+            }
+            if !kernel.iter().any(|&x| x > 0.0) {
+                return Err(TimsError::new(format!(
+                    "{name} kernel has no positive values"
+                )));
+            }
+        }
+        if self.tof.len() < 3 {
+            return Err(TimsError::new(format!(
+                "TOF kernel has length {}, expected at least 3",
+                self.tof.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read kernels that were written with [`JsonFormat::write_to_json`].
+    pub fn load(uri: impl Into<Uri>) -> TimsResult<Self> {
+        Self::read_from_json(uri).map_err(|e| TimsError::new(e.to_string()))
+    }
+
+    pub fn save(&self, uri: impl Into<Uri>) -> TimsResult<()> {
+        self.clone()
+            .write_to_json(uri)
+            .map_err(|e| TimsError::new(e.to_string()))
+    }
+}
+
+/// Where the kernels of an MS level come from.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum KernelSource {
+    /// Learn the kernels from the average peak of all frames of the MS
+    /// level in the run.
+    #[default]
+    Learned,
+    /// Derive the kernels from a (previously learned) average peak.
+    AveragePeak(Grid2D),
+    /// Use the given kernels as is.
+    Explicit(CentroidingKernels),
+}
+
+/// The kernels for MS1 and MS2 frames.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct KernelConfig {
+    pub ms1: KernelSource,
+    /// The source of MS2 kernels, or `None` to reuse the MS1 kernels.
+    pub ms2: Option<KernelSource>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedGrid {
+    shape: [usize; 2],
+    data: Vec<u64>,
+}
+
+impl JsonFormat for SerializedGrid {}
+
+/// Save an average peak, as returned by
+/// [`crate::get_average_ms1_peak`], as JSON.
+pub fn save_average_peak(
+    average_peak: &Grid2D,
+    uri: impl Into<Uri>,
+) -> TimsResult<()> {
+    SerializedGrid {
+        shape: average_peak.shape(),
+        data: average_peak.data().to_vec(),
+    }
+    .write_to_json(uri)
+    .map_err(|e| TimsError::new(e.to_string()))
+}
+
+/// Load an average peak that was saved with [`save_average_peak`].
+pub fn load_average_peak(uri: impl Into<Uri>) -> TimsResult<Grid2D> {
+    let grid = SerializedGrid::read_from_json(uri)
+        .map_err(|e| TimsError::new(e.to_string()))?;
+    Grid2D::new(grid.shape, grid.data)
+        .map_err(|e| TimsError::new(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn average_peak() -> Grid2D {
+        let mut grid = Grid2D::empty([5, 7]);
+        for (tof, tof_weight) in [1, 4, 10, 4, 1].into_iter().enumerate() {
+            for (scan, scan_weight) in
+                [1, 2, 6, 10, 6, 2, 1].into_iter().enumerate()
+            {
+                grid[[tof, scan]] = tof_weight * scan_weight;
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_kernels_from_average_peak() {
+        let kernels = CentroidingKernels::from_average_peak(&average_peak())
+            .expect("Kernels can be extracted");
+        assert_eq!(kernels.tof, vec![0.4, 1.0, 0.4]);
+        assert_eq!(kernels.scan, vec![0.2, 0.6, 1.0, 0.6, 0.2]);
+        assert!(
+            CentroidingKernels::from_average_peak(&Grid2D::empty([5, 7]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let kernels = CentroidingKernels {
+            tof: vec![0.5, 1.0, 0.5],
+            scan: vec![1.0],
+        };
+        assert!(kernels.validate().is_ok());
+        for (tof, scan) in [
+            (vec![0.5, 1.0, 0.5], vec![]),
+            (vec![], vec![1.0]),
+            (vec![1.0, 0.5], vec![1.0]),
+            (vec![0.0, 0.0, 0.0], vec![1.0]),
+            (vec![0.5, f32::NAN, 0.5], vec![1.0]),
+            (vec![0.5, 1.0, 0.5], vec![-1.0, 1.0]),
+        ] {
+            assert!(CentroidingKernels { tof, scan }.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let grid_path = dir.path().join("average_peak.json");
+        save_average_peak(&average_peak(), &grid_path).unwrap();
+        assert_eq!(load_average_peak(&grid_path).unwrap(), average_peak());
+        let kernels_path = dir.path().join("kernels.json");
+        let kernels =
+            CentroidingKernels::from_average_peak(&average_peak()).unwrap();
+        kernels.save(&kernels_path).unwrap();
+        assert_eq!(CentroidingKernels::load(&kernels_path).unwrap(), kernels);
+    }
+}
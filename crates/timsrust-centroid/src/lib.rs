@@ -47,15 +47,22 @@
 //!
 mod buffer;
 mod centroider;
+pub mod correlation;
 mod error;
+pub mod features;
+pub mod isotopes;
+pub mod kernels;
 mod peakbuffer;
 mod peaks;
 // mod runner;
 mod smoothing;
 pub mod spectrum_reader;
+#[cfg(test)]
+mod testing;
 
 pub use error::{TimsError, TimsResult};
 pub use peaks::{
-    Peak, PeakReader, get_average_ms1_peak, get_best_peak_for_frame,
+    Peak, PeakReader, get_average_ms1_peak, get_average_peak,
+    get_best_peak_for_frame,
 };
 // pub use runner::run;
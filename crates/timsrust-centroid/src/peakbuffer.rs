@@ -138,7 +138,14 @@ impl PeakBuffer {
                 scan: scan_index - self.scan_kernel_apex,
                 tof: 1 + self.tof_index as u32 - self.tof_kernel_apex,
                 apex_intensity,
+                ..Default::default()
             };
+            let peak = measure_shape(
+                peak,
+                &self.tofs,
+                self.tof_kernel_len as u32 / 2,
+                self.scan_smoother.len() as u32 / 2,
+            );
             if unique_peak {
                 self.peak_queue.push(peak);
             } else {
@@ -168,16 +175,85 @@ impl PeakBuffer {
             sum.scan += peak.scan;
             sum.tof += peak.tof;
             sum.apex_intensity += peak.apex_intensity;
+            sum.area += peak.area;
+            sum.ion_count += peak.ion_count;
+            sum.tof_fwhm += peak.tof_fwhm;
+            sum.scan_fwhm += peak.scan_fwhm;
+            sum.tof_apex += peak.tof_apex;
+            sum.scan_apex += peak.scan_apex;
         }
         Peak {
             frame: sum.frame / len,
             scan: sum.scan / len,
             tof: sum.tof / len,
             apex_intensity: sum.apex_intensity / len as u64,
+            area: sum.area / len as u64,
+            ion_count: sum.ion_count / len,
+            tof_fwhm: sum.tof_fwhm / len as f32,
+            scan_fwhm: sum.scan_fwhm / len as f32,
+            tof_apex: sum.tof_apex / len as f64,
+            scan_apex: sum.scan_apex / len as f64,
         }
     }
 }
 
+/// Fill in the area, ion count, FWHM and fractional apex of `peak` from the
+/// raw ions within `tof_radius` and `scan_radius` of its apex.
+fn measure_shape(
+    mut peak: Peak,
+    tofs: &[FxHashMap<u32, u64>],
+    tof_radius: u32,
+    scan_radius: u32,
+) -> Peak {
+    let tof_start = peak.tof.saturating_sub(tof_radius);
+    let scan_start = peak.scan.saturating_sub(scan_radius);
+    let mut tof_profile =
+        vec![0; (peak.tof + tof_radius - tof_start + 1) as usize];
+    let mut scan_profile =
+        vec![0; (peak.scan + scan_radius - scan_start + 1) as usize];
+    let mut tof_moment = 0.0;
+    let mut scan_moment = 0.0;
+    for (tof_offset, tof_bin) in tof_profile.iter_mut().enumerate() {
+        let tof = tof_start + tof_offset as u32;
+        let Some(scans) = tofs.get(tof as usize) else {
+            break;
+        };
+        for (scan_offset, scan_bin) in scan_profile.iter_mut().enumerate() {
+            let scan = scan_start + scan_offset as u32;
+            if let Some(&intensity) = scans.get(&scan) {
+                *tof_bin += intensity;
+                *scan_bin += intensity;
+                peak.area += intensity;
+                peak.ion_count += 1;
+                tof_moment += intensity as f64 * tof as f64;
+                scan_moment += intensity as f64 * scan as f64;
+            }
+        }
+    }
+    if peak.area > 0 {
+        peak.tof_apex = tof_moment / peak.area as f64;
+        peak.scan_apex = scan_moment / peak.area as f64;
+    } else {
+        peak.tof_apex = peak.tof as f64;
+        peak.scan_apex = peak.scan as f64;
+    }
+    peak.tof_fwhm = fwhm(&tof_profile);
+    peak.scan_fwhm = fwhm(&scan_profile);
+    peak
+}
+
+/// The number of bins at or above half of the maximum of `profile`.
+fn fwhm(profile: &[u64]) -> f32 {
+    let half_max = profile.iter().max().copied().unwrap_or(0) as f64 / 2.0;
+    if half_max == 0.0 {
+        return 0.0;
+    }
+    profile
+        .iter()
+        .filter(|&&value| value as f64 >= half_max)
+        .count() as f32
+}
+
 impl Iterator for PeakBuffer {
     type Item = Peak;
 
@@ -224,4 +300,35 @@ mod tests {
         );
         let _ = pb.next();
     }
+
+    #[test]
+    fn test_measure_shape() {
+        let tofs = vec![
+            FxHashMap::default(),
+            FxHashMap::from_iter([(2u32, 10u64)]),
+            FxHashMap::from_iter([(1u32, 10u64), (2u32, 40u64), (3u32, 10u64)]),
+            FxHashMap::from_iter([(2u32, 20u64), (3u32, 10u64)]),
+            FxHashMap::from_iter([(2u32, 1000u64)]),
+        ];
+        let peak = Peak {
+            scan: 2,
+            tof: 2,
+            apex_intensity: 40,
+            ..Default::default()
+        };
+        let peak = measure_shape(peak, &tofs, 1, 1);
+        assert_eq!(peak.area, 100);
+        assert_eq!(peak.ion_count, 6);
+        assert_eq!(peak.tof_fwhm, 2.0);
+        assert_eq!(peak.scan_fwhm, 1.0);
+        assert_eq!(peak.tof_apex, 2.2);
+        assert_eq!(peak.scan_apex, 2.1);
+    }
+
+    #[test]
+    fn test_fwhm() {
+        assert_eq!(fwhm(&[]), 0.0);
+        assert_eq!(fwhm(&[0, 0]), 0.0);
+        assert_eq!(fwhm(&[1, 4, 8, 4, 1]), 3.0);
+    }
 }
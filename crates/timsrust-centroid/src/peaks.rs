@@ -1,21 +1,22 @@
+use std::hash::{Hash, Hasher};
+
+use crate::kernels::{CentroidingKernels, Grid2D, KernelConfig, KernelSource};
 use crate::{TimsError, TimsResult, centroider::FrameCentroider};
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
 use timsrust_core::utils::reader::{IndexedReader, Reader};
 use timsrust_core::utils::thread::Synced;
-use timsrust_core::utils::vec::extract_kernel;
-use timsrust_core::utils::{ndarray::NDArray, vec::arg_max};
-use timsrust_core::{Frame, FrameInfo, FrameIons, FrameReader, MSLevel};
+use timsrust_core::utils::vec::arg_max;
+use timsrust_core::{
+    Frame, FrameInfo, FrameIons, FrameReader, MSLevel, PeakShape,
+};
 
-/// Full width at half maximum (FWHM) threshold for kernel extraction.
-const FWHM: f32 = 0.5;
 /// Maximum TOF (time-of-flight) width for peak extraction.
 const TOF_WIDTH: usize = 32;
 /// Maximum scan width for peak extraction.
 const SCAN_WIDTH: usize = 256;
 
-type Grid2D = NDArray<u64, 2>;
-type TOFMap = Vec<FxHashMap<u32, u64>>;
+pub(crate) type TOFMap = Vec<FxHashMap<u32, u64>>;
 
 /// Represents a centroided peak in a frame.
 ///
@@ -24,6 +25,16 @@ type TOFMap = Vec<FxHashMap<u32, u64>>;
 /// - `scan`: Scan index within the frame.
 /// - `tof`: Time-of-flight index.
 /// - `apex_intensity`: Intensity at the apex of the peak (unitless).
+/// - `area`: Summed intensity of all raw ions in the peak.
+/// - `ion_count`: Number of raw ions, i.e. occupied (TOF, scan) bins, in the
+///   peak. Each bin already sums all detector events at its TOF and scan.
+/// - `tof_fwhm`: Full width at half maximum in TOF bins.
+/// - `scan_fwhm`: Full width at half maximum in scans.
+/// - `tof_apex`: Intensity-weighted (fractional) TOF index of the apex.
+/// - `scan_apex`: Intensity-weighted (fractional) scan index of the apex.
+///
+/// The peak extent is the window of one TOF and one scan kernel length
+/// around `tof` and `scan`.
 ///
 /// # Example
 /// ```
@@ -33,15 +44,72 @@ type TOFMap = Vec<FxHashMap<u32, u64>>;
 ///     scan: 10,
 ///     tof: 100,
 ///     apex_intensity: 5000,
+///     area: 12000,
+///     ion_count: 7,
+///     ..Default::default()
 /// };
 /// assert_eq!(peak.frame, 1);
 /// ```
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+///
+/// Equality and hashing compare the float fields bit by bit, so that peaks
+/// can be used as keys of hash maps and sets.
+#[derive(Debug, Clone, Default)]
 pub struct Peak {
     pub frame: u32,
     pub scan: u32,
     pub tof: u32,
     pub apex_intensity: u64,
+    pub area: u64,
+    pub ion_count: u32,
+    pub tof_fwhm: f32,
+    pub scan_fwhm: f32,
+    pub tof_apex: f64,
+    pub scan_apex: f64,
+}
+
+impl PartialEq for Peak {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Peak {}
+
+impl Hash for Peak {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl Peak {
+    /// All fields, with floats as their bit patterns.
+    #[allow(clippy::type_complexity)]
+    fn key(&self) -> (u32, u32, u32, u64, u64, u32, u32, u32, u64, u64) {
+        (
+            self.frame,
+            self.scan,
+            self.tof,
+            self.apex_intensity,
+            self.area,
+            self.ion_count,
+            self.tof_fwhm.to_bits(),
+            self.scan_fwhm.to_bits(),
+            self.tof_apex.to_bits(),
+            self.scan_apex.to_bits(),
+        )
+    }
+
+    /// The shape metrics of this peak, as attached to centroided spectra.
+    pub fn shape(&self) -> PeakShape {
+        PeakShape {
+            area: self.area,
+            ion_count: self.ion_count,
+            tof_fwhm: self.tof_fwhm,
+            scan_fwhm: self.scan_fwhm,
+            tof_apex: self.tof_apex,
+            scan_apex: self.scan_apex,
+        }
+    }
 }
 
 /// Reads and extracts centroided peaks from frames.
@@ -51,9 +119,14 @@ pub struct Peak {
 #[derive(Debug)]
 pub struct PeakReader<IonReader, InfoReader> {
     frame_reader: FrameReader<IonReader, InfoReader>,
+    frame_indices: Vec<usize>,
     centroider_ms1: FrameCentroider,
     centroider_ms2: FrameCentroider,
-    average_peak: Grid2D,
+    kernels_ms1: CentroidingKernels,
+    kernels_ms2: CentroidingKernels,
+    average_peak_ms1: Option<Grid2D>,
+    average_peak_ms2: Option<Grid2D>,
+    centroid_ms1: bool,
 }
 
 impl<IonReader, InfoReader> PeakReader<IonReader, InfoReader>
@@ -63,6 +136,8 @@ where
 {
     /// Constructs a new [`PeakReader`] from the given frame reader and minimum ion counts.
     ///
+    /// The kernels of both MS levels are learned from the average MS1 peak.
+    ///
     /// # Arguments
     /// * `frame_reader` - A [`FrameReader`] providing frame data.
     /// * `min_ion_count_ms1` - Minimum ion count for centroiding MS1 frames.
@@ -75,34 +150,130 @@ where
         min_ion_count_ms1: f64,
         min_ion_count_ms2: f64,
     ) -> TimsResult<Self> {
-        let average_peak = get_average_ms1_peak(&frame_reader)?;
-        let tof_kernel = average_peak.project_axis(0);
-        let tof_kernel = extract_kernel(&tof_kernel, FWHM)
-            .ok_or(TimsError::new("Failed to extract kernel"))?;
-        let scan_kernel = average_peak.project_axis(1);
-        let scan_kernel = extract_kernel(&scan_kernel, FWHM)
-            .ok_or(TimsError::new("Failed to extract kernel"))?;
-        let min_ion_count_ms1 = if min_ion_count_ms1 <= 0.0 {
-            usize::MAX
-        } else if min_ion_count_ms1 < 1.0 {
-            (min_ion_count_ms1 * scan_kernel.len() as f64).ceil() as usize
-        } else {
-            min_ion_count_ms1 as usize
+        Self::with_kernels(
+            frame_reader,
+            min_ion_count_ms1,
+            min_ion_count_ms2,
+            KernelConfig::default(),
+        )
+    }
+
+    /// Constructs a new [`PeakReader`] with kernels from the given sources.
+    ///
+    /// Fractional minimum ion counts are relative to the scan kernel of
+    /// their own MS level.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use timsrust_centroid::PeakReader;
+    /// use timsrust_centroid::kernels::{
+    ///     CentroidingKernels, KernelConfig, KernelSource,
+    /// };
+    /// let kernels = CentroidingKernels::load("kernels.json").unwrap();
+    /// let config = KernelConfig {
+    ///     ms1: KernelSource::Explicit(kernels),
+    ///     ms2: Some(KernelSource::Learned),
+    /// };
+    /// let frame_reader = /* e.g. TdfFrameReader::new("example.d").unwrap() */;
+    /// let reader =
+    ///     PeakReader::with_kernels(frame_reader, 10.0, 5.0, config).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if kernel extraction fails or a kernel is invalid,
+    /// see [`CentroidingKernels::validate`].
+    pub fn with_kernels(
+        frame_reader: FrameReader<IonReader, InfoReader>,
+        min_ion_count_ms1: f64,
+        min_ion_count_ms2: f64,
+        config: KernelConfig,
+    ) -> TimsResult<Self> {
+        let (kernels_ms1, average_peak_ms1) =
+            resolve_kernels(&frame_reader, config.ms1, MSLevel::MS1)?;
+        let (kernels_ms2, average_peak_ms2) = match config.ms2 {
+            Some(source) => {
+                resolve_kernels(&frame_reader, source, MSLevel::MS2)?
+            },
+            None => (kernels_ms1.clone(), None),
         };
-        let min_ion_count_ms2 = if min_ion_count_ms2 < 1.0 {
-            (min_ion_count_ms2 * scan_kernel.len() as f64).ceil() as usize
-        } else {
-            min_ion_count_ms2 as usize
+        let min_ion_count_ms1 = min_ion_count(min_ion_count_ms1, &kernels_ms1)?;
+        let centroider_ms1 = FrameCentroider::new(
+            &kernels_ms1.scan,
+            &kernels_ms1.tof,
+            min_ion_count_ms1,
+        );
+        Self::from_parts(
+            frame_reader,
+            Some(centroider_ms1),
+            (kernels_ms1, average_peak_ms1),
+            (kernels_ms2, average_peak_ms2),
+            min_ion_count_ms2,
+        )
+    }
+
+    /// Constructs a [`PeakReader`] that only centroids MS2 frames. MS1
+    /// frames yield no peaks.
+    ///
+    /// MS2 kernels come from `config.ms2`, learned from MS2 frames, or if it
+    /// is `None` from `config.ms1`, learned from MS1 frames. The MS1
+    /// kernels are set to the MS2 kernels.
+    ///
+    /// # Errors
+    /// Returns an error if kernel extraction fails or a kernel is invalid,
+    /// see [`CentroidingKernels::validate`].
+    pub fn ms2_only(
+        frame_reader: FrameReader<IonReader, InfoReader>,
+        min_ion_count_ms2: f64,
+        config: KernelConfig,
+    ) -> TimsResult<Self> {
+        let (kernels, average_peak) = match config.ms2 {
+            Some(source) => {
+                resolve_kernels(&frame_reader, source, MSLevel::MS2)?
+            },
+            None => resolve_kernels(&frame_reader, config.ms1, MSLevel::MS1)?,
         };
-        let centroider_ms1 =
-            FrameCentroider::new(&scan_kernel, &tof_kernel, min_ion_count_ms1);
-        let centroider_ms2 =
-            FrameCentroider::new(&scan_kernel, &tof_kernel, min_ion_count_ms2);
+        Self::from_parts(
+            frame_reader,
+            None,
+            (kernels.clone(), None),
+            (kernels, average_peak),
+            min_ion_count_ms2,
+        )
+    }
+
+    fn from_parts(
+        frame_reader: FrameReader<IonReader, InfoReader>,
+        centroider_ms1: Option<FrameCentroider>,
+        (kernels_ms1, average_peak_ms1): (CentroidingKernels, Option<Grid2D>),
+        (kernels_ms2, average_peak_ms2): (CentroidingKernels, Option<Grid2D>),
+        min_ion_count_ms2: f64,
+    ) -> TimsResult<Self> {
+        let min_ion_count_ms2 = min_ion_count(min_ion_count_ms2, &kernels_ms2)?;
+        let centroider_ms2 = FrameCentroider::new(
+            &kernels_ms2.scan,
+            &kernels_ms2.tof,
+            min_ion_count_ms2,
+        );
+        let centroid_ms1 = centroider_ms1.is_some();
+        let centroider_ms1 = centroider_ms1.unwrap_or_else(|| {
+            FrameCentroider::new(
+                &kernels_ms1.scan,
+                &kernels_ms1.tof,
+                usize::MAX,
+            )
+        });
+        let mut frame_indices = frame_reader.iter_indices().collect::<Vec<_>>();
+        frame_indices.sort_unstable();
         let result = Self {
             frame_reader,
+            frame_indices,
             centroider_ms1,
             centroider_ms2,
-            average_peak,
+            kernels_ms1,
+            kernels_ms2,
+            average_peak_ms1,
+            average_peak_ms2,
+            centroid_ms1,
         };
         Ok(result)
     }
@@ -113,7 +284,14 @@ where
 
     /// Returns the number of frames in the dataset.
     pub fn frame_count(&self) -> usize {
-        self.frame_reader.len()
+        self.frame_indices.len()
+    }
+
+    /// Returns the indices of all frames, in ascending order.
+    ///
+    /// Frame indices are not necessarily zero-based or contiguous.
+    pub fn frame_indices(&self) -> &[usize] {
+        &self.frame_indices
     }
 
     /// Returns the length of the TOF kernel (FWHM).
@@ -126,6 +304,21 @@ where
         self.centroider_ms1.scan_smoother().kernel().len()
     }
 
+    /// Returns the kernels used for centroiding MS1 frames.
+    pub fn kernels_ms1(&self) -> &CentroidingKernels {
+        &self.kernels_ms1
+    }
+
+    /// Returns the kernels used for centroiding MS2 frames.
+    pub fn kernels_ms2(&self) -> &CentroidingKernels {
+        &self.kernels_ms2
+    }
+
+    /// Whether MS1 frames are centroided, see [`Self::ms2_only`].
+    pub fn centroids_ms1(&self) -> bool {
+        self.centroid_ms1
+    }
+
     /// Returns the minimum MS1 ion count used for centroiding.
     pub fn min_count_ms1(&self) -> usize {
         self.centroider_ms1.scan_smoother().min_count()
@@ -152,8 +345,16 @@ where
     /// let peaks = reader.get_peaks_from_frame(0).unwrap();
     /// ```
     pub fn get_peaks_from_frame(&self, index: usize) -> TimsResult<Vec<Peak>> {
-        let tofs = self.get_transposed_tofs(index)?;
-        let frame = self.frame_reader().get_frame(index).unwrap();
+        let frame = self
+            .frame_reader()
+            .get_frame(index)
+            .map_err(|e| TimsError::new(e.to_string()))?;
+        let skip =
+            frame.info().ms_level() == MSLevel::MS1 && !self.centroid_ms1;
+        if frame.ions().is_empty() || skip {
+            return Ok(vec![]);
+        }
+        let tofs = transpose_tofs(&frame);
         let peaks = if frame.info().ms_level() == MSLevel::MS1 {
             self.centroider_ms1.centroid(tofs, frame.index()).collect()
         } else if frame.info().ms_level() == MSLevel::MS2 {
@@ -201,7 +402,8 @@ where
         }
     }
 
-    /// Returns a reference to the average MS1 peak grid.
+    /// Returns a reference to the average MS1 peak grid, if the MS1 kernels
+    /// were derived from one.
     ///
     /// # Example
     /// ```ignore
@@ -210,20 +412,43 @@ where
     /// let reader = PeakReader::new(frame_reader, 10.0, 5.0).unwrap();
     /// let avg_peak = reader.get_average_ms1_peak();
     /// ```
-    pub fn get_average_ms1_peak(&self) -> &Grid2D {
-        &self.average_peak
+    pub fn get_average_ms1_peak(&self) -> Option<&Grid2D> {
+        self.average_peak_ms1.as_ref()
     }
+
+    /// Returns a reference to the average MS2 peak grid, if separate MS2
+    /// kernels were derived from one.
+    pub fn get_average_ms2_peak(&self) -> Option<&Grid2D> {
+        self.average_peak_ms2.as_ref()
+    }
+}
+
+/// The minimum ion count of a centroider, where fractional counts are
+/// relative to the scan kernel.
+fn min_ion_count(
+    min_ion_count: f64,
+    kernels: &CentroidingKernels,
+) -> TimsResult<usize> {
+    if min_ion_count.is_nan() || min_ion_count < 0.0 {
+        return Err(TimsError::new(format!(
+            "Invalid minimum ion count {min_ion_count}"
+        )));
+    }
+    let count = if min_ion_count < 1.0 {
+        (min_ion_count * kernels.scan.len() as f64).ceil() as usize
+    } else {
+        min_ion_count as usize
+    };
+    Ok(count)
 }
 
 fn transpose_tofs(frame: &Frame) -> TOFMap {
-    let max_tof = usize::from(
-        *frame
-            .ions()
-            .tof_indices()
-            .iter()
-            .max()
-            .expect("Failed to find max TOF"),
-    ) + 1;
+    let max_tof = frame
+        .ions()
+        .tof_indices()
+        .iter()
+        .max()
+        .map_or(0, |&tof| usize::from(tof) + 1);
     let mut tofs = vec![FxHashMap::default(); max_tof];
     frame.ions().scan_offsets().windows(2).enumerate().for_each(
         |(scan_index, s)| {
@@ -239,9 +464,42 @@ fn transpose_tofs(frame: &Frame) -> TOFMap {
     tofs
 }
 
+fn resolve_kernels<IR, InfoR>(
+    frame_reader: &FrameReader<IR, InfoR>,
+    source: KernelSource,
+    ms_level: MSLevel,
+) -> TimsResult<(CentroidingKernels, Option<Grid2D>)>
+where
+    IR: Reader<FrameIons> + Sync + Send,
+    InfoR: Reader<FrameInfo> + IndexedReader<FrameInfo> + Sync + Send,
+{
+    let average_peak = match source {
+        KernelSource::Explicit(kernels) => {
+            kernels.validate()?;
+            return Ok((kernels, None));
+        },
+        KernelSource::AveragePeak(average_peak) => average_peak,
+        KernelSource::Learned => get_average_peak(frame_reader, ms_level)?,
+    };
+    let kernels = CentroidingKernels::from_average_peak(&average_peak)?;
+    Ok((kernels, Some(average_peak)))
+}
+
 pub fn get_average_ms1_peak<IR, InfoR>(
     frame_reader: &FrameReader<IR, InfoR>,
 ) -> TimsResult<Grid2D>
+where
+    IR: Reader<FrameIons> + Sync + Send,
+    InfoR: Reader<FrameInfo> + IndexedReader<FrameInfo> + Sync + Send,
+{
+    get_average_peak(frame_reader, MSLevel::MS1)
+}
+
+/// Sums the best peak of every frame of the given MS level.
+pub fn get_average_peak<IR, InfoR>(
+    frame_reader: &FrameReader<IR, InfoR>,
+    ms_level: MSLevel,
+) -> TimsResult<Grid2D>
 where
     IR: Reader<FrameIons> + Sync + Send,
     InfoR: Reader<FrameInfo> + IndexedReader<FrameInfo> + Sync + Send,
@@ -251,7 +509,7 @@ where
     let synced_summed_grid =
         Synced::from(Grid2D::empty([TOF_WIDTH * 2 + 1, SCAN_WIDTH * 2 + 1]));
     frame_reader
-        .parallel_filter(|f| f.info().ms_level() == MSLevel::MS1)
+        .parallel_filter(|f| f.info().ms_level() == ms_level)
         .for_each(|frame| {
             if let Ok(frame) = frame {
                 if frame.ions().intensities().is_empty() {
@@ -298,3 +556,79 @@ pub fn get_best_peak_for_frame(frame: Frame) -> Grid2D {
     );
     grid
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn peaks_are_hashable() {
+        let peak = Peak {
+            scan: 10,
+            tof: 100,
+            tof_fwhm: 2.5,
+            scan_apex: f64::NAN,
+            ..Default::default()
+        };
+        let other = Peak {
+            tof_fwhm: 3.0,
+            ..peak.clone()
+        };
+        assert_eq!(peak, peak.clone());
+        assert_ne!(peak, other);
+        let peaks = HashSet::from([peak.clone(), peak.clone(), other]);
+        assert_eq!(peaks.len(), 2);
+        assert!(peaks.contains(&peak));
+    }
+
+    #[test]
+    fn ms2_only() {
+        use crate::testing;
+
+        let config = KernelConfig {
+            ms1: KernelSource::Explicit(testing::kernels()),
+            ms2: None,
+        };
+        let reader =
+            PeakReader::ms2_only(testing::frame_reader(1), 1.0, config.clone())
+                .unwrap();
+        assert!(!reader.centroids_ms1());
+        assert!(reader.get_peaks_from_frame(1).unwrap().is_empty());
+        assert!(!reader.get_peaks_from_frame(2).unwrap().is_empty());
+        assert!(testing::peak_reader(1).centroids_ms1());
+        assert!(
+            PeakReader::ms2_only(testing::frame_reader(1), -1.0, config)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn invalid_kernels_are_rejected() {
+        use crate::testing;
+
+        for kernels in [
+            CentroidingKernels {
+                tof: vec![0.5, 1.0, 0.5],
+                scan: vec![],
+            },
+            CentroidingKernels {
+                tof: vec![1.0],
+                scan: vec![0.5, 1.0, 0.5],
+            },
+        ] {
+            let config = KernelConfig {
+                ms1: KernelSource::Explicit(testing::kernels()),
+                ms2: Some(KernelSource::Explicit(kernels)),
+            };
+            let reader = PeakReader::with_kernels(
+                testing::frame_reader(1),
+                1.0,
+                1.0,
+                config,
+            );
+            assert!(reader.is_err());
+        }
+    }
+}
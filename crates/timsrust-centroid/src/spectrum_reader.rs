@@ -1,3 +1,4 @@
+pub mod dda_spectrum_reader;
 pub mod narrow_spectrum_reader;
 pub mod wide_spectrum_reader;
 
@@ -6,16 +7,29 @@ use std::sync::Arc;
 use rayon::prelude::*;
 use timsrust_core::{
     Converter, FrameInfo, FrameIons, FrameReader, Im, InvertibleConverter, Mz,
-    ScanIndex, Spectrum, TofIndex,
+    Precursor, ScanIndex, Spectrum, TofIndex,
 };
 
+pub use dda_spectrum_reader::{
+    DDASpectrumReader, PasefPrecursor, PasefSelection,
+};
 pub use narrow_spectrum_reader::NarrowSpectrumReader;
 use timsrust_core::utils::reader::{IndexedReader, ParIterableReader};
 pub use wide_spectrum_reader::WideSpectrumReader;
 
-use crate::{PeakReader, error::TimsResult};
+use crate::{
+    PeakReader, correlation::ElutionCorrelationParams, error::TimsResult,
+    isotopes::DeisotopingStrategy, kernels::KernelConfig,
+};
+
+/// Number of centroided MS2 frames kept for random access to spectra.
+///
+/// Consecutive spectra mostly come from the same few frames, so that each
+/// frame is centroided about once when spectra are read in order.
+const RANDOM_ACCESS_FRAMES: usize = 16;
 
 /// Reads and extracts spectra from frames.
+#[allow(clippy::large_enum_variant)]
 pub enum SpectrumReader<
     IonReader,
     InfoReader,
@@ -64,8 +78,11 @@ where
             )?;
             SpectrumReader::Narrow(reader)
         } else {
-            let peak_reader =
-                PeakReader::new(frame_reader, -1.0, min_ms2_ion_count)?;
+            let peak_reader = PeakReader::ms2_only(
+                frame_reader,
+                min_ms2_ion_count,
+                KernelConfig::default(),
+            )?;
             let reader = WideSpectrumReader::new(
                 peak_reader,
                 min_spectrum_size,
@@ -76,7 +93,74 @@ where
         Ok(spectrum_reader)
     }
 
-    /// Returns the number of spectra processed so far.
+    /// Set the precursor charges to consider.
+    ///
+    /// Only affects readers that use precursors.
+    pub fn set_charges(&mut self, charges: Vec<u8>) {
+        if let SpectrumReader::Narrow(reader) = self {
+            reader.set_charges(charges)
+        }
+    }
+
+    /// Set how charge states and monoisotopic precursor peaks are assigned.
+    ///
+    /// Only affects readers that use precursors.
+    pub fn set_deisotoping(&mut self, strategy: DeisotopingStrategy) {
+        if let SpectrumReader::Narrow(reader) = self {
+            reader.set_deisotoping(strategy)
+        }
+    }
+
+    /// Only keep fragments that co-elute with their precursor.
+    ///
+    /// Only affects readers that use precursors.
+    pub fn set_elution_correlation(
+        &mut self,
+        params: Option<ElutionCorrelationParams>,
+    ) {
+        if let SpectrumReader::Narrow(reader) = self {
+            reader.set_elution_correlation(params)
+        }
+    }
+
+    /// Only keep precursors without a preceding isotope peak.
+    ///
+    /// Only affects readers that use precursors.
+    pub fn set_monoisotopic_only(&mut self, value: bool) {
+        if let SpectrumReader::Narrow(reader) = self {
+            reader.set_monoisotopic_only(value)
+        }
+    }
+
+    /// Only keep the highest matching charge state per precursor peak.
+    ///
+    /// Only affects readers that use precursors.
+    pub fn set_highest_charge_state_only(&mut self, value: bool) {
+        if let SpectrumReader::Narrow(reader) = self {
+            reader.set_highest_charge_state_only(value)
+        }
+    }
+
+    /// Random access to the spectrum at position `index`.
+    ///
+    /// The first call processes all frames once to build the spectrum
+    /// index; see [`SpectrumLocation`].
+    pub fn get(&self, index: usize) -> Result<Spectrum, TimsCentroidError> {
+        match self {
+            SpectrumReader::Narrow(reader) => reader.get(index),
+            SpectrumReader::Wide(reader) => reader.get(index),
+        }
+    }
+
+    /// The location of every spectrum, ordered by spectrum id.
+    pub fn spectrum_index(&self) -> &[SpectrumLocation] {
+        match self {
+            SpectrumReader::Narrow(reader) => reader.spectrum_index(),
+            SpectrumReader::Wide(reader) => reader.spectrum_index(),
+        }
+    }
+
+    /// Returns the exact number of spectra.
     pub fn len(&self) -> usize {
         match self {
             SpectrumReader::Narrow(reader) => reader.len(),
@@ -107,6 +191,14 @@ where
         }
     }
 
+    /// The indices of all frames, in ascending order.
+    pub fn frame_indices(&self) -> &[usize] {
+        match self {
+            SpectrumReader::Narrow(reader) => reader.frame_indices(),
+            SpectrumReader::Wide(reader) => reader.frame_indices(),
+        }
+    }
+
     pub fn tof_fwhm(&self) -> usize {
         match self {
             SpectrumReader::Narrow(reader) => reader.tof_fwhm(),
@@ -161,6 +253,64 @@ enum A<
 #[error("{0}")]
 pub struct TimsCentroidError(String);
 
+impl TimsCentroidError {
+    fn no_spectrum_at(index: usize) -> Self {
+        Self(format!("No spectrum at index {index}"))
+    }
+}
+
+/// Where a centroided spectrum comes from.
+///
+/// Spectrum ids are `frame_index << 32 | window`, so sorting locations
+/// sorts spectra by id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpectrumLocation {
+    /// The frame whose peaks form the spectrum.
+    pub frame_index: usize,
+    /// The window (scan slice) of the spectrum within its frame.
+    pub window: usize,
+    /// The frame the precursor was detected in. Equal to `frame_index` for
+    /// spectra without detected precursors.
+    pub precursor_frame_index: usize,
+}
+
+impl SpectrumLocation {
+    fn new(precursor_frame_index: usize, spectrum_id: usize) -> Self {
+        Self {
+            frame_index: spectrum_id >> 32,
+            window: spectrum_id & 0xFFFF_FFFF,
+            precursor_frame_index,
+        }
+    }
+
+    pub fn spectrum_id(&self) -> usize {
+        (self.frame_index << 32) | self.window
+    }
+}
+
+/// Process every frame once and collect the sorted locations of all spectra,
+/// together with their precursors.
+fn build_spectrum_index(
+    frame_indices: &[usize],
+    get_spectra_from_frame: impl Fn(usize) -> Vec<Spectrum> + Sync,
+) -> Vec<(SpectrumLocation, Option<Precursor>)> {
+    let mut locations: Vec<(SpectrumLocation, Option<Precursor>)> =
+        frame_indices
+            .par_iter()
+            .flat_map_iter(|&index| {
+                get_spectra_from_frame(index)
+                    .into_iter()
+                    .map(move |spectrum| {
+                        let location =
+                            SpectrumLocation::new(index, spectrum.index());
+                        (location, spectrum.precursor().clone())
+                    })
+            })
+            .collect();
+    locations.sort_unstable_by_key(|(location, _)| *location);
+    locations
+}
+
 impl<
     'a,
     IonReader: timsrust_core::utils::reader::Reader<FrameIons> + Sync + Send,
@@ -0,0 +1,277 @@
+use crate::{Peak, PeakReader, spectrum_reader::TimsCentroidError};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use timsrust_core::utils::reader::{ParIterableReader, Reader};
+use timsrust_core::{
+    FrameInfo, FrameIons, IsolationWindow, Precursor, Spectrum, TofIndex,
+};
+
+/// Number of consecutive precursors that share centroided frames when
+/// iterating over all spectra.
+const CHUNK_SIZE: usize = 64;
+
+type FramePeaks = Result<Vec<Peak>, TimsCentroidError>;
+
+/// The scans of a PASEF frame in which a precursor was fragmented.
+///
+/// Scans are half-open: `scan_start..scan_end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PasefSelection {
+    pub frame_index: usize,
+    pub scan_start: usize,
+    pub scan_end: usize,
+}
+
+impl PasefSelection {
+    fn contains(&self, peak: &Peak) -> bool {
+        (self.scan_start..self.scan_end).contains(&(peak.scan as usize))
+    }
+}
+
+/// A DDA precursor with all PASEF frame slices in which it was fragmented.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasefPrecursor {
+    pub precursor: Precursor,
+    pub isolation_window: IsolationWindow,
+    pub selections: Vec<PasefSelection>,
+}
+
+/// Reads DDA-PASEF fragment spectra from 2D-centroided MS2 frames.
+///
+/// Every PASEF frame is centroided in scan × TOF, after which the peaks
+/// within the scan range of a precursor are kept. Peaks of the same TOF
+/// from repeated fragmentations of a precursor are summed.
+pub struct DDASpectrumReader<IonReader, InfoReader> {
+    peak_reader: PeakReader<IonReader, InfoReader>,
+    precursors: Vec<PasefPrecursor>,
+}
+
+impl<IonReader, InfoReader> DDASpectrumReader<IonReader, InfoReader>
+where
+    IonReader: Reader<FrameIons> + Sync + Send,
+    InfoReader: Reader<FrameInfo>
+        + timsrust_core::utils::reader::IndexedReader<FrameInfo>
+        + Sync
+        + Send,
+{
+    /// Create a reader with one spectrum per precursor, in the given order.
+    pub fn new(
+        peak_reader: PeakReader<IonReader, InfoReader>,
+        precursors: Vec<PasefPrecursor>,
+    ) -> Self {
+        Self {
+            peak_reader,
+            precursors,
+        }
+    }
+
+    pub fn precursors(&self) -> &[PasefPrecursor] {
+        &self.precursors
+    }
+
+    /// Random access to the spectrum of the precursor at position `index`.
+    pub fn get(&self, index: usize) -> Result<Spectrum, TimsCentroidError> {
+        let pasef_precursor = self
+            .precursors
+            .get(index)
+            .ok_or_else(|| TimsCentroidError::no_spectrum_at(index))?;
+        let mut peaks = vec![];
+        for selection in &pasef_precursor.selections {
+            let frame_peaks =
+                self.get_peaks_from_frame(selection.frame_index)?;
+            peaks.extend(
+                frame_peaks.into_iter().filter(|p| selection.contains(p)),
+            );
+        }
+        Ok(to_spectrum(pasef_precursor, peaks))
+    }
+
+    pub fn len(&self) -> usize {
+        self.precursors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn scan_fwhm(&self) -> usize {
+        self.peak_reader.scan_fwhm()
+    }
+
+    pub fn tof_fwhm(&self) -> usize {
+        self.peak_reader.tof_fwhm()
+    }
+
+    fn get_peaks_from_frame(
+        &self,
+        frame_index: usize,
+    ) -> Result<Vec<Peak>, TimsCentroidError> {
+        self.peak_reader
+            .get_peaks_from_frame(frame_index)
+            .map_err(|e| TimsCentroidError(e.to_string()))
+    }
+
+    /// All spectra of a chunk of consecutive precursors, centroiding each
+    /// of their frames only once.
+    fn get_chunk(
+        &self,
+        precursors: &[PasefPrecursor],
+    ) -> Vec<Result<Spectrum, TimsCentroidError>> {
+        let mut frames: FxHashMap<usize, FramePeaks> = FxHashMap::default();
+        precursors
+            .iter()
+            .map(|pasef_precursor| {
+                let mut peaks = vec![];
+                for selection in &pasef_precursor.selections {
+                    let frame_peaks = frames
+                        .entry(selection.frame_index)
+                        .or_insert_with(|| {
+                            self.get_peaks_from_frame(selection.frame_index)
+                        })
+                        .as_ref()
+                        .map_err(|e| TimsCentroidError(e.0.clone()))?;
+                    peaks.extend(
+                        frame_peaks
+                            .iter()
+                            .filter(|p| selection.contains(p))
+                            .cloned(),
+                    );
+                }
+                Ok(to_spectrum(pasef_precursor, peaks))
+            })
+            .collect()
+    }
+
+    pub fn _par_iter(
+        &self,
+    ) -> impl ParallelIterator<Item = Result<Spectrum, TimsCentroidError>> + '_
+    {
+        self.precursors
+            .par_chunks(CHUNK_SIZE)
+            .flat_map_iter(|chunk| self.get_chunk(chunk))
+    }
+}
+
+impl<IonReader, InfoReader> Reader<Spectrum>
+    for DDASpectrumReader<IonReader, InfoReader>
+where
+    IonReader: Reader<FrameIons> + Sync + Send,
+    InfoReader: Reader<FrameInfo>
+        + timsrust_core::utils::reader::IndexedReader<FrameInfo>
+        + Sync
+        + Send,
+{
+    type Error = TimsCentroidError;
+
+    fn get(&self, index: usize) -> Result<Spectrum, Self::Error> {
+        DDASpectrumReader::get(self, index)
+    }
+}
+
+impl<'a, IonReader, InfoReader> ParIterableReader<'a, Spectrum>
+    for DDASpectrumReader<IonReader, InfoReader>
+where
+    IonReader: Reader<FrameIons> + Sync + Send,
+    InfoReader: Reader<FrameInfo>
+        + timsrust_core::utils::reader::IndexedReader<FrameInfo>
+        + Sync
+        + Send,
+{
+    type Error = TimsCentroidError;
+
+    fn par_iter(
+        &'a self,
+    ) -> impl ParallelIterator<Item = Result<Spectrum, Self::Error>> {
+        self._par_iter()
+    }
+}
+
+fn to_spectrum(pasef_precursor: &PasefPrecursor, peaks: Vec<Peak>) -> Spectrum {
+    let peaks = merge_peaks(peaks);
+    let precursor = pasef_precursor.precursor.clone();
+    Spectrum::new(
+        peaks.iter().map(|p| p.apex_intensity as f64).collect(),
+        precursor.index(),
+        Some(precursor),
+        peaks
+            .iter()
+            .map(|p| TofIndex::try_from(p.tof).unwrap())
+            .collect(),
+        pasef_precursor.isolation_window.clone(),
+    )
+    .with_peak_shapes(peaks.iter().map(Peak::shape).collect())
+    .expect("One shape per peak")
+}
+
+/// Sort peaks by TOF and sum the intensities of peaks with the same TOF.
+///
+/// The shape of a merged peak is that of its most intense constituent.
+fn merge_peaks(mut peaks: Vec<Peak>) -> Vec<Peak> {
+    peaks.sort_by_key(|p| p.tof);
+    let mut merged: Vec<Peak> = Vec::with_capacity(peaks.len());
+    for peak in peaks {
+        match merged.last_mut() {
+            Some(last) if last.tof == peak.tof => {
+                let apex_intensity = last.apex_intensity + peak.apex_intensity;
+                let area = last.area + peak.area;
+                let ion_count = last.ion_count + peak.ion_count;
+                if peak.apex_intensity > last.apex_intensity {
+                    *last = peak;
+                }
+                last.apex_intensity = apex_intensity;
+                last.area = area;
+                last.ion_count = ion_count;
+            },
+            _ => merged.push(peak),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dummy_peak(frame: u32, scan: u32, tof: u32, intensity: u64) -> Peak {
+        Peak {
+            frame,
+            scan,
+            tof,
+            apex_intensity: intensity,
+            area: intensity * 2,
+            ion_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_selection_contains() {
+        let selection = PasefSelection {
+            frame_index: 1,
+            scan_start: 10,
+            scan_end: 20,
+        };
+        assert!(!selection.contains(&dummy_peak(1, 9, 100, 10)));
+        assert!(selection.contains(&dummy_peak(1, 10, 100, 10)));
+        assert!(selection.contains(&dummy_peak(1, 19, 100, 10)));
+        assert!(!selection.contains(&dummy_peak(1, 20, 100, 10)));
+    }
+
+    #[test]
+    fn test_merge_peaks() {
+        let peaks = vec![
+            dummy_peak(2, 15, 200, 30),
+            dummy_peak(1, 12, 100, 10),
+            dummy_peak(1, 14, 200, 50),
+        ];
+        let merged = merge_peaks(peaks);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].tof, 100);
+        assert_eq!(merged[0].apex_intensity, 10);
+        assert_eq!(merged[1].tof, 200);
+        assert_eq!(merged[1].apex_intensity, 80);
+        assert_eq!(merged[1].area, 160);
+        assert_eq!(merged[1].ion_count, 2);
+        assert_eq!((merged[1].frame, merged[1].scan), (1, 14));
+    }
+}
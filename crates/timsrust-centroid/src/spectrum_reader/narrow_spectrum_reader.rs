@@ -1,8 +1,18 @@
-use crate::{Peak, PeakReader, TimsResult, spectrum_reader::TimsCentroidError};
+use crate::{
+    Peak, PeakReader, TimsResult,
+    correlation::{
+        CycleFrames, ElutionCorrelationParams, ElutionCorrelator, FrameCache,
+        mz_to_tof,
+    },
+    isotopes::{DeisotopingStrategy, IsotopeScorer},
+    spectrum_reader::{
+        RANDOM_ACCESS_FRAMES, SpectrumLocation, TimsCentroidError,
+        build_spectrum_index,
+    },
+};
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
-use std::sync::Arc;
-use std::sync::atomic;
+use std::sync::{Arc, OnceLock};
 use timsrust_core::FrameInfo;
 use timsrust_core::utils::reader::ParIterableReader;
 use timsrust_core::utils::reader::Reader;
@@ -21,12 +31,25 @@ pub struct NarrowSpectrumReader<
     peak_reader: PeakReader<IonReader, InfoReader>,
     im_converter: Arc<ImConverter>,
     mz_converter: Arc<MzConverter>,
-    spectrum_id: atomic::AtomicUsize,
-    precursor_id: atomic::AtomicUsize,
+    spectrum_index: OnceLock<SpectrumIndex>,
     min_spectrum_size: usize,
     monoisotopic_only: bool,
     highest_charge_state_only: bool,
     charges: Vec<u8>,
+    deisotoping: DeisotopingStrategy,
+    elution_correlation: Option<ElutionCorrelationParams>,
+    ms1_frames: OnceLock<Vec<usize>>,
+    frame_cache: OnceLock<FrameCache>,
+    ms2_peak_cache: FrameCache<Vec<Peak>>,
+}
+
+/// The location and precursor of every spectrum, ordered by spectrum id.
+///
+/// Keeping the precursors allows random access without deisotoping the MS1
+/// frame again.
+struct SpectrumIndex {
+    locations: Vec<SpectrumLocation>,
+    precursors: Vec<Precursor>,
 }
 
 impl<
@@ -47,32 +70,65 @@ impl<
         im_converter: Arc<ImConverter>,
         mz_converter: Arc<MzConverter>,
     ) -> TimsResult<Self> {
-        let spectrum_id = atomic::AtomicUsize::new(0);
-        let precursor_id = atomic::AtomicUsize::new(0);
         let result = Self {
             peak_reader,
             mz_converter,
             im_converter,
-            spectrum_id,
-            precursor_id,
+            spectrum_index: OnceLock::new(),
             min_spectrum_size,
             monoisotopic_only: true,
             highest_charge_state_only: true,
             charges: (1..6).collect(),
+            deisotoping: DeisotopingStrategy::default(),
+            elution_correlation: None,
+            ms1_frames: OnceLock::new(),
+            frame_cache: OnceLock::new(),
+            ms2_peak_cache: FrameCache::new(RANDOM_ACCESS_FRAMES),
         };
         Ok(result)
     }
 
     pub fn set_charges(&mut self, charges: Vec<u8>) {
         self.charges = charges;
+        self.spectrum_index = OnceLock::new();
     }
 
     pub fn set_monoisotopic_only(&mut self, value: bool) {
         self.monoisotopic_only = value;
+        self.spectrum_index = OnceLock::new();
     }
 
     pub fn set_highest_charge_state_only(&mut self, value: bool) {
         self.highest_charge_state_only = value;
+        self.spectrum_index = OnceLock::new();
+    }
+
+    /// Set how charge states and monoisotopic precursor peaks are assigned.
+    pub fn set_deisotoping(&mut self, strategy: DeisotopingStrategy) {
+        self.deisotoping = strategy;
+        self.spectrum_index = OnceLock::new();
+    }
+
+    pub fn deisotoping(&self) -> &DeisotopingStrategy {
+        &self.deisotoping
+    }
+
+    /// Only keep fragments that co-elute with their precursor, or keep all
+    /// fragments if `None` (the default).
+    ///
+    /// Kept fragments are annotated with their score, see
+    /// [`Spectrum::correlations`].
+    pub fn set_elution_correlation(
+        &mut self,
+        params: Option<ElutionCorrelationParams>,
+    ) {
+        self.elution_correlation = params;
+        self.spectrum_index = OnceLock::new();
+        self.frame_cache = OnceLock::new();
+    }
+
+    pub fn elution_correlation(&self) -> Option<&ElutionCorrelationParams> {
+        self.elution_correlation.as_ref()
     }
 
     pub fn charges(&self) -> &[u8] {
@@ -91,56 +147,300 @@ impl<
         self.min_spectrum_size
     }
 
-    pub fn get_spectral_chunk_from_frame(
+    /// Deisotope the MS1 frame at `index` into precursors, or `None` if the
+    /// frame is not an MS1 frame or yields no precursors.
+    fn get_precursors_from_frame(
         &self,
         index: usize,
-    ) -> Option<SpectralChunk> {
-        if let Ok(frame) = self
+    ) -> Option<Vec<timsrust_core::Precursor>> {
+        let frame = self
             .peak_reader
             .frame_reader()
             .get_partial_frame_without_ions(index)
-        {
-            if frame.info().ms_level() != timsrust_core::MSLevel::MS1 {
-                return None;
+            .ok()?;
+        if frame.info().ms_level() != timsrust_core::MSLevel::MS1 {
+            return None;
+        }
+        let ms1_peaks = self.peak_reader.get_peaks_from_frame(index).ok()?;
+        let precursors = deisotope(
+            ms1_peaks,
+            &frame,
+            self.im_converter.as_ref(),
+            self.mz_converter.as_ref(),
+            self.peak_reader.scan_fwhm(),
+            self.monoisotopic_only,
+            self.highest_charge_state_only,
+            &self.charges,
+            &self.deisotoping,
+        );
+        if precursors.is_empty() {
+            return None;
+        }
+        Some(precursors)
+    }
+
+    /// Read the MS2 peaks of `ms2_frame_index` sorted by scan, or `None` if
+    /// the frame has no peaks.
+    fn get_ms2_peaks(&self, ms2_frame_index: usize) -> Option<Vec<Peak>> {
+        let mut ms2_peaks = self
+            .peak_reader
+            .get_peaks_from_frame(ms2_frame_index)
+            .ok()?;
+        if ms2_peaks.is_empty() {
+            return None;
+        }
+        ms2_peaks.sort_by_key(|a| (a.scan, a.tof));
+        Some(ms2_peaks)
+    }
+
+    pub fn get_spectral_chunk_from_frame(
+        &self,
+        index: usize,
+    ) -> Option<SpectralChunk> {
+        let precursors = self.get_precursors_from_frame(index)?;
+        let mut spectral_chunk = SpectralChunk {
+            peaks: FxHashMap::default(),
+            precursors,
+        };
+        for ms2_frame_index in find_ms2_frames(
+            index,
+            self.peak_reader.frame_reader().info_reader(),
+        ) {
+            if let Some(ms2_peaks) = self.get_ms2_peaks(ms2_frame_index) {
+                spectral_chunk.peaks.insert(ms2_frame_index, ms2_peaks);
             }
-            if let Ok(ms1_peaks) = self.peak_reader.get_peaks_from_frame(index)
-            {
-                let precursors = deisotope(
-                    ms1_peaks,
-                    &frame,
-                    self.im_converter.as_ref(),
-                    self.mz_converter.as_ref(),
-                    &self.precursor_id,
-                    self.peak_reader.scan_fwhm(),
-                    self.monoisotopic_only,
-                    self.highest_charge_state_only,
-                    &self.charges,
+        }
+        Some(spectral_chunk)
+    }
+
+    /// Extract the spectra of a single MS2 frame, using the precursors of
+    /// the MS1 frame at `ms1_frame_index`.
+    pub fn get_spectra_from_ms2_frame(
+        &self,
+        ms1_frame_index: usize,
+        ms2_frame_index: usize,
+    ) -> Vec<timsrust_core::Spectrum> {
+        let Some(precursors) = self.get_precursors_from_frame(ms1_frame_index)
+        else {
+            return vec![];
+        };
+        let Some(ms2_peaks) = self.get_ms2_peaks(ms2_frame_index) else {
+            return vec![];
+        };
+        let ms1_cycle = self.get_ms1_cycle_frames(ms1_frame_index);
+        self.create_spectra(
+            &ms2_peaks,
+            &precursors,
+            ms1_frame_index,
+            ms2_frame_index,
+            ms1_cycle.as_ref(),
+        )
+    }
+
+    fn create_spectra(
+        &self,
+        ms2_peaks: &[Peak],
+        precursors: &[timsrust_core::Precursor],
+        ms1_frame_index: usize,
+        ms2_frame_index: usize,
+        ms1_cycle: Option<&CycleFrames>,
+    ) -> Vec<timsrust_core::Spectrum> {
+        let correlation = self.correlation(
+            precursors,
+            ms1_frame_index,
+            ms2_frame_index,
+            ms1_cycle,
+        );
+        create_spectra_from_ms2_peaks(
+            ms2_peaks,
+            precursors,
+            self.peak_reader.scan_fwhm(),
+            self.min_spectrum_size,
+            self.quadrupole_settings(ms2_frame_index).as_ref(),
+            ms2_frame_index,
+            correlation.as_ref(),
+        )
+    }
+
+    /// Recreate the spectrum at `location` from its cached precursor.
+    ///
+    /// The MS2 peaks of the most recently used frames are cached, so that
+    /// the spectra of a frame share a single centroiding pass.
+    fn get_spectrum(
+        &self,
+        location: &SpectrumLocation,
+        precursor: &Precursor,
+    ) -> Option<timsrust_core::Spectrum> {
+        let ms2_peaks = self
+            .ms2_peak_cache
+            .get_or_insert_with(location.frame_index, || {
+                self.get_ms2_peaks(location.frame_index)
+            })?;
+        let precursors = std::slice::from_ref(precursor);
+        let (_, lower_id, upper_id, scan) =
+            split_peaks(&ms2_peaks, self.peak_reader.scan_fwhm(), precursors)
+                .next()?;
+        let ms1_cycle =
+            self.get_ms1_cycle_frames(location.precursor_frame_index);
+        let correlation = self.correlation(
+            precursors,
+            location.precursor_frame_index,
+            location.frame_index,
+            ms1_cycle.as_ref(),
+        );
+        to_spectrum(
+            precursor,
+            &ms2_peaks[lower_id..upper_id],
+            self.quadrupole_settings(location.frame_index).as_ref(),
+            self.min_spectrum_size,
+            scan,
+            location.spectrum_id(),
+            correlation.as_ref().map(|(correlator, precursor_tofs)| {
+                (correlator, precursor_tofs[0])
+            }),
+        )
+    }
+
+    fn quadrupole_settings(
+        &self,
+        ms2_frame_index: usize,
+    ) -> Arc<timsrust_core::QuadrupoleSettings> {
+        self.peak_reader
+            .frame_reader()
+            .get_partial_frame_without_ions(ms2_frame_index)
+            .expect("Known to exist")
+            .info()
+            .quadrupole_settings()
+            .clone()
+    }
+
+    /// The elution correlator of an MS2 frame and the TOF index of every
+    /// precursor, if fragments are filtered on elution correlation.
+    fn correlation<'a>(
+        &'a self,
+        precursors: &[timsrust_core::Precursor],
+        ms1_frame_index: usize,
+        ms2_frame_index: usize,
+        ms1_cycle: Option<&'a CycleFrames>,
+    ) -> Option<Correlation<'a>> {
+        self.elution_correlation.as_ref().zip(ms1_cycle).map(
+            |(params, ms1_cycle)| {
+                let ms2_cycle = self.get_cycle_frames(
+                    ms1_frame_index,
+                    ms2_frame_index,
+                    params.cycles,
                 );
-                if precursors.is_empty() {
-                    return None;
-                }
-                let mut spectral_chunk = SpectralChunk {
-                    peaks: FxHashMap::default(),
-                    precursors,
-                };
-                for ms2_frame_index in find_ms2_frames(
-                    index,
-                    self.peak_reader.frame_reader().info_reader(),
-                ) {
-                    if let Ok(mut ms2_peaks) =
-                        self.peak_reader.get_peaks_from_frame(ms2_frame_index)
+                let correlator = ElutionCorrelator::new(
+                    ms1_cycle,
+                    ms2_cycle,
+                    self.peak_reader.scan_fwhm() as u32,
+                    params,
+                );
+                let precursor_tofs = precursors
+                    .iter()
+                    .map(|precursor| {
+                        mz_to_tof(
+                            self.mz_converter.as_ref(),
+                            f64::from(precursor.mz()),
+                            ms1_cycle.max_tof(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                (correlator, precursor_tofs)
+            },
+        )
+    }
+
+    /// The indices of all MS1 frames, in order.
+    fn ms1_frames(&self) -> &[usize] {
+        self.ms1_frames.get_or_init(|| {
+            let info_reader = self.peak_reader.frame_reader().info_reader();
+            self.peak_reader
+                .frame_indices()
+                .iter()
+                .copied()
+                .filter(|&index| {
+                    info_reader.get(index).is_ok_and(|info| {
+                        info.ms_level() == timsrust_core::MSLevel::MS1
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// The raw MS1 frames of the cycles around `ms1_frame_index`, if
+    /// fragments are filtered on elution correlation.
+    fn get_ms1_cycle_frames(
+        &self,
+        ms1_frame_index: usize,
+    ) -> Option<CycleFrames> {
+        self.elution_correlation.as_ref().map(|params| {
+            self.get_cycle_frames(
+                ms1_frame_index,
+                ms1_frame_index,
+                params.cycles,
+            )
+        })
+    }
+
+    /// The raw frames at the same position as `frame_index` in the `cycles`
+    /// cycles before and after the cycle starting at `ms1_frame_index`.
+    ///
+    /// MS2 frames of other cycles are only used if they have the same
+    /// quadrupole settings.
+    fn get_cycle_frames(
+        &self,
+        ms1_frame_index: usize,
+        frame_index: usize,
+        cycles: usize,
+    ) -> CycleFrames {
+        let ms1_frames = self.ms1_frames();
+        let Ok(position) = ms1_frames.binary_search(&ms1_frame_index) else {
+            return CycleFrames::new(vec![None], 0);
+        };
+        let info_reader = self.peak_reader.frame_reader().info_reader();
+        let quadrupole_index = info_reader
+            .get(frame_index)
+            .ok()
+            .map(|info| info.quadrupole_settings().index);
+        let offset = frame_index - ms1_frame_index;
+        let start = position.saturating_sub(cycles);
+        let end = (position + cycles + 1).min(ms1_frames.len());
+        let frames = (start..end)
+            .map(|cycle| {
+                let index = ms1_frames[cycle] + offset;
+                if offset > 0 {
+                    let cycle_end = ms1_frames
+                        .get(cycle + 1)
+                        .copied()
+                        .unwrap_or(usize::MAX);
+                    let info = info_reader.get(index).ok()?;
+                    if index >= cycle_end
+                        || Some(info.quadrupole_settings().index)
+                            != quadrupole_index
                     {
-                        if ms2_peaks.is_empty() {
-                            continue;
-                        }
-                        ms2_peaks.sort_by_key(|a| a.scan);
-                        spectral_chunk.peaks.insert(ms2_frame_index, ms2_peaks);
+                        return None;
                     }
                 }
-                return Some(spectral_chunk);
-            }
-        }
-        None
+                self.frame_cache(cycles).get_or_insert_with(index, || {
+                    self.peak_reader.get_transposed_tofs(index).ok()
+                })
+            })
+            .collect();
+        CycleFrames::new(frames, position - start)
+    }
+
+    /// A cache that holds the frames of twice `2 * cycles + 1` cycles.
+    fn frame_cache(&self, cycles: usize) -> &FrameCache {
+        self.frame_cache.get_or_init(|| {
+            let ms1_frames = self.ms1_frames();
+            let cycle_length = ms1_frames
+                .windows(2)
+                .map(|window| window[1] - window[0])
+                .max()
+                .unwrap_or(1);
+            FrameCache::new(2 * (2 * cycles + 1) * cycle_length)
+        })
     }
 
     pub fn get_spectra_from_frame(
@@ -148,40 +448,83 @@ impl<
         index: usize,
     ) -> Vec<timsrust_core::Spectrum> {
         match self.get_spectral_chunk_from_frame(index) {
-            Some(chunk) => chunk
-                .peaks
-                .iter()
-                .flat_map(|(ms2_frame_index, ms2_peaks)| {
-                    create_spectra_from_ms2_peaks(
-                        ms2_peaks,
-                        &chunk.precursors,
-                        self.peak_reader.scan_fwhm(),
-                        &self.spectrum_id,
-                        self.min_spectrum_size,
-                        self.peak_reader
-                            .frame_reader()
-                            .get_partial_frame_without_ions(*ms2_frame_index)
-                            .expect("Known to exist")
-                            .info()
-                            .quadrupole_settings(),
-                        *ms2_frame_index,
-                    )
-                })
-                .collect(),
+            Some(chunk) => {
+                let mut ms2_frame_indices: Vec<usize> =
+                    chunk.peaks.keys().copied().collect();
+                ms2_frame_indices.sort_unstable();
+                let ms1_cycle = self.get_ms1_cycle_frames(index);
+                ms2_frame_indices
+                    .into_iter()
+                    .flat_map(|ms2_frame_index| {
+                        self.create_spectra(
+                            &chunk.peaks[&ms2_frame_index],
+                            &chunk.precursors,
+                            index,
+                            ms2_frame_index,
+                            ms1_cycle.as_ref(),
+                        )
+                    })
+                    .collect()
+            },
             None => vec![],
         }
     }
 
+    /// The location of every spectrum, ordered by spectrum id.
+    ///
+    /// Computed on first use by processing all frames once.
+    pub fn spectrum_index(&self) -> &[SpectrumLocation] {
+        &self.index().locations
+    }
+
+    fn index(&self) -> &SpectrumIndex {
+        self.spectrum_index.get_or_init(|| {
+            let (locations, precursors) = build_spectrum_index(
+                self.peak_reader.frame_indices(),
+                |index| self.get_spectra_from_frame(index),
+            )
+            .into_iter()
+            .map(|(location, precursor)| {
+                (location, precursor.expect("Spectra have a precursor"))
+            })
+            .unzip();
+            SpectrumIndex {
+                locations,
+                precursors,
+            }
+        })
+    }
+
+    /// Random access to the spectrum at position `index` of
+    /// [`Self::spectrum_index`].
+    ///
+    /// Only the MS2 peaks of a few recent frames are cached, so reading the
+    /// spectra out of frame order centroids frames repeatedly. Prefer
+    /// [`Self::get_spectra_from_frame`] for sequential exports.
+    pub fn get(
+        &self,
+        index: usize,
+    ) -> Result<timsrust_core::Spectrum, TimsCentroidError> {
+        let spectrum_index = self.index();
+        let location = spectrum_index
+            .locations
+            .get(index)
+            .ok_or_else(|| TimsCentroidError::no_spectrum_at(index))?;
+        self.get_spectrum(location, &spectrum_index.precursors[index])
+            .ok_or_else(|| TimsCentroidError::no_spectrum_at(index))
+    }
+
     pub fn _par_iter(
         &self,
     ) -> impl ParallelIterator<Item = timsrust_core::Spectrum> + '_ {
-        (0..self.frame_count())
-            .into_par_iter()
-            .flat_map(|index| self.get_spectra_from_frame(index))
+        self.peak_reader
+            .frame_indices()
+            .par_iter()
+            .flat_map(|&index| self.get_spectra_from_frame(index))
     }
 
     pub(crate) fn len(&self) -> usize {
-        self.spectrum_id.load(atomic::Ordering::Relaxed)
+        self.spectrum_index().len()
     }
 
     pub fn scan_fwhm(&self) -> usize {
@@ -196,8 +539,8 @@ impl<
         self.peak_reader.frame_count()
     }
 
-    pub fn spectrum_id(&self) -> &atomic::AtomicUsize {
-        &self.spectrum_id
+    pub fn frame_indices(&self) -> &[usize] {
+        self.peak_reader.frame_indices()
     }
 }
 
@@ -236,70 +579,50 @@ fn deisotope(
     frame: &timsrust_core::Frame,
     im_converter: impl Converter<ScanIndex, Im>,
     mz_converter: impl Converter<TofIndex, Mz>,
-    precursor_id: &atomic::AtomicUsize,
     scan_fwhm: usize,
     try_monoisotopic_only: bool,
     highest_charge_state_only: bool,
     charges: &[u8],
+    strategy: &DeisotopingStrategy,
 ) -> Vec<timsrust_core::Precursor> {
+    // Deterministic precursor ids: the MS1 frame index in the high bits and
+    // the position in (scan, tof) order in the low bits.
     let mut id = frame.info().index() << 32;
-    peaks.sort_by_key(|p| p.scan);
-    // const PROTON_MASS: f64 = 1.007276466812;
-    const ISOTOPE_MASS: f64 = 1.0033548378;
-    const MAX_DELTA_MZ: f64 = 0.01;
+    peaks.sort_by_key(|p| (p.scan, p.tof));
     let mut result = vec![];
     let mzs = peaks
         .iter()
-        .map(|p| mz_converter.convert(TofIndex::try_from(p.tof).unwrap()))
+        .map(|p| {
+            f64::from(mz_converter.convert(TofIndex::try_from(p.tof).unwrap()))
+        })
         .collect::<Vec<_>>();
-    let mut lower = 0;
-    let mut upper = 0;
+    let scorer = IsotopeScorer::new(
+        mzs.clone(),
+        peaks.iter().map(|p| p.scan).collect(),
+        peaks.iter().map(|p| p.apex_intensity as f64).collect(),
+        scan_fwhm as u32 / 2,
+    );
     for (i, peak) in peaks.iter().enumerate() {
-        while (lower < peaks.len())
-            && (peaks[lower].scan
-                < (peak.scan.saturating_sub(scan_fwhm as u32 / 2)))
-        {
-            lower += 1;
-        }
-        while (upper < peaks.len())
-            && (peaks[upper].scan < (peak.scan + scan_fwhm as u32 / 2))
-        {
-            upper += 1;
-        }
-        let mz = f64::from(mzs[i]);
-        for charge in charges.iter().rev() {
-            if try_monoisotopic_only {
-                let previous_isotope_mz = mz - ISOTOPE_MASS / *charge as f64;
-                if mzs[lower..upper].iter().any(|&x| {
-                    (f64::from(x) - previous_isotope_mz).abs() < MAX_DELTA_MZ
-                }) {
-                    continue;
-                }
-            }
-            let next_isotope_mz = mz + ISOTOPE_MASS / *charge as f64;
-            if mzs[lower..upper]
-                .iter()
-                .any(|&x| (f64::from(x) - next_isotope_mz).abs() < MAX_DELTA_MZ)
-            {
-                id += 1;
-                // found isotope peak
-                _ = precursor_id.fetch_add(1, atomic::Ordering::Relaxed);
-                let scan = ScanIndex::try_from(peak.scan).unwrap();
-                let precursor = timsrust_core::Precursor::new(
-                    Mz::from(mz as f32),
-                    im_converter.convert(scan),
-                    Rt::from(frame.info().rt_in_seconds()),
-                    scan,
-                    Some(Charge::try_from(*charge as usize).unwrap()),
-                    Some(peak.apex_intensity as f64),
-                    id,
-                    FrameIndex::try_from(frame.index() as u32).unwrap(),
-                );
-                result.push(precursor);
-                if highest_charge_state_only {
-                    break;
-                }
-            }
+        for charge in scorer.monoisotopic_charges(
+            i,
+            charges,
+            strategy,
+            try_monoisotopic_only,
+            highest_charge_state_only,
+        ) {
+            id += 1;
+            let scan = ScanIndex::try_from(peak.scan).unwrap();
+            let precursor = timsrust_core::Precursor::new(
+                Mz::from(mzs[i] as f32),
+                im_converter.convert(scan),
+                Rt::from(frame.info().rt_in_seconds()),
+                scan,
+                Some(Charge::try_from(charge as usize).unwrap()),
+                Some(peak.apex_intensity as f64),
+                id,
+                FrameIndex::try_from(frame.index() as u32).unwrap(),
+            );
+            result.push(precursor);
         }
     }
     result
@@ -321,14 +644,17 @@ fn find_ms2_frames(
     })
 }
 
+/// An [`ElutionCorrelator`] with the TOF index of every precursor.
+type Correlation<'a> = (ElutionCorrelator<'a>, Vec<u32>);
+
 fn create_spectra_from_ms2_peaks(
     peaks: &[Peak],
     precursors: &[timsrust_core::Precursor],
     scan_fwhm: usize,
-    spectrum_id: &atomic::AtomicUsize,
     min_spectrum_size: usize,
     quadrupole_settings: &timsrust_core::QuadrupoleSettings,
     ms2_frame_index: usize,
+    correlation: Option<&Correlation>,
 ) -> Vec<timsrust_core::Spectrum> {
     assert!(precursors.is_sorted_by(|a, b| a.scan_index() <= b.scan_index()));
     assert!(peaks.is_sorted_by(|a, b| a.scan <= b.scan));
@@ -341,22 +667,19 @@ fn create_spectra_from_ms2_peaks(
     split_peaks(peaks, scan_fwhm, precursors)
         .enumerate()
         .map(|(local_index, (_precursor_id, lower_id, upper_id, scan))| {
-            (
-                local_index,
-                precursors[_precursor_id].clone(),
-                &peaks[lower_id..upper_id],
-                scan,
-            )
+            (local_index, _precursor_id, &peaks[lower_id..upper_id], scan)
         })
-        .filter_map(|(local_index, precursor, subpeaks, scan)| {
+        .filter_map(|(local_index, precursor_id, subpeaks, scan)| {
             to_spectrum(
-                &precursor,
+                &precursors[precursor_id],
                 subpeaks,
                 quadrupole_settings,
                 min_spectrum_size,
                 scan,
-                spectrum_id,
                 base_index + local_index,
+                correlation.map(|(correlator, precursor_tofs)| {
+                    (correlator, precursor_tofs[precursor_id])
+                }),
             )
         })
         .collect()
@@ -368,8 +691,8 @@ fn to_spectrum(
     quadrupole_settings: &timsrust_core::QuadrupoleSettings,
     min_spectrum_size: usize,
     scan: usize,
-    spectrum_id: &atomic::AtomicUsize,
     index: usize,
+    correlation: Option<(&ElutionCorrelator, u32)>,
 ) -> Option<timsrust_core::Spectrum> {
     if subpeaks.len() < min_spectrum_size {
         return None;
@@ -380,13 +703,31 @@ fn to_spectrum(
     }
     let mut subpeaks = subpeaks.to_vec();
     subpeaks.sort_by_key(|a| a.tof);
+    let mut correlations = None;
+    if let Some((correlator, precursor_tof)) = correlation {
+        let profiles = correlator.precursor_profiles(
+            precursor_tof,
+            usize::from(precursor.scan_index()) as u32,
+        );
+        let min_score = correlator.params().min_score;
+        let (kept, scores): (Vec<Peak>, Vec<f32>) = subpeaks
+            .into_iter()
+            .map(|peak| {
+                let score = correlator.score(&profiles, &peak);
+                (peak, score)
+            })
+            .filter(|&(_, score)| score >= min_score)
+            .unzip();
+        if kept.len() < min_spectrum_size {
+            return None;
+        }
+        subpeaks = kept;
+        correlations = Some(scores);
+    }
     let intensity_values = subpeaks
         .iter()
         .map(|p| p.apex_intensity as f32)
         .collect::<Vec<_>>();
-    // Keep the atomic only for `len()`; the spectrum index itself is the
-    // deterministic `index` derived from the MS2 frame (see caller).
-    _ = spectrum_id.fetch_add(1, atomic::Ordering::Relaxed);
     let isolation_window = timsrust_core::IsolationWindow::new_from_center(
         Mz::from(quad_info.isolation_mz),
         Mz::from(quad_info.isolation_width),
@@ -401,7 +742,13 @@ fn to_spectrum(
             .map(|p| TofIndex::try_from(p.tof).unwrap())
             .collect(),
         isolation_window,
-    );
+    )
+    .with_peak_shapes(subpeaks.iter().map(Peak::shape).collect())
+    .expect("One shape per peak");
+    let spectrum = match correlations {
+        Some(correlations) => spectrum.with_correlations(correlations),
+        None => spectrum,
+    };
     // let spectrum = timsrust_core::Spectrum {
     //     tof_indices: subpeaks
     //         .iter()
@@ -483,3 +830,99 @@ pub fn split_peaks<'a>(
     }
     results.into_iter()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        FRAGMENT_MZS, INTERFERENCE_MZ, PRECURSORS, TestImConverter,
+        TestInfoReader, TestIonReader, TestMzConverter, peak_reader,
+    };
+
+    fn reader(
+        cycles: usize,
+    ) -> NarrowSpectrumReader<
+        TestIonReader,
+        TestInfoReader,
+        TestImConverter,
+        TestMzConverter,
+    > {
+        NarrowSpectrumReader::new(
+            peak_reader(cycles),
+            5,
+            Arc::new(TestImConverter),
+            Arc::new(TestMzConverter),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn spectra() {
+        let reader = reader(2);
+        assert_eq!(reader.frame_indices(), &[1, 2, 3, 4, 5, 6]);
+        let mut spectra = reader._par_iter().collect::<Vec<_>>();
+        spectra.sort_by_key(|spectrum| spectrum.index());
+        assert_eq!(spectra.len(), 2 * PRECURSORS.len());
+        let locations = spectra
+            .iter()
+            .map(|spectrum| {
+                let precursor = spectrum.precursor().as_ref().unwrap();
+                (spectrum.index() >> 32, usize::from(precursor.frame_index()))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(locations, vec![(2, 1), (3, 1), (5, 4), (6, 4)]);
+        for (spectrum, &(mz, _, _)) in
+            spectra.iter().zip(PRECURSORS.iter().cycle())
+        {
+            let precursor = spectrum.precursor().as_ref().unwrap();
+            assert!((f64::from(precursor.mz()) - mz).abs() < 0.01);
+            assert_eq!(precursor.charge().map(i8::from), Some(2));
+            assert_eq!(spectrum.len(), 6);
+        }
+    }
+
+    #[test]
+    fn random_access() {
+        let reader = reader(3);
+        let mut spectra = reader._par_iter().collect::<Vec<_>>();
+        spectra.sort_by_key(|spectrum| spectrum.index());
+        assert_eq!(reader.len(), spectra.len());
+        for (index, spectrum) in spectra.iter().enumerate() {
+            assert_eq!(&reader.get(index).unwrap(), spectrum);
+        }
+        assert!(reader.get(spectra.len()).is_err());
+        // The peaks of recently read frames are not centroided again.
+        let location = reader.spectrum_index().last().unwrap();
+        assert!(
+            reader
+                .ms2_peak_cache
+                .get_or_insert_with(location.frame_index, || unreachable!())
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn elution_correlation() {
+        let cycles = 7;
+        let mut reader = reader(cycles);
+        reader.set_elution_correlation(Some(ElutionCorrelationParams {
+            cycles: 2,
+            ..Default::default()
+        }));
+        let mut spectra = reader._par_iter().collect::<Vec<_>>();
+        spectra.sort_by_key(|spectrum| spectrum.index());
+        assert_eq!(spectra.len(), cycles * PRECURSORS.len());
+        for (index, spectrum) in spectra.iter().enumerate() {
+            let mzs = spectrum
+                .mz_values(TestMzConverter)
+                .into_iter()
+                .map(f64::from)
+                .collect::<Vec<_>>();
+            assert_eq!(mzs.len(), FRAGMENT_MZS.len());
+            assert!(mzs.iter().all(|mz| (mz - INTERFERENCE_MZ).abs() > 1.0));
+            let correlations = spectrum.correlations().unwrap();
+            assert!(correlations.iter().all(|&score| score >= 0.5));
+            assert_eq!(&reader.get(index).unwrap(), spectrum);
+        }
+    }
+}
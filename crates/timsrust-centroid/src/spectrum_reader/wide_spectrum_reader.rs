@@ -1,6 +1,13 @@
-use crate::{Peak, PeakReader, TimsResult};
+use crate::{
+    Peak, PeakReader, TimsResult,
+    correlation::FrameCache,
+    spectrum_reader::{
+        RANDOM_ACCESS_FRAMES, SpectrumLocation, TimsCentroidError,
+        build_spectrum_index,
+    },
+};
 use rayon::prelude::*;
-use std::sync::{Arc, atomic};
+use std::sync::{Arc, OnceLock};
 use timsrust_core::{
     FrameIndex, Im, InvertibleConverter, Rt, ScanIndex, TofIndex,
 };
@@ -12,8 +19,9 @@ pub struct WideSpectrumReader<
 > {
     peak_reader: PeakReader<IonReader, InfoReader>,
     im_converter: Arc<ImConverter>,
-    spec_id: atomic::AtomicUsize,
+    spectrum_index: OnceLock<Vec<SpectrumLocation>>,
     min_spectrum_size: usize,
+    spectrum_cache: FrameCache<Vec<timsrust_core::Spectrum>>,
 }
 
 impl<
@@ -32,12 +40,12 @@ impl<
         min_spectrum_size: usize,
         im_converter: Arc<ImConverter>,
     ) -> TimsResult<Self> {
-        let spec_id = atomic::AtomicUsize::new(0);
         let result = Self {
             peak_reader,
             im_converter,
-            spec_id,
+            spectrum_index: OnceLock::new(),
             min_spectrum_size,
+            spectrum_cache: FrameCache::new(RANDOM_ACCESS_FRAMES),
         };
         Ok(result)
     }
@@ -61,7 +69,6 @@ impl<
                             &frame,
                             self.peak_reader.scan_fwhm(),
                             self.im_converter.as_ref(),
-                            &self.spec_id,
                             self.min_spectrum_size,
                         );
                         return spectra;
@@ -72,8 +79,51 @@ impl<
         vec![]
     }
 
+    /// The location of every spectrum, ordered by spectrum id.
+    ///
+    /// Computed on first use by processing all frames once.
+    pub fn spectrum_index(&self) -> &[SpectrumLocation] {
+        self.spectrum_index.get_or_init(|| {
+            build_spectrum_index(self.peak_reader.frame_indices(), |index| {
+                self.get_spectra_from_frame(index)
+            })
+            .into_iter()
+            .map(|(location, _)| location)
+            .collect()
+        })
+    }
+
+    /// Random access to the spectrum at position `index` of
+    /// [`Self::spectrum_index`].
+    ///
+    /// The spectra of a few recent frames are cached, so reading them out
+    /// of frame order centroids frames repeatedly. Prefer
+    /// [`Self::get_spectra_from_frame`] for sequential exports.
+    pub fn get(
+        &self,
+        index: usize,
+    ) -> Result<timsrust_core::Spectrum, TimsCentroidError> {
+        let location = self
+            .spectrum_index()
+            .get(index)
+            .ok_or_else(|| TimsCentroidError::no_spectrum_at(index))?;
+        let spectra = self
+            .spectrum_cache
+            .get_or_insert_with(location.frame_index, || {
+                Some(self.get_spectra_from_frame(location.frame_index))
+            })
+            .expect("Spectra are always computed");
+        // Spectrum ids increase with the window within a frame.
+        spectra
+            .binary_search_by_key(&location.spectrum_id(), |spectrum| {
+                spectrum.index()
+            })
+            .map(|position| spectra[position].clone())
+            .map_err(|_| TimsCentroidError::no_spectrum_at(index))
+    }
+
     pub fn len(&self) -> usize {
-        self.spec_id.load(atomic::Ordering::Relaxed)
+        self.spectrum_index().len()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -92,12 +142,17 @@ impl<
         self.peak_reader.frame_count()
     }
 
+    pub fn frame_indices(&self) -> &[usize] {
+        self.peak_reader.frame_indices()
+    }
+
     pub fn _par_iter(
         &self,
     ) -> impl ParallelIterator<Item = timsrust_core::Spectrum> + '_ {
-        (0..self.frame_count())
-            .into_par_iter()
-            .flat_map(|index| self.get_spectra_from_frame(index))
+        self.peak_reader
+            .frame_indices()
+            .par_iter()
+            .flat_map(|&index| self.get_spectra_from_frame(index))
     }
 }
 
@@ -106,13 +161,15 @@ fn peaks_to_spectra(
     frame: &timsrust_core::Frame,
     scan_fwhm: usize,
     im_converter: impl InvertibleConverter<ScanIndex, Im>,
-    spec_id: &atomic::AtomicUsize,
     min_spectrum_size: usize,
 ) -> Vec<timsrust_core::Spectrum> {
-    let mut id = frame.info().index() << 32;
-    peaks.sort_by_key(|a| a.scan);
+    // Deterministic spectrum ids: the frame index in the high bits and the
+    // (1-based) scan window in the low bits.
+    let base_index = frame.info().index() << 32;
+    peaks.sort_by_key(|a| (a.scan, a.tof));
     split_peaks(&peaks, scan_fwhm)
-        .filter_map(|(subpeaks, scan)| {
+        .enumerate()
+        .filter_map(|(window, (subpeaks, scan))| {
             if subpeaks.len() < min_spectrum_size {
                 return None;
             }
@@ -137,8 +194,7 @@ fn peaks_to_spectra(
                 .iter()
                 .map(|p| p.apex_intensity as f32)
                 .collect::<Vec<_>>();
-            id += 1;
-            _ = spec_id.fetch_add(1, atomic::Ordering::Relaxed);
+            let id = base_index + window + 1;
             let scan = ScanIndex::try_from(scan as u32).unwrap();
             let precursor = timsrust_core::Precursor::new(
                 isolation_mz,
@@ -166,7 +222,9 @@ fn peaks_to_spectra(
                     .map(|p| TofIndex::try_from(p.tof).unwrap())
                     .collect(),
                 isolation_window,
-            );
+            )
+            .with_peak_shapes(subpeaks.iter().map(Peak::shape).collect())
+            .expect("One shape per peak");
             // let spectrum = timsrust_core::Spectrum {
             //     // mz_values: mz_values.into_iter().map(|x| x.into()).collect(),
             //     tof_indices: subpeaks
@@ -227,6 +285,7 @@ fn split_peaks(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{TestImConverter, peak_reader};
 
     fn dummy_peak(scan: u32, tof: u32, intensity: u32) -> Peak {
         Peak {
@@ -252,6 +311,37 @@ mod tests {
         assert_eq!(total, 2 * peaks.len());
     }
 
+    #[test]
+    fn random_access() {
+        let reader = WideSpectrumReader::new(
+            peak_reader(2),
+            1,
+            Arc::new(TestImConverter),
+        )
+        .unwrap();
+        let mut spectra = reader._par_iter().collect::<Vec<_>>();
+        spectra.sort_by_key(|spectrum| spectrum.index());
+        assert!(!spectra.is_empty());
+        assert_eq!(reader.len(), spectra.len());
+        for (index, spectrum) in spectra.iter().enumerate() {
+            assert_eq!(&reader.get(index).unwrap(), spectrum);
+        }
+        let frames = reader
+            .spectrum_index()
+            .iter()
+            .map(|location| location.frame_index)
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(frames.into_iter().collect::<Vec<_>>(), vec![2, 3, 5, 6]);
+        // The spectra of recently read frames are not centroided again.
+        for frame_index in [2, 3, 5, 6] {
+            let cached = reader
+                .spectrum_cache
+                .get_or_insert_with(frame_index, || unreachable!())
+                .unwrap();
+            assert_eq!(*cached, reader.get_spectra_from_frame(frame_index));
+        }
+    }
+
     // #[test]
     // fn test_peaks_to_spectra_empty() {
     //     // Use dummy converters and frame
@@ -260,14 +350,12 @@ mod tests {
     //     let frame = Frame::default();
     //     let im_converter = unsafe { std::mem::zeroed() };
     //     let mz_converter = unsafe { std::mem::zeroed() };
-    //     let spec_id = AtomicUsize::new(0);
     //     let spectra = peaks_to_spectra(
     //         peaks,
     //         &frame,
     //         4,
     //         im_converter,
     //         mz_converter,
-    //         &spec_id,
     //         2,
     //     );
     //     assert!(spectra.is_empty());
@@ -0,0 +1,230 @@
+//! Builds small synthetic DIA runs in memory for tests.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use timsrust_core::{
+    AcquisitionType, Converter, FrameInfo, FrameIons, FrameReader, Im,
+    IntensityIndex, IsolationWindow, MSLevel, Mz, QuadrupoleSettings,
+    ScanIndex, TofIndex,
+    utils::reader::{IndexedReader, Reader},
+};
+
+use crate::{
+    PeakReader,
+    kernels::{CentroidingKernels, KernelConfig, KernelSource},
+};
+
+pub(crate) const SCAN_COUNT: usize = 40;
+/// The precursors as `(mz, scan, isolation window center)`, one per MS2
+/// frame of a cycle.
+pub(crate) const PRECURSORS: [(f64, usize, f64); 2] =
+    [(500.0, 15, 500.0), (700.0, 25, 700.0)];
+/// Fragments that co-elute with their precursor.
+pub(crate) const FRAGMENT_MZS: [f64; 5] = [420.0, 450.0, 480.0, 520.0, 560.0];
+/// A fragment per MS2 frame whose elution profile is inverted.
+pub(crate) const INTERFERENCE_MZ: f64 = 600.0;
+
+#[derive(Debug, thiserror::Error)]
+#[error("No frame {0}")]
+pub(crate) struct MissingFrame(usize);
+
+#[derive(Debug)]
+pub(crate) struct TestIonReader(BTreeMap<usize, FrameIons>);
+
+impl Reader<FrameIons> for TestIonReader {
+    type Error = MissingFrame;
+
+    fn get(&self, index: usize) -> Result<FrameIons, Self::Error> {
+        self.0.get(&index).cloned().ok_or(MissingFrame(index))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct TestInfoReader(BTreeMap<usize, FrameInfo>);
+
+impl Reader<FrameInfo> for TestInfoReader {
+    type Error = MissingFrame;
+
+    fn get(&self, index: usize) -> Result<FrameInfo, Self::Error> {
+        self.0.get(&index).cloned().ok_or(MissingFrame(index))
+    }
+}
+
+impl IndexedReader<FrameInfo> for TestInfoReader {
+    type Iter = std::vec::IntoIter<usize>;
+
+    fn iter(&self) -> Self::Iter {
+        self.0.keys().copied().collect::<Vec<_>>().into_iter()
+    }
+}
+
+pub(crate) type TestPeakReader = PeakReader<TestIonReader, TestInfoReader>;
+
+/// `mz = 400 + 0.01 * tof`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TestMzConverter;
+
+impl Converter<TofIndex, Mz> for TestMzConverter {
+    fn convert(&self, value: TofIndex) -> Mz {
+        Mz::from(400.0 + 0.01 * f64::from(u32::from(value)))
+    }
+}
+
+pub(crate) fn mz_to_tof(mz: f64) -> usize {
+    ((mz - 400.0) / 0.01).round() as usize
+}
+
+/// `im = 1.5 - 0.01 * scan`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TestImConverter;
+
+impl Converter<ScanIndex, Im> for TestImConverter {
+    fn convert(&self, value: ScanIndex) -> Im {
+        Im::from(1.5 - 0.01 * f64::from(u32::from(value)))
+    }
+}
+
+impl Converter<Im, ScanIndex> for TestImConverter {
+    fn convert(&self, value: Im) -> ScanIndex {
+        let scan = ((1.5 - f64::from(value)) / 0.01).round().max(0.0);
+        ScanIndex::try_from(scan as u32).unwrap()
+    }
+}
+
+/// The elution profile of the precursors over `cycles` cycles.
+fn elution(cycle: usize, cycles: usize) -> f64 {
+    let center = (cycles as f64 - 1.0) / 2.0;
+    let distance = (cycle as f64 - center) / cycles as f64;
+    (-8.0 * distance * distance).exp()
+}
+
+/// A DIA run of `cycles` cycles of an MS1 frame followed by two MS2 frames,
+/// with one-based frame indices.
+///
+/// Every MS1 frame contains the isotope envelopes of [`PRECURSORS`] and
+/// every MS2 frame the [`FRAGMENT_MZS`] of its precursor.
+pub(crate) fn frame_reader(
+    cycles: usize,
+) -> FrameReader<TestIonReader, TestInfoReader> {
+    let quadrupoles = PRECURSORS
+        .iter()
+        .enumerate()
+        .map(|(index, &(_, _, center))| {
+            Arc::new(QuadrupoleSettings {
+                index: index + 1,
+                scan_starts: vec![0],
+                scan_ends: vec![SCAN_COUNT],
+                isolation_windows: vec![IsolationWindow::new_from_center(
+                    Mz::from(center),
+                    Mz::from(50.0),
+                    30.0,
+                )],
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut ions = BTreeMap::new();
+    let mut infos = BTreeMap::new();
+    for cycle in 0..cycles {
+        let abundance = elution(cycle, cycles);
+        let index = cycle * 3 + 1;
+        let mut peaks = vec![];
+        for &(mz, scan, _) in &PRECURSORS {
+            for (isotope, ratio) in [1.0, 0.55, 0.15].into_iter().enumerate() {
+                let mz = mz + isotope as f64 * 1.0033548378 / 2.0;
+                peaks.push((scan, mz_to_tof(mz), 20000.0 * abundance * ratio));
+            }
+        }
+        ions.insert(index, frame_ions(&peaks));
+        infos.insert(
+            index,
+            frame_info(index, MSLevel::MS1, Default::default(), cycle),
+        );
+        for (window, &(_, scan, _)) in PRECURSORS.iter().enumerate() {
+            let index = index + window + 1;
+            let mut peaks = FRAGMENT_MZS
+                .iter()
+                .map(|&mz| (scan, mz_to_tof(mz), 5000.0 * abundance))
+                .collect::<Vec<_>>();
+            peaks.push((
+                scan,
+                mz_to_tof(INTERFERENCE_MZ),
+                5000.0 * (1.5 - abundance),
+            ));
+            ions.insert(index, frame_ions(&peaks));
+            infos.insert(
+                index,
+                frame_info(
+                    index,
+                    MSLevel::MS2,
+                    quadrupoles[window].clone(),
+                    cycle,
+                ),
+            );
+        }
+    }
+    FrameReader::new(TestIonReader(ions), TestInfoReader(infos))
+}
+
+fn frame_info(
+    index: usize,
+    ms_level: MSLevel,
+    quadrupole_settings: Arc<QuadrupoleSettings>,
+    cycle: usize,
+) -> FrameInfo {
+    let window_group = quadrupole_settings.index as u8;
+    FrameInfo::new(
+        quadrupole_settings,
+        index,
+        index as f64,
+        1.0,
+        AcquisitionType::DIAPASEF,
+        ms_level,
+        window_group,
+        Some(cycle),
+    )
+}
+
+/// Ions of peaks `(scan, tof, apex_intensity)`, each spread over the
+/// neighbouring scans and TOF indices.
+fn frame_ions(peaks: &[(usize, usize, f64)]) -> FrameIons {
+    let mut scans = vec![BTreeMap::new(); SCAN_COUNT];
+    for &(scan, tof, intensity) in peaks {
+        for scan_offset in [-1, 0, 1_isize] {
+            for tof_offset in [-1, 0, 1_isize] {
+                let weight =
+                    0.5_f64.powi((scan_offset.abs() + tof_offset.abs()) as i32);
+                let value = (intensity * weight).round().max(1.0) as u32;
+                *scans[scan.checked_add_signed(scan_offset).unwrap()]
+                    .entry(tof.checked_add_signed(tof_offset).unwrap())
+                    .or_insert(0) += value;
+            }
+        }
+    }
+    let mut scan_offsets = vec![0];
+    let mut tof_indices = vec![];
+    let mut intensities = vec![];
+    for scan in scans {
+        for (tof, intensity) in scan {
+            tof_indices.push(TofIndex::try_from(tof).unwrap());
+            intensities.push(IntensityIndex::try_from(intensity).unwrap());
+        }
+        scan_offsets.push(tof_indices.len());
+    }
+    FrameIons::new(scan_offsets, tof_indices, intensities)
+}
+
+/// Small fixed kernels, as the runs have too few peaks to learn them.
+pub(crate) fn kernels() -> CentroidingKernels {
+    CentroidingKernels {
+        tof: vec![0.5, 1.0, 0.5],
+        scan: vec![0.5, 1.0, 0.5],
+    }
+}
+
+pub(crate) fn peak_reader(cycles: usize) -> TestPeakReader {
+    let config = KernelConfig {
+        ms1: KernelSource::Explicit(kernels()),
+        ms2: None,
+    };
+    PeakReader::with_kernels(frame_reader(cycles), 1.0, 1.0, config).unwrap()
+}
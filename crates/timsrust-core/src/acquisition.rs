@@ -1,5 +1,6 @@
 /// The kind of acquisition that was used.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AcquisitionType {
     DDAPASEF,
     DIAPASEF,
@@ -80,3 +80,39 @@ macro_rules! bit_conversion {
 bit_conversion!(TofIndex, Mz);
 bit_conversion!(ScanIndex, Im);
 bit_conversion!(FrameIndex, Rt);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_bounds() {
+        assert_eq!(u32::from(ScanIndex::try_from(0u32).unwrap()), 0);
+        let max = u32::MAX - 1;
+        assert_eq!(u32::from(TofIndex::try_from(max).unwrap()), max);
+        assert!(TofIndex::try_from(u32::MAX).is_err());
+        assert!(FrameIndex::try_from(u64::from(u32::MAX)).is_err());
+        assert!(FrameIndex::try_from(-1i32).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let index = FrameIndex::try_from(7u32).unwrap();
+        assert_eq!(serde_json::to_string(&index).unwrap(), "7");
+        let read: FrameIndex = serde_json::from_str("7").unwrap();
+        assert_eq!(read, index);
+        for invalid in ["-1", "4294967295", "4294967296", "1.5"] {
+            assert!(serde_json::from_str::<ScanIndex>(invalid).is_err());
+        }
+        let charge = Charge::try_from(-3i8).unwrap();
+        assert_eq!(serde_json::to_string(&charge).unwrap(), "-3");
+        let read: Charge = serde_json::from_str("-3").unwrap();
+        assert_eq!(read, charge);
+        for invalid in ["0", "128", "\"2\""] {
+            assert!(serde_json::from_str::<Charge>(invalid).is_err());
+        }
+        let mz: Mz = serde_json::from_str("500.25").unwrap();
+        assert_eq!(mz, Mz::from(500.25));
+    }
+}
@@ -1,6 +1,11 @@
 use crate::CoordinateError;
 
 #[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "i8", into = "i8")
+)]
 pub struct Charge(std::num::NonZeroI8);
 
 impl std::fmt::Display for Charge {
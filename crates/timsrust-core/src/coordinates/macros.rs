@@ -3,6 +3,11 @@ macro_rules! value_dimension {
         $value:ident
     ) => {
         #[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
+        #[cfg_attr(
+            feature = "serde",
+            derive(serde::Serialize, serde::Deserialize),
+            serde(transparent)
+        )]
         pub struct $value(f64);
 
         impl Eq for $value {}
@@ -43,7 +48,13 @@ macro_rules! index_dimension {
     (
         $index:ident
     ) => {
+        // Serialized as the zero-based index, i.e. as `u32::from`.
         #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+        #[cfg_attr(
+            feature = "serde",
+            derive(serde::Serialize, serde::Deserialize),
+            serde(try_from = "u32", into = "u32")
+        )]
         pub struct $index(std::num::NonZeroU32);
 
         impl std::fmt::Display for $index {
@@ -107,8 +118,10 @@ macro_rules! index_dimension {
         impl TryFrom<u32> for $index {
             type Error = CoordinateError;
             fn try_from(index: u32) -> Result<Self, Self::Error> {
-                let x =
-                    std::num::NonZeroU32::new(index + 1).ok_or_else(|| {
+                let x = index
+                    .checked_add(1)
+                    .and_then(std::num::NonZeroU32::new)
+                    .ok_or_else(|| {
                         CoordinateError(format!(
                             "index {} too high for {}",
                             index,
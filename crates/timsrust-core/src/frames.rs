@@ -13,6 +13,7 @@ use std::sync::Arc;
 
 /// A frame with all unprocessed data as it was acquired.
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Frame {
     ions: FrameIons,
     info: FrameInfo,
@@ -64,6 +65,7 @@ impl Frame {
 
 /// The MS level used.
 #[derive(Debug, PartialEq, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MSLevel {
     MS1,
     MS2,
@@ -84,6 +86,7 @@ impl MSLevel {
 }
 
 #[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameIons {
     scan_offsets: Vec<usize>,
     tof_indices: Vec<TofIndex>,
@@ -160,6 +163,7 @@ impl FrameIons {
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameInfo {
     quadrupole_settings: Arc<QuadrupoleSettings>,
     index: usize,
@@ -257,6 +261,11 @@ where
         &self.info_reader
     }
 
+    /// Consume `self` and return the ion and info readers.
+    pub fn into_parts(self) -> (IonReader, InfoReader) {
+        (self.ion_reader, self.info_reader)
+    }
+
     pub fn ion_reader_index(
         &self,
         index: usize,
@@ -381,3 +390,44 @@ where
 }
 
 custom_error!(pub FrameReaderError);
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serde() {
+        let frame = FrameIons::new(
+            vec![0, 1, 3],
+            [10, 20, 30]
+                .map(|tof| TofIndex::try_from(tof).unwrap())
+                .into(),
+            [1, 2, 3]
+                .map(|intensity| IntensityIndex::try_from(intensity).unwrap())
+                .into(),
+        )
+        .add_info(FrameInfo::new(
+            Arc::new(QuadrupoleSettings::default()),
+            5,
+            12.5,
+            1.0,
+            AcquisitionType::DIAPASEF,
+            MSLevel::MS2,
+            3,
+            Some(1),
+        ));
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(
+            json["ions"]["tof_indices"],
+            serde_json::json!([10, 20, 30])
+        );
+        let read: Frame = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(read, frame);
+        let mut invalid = json.clone();
+        invalid["ions"]["intensities"][1] = serde_json::json!(u32::MAX);
+        assert!(serde_json::from_value::<Frame>(invalid).is_err());
+        let mut invalid = json;
+        invalid["info"]["ms_level"] = serde_json::json!("MS3");
+        assert!(serde_json::from_value::<Frame>(invalid).is_err());
+    }
+}
@@ -20,6 +20,7 @@
 //!   in the provided ms2 folder:
 //!     * *.ms2spectrum.bin
 //!     * *.ms2spectrum.parquet
+//!     * *.GlobalMetaData.ms2.parquet (optional, run metadata)
 
 mod acquisition;
 mod coordinates;
@@ -29,6 +30,8 @@ mod frames;
 // mod ions;
 mod precursors;
 mod quadrupole;
+#[cfg(feature = "arrow")]
+mod record_batch;
 // mod query;
 // pub mod prelude;
 mod spectra;
@@ -39,9 +42,14 @@ pub use error::*;
 pub use frames::*;
 pub use precursors::*;
 pub use quadrupole::*;
+#[cfg(feature = "arrow")]
+pub use record_batch::{RecordBatchConvertible, UNIT_KEY};
 pub use spectra::*;
 
 pub use timsrust_utils as utils;
 
 #[cfg(feature = "io")]
 pub use filemanager as io;
+
+#[cfg(feature = "arrow")]
+pub use arrow;
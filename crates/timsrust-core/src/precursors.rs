@@ -2,6 +2,7 @@ use crate::{Charge, FrameIndex, Im, Mz, Rt, ScanIndex};
 
 /// The MS1 precursor that got selected for fragmentation.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Precursor {
     mz: Mz,
     rt: Rt,
@@ -71,3 +72,37 @@ impl Precursor {
         self.frame_index
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serde() {
+        let precursor = Precursor::new(
+            Mz::from(500.25),
+            Im::from(0.9),
+            Rt::from(60.5),
+            ScanIndex::try_from(400).unwrap(),
+            Some(Charge::try_from(2).unwrap()),
+            Some(1200.5),
+            3,
+            FrameIndex::try_from(12).unwrap(),
+        );
+        let json = serde_json::to_value(&precursor).unwrap();
+        assert_eq!(json["charge"], 2);
+        assert_eq!(json["scan_index"], 400);
+        let read: Precursor = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(read, precursor);
+        for (field, value) in [
+            ("charge", serde_json::json!(0)),
+            ("charge", serde_json::json!(200)),
+            ("scan_index", serde_json::json!(-1)),
+            ("frame_index", serde_json::json!(u32::MAX)),
+        ] {
+            let mut json = json.clone();
+            json[field] = value;
+            assert!(serde_json::from_value::<Precursor>(json).is_err());
+        }
+    }
+}
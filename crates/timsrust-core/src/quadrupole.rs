@@ -4,6 +4,7 @@ use crate::Mz;
 
 /// The quadrupole settings used for fragmentation.
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QuadrupoleSettings {
     pub index: usize,
     pub scan_starts: Vec<usize>,
@@ -51,6 +52,7 @@ impl QuadrupoleSettings {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IsolationWindow {
     lower: Mz,
     upper: Mz,
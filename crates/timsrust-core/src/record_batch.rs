@@ -0,0 +1,972 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, AsArray, Float32Array, Float64Array, Int8Array, ListArray,
+    PrimitiveArray, RecordBatch, StringArray, StructArray, UInt8Array,
+    UInt32Array, UInt64Array,
+};
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::datatypes::{
+    ArrowPrimitiveType, DataType, Field, Fields, Float32Type, Float64Type,
+    Int8Type, Schema, SchemaRef, UInt8Type, UInt32Type, UInt64Type,
+};
+use arrow::error::ArrowError;
+
+use crate::{
+    AcquisitionType, Charge, Frame, FrameIndex, FrameInfo, FrameIons, Im,
+    IntensityIndex, IsolationWindow, MSLevel, Mz, PeakShape, Precursor,
+    QuadrupoleSettings, Rt, ScanIndex, Spectrum, TofIndex,
+};
+
+/// Field metadata key holding the unit of a column.
+pub const UNIT_KEY: &str = "unit";
+
+/// Conversion of a slice of items to and from an Arrow [`RecordBatch`] with
+/// one row per item.
+///
+/// Peak and ion arrays are stored as list columns, so the values of all rows
+/// share one contiguous buffer. Columns are looked up by name when reading,
+/// so batches that went through other Arrow tools (e.g. pyarrow) with their
+/// columns reordered still convert back.
+///
+/// # Example
+/// ```
+/// use timsrust_core::{IsolationWindow, Mz, RecordBatchConvertible, Spectrum};
+///
+/// let spectra = vec![Spectrum::new(
+///     vec![10.0, 20.0],
+///     0,
+///     None,
+///     vec![Mz::from(150.0), Mz::from(300.0)],
+///     IsolationWindow::default(),
+/// )];
+/// let batch = Spectrum::to_record_batch(&spectra).unwrap();
+/// assert_eq!(batch.num_rows(), 1);
+/// assert_eq!(Spectrum::<Mz>::from_record_batch(&batch).unwrap(), spectra);
+/// ```
+pub trait RecordBatchConvertible: Sized {
+    /// The fixed schema of the record batches. Fields with a physical unit
+    /// carry it as [`UNIT_KEY`] metadata.
+    fn schema() -> SchemaRef;
+
+    fn to_record_batch(items: &[Self]) -> Result<RecordBatch, ArrowError>;
+
+    fn from_record_batch(batch: &RecordBatch) -> Result<Vec<Self>, ArrowError>;
+}
+
+impl RecordBatchConvertible for Precursor {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(precursor_fields()))
+    }
+
+    fn to_record_batch(items: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let precursors = items.iter().map(Some).collect::<Vec<_>>();
+        RecordBatch::try_new(Self::schema(), precursor_columns(&precursors))
+    }
+
+    fn from_record_batch(batch: &RecordBatch) -> Result<Vec<Self>, ArrowError> {
+        read_precursors(batch)
+    }
+}
+
+impl RecordBatchConvertible for Spectrum<Mz> {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("index", DataType::UInt64, false),
+            with_unit(
+                Field::new("mz", list_type(DataType::Float64), false),
+                "m/z",
+            ),
+            Field::new("intensity", list_type(DataType::Float64), false),
+            with_unit(
+                Field::new("isolation_lower", DataType::Float64, false),
+                "m/z",
+            ),
+            with_unit(
+                Field::new("isolation_upper", DataType::Float64, false),
+                "m/z",
+            ),
+            with_unit(
+                Field::new("collision_energy", DataType::Float64, false),
+                "eV",
+            ),
+            Field::new("precursor", DataType::Struct(precursor_fields()), true),
+            Field::new(
+                "peak_shapes",
+                list_type(DataType::Struct(peak_shape_fields())),
+                true,
+            ),
+            Field::new("correlations", list_type(DataType::Float32), true),
+        ]))
+    }
+
+    fn to_record_batch(items: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let lengths = || items.iter().map(Spectrum::len);
+        let precursors = items
+            .iter()
+            .map(|spectrum| spectrum.precursor().as_ref())
+            .collect::<Vec<_>>();
+        let precursor = StructArray::try_new(
+            precursor_fields(),
+            precursor_columns(&precursors),
+            nulls(precursors.iter().map(Option::is_some)),
+        )?;
+        let peak_shapes = items
+            .iter()
+            .flat_map(|spectrum| spectrum.peak_shapes().unwrap_or_default())
+            .copied()
+            .collect::<Vec<_>>();
+        let peak_shapes = list_array(
+            Arc::new(peak_shape_array(&peak_shapes)?),
+            items.iter().map(|s| s.peak_shapes().map_or(0, <[_]>::len)),
+            nulls(items.iter().map(|s| s.peak_shapes().is_some())),
+        )?;
+        let correlations = list_array(
+            Arc::new(Float32Array::from_iter_values(items.iter().flat_map(
+                |spectrum| {
+                    spectrum.correlations().unwrap_or_default().iter().copied()
+                },
+            ))),
+            items.iter().map(|s| s.correlations().map_or(0, <[_]>::len)),
+            nulls(items.iter().map(|s| s.correlations().is_some())),
+        )?;
+        let windows = || items.iter().map(Spectrum::isolation_window);
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(UInt64Array::from_iter_values(
+                    items.iter().map(|spectrum| spectrum.index() as u64),
+                )),
+                list_array(
+                    Arc::new(Float64Array::from_iter_values(
+                        items.iter().flat_map(|spectrum| {
+                            spectrum.mz_values().iter().map(|&mz| f64::from(mz))
+                        }),
+                    )),
+                    lengths(),
+                    None,
+                )?,
+                list_array(
+                    Arc::new(Float64Array::from_iter_values(
+                        items.iter().flat_map(|spectrum| {
+                            spectrum.intensities().iter().copied()
+                        }),
+                    )),
+                    lengths(),
+                    None,
+                )?,
+                Arc::new(Float64Array::from_iter_values(
+                    windows().map(|window| f64::from(window.lower())),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    windows().map(|window| f64::from(window.upper())),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    windows().map(IsolationWindow::collision_energy),
+                )),
+                Arc::new(precursor),
+                peak_shapes,
+                correlations,
+            ],
+        )
+    }
+
+    fn from_record_batch(batch: &RecordBatch) -> Result<Vec<Self>, ArrowError> {
+        let index = primitive::<UInt64Type>(batch, "index")?;
+        let mz = list::<Float64Type>(batch, "mz")?;
+        let intensity = list::<Float64Type>(batch, "intensity")?;
+        let lower = primitive::<Float64Type>(batch, "isolation_lower")?;
+        let upper = primitive::<Float64Type>(batch, "isolation_upper")?;
+        let ce = primitive::<Float64Type>(batch, "collision_energy")?;
+        let (precursor_array, precursor_batch) =
+            struct_column(column(batch, "precursor")?, "precursor")?;
+        let precursors = read_precursors(&precursor_batch)?;
+        let peak_shape_list = column(batch, "peak_shapes")?
+            .as_list_opt::<i32>()
+            .ok_or_else(|| type_error("peak_shapes"))?;
+        let (_, peak_shape_batch) =
+            struct_column(peak_shape_list.values(), "peak_shapes")?;
+        let peak_shapes = read_peak_shapes(&peak_shape_batch)?;
+        let correlations = list::<Float32Type>(batch, "correlations")?;
+        (0..batch.num_rows())
+            .map(|row| {
+                let mz_values = mz.get(row).ok_or_else(|| null_error("mz"))?;
+                let intensities = intensity
+                    .get(row)
+                    .ok_or_else(|| null_error("intensity"))?;
+                if mz_values.len() != intensities.len() {
+                    return Err(invalid(format!(
+                        "row {row} has {} m/z values and {} intensities",
+                        mz_values.len(),
+                        intensities.len()
+                    )));
+                }
+                let precursor = (!precursor_array.is_null(row))
+                    .then(|| precursors[row].clone());
+                let mut spectrum = Spectrum::new(
+                    intensities.to_vec(),
+                    index.value(row) as usize,
+                    precursor,
+                    mz_values.iter().map(|&mz| Mz::from(mz)).collect(),
+                    IsolationWindow::new_from_bounds(
+                        Mz::from(lower.value(row)),
+                        Mz::from(upper.value(row)),
+                        ce.value(row),
+                    ),
+                );
+                if !peak_shape_list.is_null(row) {
+                    let offsets = peak_shape_list.value_offsets();
+                    let range =
+                        offsets[row] as usize..offsets[row + 1] as usize;
+                    spectrum = spectrum
+                        .with_peak_shapes(peak_shapes[range].to_vec())
+                        .map_err(|e| invalid(e.to_string()))?;
+                }
+                if let Some(correlations) = correlations.get(row) {
+                    check_len(
+                        correlations.len(),
+                        spectrum.len(),
+                        "correlations",
+                    )?;
+                    spectrum =
+                        spectrum.with_correlations(correlations.to_vec());
+                }
+                Ok(spectrum)
+            })
+            .collect()
+    }
+}
+
+impl RecordBatchConvertible for FrameIons {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(frame_ion_fields()))
+    }
+
+    fn to_record_batch(items: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let ions = items.iter().collect::<Vec<_>>();
+        RecordBatch::try_new(Self::schema(), frame_ion_columns(&ions)?)
+    }
+
+    fn from_record_batch(batch: &RecordBatch) -> Result<Vec<Self>, ArrowError> {
+        read_frame_ions(batch)
+    }
+}
+
+impl RecordBatchConvertible for Frame {
+    fn schema() -> SchemaRef {
+        let mut fields = vec![
+            Field::new("index", DataType::UInt64, false),
+            with_unit(Field::new("rt", DataType::Float64, false), "s"),
+            Field::new("intensity_correction_factor", DataType::Float64, false),
+            Field::new("acquisition_type", DataType::Utf8, false),
+            Field::new("ms_level", DataType::UInt8, false),
+            Field::new("window_group", DataType::UInt8, false),
+            Field::new("cycle_index", DataType::UInt64, true),
+            Field::new(
+                "quadrupole",
+                DataType::Struct(quadrupole_fields()),
+                false,
+            ),
+        ];
+        fields.extend(frame_ion_fields().iter().map(|f| f.as_ref().clone()));
+        Arc::new(Schema::new(fields))
+    }
+
+    fn to_record_batch(items: &[Self]) -> Result<RecordBatch, ArrowError> {
+        let infos = || items.iter().map(Frame::info);
+        let settings = infos()
+            .map(|info| info.quadrupole_settings().as_ref())
+            .collect::<Vec<_>>();
+        let ions = items.iter().map(Frame::ions).collect::<Vec<_>>();
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from_iter_values(
+                infos().map(|info| info.index() as u64),
+            )),
+            Arc::new(Float64Array::from_iter_values(
+                infos().map(FrameInfo::rt_in_seconds),
+            )),
+            Arc::new(Float64Array::from_iter_values(
+                infos().map(FrameInfo::intensity_correction_factor),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                infos()
+                    .map(|info| acquisition_type_name(info.acquisition_type())),
+            )),
+            Arc::new(UInt8Array::from_iter_values(
+                infos().map(|info| ms_level_number(info.ms_level())),
+            )),
+            Arc::new(UInt8Array::from_iter_values(
+                infos().map(FrameInfo::window_group),
+            )),
+            Arc::new(UInt64Array::from_iter(
+                infos().map(|info| info.cycle_index().map(|i| i as u64)),
+            )),
+            Arc::new(StructArray::try_new(
+                quadrupole_fields(),
+                quadrupole_columns(&settings)?,
+                None,
+            )?),
+        ];
+        columns.extend(frame_ion_columns(&ions)?);
+        RecordBatch::try_new(Self::schema(), columns)
+    }
+
+    fn from_record_batch(batch: &RecordBatch) -> Result<Vec<Self>, ArrowError> {
+        let index = primitive::<UInt64Type>(batch, "index")?;
+        let rt = primitive::<Float64Type>(batch, "rt")?;
+        let correction =
+            primitive::<Float64Type>(batch, "intensity_correction_factor")?;
+        let acquisition_type = column(batch, "acquisition_type")?
+            .as_string_opt::<i32>()
+            .ok_or_else(|| type_error("acquisition_type"))?;
+        let ms_level = primitive::<UInt8Type>(batch, "ms_level")?;
+        let window_group = primitive::<UInt8Type>(batch, "window_group")?;
+        let cycle_index = primitive::<UInt64Type>(batch, "cycle_index")?;
+        let (_, quadrupole_batch) =
+            struct_column(column(batch, "quadrupole")?, "quadrupole")?;
+        let settings = read_quadrupole_settings(&quadrupole_batch)?;
+        let ions = read_frame_ions(batch)?;
+        let mut shared: Vec<Arc<QuadrupoleSettings>> = vec![];
+        ions.into_iter()
+            .zip(settings)
+            .enumerate()
+            .map(|(row, (ions, settings))| {
+                // Frames share their quadrupole settings, as in the readers.
+                let settings = match shared.iter().find(|s| ***s == settings) {
+                    Some(settings) => settings.clone(),
+                    None => {
+                        shared.push(Arc::new(settings));
+                        shared[shared.len() - 1].clone()
+                    },
+                };
+                let info = FrameInfo::new(
+                    settings,
+                    index.value(row) as usize,
+                    rt.value(row),
+                    correction.value(row),
+                    parse_acquisition_type(acquisition_type.value(row))?,
+                    parse_ms_level(ms_level.value(row))?,
+                    window_group.value(row),
+                    (!cycle_index.is_null(row))
+                        .then(|| cycle_index.value(row) as usize),
+                );
+                Ok(ions.add_info(info))
+            })
+            .collect()
+    }
+}
+
+fn precursor_fields() -> Fields {
+    Fields::from(vec![
+        with_unit(Field::new("mz", DataType::Float64, false), "m/z"),
+        with_unit(Field::new("rt", DataType::Float64, false), "s"),
+        with_unit(Field::new("im", DataType::Float64, false), "Vs/cm2"),
+        Field::new("scan_index", DataType::UInt32, false),
+        Field::new("charge", DataType::Int8, true),
+        Field::new("intensity", DataType::Float64, true),
+        Field::new("index", DataType::UInt64, false),
+        Field::new("frame_index", DataType::UInt32, false),
+    ])
+}
+
+/// Columns of [`precursor_fields`], with default values for missing
+/// precursors.
+fn precursor_columns(precursors: &[Option<&Precursor>]) -> Vec<ArrayRef> {
+    let values = |f: fn(&Precursor) -> f64| {
+        Arc::new(Float64Array::from_iter_values(
+            precursors.iter().map(|p| p.map_or(0.0, f)),
+        )) as ArrayRef
+    };
+    vec![
+        values(|p| f64::from(p.mz())),
+        values(|p| f64::from(p.rt())),
+        values(|p| f64::from(p.im())),
+        Arc::new(UInt32Array::from_iter_values(
+            precursors
+                .iter()
+                .map(|p| p.map_or(0, |p| p.scan_index().into())),
+        )),
+        Arc::new(Int8Array::from_iter(precursors.iter().map(|p| {
+            p.and_then(|p| p.charge().as_ref().map(|&c| i8::from(c)))
+        }))),
+        Arc::new(Float64Array::from_iter(
+            precursors.iter().map(|p| p.and_then(|p| *p.intensity())),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            precursors.iter().map(|p| p.map_or(0, |p| p.index() as u64)),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            precursors
+                .iter()
+                .map(|p| p.map_or(0, |p| p.frame_index().into())),
+        )),
+    ]
+}
+
+fn read_precursors(batch: &RecordBatch) -> Result<Vec<Precursor>, ArrowError> {
+    let mz = primitive::<Float64Type>(batch, "mz")?;
+    let rt = primitive::<Float64Type>(batch, "rt")?;
+    let im = primitive::<Float64Type>(batch, "im")?;
+    let scan_index = primitive::<UInt32Type>(batch, "scan_index")?;
+    let charge = primitive::<Int8Type>(batch, "charge")?;
+    let intensity = primitive::<Float64Type>(batch, "intensity")?;
+    let index = primitive::<UInt64Type>(batch, "index")?;
+    let frame_index = primitive::<UInt32Type>(batch, "frame_index")?;
+    (0..batch.num_rows())
+        .map(|row| {
+            let charge = match charge.is_null(row) {
+                true => None,
+                false => {
+                    Some(Charge::try_from(charge.value(row)).map_err(invalid)?)
+                },
+            };
+            Ok(Precursor::new(
+                Mz::from(mz.value(row)),
+                Im::from(im.value(row)),
+                Rt::from(rt.value(row)),
+                ScanIndex::try_from(scan_index.value(row)).map_err(invalid)?,
+                charge,
+                (!intensity.is_null(row)).then(|| intensity.value(row)),
+                index.value(row) as usize,
+                FrameIndex::try_from(frame_index.value(row))
+                    .map_err(invalid)?,
+            ))
+        })
+        .collect()
+}
+
+fn peak_shape_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("area", DataType::UInt64, false),
+        Field::new("ion_count", DataType::UInt32, false),
+        Field::new("tof_fwhm", DataType::Float32, false),
+        Field::new("scan_fwhm", DataType::Float32, false),
+        Field::new("tof_apex", DataType::Float64, false),
+        Field::new("scan_apex", DataType::Float64, false),
+    ])
+}
+
+fn peak_shape_array(shapes: &[PeakShape]) -> Result<StructArray, ArrowError> {
+    StructArray::try_new(
+        peak_shape_fields(),
+        vec![
+            Arc::new(UInt64Array::from_iter_values(
+                shapes.iter().map(|s| s.area),
+            )),
+            Arc::new(UInt32Array::from_iter_values(
+                shapes.iter().map(|s| s.ion_count),
+            )),
+            Arc::new(Float32Array::from_iter_values(
+                shapes.iter().map(|s| s.tof_fwhm),
+            )),
+            Arc::new(Float32Array::from_iter_values(
+                shapes.iter().map(|s| s.scan_fwhm),
+            )),
+            Arc::new(Float64Array::from_iter_values(
+                shapes.iter().map(|s| s.tof_apex),
+            )),
+            Arc::new(Float64Array::from_iter_values(
+                shapes.iter().map(|s| s.scan_apex),
+            )),
+        ],
+        None,
+    )
+}
+
+fn read_peak_shapes(batch: &RecordBatch) -> Result<Vec<PeakShape>, ArrowError> {
+    let area = primitive::<UInt64Type>(batch, "area")?;
+    let ion_count = primitive::<UInt32Type>(batch, "ion_count")?;
+    let tof_fwhm = primitive::<Float32Type>(batch, "tof_fwhm")?;
+    let scan_fwhm = primitive::<Float32Type>(batch, "scan_fwhm")?;
+    let tof_apex = primitive::<Float64Type>(batch, "tof_apex")?;
+    let scan_apex = primitive::<Float64Type>(batch, "scan_apex")?;
+    Ok((0..batch.num_rows())
+        .map(|row| PeakShape {
+            area: area.value(row),
+            ion_count: ion_count.value(row),
+            tof_fwhm: tof_fwhm.value(row),
+            scan_fwhm: scan_fwhm.value(row),
+            tof_apex: tof_apex.value(row),
+            scan_apex: scan_apex.value(row),
+        })
+        .collect())
+}
+
+fn frame_ion_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("scan_offsets", list_type(DataType::UInt64), false),
+        Field::new("tof_indices", list_type(DataType::UInt32), false),
+        Field::new("intensities", list_type(DataType::UInt32), false),
+    ])
+}
+
+fn frame_ion_columns(ions: &[&FrameIons]) -> Result<Vec<ArrayRef>, ArrowError> {
+    let lengths = || ions.iter().map(|ions| ions.tof_indices().len());
+    Ok(vec![
+        list_array(
+            Arc::new(UInt64Array::from_iter_values(ions.iter().flat_map(
+                |ions| ions.scan_offsets().iter().map(|&o| o as u64),
+            ))),
+            ions.iter().map(|ions| ions.scan_offsets().len()),
+            None,
+        )?,
+        list_array(
+            Arc::new(UInt32Array::from_iter_values(ions.iter().flat_map(
+                |ions| ions.tof_indices().iter().map(|&tof| u32::from(tof)),
+            ))),
+            lengths(),
+            None,
+        )?,
+        list_array(
+            Arc::new(UInt32Array::from_iter_values(ions.iter().flat_map(
+                |ions| ions.intensities().iter().map(|&i| u32::from(i)),
+            ))),
+            lengths(),
+            None,
+        )?,
+    ])
+}
+
+fn read_frame_ions(batch: &RecordBatch) -> Result<Vec<FrameIons>, ArrowError> {
+    let scan_offsets = list::<UInt64Type>(batch, "scan_offsets")?;
+    let tof_indices = list::<UInt32Type>(batch, "tof_indices")?;
+    let intensities = list::<UInt32Type>(batch, "intensities")?;
+    (0..batch.num_rows())
+        .map(|row| {
+            let offsets = scan_offsets
+                .get(row)
+                .ok_or_else(|| null_error("scan_offsets"))?;
+            let tofs = tof_indices
+                .get(row)
+                .ok_or_else(|| null_error("tof_indices"))?;
+            let intensities = intensities
+                .get(row)
+                .ok_or_else(|| null_error("intensities"))?;
+            check_len(intensities.len(), tofs.len(), "intensities")?;
+            if offsets
+                .last()
+                .is_none_or(|&last| last as usize != tofs.len())
+            {
+                return Err(invalid(format!(
+                    "row {row} has scan offsets that do not end at {}",
+                    tofs.len()
+                )));
+            }
+            Ok(FrameIons::new(
+                offsets.iter().map(|&o| o as usize).collect(),
+                tofs.iter()
+                    .map(|&tof| TofIndex::try_from(tof))
+                    .collect::<Result<_, _>>()
+                    .map_err(invalid)?,
+                intensities
+                    .iter()
+                    .map(|&i| IntensityIndex::try_from(i))
+                    .collect::<Result<_, _>>()
+                    .map_err(invalid)?,
+            ))
+        })
+        .collect()
+}
+
+fn quadrupole_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("index", DataType::UInt64, false),
+        Field::new("scan_starts", list_type(DataType::UInt64), false),
+        Field::new("scan_ends", list_type(DataType::UInt64), false),
+        with_unit(
+            Field::new("isolation_lower", list_type(DataType::Float64), false),
+            "m/z",
+        ),
+        with_unit(
+            Field::new("isolation_upper", list_type(DataType::Float64), false),
+            "m/z",
+        ),
+        with_unit(
+            Field::new("collision_energy", list_type(DataType::Float64), false),
+            "eV",
+        ),
+    ])
+}
+
+fn quadrupole_columns(
+    settings: &[&QuadrupoleSettings],
+) -> Result<Vec<ArrayRef>, ArrowError> {
+    let scans = |f: fn(&QuadrupoleSettings) -> &Vec<usize>| {
+        list_array(
+            Arc::new(UInt64Array::from_iter_values(
+                settings.iter().flat_map(|s| f(s).iter().map(|&x| x as u64)),
+            )),
+            settings.iter().map(|s| f(s).len()),
+            None,
+        )
+    };
+    let windows = |f: fn(&IsolationWindow) -> f64| {
+        list_array(
+            Arc::new(Float64Array::from_iter_values(
+                settings
+                    .iter()
+                    .flat_map(|s| s.isolation_windows.iter().map(f)),
+            )),
+            settings.iter().map(|s| s.isolation_windows.len()),
+            None,
+        )
+    };
+    Ok(vec![
+        Arc::new(UInt64Array::from_iter_values(
+            settings.iter().map(|s| s.index as u64),
+        )),
+        scans(|s| &s.scan_starts)?,
+        scans(|s| &s.scan_ends)?,
+        windows(|w| f64::from(w.lower()))?,
+        windows(|w| f64::from(w.upper()))?,
+        windows(IsolationWindow::collision_energy)?,
+    ])
+}
+
+fn read_quadrupole_settings(
+    batch: &RecordBatch,
+) -> Result<Vec<QuadrupoleSettings>, ArrowError> {
+    let index = primitive::<UInt64Type>(batch, "index")?;
+    let scan_starts = list::<UInt64Type>(batch, "scan_starts")?;
+    let scan_ends = list::<UInt64Type>(batch, "scan_ends")?;
+    let lower = list::<Float64Type>(batch, "isolation_lower")?;
+    let upper = list::<Float64Type>(batch, "isolation_upper")?;
+    let ce = list::<Float64Type>(batch, "collision_energy")?;
+    (0..batch.num_rows())
+        .map(|row| {
+            let scans = |list: &ListColumn<UInt64Type>, name| {
+                let scans = list.get(row).ok_or_else(|| null_error(name))?;
+                Ok::<_, ArrowError>(
+                    scans.iter().map(|&s| s as usize).collect::<Vec<_>>(),
+                )
+            };
+            let lower = lower
+                .get(row)
+                .ok_or_else(|| null_error("isolation_lower"))?;
+            let upper = upper
+                .get(row)
+                .ok_or_else(|| null_error("isolation_upper"))?;
+            let ce =
+                ce.get(row).ok_or_else(|| null_error("collision_energy"))?;
+            check_len(upper.len(), lower.len(), "isolation_upper")?;
+            check_len(ce.len(), lower.len(), "collision_energy")?;
+            Ok(QuadrupoleSettings {
+                index: index.value(row) as usize,
+                scan_starts: scans(&scan_starts, "scan_starts")?,
+                scan_ends: scans(&scan_ends, "scan_ends")?,
+                isolation_windows: (0..lower.len())
+                    .map(|i| {
+                        IsolationWindow::new_from_bounds(
+                            Mz::from(lower[i]),
+                            Mz::from(upper[i]),
+                            ce[i],
+                        )
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+fn acquisition_type_name(acquisition_type: AcquisitionType) -> &'static str {
+    match acquisition_type {
+        AcquisitionType::DDAPASEF => "DDAPASEF",
+        AcquisitionType::DIAPASEF => "DIAPASEF",
+        AcquisitionType::DiagonalDIAPASEF => "DiagonalDIAPASEF",
+        AcquisitionType::Unknown => "Unknown",
+    }
+}
+
+fn parse_acquisition_type(name: &str) -> Result<AcquisitionType, ArrowError> {
+    match name {
+        "DDAPASEF" => Ok(AcquisitionType::DDAPASEF),
+        "DIAPASEF" => Ok(AcquisitionType::DIAPASEF),
+        "DiagonalDIAPASEF" => Ok(AcquisitionType::DiagonalDIAPASEF),
+        "Unknown" => Ok(AcquisitionType::Unknown),
+        _ => Err(invalid(format!("unknown acquisition type {name}"))),
+    }
+}
+
+fn ms_level_number(ms_level: MSLevel) -> u8 {
+    match ms_level {
+        MSLevel::MS1 => 1,
+        MSLevel::MS2 => 2,
+        MSLevel::Unknown => 0,
+    }
+}
+
+fn parse_ms_level(number: u8) -> Result<MSLevel, ArrowError> {
+    match number {
+        1 => Ok(MSLevel::MS1),
+        2 => Ok(MSLevel::MS2),
+        0 => Ok(MSLevel::Unknown),
+        _ => Err(invalid(format!("unknown MS level {number}"))),
+    }
+}
+
+fn with_unit(field: Field, unit: &str) -> Field {
+    field.with_metadata(HashMap::from([(
+        UNIT_KEY.to_string(),
+        unit.to_string(),
+    )]))
+}
+
+fn list_type(item: DataType) -> DataType {
+    DataType::List(Arc::new(Field::new_list_field(item, false)))
+}
+
+/// A list array of `values`, split into rows of `lengths`.
+///
+/// Fails if the rows hold more than `i32::MAX` values in total, which do not
+/// fit the offsets of a `List`.
+fn list_array(
+    values: ArrayRef,
+    lengths: impl IntoIterator<Item = usize>,
+    nulls: Option<NullBuffer>,
+) -> Result<ArrayRef, ArrowError> {
+    let lengths = lengths.into_iter().collect::<Vec<_>>();
+    let total = lengths.iter().try_fold(0_usize, |total, &len| {
+        total
+            .checked_add(len)
+            .ok_or(ArrowError::OffsetOverflowError(usize::MAX))
+    })?;
+    if i32::try_from(total).is_err() {
+        return Err(ArrowError::OffsetOverflowError(total));
+    }
+    let field =
+        Arc::new(Field::new_list_field(values.data_type().clone(), false));
+    Ok(Arc::new(ListArray::try_new(
+        field,
+        OffsetBuffer::from_lengths(lengths),
+        values,
+        nulls,
+    )?))
+}
+
+/// A null buffer, or `None` if all values are valid.
+fn nulls(valid: impl Iterator<Item = bool>) -> Option<NullBuffer> {
+    let nulls = NullBuffer::from_iter(valid);
+    (nulls.null_count() > 0).then_some(nulls)
+}
+
+fn column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a ArrayRef, ArrowError> {
+    batch.column_by_name(name).ok_or_else(|| {
+        ArrowError::SchemaError(format!("missing column {name}"))
+    })
+}
+
+fn primitive<'a, T: ArrowPrimitiveType>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a PrimitiveArray<T>, ArrowError> {
+    column(batch, name)?
+        .as_primitive_opt::<T>()
+        .ok_or_else(|| type_error(name))
+}
+
+/// A list column of primitive values, borrowed from a [`RecordBatch`].
+struct ListColumn<'a, T: ArrowPrimitiveType> {
+    list: &'a ListArray,
+    values: &'a [T::Native],
+}
+
+impl<'a, T: ArrowPrimitiveType> ListColumn<'a, T> {
+    /// The values of a row, or `None` if the row is null.
+    fn get(&self, row: usize) -> Option<&'a [T::Native]> {
+        if self.list.is_null(row) {
+            return None;
+        }
+        let offsets = self.list.value_offsets();
+        Some(&self.values[offsets[row] as usize..offsets[row + 1] as usize])
+    }
+}
+
+fn list<'a, T: ArrowPrimitiveType>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<ListColumn<'a, T>, ArrowError> {
+    let list = column(batch, name)?
+        .as_list_opt::<i32>()
+        .ok_or_else(|| type_error(name))?;
+    let values = list
+        .values()
+        .as_primitive_opt::<T>()
+        .ok_or_else(|| type_error(name))?;
+    Ok(ListColumn {
+        list,
+        values: values.values(),
+    })
+}
+
+/// A struct column and its children as a [`RecordBatch`].
+fn struct_column<'a>(
+    array: &'a ArrayRef,
+    name: &str,
+) -> Result<(&'a StructArray, RecordBatch), ArrowError> {
+    let array = array.as_struct_opt().ok_or_else(|| type_error(name))?;
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(array.fields().clone())),
+        array.columns().to_vec(),
+    )?;
+    Ok((array, batch))
+}
+
+fn check_len(
+    len: usize,
+    expected: usize,
+    name: &str,
+) -> Result<(), ArrowError> {
+    match len == expected {
+        true => Ok(()),
+        false => Err(invalid(format!(
+            "{name} has {len} values instead of {expected}"
+        ))),
+    }
+}
+
+fn type_error(name: &str) -> ArrowError {
+    ArrowError::SchemaError(format!("column {name} has an unexpected type"))
+}
+
+fn null_error(name: &str) -> ArrowError {
+    ArrowError::InvalidArgumentError(format!("column {name} contains nulls"))
+}
+
+fn invalid(error: impl ToString) -> ArrowError {
+    ArrowError::InvalidArgumentError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn precursor(index: usize, charge: Option<i8>) -> Precursor {
+        Precursor::new(
+            Mz::from(500.25),
+            Im::from(0.9),
+            Rt::from(61.5),
+            ScanIndex::try_from(400u32).unwrap(),
+            charge.map(|charge| Charge::try_from(charge).unwrap()),
+            index.is_multiple_of(2).then_some(1200.0),
+            index,
+            FrameIndex::try_from(12u32).unwrap(),
+        )
+    }
+
+    fn frame(index: usize, settings: &Arc<QuadrupoleSettings>) -> Frame {
+        let tofs = [10u32, 20, 30].map(|t| TofIndex::try_from(t).unwrap());
+        let intensities =
+            [1u32, 2, 3].map(|i| IntensityIndex::try_from(i).unwrap());
+        FrameIons::new(vec![0, 2, 2, 3], tofs.into(), intensities.into())
+            .add_info(FrameInfo::new(
+                settings.clone(),
+                index,
+                1.5 * index as f64,
+                0.01,
+                AcquisitionType::DIAPASEF,
+                MSLevel::MS2,
+                3,
+                (index > 1).then_some(index / 2),
+            ))
+    }
+
+    #[test]
+    fn precursor_round_trip() {
+        let precursors = vec![precursor(0, Some(2)), precursor(1, None)];
+        let batch = Precursor::to_record_batch(&precursors).unwrap();
+        let field = batch.schema().field_with_name("rt").unwrap().clone();
+        assert_eq!(field.metadata()[UNIT_KEY], "s");
+        assert_eq!(Precursor::from_record_batch(&batch).unwrap(), precursors);
+    }
+
+    #[test]
+    fn spectrum_round_trip() {
+        let spectrum = |index: usize, precursor| {
+            Spectrum::new(
+                vec![10.0, 20.0 * index as f64],
+                index,
+                precursor,
+                vec![Mz::from(150.125), Mz::from(300.5)],
+                IsolationWindow::new_from_bounds(
+                    Mz::from(499.0),
+                    Mz::from(501.0),
+                    25.0,
+                ),
+            )
+        };
+        let shapes = vec![PeakShape::default(); 2];
+        let spectra = vec![
+            spectrum(0, Some(precursor(0, Some(3))))
+                .with_peak_shapes(shapes)
+                .unwrap()
+                .with_correlations(vec![0.5, 0.75]),
+            spectrum(1, None),
+            spectrum(2, Some(precursor(2, None)))
+                .with_correlations(vec![1.0; 2]),
+        ];
+        let batch = Spectrum::to_record_batch(&spectra).unwrap();
+        assert_eq!(batch.schema(), Spectrum::<Mz>::schema());
+        assert_eq!(column(&batch, "precursor").unwrap().null_count(), 1);
+        let read = Spectrum::<Mz>::from_record_batch(&batch).unwrap();
+        assert_eq!(read, spectra);
+        let sliced = Spectrum::<Mz>::from_record_batch(&batch.slice(1, 2));
+        assert_eq!(sliced.unwrap(), spectra[1..]);
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let settings = Arc::new(QuadrupoleSettings {
+            index: 1,
+            scan_starts: vec![0, 2],
+            scan_ends: vec![2, 3],
+            isolation_windows: vec![
+                IsolationWindow::new_from_bounds(
+                    Mz::from(400.0),
+                    Mz::from(425.0),
+                    20.0,
+                ),
+                IsolationWindow::new_from_bounds(
+                    Mz::from(425.0),
+                    Mz::from(450.0),
+                    22.0,
+                ),
+            ],
+        });
+        let frames = vec![frame(1, &settings), frame(2, &settings)];
+        let batch = Frame::to_record_batch(&frames).unwrap();
+        let read = Frame::from_record_batch(&batch).unwrap();
+        assert_eq!(read, frames);
+        assert!(Arc::ptr_eq(
+            read[0].info().quadrupole_settings(),
+            read[1].info().quadrupole_settings()
+        ));
+        let ions = frames.iter().map(|f| f.ions().clone()).collect::<Vec<_>>();
+        let batch = FrameIons::to_record_batch(&ions).unwrap();
+        assert_eq!(FrameIons::from_record_batch(&batch).unwrap(), ions);
+    }
+
+    #[test]
+    fn list_offset_overflow() {
+        let values: ArrayRef = Arc::new(Float64Array::from(vec![1.0]));
+        let lengths = [i32::MAX as usize, 1];
+        assert!(matches!(
+            list_array(values.clone(), lengths, None),
+            Err(ArrowError::OffsetOverflowError(_))
+        ));
+        assert!(list_array(values, [1], None).is_ok());
+    }
+
+    #[test]
+    fn missing_column() {
+        let batch = Precursor::to_record_batch(&[precursor(0, None)]).unwrap();
+        let batch = batch.project(&[0, 1]).unwrap();
+        assert!(matches!(
+            Precursor::from_record_batch(&batch),
+            Err(ArrowError::SchemaError(_))
+        ));
+    }
+}
@@ -1,16 +1,90 @@
 use timsrust_utils::vec::get_top_n;
 
 use super::Precursor;
-use crate::{IsolationWindow, Mz, TofIndex, coordinates::Converter};
+use crate::{
+    IsolationWindow, Mz, SpectrumError, TofIndex, coordinates::Converter,
+};
+
+/// Shape metrics of a centroided peak, as measured by the centroider.
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeakShape {
+    /// Summed intensity of all raw ions in the peak.
+    pub area: u64,
+    /// Number of raw ions in the peak, i.e. of occupied (TOF, scan) bins.
+    ///
+    /// Every bin holds the summed intensity of all detector events at that
+    /// TOF and scan, so this is not a count of detector events.
+    pub ion_count: u32,
+    /// Full width at half maximum along the TOF axis, in TOF bins.
+    pub tof_fwhm: f32,
+    /// Full width at half maximum along the scan axis, in scans.
+    pub scan_fwhm: f32,
+    /// Intensity-weighted (fractional) TOF position of the apex.
+    pub tof_apex: f64,
+    /// Intensity-weighted (fractional) scan position of the apex.
+    pub scan_apex: f64,
+}
 
 /// An MS2 spectrum with centroided mz values and summed intensities.
 #[derive(Debug, PartialEq, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "SpectrumData<C>")
+)]
 pub struct Spectrum<C = TofIndex> {
     intensities: Vec<f64>,
     precursor: Option<Precursor>,
     index: usize,
     coordinates: Vec<C>,
     isolation_window: IsolationWindow,
+    peak_shapes: Option<Vec<PeakShape>>,
+    correlations: Option<Vec<f32>>,
+}
+
+/// The fields of a deserialized [`Spectrum`], before their lengths are
+/// checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SpectrumData<C> {
+    intensities: Vec<f64>,
+    precursor: Option<Precursor>,
+    index: usize,
+    coordinates: Vec<C>,
+    isolation_window: IsolationWindow,
+    peak_shapes: Option<Vec<PeakShape>>,
+    correlations: Option<Vec<f32>>,
+}
+
+#[cfg(feature = "serde")]
+impl<C> TryFrom<SpectrumData<C>> for Spectrum<C> {
+    type Error = SpectrumError;
+
+    fn try_from(data: SpectrumData<C>) -> Result<Self, Self::Error> {
+        let peak_count = data.coordinates.len();
+        let lengths = [
+            ("intensities", Some(data.intensities.len())),
+            ("peak shapes", data.peak_shapes.as_ref().map(Vec::len)),
+            ("correlations", data.correlations.as_ref().map(Vec::len)),
+        ];
+        for (name, length) in lengths {
+            if let Some(length) = length.filter(|&l| l != peak_count) {
+                return Err(SpectrumError::new(format!(
+                    "{length} {name} for {peak_count} peaks"
+                )));
+            }
+        }
+        Ok(Spectrum {
+            intensities: data.intensities,
+            precursor: data.precursor,
+            index: data.index,
+            coordinates: data.coordinates,
+            isolation_window: data.isolation_window,
+            peak_shapes: data.peak_shapes,
+            correlations: data.correlations,
+        })
+    }
 }
 
 impl<C> Spectrum<C> {
@@ -31,9 +105,40 @@ impl<C> Spectrum<C> {
             index,
             coordinates,
             isolation_window,
+            peak_shapes: None,
+            correlations: None,
         }
     }
 
+    /// Annotate every peak with its [`PeakShape`].
+    ///
+    /// # Errors
+    /// Returns an error if there is not exactly one shape per peak.
+    pub fn with_peak_shapes(
+        mut self,
+        peak_shapes: Vec<PeakShape>,
+    ) -> Result<Self, SpectrumError> {
+        if peak_shapes.len() != self.coordinates.len() {
+            return Err(SpectrumError::new(format!(
+                "{} peak shapes for {} peaks",
+                peak_shapes.len(),
+                self.coordinates.len()
+            )));
+        }
+        self.peak_shapes = Some(peak_shapes);
+        Ok(self)
+    }
+
+    /// Annotate every peak with its elution correlation to the precursor.
+    pub fn with_correlations(mut self, correlations: Vec<f32>) -> Self {
+        assert!(
+            correlations.len() == self.coordinates.len(),
+            "Correlations and coordinates must have the same length"
+        );
+        self.correlations = Some(correlations);
+        self
+    }
+
     pub fn intensities(&self) -> &Vec<f64> {
         &self.intensities
     }
@@ -62,6 +167,18 @@ impl<C> Spectrum<C> {
         &self.coordinates
     }
 
+    /// The shape of each peak, if the spectrum was centroided by a reader
+    /// that measures them.
+    pub fn peak_shapes(&self) -> Option<&[PeakShape]> {
+        self.peak_shapes.as_deref()
+    }
+
+    /// The precursor–fragment elution correlation of each peak, if the
+    /// spectrum was filtered on co-elution.
+    pub fn correlations(&self) -> Option<&[f32]> {
+        self.correlations.as_deref()
+    }
+
     pub fn convert_to<X>(self, converter: impl Converter<C, X>) -> Spectrum<X>
     where
         C: Copy,
@@ -72,6 +189,8 @@ impl<C> Spectrum<C> {
             index: self.index,
             coordinates: converter.batch_convert(&self.coordinates),
             isolation_window: self.isolation_window,
+            peak_shapes: self.peak_shapes,
+            correlations: self.correlations,
         }
     }
 
@@ -92,6 +211,18 @@ impl<C> Spectrum<C> {
                 .map(|&index| self.coordinates[index].clone())
                 .collect(),
             isolation_window: self.isolation_window.clone(),
+            peak_shapes: self.peak_shapes.as_ref().map(|peak_shapes| {
+                top_indices
+                    .iter()
+                    .map(|&index| peak_shapes[index])
+                    .collect()
+            }),
+            correlations: self.correlations.as_ref().map(|correlations| {
+                top_indices
+                    .iter()
+                    .map(|&index| correlations[index])
+                    .collect()
+            }),
         }
     }
 }
@@ -128,3 +259,76 @@ impl Spectrum<Mz> {
         self.convert_to(converter)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectrum() -> Spectrum<Mz> {
+        Spectrum::new(
+            vec![10.0, 20.0],
+            0,
+            None,
+            vec![Mz::from(100.0), Mz::from(200.0)],
+            IsolationWindow::default(),
+        )
+    }
+
+    #[test]
+    fn peak_shapes() {
+        let shapes = vec![PeakShape::default(); 2];
+        let spectrum = spectrum().with_peak_shapes(shapes.clone()).unwrap();
+        assert_eq!(spectrum.peak_shapes(), Some(shapes.as_slice()));
+        assert!(
+            spectrum
+                .with_peak_shapes(vec![PeakShape::default()])
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use crate::{Charge, FrameIndex, Im, Rt, ScanIndex};
+
+        let precursor = Precursor::new(
+            Mz::from(500.25),
+            Im::from(0.9),
+            Rt::from(60.5),
+            ScanIndex::try_from(400).unwrap(),
+            Some(Charge::try_from(-2).unwrap()),
+            None,
+            3,
+            FrameIndex::try_from(12).unwrap(),
+        );
+        let spectrum = Spectrum::new(
+            vec![10.0, 20.0],
+            4,
+            Some(precursor),
+            vec![TofIndex::try_from(100).unwrap(), TofIndex::from(200u16)],
+            IsolationWindow::new_from_center(
+                Mz::from(500.0),
+                Mz::from(2.0),
+                25.0,
+            ),
+        )
+        .with_peak_shapes(vec![PeakShape::default(); 2])
+        .unwrap()
+        .with_correlations(vec![0.5, 0.9]);
+        let json = serde_json::to_value(&spectrum).unwrap();
+        assert_eq!(json["coordinates"], serde_json::json!([100, 200]));
+        let read: Spectrum = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(read, spectrum);
+        for (field, value) in [
+            ("intensities", serde_json::json!([10.0])),
+            ("coordinates", serde_json::json!([100, 200, 300])),
+            ("peak_shapes", serde_json::json!([])),
+            ("correlations", serde_json::json!([0.5])),
+            ("coordinates", serde_json::json!([100, u32::MAX])),
+        ] {
+            let mut json = json.clone();
+            json[field] = value;
+            assert!(serde_json::from_value::<Spectrum>(json).is_err());
+        }
+    }
+}
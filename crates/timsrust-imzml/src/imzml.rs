@@ -0,0 +1,552 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use sha1_smol::Sha1;
+use timsrust_core::{MSLevel, Mz, Spectrum};
+use uuid::Uuid;
+
+/// Layout of the binary data in the `.ibd` file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImzMLMode {
+    /// All spectra share a single m/z array, which is written only once.
+    Continuous,
+    /// Every spectrum stores its own m/z array.
+    #[default]
+    Processed,
+}
+
+/// Writes spectra with pixel coordinates to an imzML/ibd file pair.
+///
+/// The `.ibd` file is created next to the `.imzML` file. Binary data is
+/// streamed to the `.ibd` file while writing, the XML metadata (including
+/// the SHA-1 checksum of the `.ibd` file) is only written by
+/// [`finalize`](ImzMLWriter::finalize).
+///
+/// # Examples
+///
+/// ```
+/// use timsrust_core::{IsolationWindow, Mz, Spectrum};
+/// use timsrust_imzml::{ImzMLMode, ImzMLWriter};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("run.imzML");
+/// let mut writer = ImzMLWriter::new(&path, ImzMLMode::Processed).unwrap();
+/// let spectrum = Spectrum::new(
+///     vec![10.0, 20.0],
+///     0,
+///     None,
+///     vec![Mz::from(100.0), Mz::from(200.0)],
+///     IsolationWindow::default(),
+/// );
+/// writer.write(&spectrum, 1, 1).unwrap();
+/// writer.finalize().unwrap();
+/// assert!(dir.path().join("run.ibd").exists());
+/// ```
+pub struct ImzMLWriter {
+    imzml_path: PathBuf,
+    ibd: BufWriter<File>,
+    mode: ImzMLMode,
+    uuid: Uuid,
+    sha1: Sha1,
+    offset: u64,
+    shared_mz: Option<(Vec<f64>, ExternalArray)>,
+    entries: Vec<ImzMLEntry>,
+    centroided: bool,
+}
+
+impl ImzMLWriter {
+    /// Creates the `.ibd` file and writes its UUID header.
+    ///
+    /// The `.ibd` path is derived from `output_path` by replacing its
+    /// extension.
+    pub fn new(
+        output_path: impl AsRef<Path>,
+        mode: ImzMLMode,
+    ) -> Result<Self, ImzMLError> {
+        let imzml_path = output_path.as_ref().to_path_buf();
+        let ibd_path = imzml_path.with_extension("ibd");
+        let ibd = BufWriter::new(File::create(ibd_path)?);
+        let mut writer = Self {
+            imzml_path,
+            ibd,
+            mode,
+            uuid: Uuid::new_v4(),
+            sha1: Sha1::new(),
+            offset: 0,
+            shared_mz: None,
+            entries: vec![],
+            centroided: true,
+        };
+        let uuid_bytes = *writer.uuid.as_bytes();
+        writer.write_bytes(&uuid_bytes)?;
+        Ok(writer)
+    }
+
+    pub fn mode(&self) -> ImzMLMode {
+        self.mode
+    }
+
+    /// Marks all spectra as centroid (default) or profile spectra.
+    pub fn set_centroided(&mut self, centroided: bool) {
+        self.centroided = centroided;
+    }
+
+    pub fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends an MS1 spectrum at pixel position `(x, y)`.
+    ///
+    /// Pixel positions are 1-based, as required by imzML. In continuous
+    /// mode all spectra need to have the exact same m/z values.
+    pub fn write(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        x: u32,
+        y: u32,
+    ) -> Result<(), ImzMLError> {
+        self.write_with_ms_level(spectrum, x, y, MSLevel::MS1)
+    }
+
+    /// Appends a spectrum of the given MS level at pixel position `(x, y)`.
+    ///
+    /// Spectra of an unknown level are written as plain mass spectra.
+    pub fn write_with_ms_level(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        x: u32,
+        y: u32,
+        ms_level: MSLevel,
+    ) -> Result<(), ImzMLError> {
+        if x == 0 || y == 0 {
+            return Err(ImzMLError::InvalidPixel { x, y });
+        }
+        let mz_values: Vec<f64> = spectrum
+            .mz_values()
+            .iter()
+            .map(|&mz| f64::from(mz))
+            .collect();
+        let mz = match self.mode {
+            ImzMLMode::Processed => self.write_mz_values(&mz_values)?,
+            ImzMLMode::Continuous => match &self.shared_mz {
+                Some((shared, array)) if *shared == mz_values => *array,
+                Some(_) => {
+                    return Err(ImzMLError::MzArrayMismatch(spectrum.index()));
+                },
+                None => {
+                    let array = self.write_mz_values(&mz_values)?;
+                    self.shared_mz = Some((mz_values, array));
+                    array
+                },
+            },
+        };
+        let intensity = self.write_intensities(spectrum.intensities())?;
+        self.entries.push(ImzMLEntry {
+            x,
+            y,
+            tic: spectrum.intensities().iter().sum(),
+            ms_level,
+            mz,
+            intensity,
+        });
+        Ok(())
+    }
+
+    /// Flushes the `.ibd` file and writes the `.imzML` metadata.
+    pub fn finalize(mut self) -> Result<(), ImzMLError> {
+        self.ibd.flush()?;
+        let xml = self.to_xml();
+        let mut file = BufWriter::new(File::create(&self.imzml_path)?);
+        file.write_all(xml.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), ImzMLError> {
+        self.ibd.write_all(bytes)?;
+        self.sha1.update(bytes);
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn write_mz_values(
+        &mut self,
+        mz_values: &[f64],
+    ) -> Result<ExternalArray, ImzMLError> {
+        let bytes: Vec<u8> =
+            mz_values.iter().flat_map(|mz| mz.to_le_bytes()).collect();
+        self.write_array(&bytes, mz_values.len())
+    }
+
+    fn write_intensities(
+        &mut self,
+        intensities: &[f64],
+    ) -> Result<ExternalArray, ImzMLError> {
+        let bytes: Vec<u8> = intensities
+            .iter()
+            .flat_map(|&intensity| (intensity as f32).to_le_bytes())
+            .collect();
+        self.write_array(&bytes, intensities.len())
+    }
+
+    fn write_array(
+        &mut self,
+        bytes: &[u8],
+        length: usize,
+    ) -> Result<ExternalArray, ImzMLError> {
+        let array = ExternalArray {
+            offset: self.offset,
+            length,
+            encoded_length: bytes.len(),
+        };
+        self.write_bytes(bytes)?;
+        Ok(array)
+    }
+
+    fn to_xml(&self) -> String {
+        let mut xml = String::with_capacity(1024 + self.entries.len() * 1024);
+        self.write_xml_header(&mut xml);
+        self.write_xml_spectra(&mut xml);
+        xml.push_str("    </spectrumList>\n  </run>\n</mzML>\n");
+        xml
+    }
+
+    fn write_xml_header(&self, xml: &mut String) {
+        let mut ms_levels: Vec<MSLevel> = vec![];
+        for entry in &self.entries {
+            if !ms_levels.contains(&entry.ms_level) {
+                ms_levels.push(entry.ms_level);
+            }
+        }
+        if ms_levels.is_empty() {
+            ms_levels.push(MSLevel::MS1);
+        }
+        let file_content: String = ms_levels
+            .into_iter()
+            .map(|ms_level| spectrum_param(ms_level) + "\n      ")
+            .collect();
+        let mode = match self.mode {
+            ImzMLMode::Continuous => ims_param("IMS:1000030", "continuous", ""),
+            ImzMLMode::Processed => ims_param("IMS:1000031", "processed", ""),
+        };
+        let uuid = format!("{{{}}}", self.uuid.hyphenated()).to_uppercase();
+        let sha1 = self.sha1.digest().to_string().to_uppercase();
+        let max_x = self.entries.iter().map(|e| e.x).max().unwrap_or(0);
+        let max_y = self.entries.iter().map(|e| e.y).max().unwrap_or(0);
+        let _ = write!(
+            xml,
+            r#"<?xml version="1.0" encoding="ISO-8859-1"?>
+<mzML xmlns="http://psi.hupo.org/ms/mzml" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.0.xsd" version="1.1">
+  <cvList count="3">
+    <cv id="MS" fullName="Proteomics Standards Initiative Mass Spectrometry Ontology" version="4.1.0" URI="https://raw.githubusercontent.com/HUPO-PSI/psi-ms-CV/master/psi-ms.obo"/>
+    <cv id="UO" fullName="Unit Ontology" version="releases/2020-03-10" URI="http://ontologies.berkeleybop.org/uo.obo"/>
+    <cv id="IMS" fullName="Imaging MS Ontology" version="1.1.0" URI="https://raw.githubusercontent.com/imzML/imzML/master/imagingMS.obo"/>
+  </cvList>
+  <fileDescription>
+    <fileContent>
+      {}{}
+      {}
+      {}
+    </fileContent>
+  </fileDescription>
+  <referenceableParamGroupList count="2">
+    <referenceableParamGroup id="mzArray">
+      {}
+      <cvParam cvRef="MS" accession="MS:1000514" name="m/z array" value="" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+      {}
+      {}
+    </referenceableParamGroup>
+    <referenceableParamGroup id="intensityArray">
+      {}
+      <cvParam cvRef="MS" accession="MS:1000515" name="intensity array" value="" unitCvRef="MS" unitAccession="MS:1000131" unitName="number of detector counts"/>
+      {}
+      {}
+    </referenceableParamGroup>
+  </referenceableParamGroupList>
+  <softwareList count="1">
+    <software id="timsrust" version="{}">
+      {}
+    </software>
+  </softwareList>
+  <scanSettingsList count="1">
+    <scanSettings id="scanSettings0">
+      {}
+      {}
+    </scanSettings>
+  </scanSettingsList>
+  <instrumentConfigurationList count="1">
+    <instrumentConfiguration id="IC0">
+      {}
+    </instrumentConfiguration>
+  </instrumentConfigurationList>
+  <dataProcessingList count="1">
+    <dataProcessing id="timsrust_export">
+      <processingMethod order="0" softwareRef="timsrust">
+        {}
+      </processingMethod>
+    </dataProcessing>
+  </dataProcessingList>
+  <run id="run0" defaultInstrumentConfigurationRef="IC0">
+    <spectrumList count="{}" defaultDataProcessingRef="timsrust_export">
+"#,
+            file_content,
+            mode,
+            ims_param("IMS:1000080", "universally unique identifier", &uuid),
+            ims_param("IMS:1000091", "ibd SHA-1", &sha1),
+            cv_param("MS:1000523", "64-bit float", ""),
+            cv_param("MS:1000576", "no compression", ""),
+            ims_param("IMS:1000101", "external data", "true"),
+            cv_param("MS:1000521", "32-bit float", ""),
+            cv_param("MS:1000576", "no compression", ""),
+            ims_param("IMS:1000101", "external data", "true"),
+            env!("CARGO_PKG_VERSION"),
+            cv_param(
+                "MS:1000799",
+                "custom unreleased software tool",
+                "timsrust"
+            ),
+            ims_param(
+                "IMS:1000042",
+                "max count of pixels x",
+                &max_x.to_string()
+            ),
+            ims_param(
+                "IMS:1000043",
+                "max count of pixels y",
+                &max_y.to_string()
+            ),
+            cv_param("MS:1000122", "Bruker Daltonics instrument model", ""),
+            cv_param("MS:1000544", "Conversion to mzML", ""),
+            self.entries.len(),
+        );
+    }
+
+    fn write_xml_spectra(&self, xml: &mut String) {
+        let spectrum_type = if self.centroided {
+            cv_param("MS:1000127", "centroid spectrum", "")
+        } else {
+            cv_param("MS:1000128", "profile spectrum", "")
+        };
+        for (index, entry) in self.entries.iter().enumerate() {
+            let _ = write!(
+                xml,
+                r#"      <spectrum id="Scan={}" index="{}" defaultArrayLength="0">
+        {}
+        {}
+        {}
+        <scanList count="1">
+          {}
+          <scan instrumentConfigurationRef="IC0">
+            {}
+            {}
+          </scan>
+        </scanList>
+        <binaryDataArrayList count="2">
+          <binaryDataArray encodedLength="0">
+            <referenceableParamGroupRef ref="mzArray"/>
+{}            <binary/>
+          </binaryDataArray>
+          <binaryDataArray encodedLength="0">
+            <referenceableParamGroupRef ref="intensityArray"/>
+{}            <binary/>
+          </binaryDataArray>
+        </binaryDataArrayList>
+      </spectrum>
+"#,
+                index + 1,
+                index,
+                spectrum_params(entry.ms_level),
+                spectrum_type,
+                cv_param(
+                    "MS:1000285",
+                    "total ion current",
+                    &entry.tic.to_string()
+                ),
+                cv_param("MS:1000795", "no combination", ""),
+                ims_param("IMS:1000050", "position x", &entry.x.to_string()),
+                ims_param("IMS:1000051", "position y", &entry.y.to_string()),
+                entry.mz.to_xml(),
+                entry.intensity.to_xml(),
+            );
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ExternalArray {
+    offset: u64,
+    length: usize,
+    encoded_length: usize,
+}
+
+impl ExternalArray {
+    fn to_xml(self) -> String {
+        format!(
+            "            {}\n            {}\n            {}\n",
+            ims_param(
+                "IMS:1000103",
+                "external array length",
+                &self.length.to_string()
+            ),
+            ims_param(
+                "IMS:1000104",
+                "external encoded length",
+                &self.encoded_length.to_string()
+            ),
+            ims_param(
+                "IMS:1000102",
+                "external offset",
+                &self.offset.to_string()
+            ),
+        )
+    }
+}
+
+/// Shifts pixel positions so that the smallest x and y become 1.
+pub(crate) fn to_one_based(pixels: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let min_x = pixels.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let min_y = pixels.iter().map(|(_, y)| *y).min().unwrap_or(0);
+    pixels
+        .iter()
+        .map(|(x, y)| (x - min_x + 1, y - min_y + 1))
+        .collect()
+}
+
+#[derive(Debug)]
+struct ImzMLEntry {
+    x: u32,
+    y: u32,
+    tic: f64,
+    ms_level: MSLevel,
+    mz: ExternalArray,
+    intensity: ExternalArray,
+}
+
+/// The spectrum type term of an MS level.
+fn spectrum_param(ms_level: MSLevel) -> String {
+    match ms_level {
+        MSLevel::MS1 => cv_param("MS:1000579", "MS1 spectrum", ""),
+        MSLevel::MS2 => cv_param("MS:1000580", "MSn spectrum", ""),
+        MSLevel::Unknown => cv_param("MS:1000294", "mass spectrum", ""),
+    }
+}
+
+/// The spectrum type and, if known, the MS level of a spectrum.
+fn spectrum_params(ms_level: MSLevel) -> String {
+    let level = match ms_level {
+        MSLevel::MS1 => "1",
+        MSLevel::MS2 => "2",
+        MSLevel::Unknown => return spectrum_param(ms_level),
+    };
+    format!(
+        "{}\n        {}",
+        spectrum_param(ms_level),
+        cv_param("MS:1000511", "ms level", level)
+    )
+}
+
+fn cv_param(accession: &str, name: &str, value: &str) -> String {
+    format!(
+        r#"<cvParam cvRef="MS" accession="{accession}" name="{name}" value="{value}"/>"#
+    )
+}
+
+fn ims_param(accession: &str, name: &str, value: &str) -> String {
+    format!(
+        r#"<cvParam cvRef="IMS" accession="{accession}" name="{name}" value="{value}"/>"#
+    )
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImzMLError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error(
+        "Spectrum {0} has different m/z values than the shared m/z array of a continuous imzML file"
+    )]
+    MzArrayMismatch(usize),
+    #[error("Invalid pixel ({x}, {y}), imzML pixels are 1-based")]
+    InvalidPixel { x: u32, y: u32 },
+    #[error("Spectrum {0} has no pixel coordinates")]
+    MissingPixel(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::IsolationWindow;
+
+    use super::*;
+
+    fn spectrum(mz_values: &[f64], intensities: &[f64]) -> Spectrum<Mz> {
+        Spectrum::new(
+            intensities.to_vec(),
+            0,
+            None,
+            mz_values.iter().map(|&mz| Mz::from(mz)).collect(),
+            IsolationWindow::default(),
+        )
+    }
+
+    #[test]
+    fn processed_offsets_and_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.imzML");
+        let mut writer = ImzMLWriter::new(&path, ImzMLMode::Processed).unwrap();
+        let uuid = *writer.uuid();
+        writer
+            .write(&spectrum(&[100.0, 200.0], &[1.0, 2.0]), 1, 1)
+            .unwrap();
+        writer.write(&spectrum(&[150.0], &[3.0]), 2, 1).unwrap();
+        writer.finalize().unwrap();
+        let ibd = std::fs::read(dir.path().join("run.ibd")).unwrap();
+        assert_eq!(&ibd[..16], uuid.as_bytes());
+        assert_eq!(ibd.len(), 16 + 2 * 12 + 12);
+        let xml = std::fs::read_to_string(&path).unwrap();
+        let sha1 = Sha1::from(&ibd).digest().to_string().to_uppercase();
+        assert!(xml.contains(&sha1));
+        assert!(xml.contains(r#"name="processed""#));
+        assert!(xml.contains(r#"name="external offset" value="40""#));
+        assert!(xml.contains(r#"name="external offset" value="48""#));
+        assert!(xml.contains(r#"name="max count of pixels x" value="2""#));
+        assert!(xml.contains("mzML1.1.0.xsd"));
+        assert!(!xml.contains(r#"accession="MS:1000031""#));
+    }
+
+    #[test]
+    fn continuous_shares_mz_array() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.imzML");
+        let mut writer =
+            ImzMLWriter::new(&path, ImzMLMode::Continuous).unwrap();
+        writer
+            .write(&spectrum(&[100.0, 200.0], &[1.0, 2.0]), 1, 1)
+            .unwrap();
+        writer
+            .write(&spectrum(&[100.0, 200.0], &[3.0, 4.0]), 1, 2)
+            .unwrap();
+        assert!(matches!(
+            writer.write(&spectrum(&[100.0], &[1.0]), 1, 3),
+            Err(ImzMLError::MzArrayMismatch(0))
+        ));
+        writer.finalize().unwrap();
+        let ibd = std::fs::read(dir.path().join("run.ibd")).unwrap();
+        assert_eq!(ibd.len(), 16 + 2 * 8 + 2 * 2 * 4);
+        let xml = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            xml.matches(r#"name="external offset" value="16""#).count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,7 @@
+mod imzml;
+mod tdf;
+mod tsf;
+
+pub use imzml::{ImzMLError, ImzMLMode, ImzMLWriter};
+pub use tdf::{TDFImzMLError, write_tdf};
+pub use tsf::{TSFImzMLError, write_tsf};
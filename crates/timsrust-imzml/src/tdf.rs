@@ -0,0 +1,285 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use serde::Deserialize;
+use timsrust_core::{
+    Frame, IsolationWindow, Spectrum, TofIndex,
+    io::formats::sql::{SqlError, SqlReader},
+};
+use timsrust_tdf::{FrameReaderError, TDFPath, TDFPathError, TdfFrameReader};
+
+use crate::{ImzMLError, ImzMLMode, ImzMLWriter, imzml::to_one_based};
+
+#[derive(Deserialize)]
+struct SqlMaldiFrameInfo {
+    #[serde(rename = "Frame")]
+    frame: i64,
+    #[serde(rename = "XIndexPos")]
+    x: i64,
+    #[serde(rename = "YIndexPos")]
+    y: i64,
+}
+
+/// Exports all imaging frames of a TDF run to imzML.
+///
+/// Only frames listed in the `MaldiFrameInfo` table are written. The ion
+/// mobility dimension is collapsed by summing the intensities of each tof
+/// index over all scans of a frame. Pixel positions are shifted so that the
+/// smallest x and y position become 1.
+///
+/// In [`ImzMLMode::Continuous`], all spectra share the tof indices seen in
+/// any frame, with an intensity of zero where a frame has no ions. This
+/// reads every frame twice.
+///
+/// # Returns
+/// The number of spectra that were written.
+///
+/// # Example
+/// ```no_run
+/// use timsrust_imzml::{ImzMLMode, write_tdf};
+/// let count = write_tdf("imaging.d", "imaging.imzML", ImzMLMode::Processed);
+/// ```
+pub fn write_tdf(
+    in_path: impl AsRef<str>,
+    out_path: impl AsRef<Path>,
+    mode: ImzMLMode,
+) -> Result<usize, TDFImzMLError> {
+    let tdf_path = TDFPath::new(in_path)?;
+    let reader = SqlReader::from(tdf_path.tdf().as_ref())?;
+    if !reader.tables()?.iter().any(|t| t == "MaldiFrameInfo") {
+        return Err(TDFImzMLError::NoImagingFrames);
+    }
+    let mut frames = reader
+        .from_table::<SqlMaldiFrameInfo>("MaldiFrameInfo")?
+        .read_all()?;
+    frames.sort_by_key(|m| m.frame);
+    let pixels = frames
+        .iter()
+        .map(|m| match (u32::try_from(m.x), u32::try_from(m.y)) {
+            (Ok(x), Ok(y)) => Ok((x, y)),
+            _ => Err(TDFImzMLError::InvalidPixel {
+                frame: m.frame,
+                x: m.x,
+                y: m.y,
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let frame_reader = TdfFrameReader::new(tdf_path.as_ref())?;
+    let mz_converter = timsrust_tdf::Tof2MzConverter::new(tdf_path.as_ref());
+    let get_frame = |m: &SqlMaldiFrameInfo| {
+        frame_reader
+            .get_frame(m.frame as usize)
+            .map_err(FrameReaderError::from)
+    };
+    let shared_tof_indices = match mode {
+        ImzMLMode::Processed => None,
+        ImzMLMode::Continuous => {
+            let mut tof_indices = BTreeSet::new();
+            for m in &frames {
+                tof_indices.extend(get_frame(m)?.ions().tof_indices());
+            }
+            Some(tof_indices.into_iter().collect::<Vec<_>>())
+        },
+    };
+    let mut writer = ImzMLWriter::new(out_path, mode)?;
+    for (index, (m, (x, y))) in
+        frames.iter().zip(to_one_based(&pixels)).enumerate()
+    {
+        let frame = get_frame(m)?;
+        let mut spectrum = collapse_frame(&frame, index);
+        if let Some(tof_indices) = &shared_tof_indices {
+            spectrum = fill_tof_indices(&spectrum, tof_indices);
+        }
+        let spectrum = spectrum.to_mz_spectrum(&mz_converter);
+        writer.write_with_ms_level(&spectrum, x, y, frame.info().ms_level())?;
+    }
+    let count = writer.len();
+    writer.finalize()?;
+    Ok(count)
+}
+
+/// Sums the intensities of each tof index over all scans of a frame.
+fn collapse_frame(frame: &Frame, index: usize) -> Spectrum {
+    let mut peaks: BTreeMap<TofIndex, f64> = BTreeMap::new();
+    let ions = frame.ions();
+    for (&tof, &intensity) in ions.tof_indices().iter().zip(ions.intensities())
+    {
+        *peaks.entry(tof).or_default() += u32::from(intensity) as f64;
+    }
+    let (tof_indices, intensities) = peaks.into_iter().unzip();
+    Spectrum::new(
+        intensities,
+        index,
+        None,
+        tof_indices,
+        IsolationWindow::default(),
+    )
+}
+
+/// Spreads the peaks of a spectrum over `tof_indices`, a sorted superset
+/// of its own tof indices.
+fn fill_tof_indices(spectrum: &Spectrum, tof_indices: &[TofIndex]) -> Spectrum {
+    let mut intensities = vec![0.0; tof_indices.len()];
+    for (tof, &intensity) in
+        spectrum.tof_indices().iter().zip(spectrum.intensities())
+    {
+        if let Ok(position) = tof_indices.binary_search(tof) {
+            intensities[position] = intensity;
+        }
+    }
+    Spectrum::new(
+        intensities,
+        spectrum.index(),
+        None,
+        tof_indices.to_vec(),
+        IsolationWindow::default(),
+    )
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TDFImzMLError {
+    #[error("{0}")]
+    ImzML(#[from] ImzMLError),
+    #[error("{0}")]
+    TDFPath(#[from] TDFPathError),
+    #[error("{0}")]
+    Sql(#[from] SqlError),
+    #[error("{0}")]
+    FrameReader(#[from] FrameReaderError),
+    #[error("TDF run has no MaldiFrameInfo table")]
+    NoImagingFrames,
+    #[error("Frame {frame} has an invalid pixel position ({x}, {y})")]
+    InvalidPixel { frame: i64, x: i64, y: i64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imaging_run(dir: &Path) -> String {
+        let source = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/test.d");
+        let run = dir.join("imaging.d");
+        std::fs::create_dir(&run).unwrap();
+        for file in ["analysis.tdf", "analysis.tdf_bin"] {
+            std::fs::copy(format!("{source}/{file}"), run.join(file)).unwrap();
+        }
+        // The fixture predates the `GlobalMetadata` table name.
+        let connection =
+            rusqlite::Connection::open(run.join("analysis.tdf")).unwrap();
+        connection
+            .execute_batch(
+                "ALTER TABLE GlobalMetaData RENAME TO Metadata;
+                ALTER TABLE Metadata RENAME TO GlobalMetadata;
+                CREATE TABLE MaldiFrameInfo (
+                    Frame INTEGER, XIndexPos INTEGER, YIndexPos INTEGER
+                );
+                INSERT INTO MaldiFrameInfo VALUES (3, 11, 20), (1, 10, 20);",
+            )
+            .unwrap();
+        run.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn imaging_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = imaging_run(dir.path());
+        let path = dir.path().join("imaging.imzML");
+        let count = write_tdf(&run, &path, ImzMLMode::Processed).unwrap();
+        assert_eq!(count, 2);
+        let xml = std::fs::read_to_string(&path).unwrap();
+        assert!(xml.contains(r#"name="position x" value="1""#));
+        assert!(xml.contains(r#"name="position x" value="2""#));
+        assert!(xml.contains(r#"name="max count of pixels y" value="1""#));
+        assert!(xml.contains(r#"name="ms level" value="1""#));
+        assert!(!xml.contains(r#"name="ms level" value="2""#));
+    }
+
+    #[test]
+    fn continuous_shares_tof_indices() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = imaging_run(dir.path());
+        rusqlite::Connection::open(format!("{run}/analysis.tdf"))
+            .unwrap()
+            .execute_batch("INSERT INTO MaldiFrameInfo VALUES (2, 12, 20);")
+            .unwrap();
+        let frame_reader = TdfFrameReader::new(&run).unwrap();
+        let frames: Vec<_> = (1..=3)
+            .map(|index| frame_reader.get_frame(index).unwrap())
+            .collect();
+        assert_ne!(
+            frames[0].ions().tof_indices(),
+            frames[2].ions().tof_indices()
+        );
+        let tof_count = frames
+            .iter()
+            .flat_map(|frame| frame.ions().tof_indices())
+            .collect::<BTreeSet<_>>()
+            .len();
+        let path = dir.path().join("imaging.imzML");
+        let count = write_tdf(&run, &path, ImzMLMode::Continuous).unwrap();
+        assert_eq!(count, 3);
+        let xml = std::fs::read_to_string(&path).unwrap();
+        assert!(xml.contains(r#"name="continuous""#));
+        let length =
+            format!(r#"name="external array length" value="{tof_count}""#);
+        assert_eq!(xml.matches(&length).count(), 6);
+        assert_eq!(
+            xml.matches(r#"name="external offset" value="16""#).count(),
+            3
+        );
+        assert_eq!(xml.matches(r#"name="ms level" value="1""#).count(), 2);
+        assert_eq!(xml.matches(r#"name="ms level" value="2""#).count(), 1);
+        assert!(xml.contains(r#"name="MSn spectrum""#));
+        let ibd = std::fs::read(dir.path().join("imaging.ibd")).unwrap();
+        assert_eq!(ibd.len(), 16 + tof_count * 8 + 3 * tof_count * 4);
+    }
+
+    #[test]
+    fn fills_tof_indices() {
+        let tof = |index: u32| TofIndex::try_from(index).unwrap();
+        let spectrum = Spectrum::new(
+            vec![2.0, 3.0],
+            4,
+            None,
+            vec![tof(5), tof(9)],
+            IsolationWindow::default(),
+        );
+        let filled = fill_tof_indices(&spectrum, &[tof(1), tof(5), tof(9)]);
+        assert_eq!(filled.intensities(), &[0.0, 2.0, 3.0]);
+        assert_eq!(filled.tof_indices(), &[tof(1), tof(5), tof(9)]);
+        assert_eq!(filled.index(), 4);
+    }
+
+    #[test]
+    fn collapses_scans() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = imaging_run(dir.path());
+        let frame = TdfFrameReader::new(&run).unwrap().get_frame(1).unwrap();
+        let spectrum = collapse_frame(&frame, 0);
+        let total: u32 = frame
+            .ions()
+            .intensities()
+            .iter()
+            .map(|&i| u32::from(i))
+            .sum();
+        assert_eq!(spectrum.intensities().iter().sum::<f64>(), total as f64);
+        assert!(spectrum.tof_indices().windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn requires_imaging_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = imaging_run(dir.path());
+        rusqlite::Connection::open(format!("{run}/analysis.tdf"))
+            .unwrap()
+            .execute_batch("DROP TABLE MaldiFrameInfo;")
+            .unwrap();
+        let path = dir.path().join("run.imzML");
+        assert!(matches!(
+            write_tdf(&run, path, ImzMLMode::Processed),
+            Err(TDFImzMLError::NoImagingFrames)
+        ));
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use timsrust_core::utils::reader::Reader;
+use timsrust_tsf::{TSFSpectrumReader, TSFSpectrumReaderError};
+
+use crate::{ImzMLError, ImzMLMode, ImzMLWriter, imzml::to_one_based};
+
+/// Exports all spectra of a TSF imaging run to imzML.
+///
+/// Pixel positions are taken from the `MaldiFrameInfo` table and shifted so
+/// that the smallest x and y position become 1.
+///
+/// # Returns
+/// The number of spectra that were written.
+///
+/// # Example
+/// ```no_run
+/// use timsrust_imzml::{ImzMLMode, write_tsf};
+/// let count = write_tsf("imaging.d", "imaging.imzML", ImzMLMode::Processed);
+/// ```
+pub fn write_tsf(
+    in_path: impl AsRef<str>,
+    out_path: impl AsRef<Path>,
+    mode: ImzMLMode,
+) -> Result<usize, TSFImzMLError> {
+    let reader = TSFSpectrumReader::new(in_path.as_ref())?;
+    let pixels = (0..reader.len())
+        .map(|index| reader.pixel(index).ok_or(ImzMLError::MissingPixel(index)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut writer = ImzMLWriter::new(out_path, mode)?;
+    writer.set_centroided(reader.is_centroided());
+    for (index, (x, y)) in to_one_based(&pixels).into_iter().enumerate() {
+        let spectrum = reader.get(index)?.to_mz_spectrum(reader.mz_converter());
+        writer.write(&spectrum, x, y)?;
+    }
+    let count = writer.len();
+    writer.finalize()?;
+    Ok(count)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TSFImzMLError {
+    #[error("{0}")]
+    ImzML(#[from] ImzMLError),
+    #[error("{0}")]
+    TSFSpectrumReader(#[from] TSFSpectrumReaderError),
+}
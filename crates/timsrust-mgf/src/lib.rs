@@ -1,3 +1,5 @@
 mod mgf;
+mod reader;
 
-pub use mgf::MGFWriter;
+pub use mgf::{MGFTitleStyle, MGFWriter, MGFWriterConfig};
+pub use reader::{MGFError, MGFReader, read_mgf};
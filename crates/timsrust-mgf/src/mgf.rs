@@ -1,77 +1,294 @@
 use std::{
     fs::File,
     io::{BufWriter, Write},
+    path::Path,
 };
 
 use timsrust_core::{Mz, Spectrum};
 
+use crate::MGFError;
+
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// How the `TITLE` of every spectrum is formatted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum MGFTitleStyle {
+    /// `index:1, im:0.9000, intensity:..., frame:..., ce:..., width:...`,
+    /// which [`MGFReader`](crate::MGFReader) reads back into a precursor.
+    #[default]
+    KeyValue,
+    /// `run.scan.scan.charge` as used by the Trans-Proteomic Pipeline, with
+    /// the spectrum index as scan and the file stem of the output as run.
+    Tpp,
+    /// `NativeID:"index=1"` with the spectrum index, in the style of the
+    /// "multiple peak list" native id format. mzML exports number their
+    /// spectra in write order instead, so these ids do not identify spectra
+    /// in an mzML file of the same run.
+    NativeId,
+}
+
+/// Formatting options of an [`MGFWriter`].
+///
+/// # Example
+/// ```
+/// use timsrust_mgf::{MGFTitleStyle, MGFWriterConfig};
+/// let config = MGFWriterConfig {
+///     title_style: MGFTitleStyle::Tpp,
+///     scans: true,
+///     ..Default::default()
+/// };
+/// assert_eq!(config.mz_precision, 4);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MGFWriterConfig {
+    pub title_style: MGFTitleStyle,
+    /// Add the precursor ion mobility as `ION_MOBILITY`.
+    pub ion_mobility: bool,
+    /// Add the spectrum index as `SCANS`.
+    pub scans: bool,
+    /// Number of decimals of peak m/z values.
+    pub mz_precision: usize,
+    /// Number of decimals of peak intensities.
+    pub intensity_precision: usize,
+}
+
+impl Default for MGFWriterConfig {
+    fn default() -> Self {
+        Self {
+            title_style: MGFTitleStyle::default(),
+            ion_mobility: false,
+            scans: false,
+            mz_precision: 4,
+            intensity_precision: 0,
+        }
+    }
+}
+
+/// Writes spectra to a Mascot Generic Format file.
+///
+/// Spectra without a precursor (e.g. MS1 or TSF spectra) are written
+/// without `PEPMASS`, `CHARGE` and `RTINSECONDS`. Writes are buffered;
+/// call [`finalize`](MGFWriter::finalize) to flush them and observe any
+/// error.
+///
+/// # Examples
+///
+/// ```
+/// use timsrust_core::{IsolationWindow, Mz, Spectrum};
+/// use timsrust_mgf::{MGFWriter, MGFWriterConfig};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("run.mgf");
+/// let config = MGFWriterConfig {
+///     mz_precision: 2,
+///     ..Default::default()
+/// };
+/// let mut writer = MGFWriter::with_config(&path, config).unwrap();
+/// let spectrum = Spectrum::new(
+///     vec![10.0],
+///     0,
+///     None,
+///     vec![Mz::from(100.125)],
+///     IsolationWindow::default(),
+/// );
+/// writer.write(&spectrum).unwrap();
+/// writer.finalize().unwrap();
+/// let mgf = std::fs::read_to_string(&path).unwrap();
+/// assert!(mgf.contains("100.12\t10\n"));
+/// ```
 pub struct MGFWriter {
     file: BufWriter<File>,
+    config: MGFWriterConfig,
+    run: String,
 }
 
 impl MGFWriter {
-    pub fn new(output_path: &str) -> Self {
-        let file = File::create(output_path).expect("Failed to create file");
-        let writer = BufWriter::new(file);
-        Self { file: writer }
+    pub fn new(output_path: impl AsRef<Path>) -> Result<Self, MGFError> {
+        Self::with_config(output_path, MGFWriterConfig::default())
     }
 
-    pub fn write(&mut self, spectrum: &Spectrum<Mz>) {
-        self.file
-            .write_all("BEGIN IONS\n".as_bytes())
-            .expect("Failed to write BEGIN IONS");
-        self.file
-            .write_all(MGFEntry::write(spectrum).as_bytes())
-            .expect("Failed to write entry");
-        self.file
-            .write_all("END IONS\n".as_bytes())
-            .expect("Failed to write END IONS");
-        self.file.flush().expect("Failed to flush to file");
+    pub fn with_config(
+        output_path: impl AsRef<Path>,
+        config: MGFWriterConfig,
+    ) -> Result<Self, MGFError> {
+        let run = output_path
+            .as_ref()
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let file = File::create(output_path)?;
+        Ok(Self {
+            file: BufWriter::with_capacity(BUFFER_SIZE, file),
+            config,
+            run,
+        })
+    }
+
+    pub fn config(&self) -> &MGFWriterConfig {
+        &self.config
     }
-}
 
-pub(crate) struct MGFEntry;
-
-impl MGFEntry {
-    pub(crate) fn write_header<C>(spectrum: &Spectrum<C>) -> String {
-        // TODO
-        let precursor = spectrum.precursor().as_ref().unwrap();
-        let title = precursor.index();
-        let intensity = precursor.intensity().unwrap_or(0.0);
-        let charge = precursor.charge().map(i8::from).unwrap_or(0);
-        let ms2_data = format!(
-            "TITLE=index:{}, im:{:.4}, intensity:{:.4}, frame:{}, ce:{:.4}, width:{:.4}\nPEPMASS={:.4}\nCHARGE={}\nRTINSECONDS={:.2}\n",
-            title,
-            precursor.im(),
-            intensity,
-            precursor.frame_index(),
-            spectrum.isolation_window().collision_energy(),
-            spectrum.isolation_window().width(),
-            precursor.mz(),
-            charge,
-            precursor.rt()
-        );
-        ms2_data
+    pub fn write(&mut self, spectrum: &Spectrum<Mz>) -> Result<(), MGFError> {
+        self.file.write_all(b"BEGIN IONS\n")?;
+        self.write_header(spectrum)?;
+        self.write_peaks(spectrum)?;
+        self.file.write_all(b"END IONS\n")?;
+        Ok(())
     }
 
-    pub(crate) fn write_peaks(spectrum: &Spectrum<Mz>) -> String {
-        let capacity = spectrum.len() * 16; // Estimate capacity for mz and intensity pairs
-        let mut ms2_data: String = String::with_capacity(capacity);
-        for (mz, intensity) in spectrum
-            .mz_values()
-            .iter()
-            .zip(spectrum.intensities().iter())
+    /// Flushes all buffered spectra to the file.
+    pub fn finalize(mut self) -> Result<(), MGFError> {
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn write_header(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+    ) -> Result<(), MGFError> {
+        let title = self.title(spectrum);
+        writeln!(self.file, "TITLE={title}")?;
+        if let Some(precursor) = spectrum.precursor() {
+            let charge = precursor.charge().map(i8::from).unwrap_or(0);
+            write!(
+                self.file,
+                "PEPMASS={:.4}\nCHARGE={}\nRTINSECONDS={:.2}\n",
+                precursor.mz(),
+                charge,
+                precursor.rt()
+            )?;
+            if self.config.ion_mobility {
+                writeln!(
+                    self.file,
+                    "ION_MOBILITY={:.4}",
+                    f64::from(precursor.im())
+                )?;
+            }
+        }
+        if self.config.scans {
+            writeln!(self.file, "SCANS={}", spectrum.index())?;
+        }
+        Ok(())
+    }
+
+    fn title(&self, spectrum: &Spectrum<Mz>) -> String {
+        let index = spectrum.index();
+        let precursor = spectrum.precursor().as_ref();
+        match self.config.title_style {
+            MGFTitleStyle::KeyValue => match precursor {
+                Some(precursor) => format!(
+                    "index:{}, im:{:.4}, intensity:{:.4}, frame:{}, ce:{:.4}, width:{:.4}",
+                    precursor.index(),
+                    precursor.im(),
+                    precursor.intensity().unwrap_or(0.0),
+                    precursor.frame_index(),
+                    spectrum.isolation_window().collision_energy(),
+                    spectrum.isolation_window().width(),
+                ),
+                None => format!("index:{index}"),
+            },
+            MGFTitleStyle::Tpp => {
+                let charge = precursor
+                    .and_then(|precursor| *precursor.charge())
+                    .map(i8::from)
+                    .unwrap_or(0);
+                format!("{}.{index}.{index}.{charge}", self.run)
+            },
+            MGFTitleStyle::NativeId => format!("NativeID:\"index={index}\""),
+        }
+    }
+
+    fn write_peaks(&mut self, spectrum: &Spectrum<Mz>) -> Result<(), MGFError> {
+        let mz_precision = self.config.mz_precision;
+        let intensity_precision = self.config.intensity_precision;
+        for (mz, intensity) in
+            spectrum.mz_values().iter().zip(spectrum.intensities())
         {
-            ms2_data.push_str(&format!("{:.4}\t{:.0}\n", mz, intensity));
+            writeln!(
+                self.file,
+                "{:.*}\t{:.*}",
+                mz_precision,
+                f64::from(*mz),
+                intensity_precision,
+                intensity
+            )?;
         }
-        ms2_data
+        Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::{
+        Charge, FrameIndex, Im, IsolationWindow, Precursor, Rt, ScanIndex,
+    };
+
+    use super::*;
 
-    pub(crate) fn write(spectrum: &Spectrum<Mz>) -> String {
-        format!(
-            "{}{}",
-            Self::write_header(spectrum),
-            Self::write_peaks(spectrum)
+    fn spectrum(precursor: bool) -> Spectrum<Mz> {
+        let precursor = precursor.then(|| {
+            Precursor::new(
+                Mz::from(500.25),
+                Im::from(0.9),
+                Rt::from(60.5),
+                ScanIndex::try_from(0).unwrap(),
+                Some(Charge::try_from(2).unwrap()),
+                None,
+                3,
+                FrameIndex::try_from(12).unwrap(),
+            )
+        });
+        Spectrum::new(
+            vec![10.0],
+            3,
+            precursor,
+            vec![Mz::from(150.125)],
+            IsolationWindow::default(),
         )
     }
+
+    fn write(config: MGFWriterConfig, spectrum: &Spectrum<Mz>) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mgf");
+        let mut writer = MGFWriter::with_config(&path, config).unwrap();
+        writer.write(spectrum).unwrap();
+        writer.finalize().unwrap();
+        std::fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn without_precursor() {
+        let mgf = write(MGFWriterConfig::default(), &spectrum(false));
+        assert_eq!(mgf, "BEGIN IONS\nTITLE=index:3\n150.1250\t10\nEND IONS\n");
+    }
+
+    #[test]
+    fn title_styles_and_extra_fields() {
+        let config = MGFWriterConfig {
+            title_style: MGFTitleStyle::Tpp,
+            ion_mobility: true,
+            scans: true,
+            intensity_precision: 1,
+            ..Default::default()
+        };
+        let mgf = write(config, &spectrum(true));
+        assert!(mgf.contains("TITLE=run.3.3.2\n"));
+        assert!(mgf.contains("ION_MOBILITY=0.9000\n"));
+        assert!(mgf.contains("SCANS=3\n"));
+        assert!(mgf.contains("150.1250\t10.0\n"));
+        let config = MGFWriterConfig {
+            title_style: MGFTitleStyle::NativeId,
+            ..Default::default()
+        };
+        let mgf = write(config, &spectrum(true));
+        assert!(mgf.contains("TITLE=NativeID:\"index=3\"\n"));
+        assert!(!mgf.contains("SCANS"));
+    }
+
+    #[test]
+    fn create_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("run.mgf");
+        assert!(matches!(MGFWriter::new(path), Err(MGFError::Io(_))));
+    }
 }
@@ -0,0 +1,491 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use timsrust_core::{
+    Charge, FrameIndex, Im, IsolationWindow, Mz, Precursor, Rt, ScanIndex,
+    Spectrum,
+};
+
+/// Streams spectra from an MGF file.
+///
+/// Every `BEGIN IONS` ... `END IONS` block yields one [`Spectrum`]. A
+/// [`Precursor`] is built when the block has a `PEPMASS`, using `CHARGE`,
+/// `RTINSECONDS` and the `key:value` pairs of the `TITLE` as written by
+/// [`MGFWriter`](crate::MGFWriter) (`index`, `im`, `intensity`, `frame`,
+/// `ce` and `width`). `ION_MOBILITY` and the first scan of `SCANS` take
+/// precedence over the `im` and `index` of the title. The isolation window
+/// is centred on `PEPMASS`. Unknown fields are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use timsrust_mgf::MGFReader;
+///
+/// let mgf = "BEGIN IONS\nTITLE=index:7, im:0.9\nPEPMASS=500.25\nCHARGE=2+\n\
+///            RTINSECONDS=60.0\n100.0 10\n200.0 20\nEND IONS\n";
+/// let mut reader = MGFReader::from_reader(mgf.as_bytes());
+/// let spectrum = reader.next().unwrap().unwrap();
+/// assert_eq!(spectrum.index(), 7);
+/// assert_eq!(spectrum.len(), 2);
+/// assert!(reader.next().is_none());
+/// ```
+pub struct MGFReader<R> {
+    lines: std::io::Lines<R>,
+    line_number: usize,
+    count: usize,
+}
+
+impl MGFReader<BufReader<File>> {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, MGFError> {
+        Ok(Self::from_reader(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> MGFReader<R> {
+    pub fn from_reader(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line_number: 0,
+            count: 0,
+        }
+    }
+
+    fn next_line(&mut self) -> Option<Result<String, MGFError>> {
+        let line = self.lines.next()?;
+        self.line_number += 1;
+        Some(line.map_err(MGFError::from))
+    }
+
+    fn parse_error(&self, message: impl Into<String>) -> MGFError {
+        MGFError::Parse {
+            line: self.line_number,
+            message: message.into(),
+        }
+    }
+
+    fn read_entry(&mut self) -> Result<Spectrum<Mz>, MGFError> {
+        let mut entry = MGFEntryFields::default();
+        loop {
+            let line = match self.next_line() {
+                Some(line) => line?,
+                None => return Err(self.parse_error("Missing END IONS")),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "END IONS" {
+                break;
+            }
+            if line.starts_with(|c: char| c.is_ascii_digit()) {
+                let mut values = line.split_whitespace();
+                let (Some(mz), Some(intensity)) = (
+                    values.next().and_then(|v| v.parse::<f64>().ok()),
+                    values.next().and_then(|v| v.parse::<f64>().ok()),
+                ) else {
+                    return Err(
+                        self.parse_error(format!("Invalid peak '{line}'"))
+                    );
+                };
+                entry.mz_values.push(Mz::from(mz));
+                entry.intensities.push(intensity);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(self.parse_error(format!("Invalid line '{line}'")));
+            };
+            match key.trim().to_uppercase().as_str() {
+                "TITLE" => entry.parse_title(value),
+                "PEPMASS" => {
+                    let mut values = value.split_whitespace();
+                    entry.pepmass = Some(
+                        values.next().and_then(|v| v.parse().ok()).ok_or_else(
+                            || {
+                                self.parse_error(format!(
+                                    "Invalid PEPMASS '{value}'"
+                                ))
+                            },
+                        )?,
+                    );
+                    if entry.intensity.is_none() {
+                        entry.intensity =
+                            values.next().and_then(|v| v.parse().ok());
+                    }
+                },
+                "CHARGE" => entry.charge = parse_charge(value),
+                "RTINSECONDS" => {
+                    entry.rt = Some(value.trim().parse().map_err(|_| {
+                        self.parse_error(format!(
+                            "Invalid RTINSECONDS '{value}'"
+                        ))
+                    })?);
+                },
+                "ION_MOBILITY" => {
+                    entry.im = Some(value.trim().parse().map_err(|_| {
+                        self.parse_error(format!(
+                            "Invalid ION_MOBILITY '{value}'"
+                        ))
+                    })?);
+                },
+                // Only the first scan of e.g. `3`, `3-5` or `3,7` is kept.
+                "SCANS" => {
+                    let first = value.split([',', '-']).next().unwrap_or("");
+                    entry.index = Some(first.trim().parse().map_err(|_| {
+                        self.parse_error(format!("Invalid SCANS '{value}'"))
+                    })?);
+                },
+                _ => {},
+            }
+        }
+        let index = entry.index.unwrap_or(self.count);
+        self.count += 1;
+        entry
+            .into_spectrum(index)
+            .map_err(|message| self.parse_error(message))
+    }
+}
+
+impl<R: BufRead> Iterator for MGFReader<R> {
+    type Item = Result<Spectrum<Mz>, MGFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.next_line()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            match line.trim() {
+                "BEGIN IONS" => return Some(self.read_entry()),
+                "" => continue,
+                line if line.starts_with('#') => continue,
+                // Global parameters before the first entry are ignored.
+                line if line.contains('=') => continue,
+                line => {
+                    return Some(Err(
+                        self.parse_error(format!("Unexpected line '{line}'"))
+                    ));
+                },
+            }
+        }
+    }
+}
+
+/// Reads all spectra of an MGF file.
+pub fn read_mgf(path: impl AsRef<Path>) -> Result<Vec<Spectrum<Mz>>, MGFError> {
+    MGFReader::new(path)?.collect()
+}
+
+#[derive(Debug, Default)]
+struct MGFEntryFields {
+    index: Option<usize>,
+    pepmass: Option<f64>,
+    charge: Option<Charge>,
+    rt: Option<f64>,
+    im: Option<f64>,
+    intensity: Option<f64>,
+    frame: Option<usize>,
+    ce: Option<f64>,
+    width: Option<f64>,
+    mz_values: Vec<Mz>,
+    intensities: Vec<f64>,
+}
+
+impl MGFEntryFields {
+    fn parse_title(&mut self, title: &str) {
+        for (key, value) in title_fields(title) {
+            match key.as_str() {
+                "index" => self.index = value.parse().ok(),
+                "im" | "mobility" | "ionmobility" => {
+                    self.im = value.parse().ok()
+                },
+                "intensity" => self.intensity = value.parse().ok(),
+                "frame" => self.frame = value.parse().ok(),
+                "ce" => self.ce = value.parse().ok(),
+                "width" => self.width = value.parse().ok(),
+                _ => {},
+            }
+        }
+    }
+
+    /// Fails with a message if a field is out of range.
+    fn into_spectrum(self, index: usize) -> Result<Spectrum<Mz>, String> {
+        let ce = self.ce.unwrap_or(0.0);
+        let isolation_window = match self.pepmass {
+            Some(pepmass) => IsolationWindow::new_from_center(
+                Mz::from(pepmass),
+                Mz::from(self.width.unwrap_or(0.0)),
+                ce,
+            ),
+            None => IsolationWindow::default(),
+        };
+        let frame = self.frame.unwrap_or(0);
+        let frame_index = FrameIndex::try_from(frame)
+            .map_err(|_| format!("Invalid frame {frame}"))?;
+        let precursor = self.pepmass.map(|pepmass| {
+            Precursor::new(
+                Mz::from(pepmass),
+                Im::from(self.im.unwrap_or(0.0)),
+                Rt::from(self.rt.unwrap_or(0.0)),
+                ScanIndex::try_from(0).unwrap(),
+                self.charge,
+                self.intensity,
+                index,
+                frame_index,
+            )
+        });
+        Ok(Spectrum::new(
+            self.intensities,
+            index,
+            precursor,
+            self.mz_values,
+            isolation_window,
+        ))
+    }
+}
+
+/// The lowercase `key:value` or `key=value` pairs of a title.
+///
+/// Pairs are separated by commas or whitespace, values may be quoted and
+/// may be separated from their key by whitespace.
+fn title_fields(title: &str) -> Vec<(String, String)> {
+    let title = title.trim().trim_matches('"');
+    let chars: Vec<char> = title.chars().collect();
+    let is_separator = |c: char| c == ',' || c.is_whitespace();
+    let mut fields = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        while i < chars.len() && is_separator(chars[i]) {
+            i += 1;
+        }
+        let key_start = i;
+        while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_')
+        {
+            i += 1;
+        }
+        let key: String = chars[key_start..i].iter().collect();
+        if i < chars.len() && (chars[i] == ':' || chars[i] == '=') {
+            i += 1;
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            let value = if chars.get(i) == Some(&'"') {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '"' {
+                    i += 1;
+                }
+                let value = chars[start..i.min(chars.len())].iter().collect();
+                i += 1;
+                value
+            } else {
+                let start = i;
+                while i < chars.len() && !is_separator(chars[i]) {
+                    i += 1;
+                }
+                chars[start..i].iter().collect()
+            };
+            if !key.is_empty() {
+                fields.push((key.to_lowercase(), value));
+            }
+        } else {
+            while i < chars.len() && !is_separator(chars[i]) {
+                i += 1;
+            }
+        }
+    }
+    fields
+}
+
+/// Parses the first charge of e.g. `2`, `2+`, `3-` or `2+ and 3+`.
+///
+/// A charge of 0 is treated as unknown.
+fn parse_charge(value: &str) -> Option<Charge> {
+    let first = value.split([',', ' ']).next()?.trim();
+    let (digits, negative) = match first.strip_suffix('-') {
+        Some(digits) => (digits, true),
+        None => (first.trim_end_matches('+'), false),
+    };
+    let charge: i8 = digits.parse().ok()?;
+    Charge::try_from(if negative { -charge } else { charge }).ok()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MGFError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid MGF at line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MGFTitleStyle, MGFWriter, MGFWriterConfig};
+
+    fn test_file(name: &str) -> String {
+        format!("{}/../../tests/{name}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn title_fields_formats() {
+        assert_eq!(
+            title_fields("index:1, im:0.9000, frame:3"),
+            vec![
+                ("index".to_string(), "1".to_string()),
+                ("im".to_string(), "0.9000".to_string()),
+                ("frame".to_string(), "3".to_string()),
+            ]
+        );
+        assert_eq!(
+            title_fields(r#"frame=1 IonMobility:"1.234""#),
+            vec![
+                ("frame".to_string(), "1".to_string()),
+                ("ionmobility".to_string(), "1.234".to_string()),
+            ]
+        );
+        assert_eq!(
+            title_fields(r#""index: 2, mobility: 1.100""#),
+            vec![
+                ("index".to_string(), "2".to_string()),
+                ("mobility".to_string(), "1.100".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn charges() {
+        assert_eq!(parse_charge("2").map(i8::from), Some(2));
+        assert_eq!(parse_charge("3+").map(i8::from), Some(3));
+        assert_eq!(parse_charge("1-").map(i8::from), Some(-1));
+        assert_eq!(parse_charge("2+ and 3+").map(i8::from), Some(2));
+        assert_eq!(parse_charge("0"), None);
+    }
+
+    #[test]
+    fn read_test_files() {
+        let spectra = read_mgf(test_file("test.mgf")).unwrap();
+        assert_eq!(spectra.len(), 3);
+        assert_eq!(spectra[1].index(), 2);
+        assert_eq!(spectra[1].len(), 2);
+        let precursor = spectra[1].precursor().as_ref().unwrap();
+        assert_eq!(f64::from(precursor.mz()), 501.0);
+        assert_eq!(f64::from(precursor.im()), 1.1);
+        assert_eq!(precursor.charge().map(i8::from), Some(3));
+        let spectra = read_mgf(test_file("test2.mgf")).unwrap();
+        assert_eq!(spectra.len(), 2);
+        assert_eq!(spectra[1].index(), 1);
+        assert_eq!(f64::from(spectra[1].mz_values()[3]), 1400.4);
+        assert_eq!(spectra[1].intensities()[3], 40.0);
+        let precursor = spectra[1].precursor().as_ref().unwrap();
+        assert_eq!(f64::from(precursor.rt()), 9.876);
+        assert_eq!(f64::from(precursor.im()), 0.9876);
+        assert_eq!(usize::from(precursor.frame_index()), 2);
+    }
+
+    fn spectrum() -> Spectrum<Mz> {
+        let precursor = Precursor::new(
+            Mz::from(500.25),
+            Im::from(0.9),
+            Rt::from(60.5),
+            ScanIndex::try_from(0).unwrap(),
+            Some(Charge::try_from(2).unwrap()),
+            Some(1000.0),
+            7,
+            FrameIndex::try_from(12).unwrap(),
+        );
+        Spectrum::new(
+            vec![10.0, 20.0],
+            7,
+            Some(precursor),
+            vec![Mz::from(150.125), Mz::from(250.5)],
+            IsolationWindow::new_from_center(
+                Mz::from(500.25),
+                Mz::from(2.0),
+                30.0,
+            ),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let spectrum = spectrum();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mgf");
+        let mut writer = MGFWriter::new(&path).unwrap();
+        writer.write(&spectrum).unwrap();
+        writer.write(&spectrum).unwrap();
+        writer.finalize().unwrap();
+        let spectra = read_mgf(&path).unwrap();
+        assert_eq!(spectra, vec![spectrum.clone(), spectrum]);
+    }
+
+    #[test]
+    fn round_trip_with_config() {
+        let spectrum = spectrum();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mgf");
+        let config = MGFWriterConfig {
+            title_style: MGFTitleStyle::Tpp,
+            ion_mobility: true,
+            scans: true,
+            ..Default::default()
+        };
+        let mut writer = MGFWriter::with_config(&path, config).unwrap();
+        writer.write(&spectrum).unwrap();
+        writer.finalize().unwrap();
+        let spectra = read_mgf(&path).unwrap();
+        assert_eq!(spectra.len(), 1);
+        // A TPP title has no key-value pairs, so the index and 1/K0 need to
+        // come from `SCANS` and `ION_MOBILITY`.
+        assert_eq!(spectra[0].index(), spectrum.index());
+        assert_eq!(spectra[0].mz_values(), spectrum.mz_values());
+        assert_eq!(spectra[0].intensities(), spectrum.intensities());
+        let precursor = spectra[0].precursor().as_ref().unwrap();
+        let expected = spectrum.precursor().as_ref().unwrap();
+        assert_eq!(precursor.index(), expected.index());
+        assert_eq!(precursor.mz(), expected.mz());
+        assert_eq!(precursor.im(), expected.im());
+        assert_eq!(precursor.rt(), expected.rt());
+        assert_eq!(precursor.charge(), expected.charge());
+    }
+
+    #[test]
+    fn scans_and_ion_mobility() {
+        let mgf = "BEGIN IONS\nTITLE=index:1, im:0.9\nPEPMASS=500.25\n\
+                   ION_MOBILITY=1.1\nSCANS=5-7\nEND IONS\n\
+                   BEGIN IONS\nSCANS=x\nEND IONS\n";
+        let mut reader = MGFReader::from_reader(mgf.as_bytes());
+        let spectrum = reader.next().unwrap().unwrap();
+        assert_eq!(spectrum.index(), 5);
+        let precursor = spectrum.precursor().as_ref().unwrap();
+        assert_eq!(f64::from(precursor.im()), 1.1);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MGFError::Parse { line: 8, .. }))
+        ));
+    }
+
+    #[test]
+    fn invalid_frame() {
+        let mgf = "BEGIN IONS\nTITLE=index:1, frame:4294967295\n\
+                   PEPMASS=500.25\nEND IONS\n";
+        let mut reader = MGFReader::from_reader(mgf.as_bytes());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MGFError::Parse { line: 4, .. }))
+        ));
+    }
+
+    #[test]
+    fn missing_end() {
+        let mut reader =
+            MGFReader::from_reader("BEGIN IONS\n100.0 1\n".as_bytes());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MGFError::Parse { line: 2, .. }))
+        ));
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use timsrust_core::io::formats::parquet::ParquetReader;
+use timsrust_core::{
+    BitConverter, Converter, FrameIndex, Im, Mz, Rt, ScanIndex, TofIndex,
+};
+
+use crate::{
+    MetadataReaderError, MiniTDFPath,
+    metadata::{GlobalMetadata, get_im_bounds},
+    precursors::ParquetPrecursor,
+};
+
+/// A converter from TOF index -> m/z.
+///
+/// miniTDF spectra store their m/z values directly, as the bits of their
+/// TOF indices. The acquisition range in the global metadata does not
+/// change this encoding, so it is not used here.
+#[derive(Clone, Debug)]
+pub struct Tof2MzConverter(BitConverter);
+
+impl Default for Tof2MzConverter {
+    fn default() -> Self {
+        Self(BitConverter())
+    }
+}
+
+impl Converter<TofIndex, Mz> for Tof2MzConverter {
+    fn convert(&self, value: TofIndex) -> Mz {
+        self.0.convert(value)
+    }
+}
+
+impl Converter<Mz, TofIndex> for Tof2MzConverter {
+    fn convert(&self, value: Mz) -> TofIndex {
+        self.0.convert(value)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UncalibratedScan2ImConverter {
+    scan_intercept: f64,
+    scan_slope: f64,
+}
+
+impl UncalibratedScan2ImConverter {
+    fn from_boundaries(im_min: f64, im_max: f64, scan_max_index: u32) -> Self {
+        let scan_intercept: f64 = im_max.sqrt();
+        let scan_slope: f64 =
+            (im_min.sqrt() - scan_intercept) / scan_max_index as f64;
+        Self {
+            scan_intercept,
+            scan_slope,
+        }
+    }
+}
+
+impl Converter<ScanIndex, Im> for UncalibratedScan2ImConverter {
+    fn convert(&self, value: ScanIndex) -> Im {
+        let value = f64::from(value);
+        let im = self.scan_intercept + self.scan_slope * value;
+        Im::from(im * im)
+    }
+}
+
+impl Converter<Im, ScanIndex> for UncalibratedScan2ImConverter {
+    fn convert(&self, value: Im) -> ScanIndex {
+        let value = f64::from(value);
+        let result = (value.sqrt() - self.scan_intercept) / self.scan_slope;
+        ScanIndex::try_from(result.max(0.0) as u32)
+            .expect("ScanIndex conversion out of bounds")
+    }
+}
+
+/// A converter from scan index -> 1/K0.
+///
+/// Runs with `OneOverK0AcqRangeLower` below `OneOverK0AcqRangeUpper` and a
+/// positive `NumScans` in their global metadata use the same model as TDF
+/// runs. All other runs store the 1/K0 of a precursor as the bits of its
+/// scan index.
+///
+/// This used to be a unit struct that always converted bits. Replace
+/// `Scan2ImConverter()` with [`Scan2ImConverter::default()`] for the old
+/// behaviour, or [`Scan2ImConverter::new`] to use the global metadata.
+#[derive(Clone, Debug)]
+pub enum Scan2ImConverter {
+    Bit(BitConverter),
+    Uncalibrated(UncalibratedScan2ImConverter),
+}
+
+impl Scan2ImConverter {
+    /// Falls back to [`Scan2ImConverter::Bit`] without global metadata.
+    pub fn new(path: &MiniTDFPath) -> Self {
+        let metadata = GlobalMetadata::new(path).unwrap_or_default();
+        Self::from_global_metadata(&metadata)
+    }
+
+    pub(crate) fn from_global_metadata(metadata: &GlobalMetadata) -> Self {
+        let scan_max_index = metadata.parse::<u32>("NumScans");
+        match (get_im_bounds(metadata), scan_max_index) {
+            (Some((im_min, im_max)), Some(scan_max_index))
+                if 0.0 <= im_min && im_min < im_max && scan_max_index > 0 =>
+            {
+                Self::Uncalibrated(
+                    UncalibratedScan2ImConverter::from_boundaries(
+                        im_min,
+                        im_max,
+                        scan_max_index,
+                    ),
+                )
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+impl Default for Scan2ImConverter {
+    fn default() -> Self {
+        Self::Bit(BitConverter())
+    }
+}
+
+impl Converter<ScanIndex, Im> for Scan2ImConverter {
+    fn convert(&self, value: ScanIndex) -> Im {
+        match self {
+            Self::Bit(converter) => converter.convert(value),
+            Self::Uncalibrated(converter) => converter.convert(value),
+        }
+    }
+}
+
+impl Converter<Im, ScanIndex> for Scan2ImConverter {
+    fn convert(&self, value: Im) -> ScanIndex {
+        match self {
+            Self::Bit(converter) => converter.convert(value),
+            Self::Uncalibrated(converter) => converter.convert(value),
+        }
+    }
+}
+
+/// A converter from MS1 parent frame -> retention time, built from the
+/// precursor table.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Frame2RtConverter {
+    forward: HashMap<FrameIndex, Rt>,
+    reverse: HashMap<Rt, FrameIndex>,
+}
+
+impl Frame2RtConverter {
+    pub fn new(path: &MiniTDFPath) -> Result<Self, MetadataReaderError> {
+        let precursors = ParquetReader::<ParquetPrecursor>::from(
+            path.ms2_parquet().as_ref(),
+        )?
+        .read_all()?;
+        Ok(Self::from_precursors(&precursors))
+    }
+
+    /// Precursors of the same MS1 frame can have slightly different
+    /// retention times, the earliest one is closest to the frame itself.
+    pub(crate) fn from_precursors(precursors: &[ParquetPrecursor]) -> Self {
+        let mut forward: HashMap<FrameIndex, Rt> = HashMap::new();
+        for precursor in precursors {
+            let frame_index =
+                FrameIndex::try_from(precursor.frame_index as u32)
+                    .expect("FrameIndex conversion out of bounds");
+            let rt = Rt::from(precursor.rt);
+            forward
+                .entry(frame_index)
+                .and_modify(|value| {
+                    if rt < *value {
+                        *value = rt
+                    }
+                })
+                .or_insert(rt);
+        }
+        Self::from_values(forward)
+    }
+
+    pub fn from_values(forward: HashMap<FrameIndex, Rt>) -> Self {
+        let reverse = forward.iter().map(|(k, v)| (*v, *k)).collect();
+        Self { forward, reverse }
+    }
+}
+
+impl Converter<FrameIndex, Rt> for Frame2RtConverter {
+    fn convert(&self, value: FrameIndex) -> Rt {
+        *self
+            .forward
+            .get(&value)
+            .expect("FrameIndex not found in converter")
+    }
+}
+
+impl Converter<Rt, FrameIndex> for Frame2RtConverter {
+    fn convert(&self, value: Rt) -> FrameIndex {
+        *self.reverse.get(&value).expect("Rt not found in converter")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn global_metadata(pairs: &[(&str, &str)]) -> GlobalMetadata {
+        GlobalMetadata::from_iter(
+            pairs
+                .iter()
+                .map(|&(key, value)| (key.to_string(), value.to_string())),
+        )
+    }
+
+    #[test]
+    fn uncalibrated_im_converter() {
+        let metadata = global_metadata(&[
+            ("OneOverK0AcqRangeLower", "0.64"),
+            ("OneOverK0AcqRangeUpper", "1.44"),
+            ("NumScans", "1000"),
+        ]);
+        let im_converter = Scan2ImConverter::from_global_metadata(&metadata);
+        let scan = |index: u32| ScanIndex::try_from(index).unwrap();
+        let im = |index: u32| f64::from(im_converter.convert(scan(index)));
+        assert!((im(0) - 1.44).abs() < 1e-9);
+        assert!((im(1000) - 0.64).abs() < 1e-9);
+        let index = u32::from(im_converter.convert(Im::from(1.0)));
+        assert!(index.abs_diff(500) <= 1);
+    }
+
+    #[test]
+    fn bit_im_converter_without_ranges() {
+        let metadata = global_metadata(&[("OneOverK0AcqRangeLower", "0.6")]);
+        let im_converter = Scan2ImConverter::from_global_metadata(&metadata);
+        let scan = im_converter.convert(Im::from(1.25));
+        assert_eq!(im_converter.convert(scan), Im::from(1.25));
+    }
+
+    #[test]
+    fn bit_im_converter_with_invalid_ranges() {
+        for (lower, upper, scans) in [
+            ("1.0", "1.0", "1000"),
+            ("1.4", "0.6", "1000"),
+            ("0.6", "1.4", "0"),
+        ] {
+            let metadata = global_metadata(&[
+                ("OneOverK0AcqRangeLower", lower),
+                ("OneOverK0AcqRangeUpper", upper),
+                ("NumScans", scans),
+            ]);
+            let im_converter =
+                Scan2ImConverter::from_global_metadata(&metadata);
+            assert!(matches!(im_converter, Scan2ImConverter::Bit(_)));
+            let scan = im_converter.convert(Im::from(1.25));
+            assert_eq!(im_converter.convert(scan), Im::from(1.25));
+        }
+    }
+}
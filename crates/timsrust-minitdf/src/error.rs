@@ -1,5 +1,6 @@
 use crate::{
-    MiniTDFPathError, MiniTDFPrecursorReaderError, MiniTDFSpectrumReaderError,
+    MetadataReaderError, MiniTDFPathError, MiniTDFPrecursorReaderError,
+    MiniTDFSpectrumReaderError,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -12,6 +13,8 @@ pub enum MiniTDFError {
     MiniTDFSpectrumReaderError(#[from] MiniTDFSpectrumReaderError),
     #[error("{0}")]
     MiniTDFPathError(#[from] MiniTDFPathError),
+    #[error("{0}")]
+    MetadataReaderError(#[from] MetadataReaderError),
 }
 
 pub type MiniTDFResult<T> = Result<T, MiniTDFError>;
@@ -1,4 +1,6 @@
+mod calibration;
 mod error;
+mod metadata;
 mod precursors;
 mod spectrum;
 mod tdf_blob;
@@ -7,7 +9,12 @@ mod timstof;
 pub(crate) use precursors::MiniTDFPrecursorReaderError;
 pub(crate) use spectrum::MiniTDFSpectrumReaderError;
 
+pub use calibration::{
+    Frame2RtConverter, Scan2ImConverter, Tof2MzConverter,
+    UncalibratedScan2ImConverter,
+};
 pub use error::{MiniTDFError, MiniTDFResult};
-pub use precursors::{MiniTDFPrecursorReader, Scan2ImConverter};
+pub use metadata::{Metadata, MetadataReaderError};
+pub use precursors::MiniTDFPrecursorReader;
 pub use spectrum::MiniTDFSpectrumReader;
 pub use timstof::{MiniTDFPath, MiniTDFPathError};
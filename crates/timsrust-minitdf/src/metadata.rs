@@ -0,0 +1,342 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use serde::Deserialize;
+use timsrust_core::io::formats::parquet::{ParquetError, ParquetReader};
+use timsrust_core::{AcquisitionType, Im, Mz, Rt};
+
+use crate::{
+    Frame2RtConverter, MiniTDFPath, Scan2ImConverter, Tof2MzConverter,
+    precursors::ParquetPrecursor,
+};
+
+const OTOF_CONTROL: &str = "Bruker otofControl";
+
+/// Metadata from a single miniTDF run.
+///
+/// The instrument and acquisition ranges are read from the
+/// `*.GlobalMetaData.ms2.parquet` key-value table, which uses the same
+/// keys as the `GlobalMetadata` table of a TDF run. Ranges that are not
+/// present there are `None`. The retention time range and converter are
+/// taken from the precursor table.
+///
+/// miniTDF spectra store m/z values directly, so no TOF calibration is
+/// parsed and this is not a `timsrust_tdf::Metadata`. It can therefore
+/// not be passed to writers that take TDF metadata.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    rt_converter: Arc<Frame2RtConverter>,
+    mz_converter: Arc<Tof2MzConverter>,
+    im_converter: Arc<Scan2ImConverter>,
+    compression_type: u8,
+    acquisition_type: AcquisitionType,
+    lower_rt: Rt,
+    upper_rt: Rt,
+    lower_im: Option<Im>,
+    upper_im: Option<Im>,
+    lower_mz: Option<Mz>,
+    upper_mz: Option<Mz>,
+    path: String,
+    max_peaks_per_scan: Option<usize>,
+    global_metadata: GlobalMetadata,
+}
+
+impl Metadata {
+    pub fn new(path: &MiniTDFPath) -> Result<Self, MetadataReaderError> {
+        let global_metadata = GlobalMetadata::new(path)?;
+        let compression_type = global_metadata
+            .parse("TimsCompressionType")
+            .ok_or_else(|| {
+                MetadataReaderError::KeyNotFound(
+                    "TimsCompressionType".to_string(),
+                )
+            })?;
+        let precursors = ParquetReader::<ParquetPrecursor>::from(
+            path.ms2_parquet().as_ref(),
+        )?
+        .read_all()?;
+        let rt_min = precursors.iter().map(|p| p.rt).reduce(f64::min);
+        let rt_max = precursors.iter().map(|p| p.rt).reduce(f64::max);
+        let acquisition_type = if precursors.iter().any(|p| p.scan_mode == 8) {
+            AcquisitionType::DDAPASEF
+        } else if precursors.iter().any(|p| p.scan_mode == 9) {
+            AcquisitionType::DIAPASEF
+        } else {
+            AcquisitionType::Unknown
+        };
+        let mz_bounds = get_mz_bounds(&global_metadata);
+        let im_bounds = get_im_bounds(&global_metadata);
+        let metadata = Metadata {
+            rt_converter: Arc::new(Frame2RtConverter::from_precursors(
+                &precursors,
+            )),
+            mz_converter: Arc::new(Tof2MzConverter::default()),
+            im_converter: Arc::new(Scan2ImConverter::from_global_metadata(
+                &global_metadata,
+            )),
+            compression_type,
+            acquisition_type,
+            lower_rt: Rt::from(rt_min.unwrap_or_default()),
+            upper_rt: Rt::from(rt_max.unwrap_or_default()),
+            lower_im: im_bounds.map(|(im_min, _)| im_min.into()),
+            upper_im: im_bounds.map(|(_, im_max)| im_max.into()),
+            lower_mz: mz_bounds.map(|(mz_min, _)| mz_min.into()),
+            upper_mz: mz_bounds.map(|(_, mz_max)| mz_max.into()),
+            path: path.as_ref().to_string(),
+            max_peaks_per_scan: global_metadata.parse("MaxNumPeaksPerScan"),
+            global_metadata,
+        };
+        Ok(metadata)
+    }
+
+    pub fn rt_converter(&self) -> &Arc<Frame2RtConverter> {
+        &self.rt_converter
+    }
+
+    pub fn mz_converter(&self) -> &Arc<Tof2MzConverter> {
+        &self.mz_converter
+    }
+
+    pub fn im_converter(&self) -> &Arc<Scan2ImConverter> {
+        &self.im_converter
+    }
+
+    /// `None` if unlimited.
+    pub fn max_peaks_per_scan(&self) -> Option<usize> {
+        self.max_peaks_per_scan
+    }
+
+    pub fn compression_type(&self) -> u8 {
+        self.compression_type
+    }
+
+    pub fn acquisition_type(&self) -> AcquisitionType {
+        self.acquisition_type
+    }
+
+    pub fn lower_rt(&self) -> Rt {
+        self.lower_rt
+    }
+
+    pub fn upper_rt(&self) -> Rt {
+        self.upper_rt
+    }
+
+    pub fn lower_im(&self) -> Option<Im> {
+        self.lower_im
+    }
+
+    pub fn upper_im(&self) -> Option<Im> {
+        self.upper_im
+    }
+
+    pub fn lower_mz(&self) -> Option<Mz> {
+        self.lower_mz
+    }
+
+    pub fn upper_mz(&self) -> Option<Mz> {
+        self.upper_mz
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn instrument_vendor(&self) -> Option<&str> {
+        self.global_metadata.get("InstrumentVendor")
+    }
+
+    pub fn instrument_name(&self) -> Option<&str> {
+        self.global_metadata.get("InstrumentName")
+    }
+
+    pub fn acquisition_software(&self) -> Option<&str> {
+        self.global_metadata.get("AcquisitionSoftware")
+    }
+
+    /// Any other value of the global metadata table.
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.global_metadata.get(key)
+    }
+}
+
+/// The key-value pairs of `*.GlobalMetaData.ms2.parquet`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GlobalMetadata(HashMap<String, String>);
+
+impl FromIterator<(String, String)> for GlobalMetadata {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl GlobalMetadata {
+    pub(crate) fn new(path: &MiniTDFPath) -> Result<Self, MetadataReaderError> {
+        let uri = path
+            .global_metadata()
+            .ok_or(MetadataReaderError::NoGlobalMetadata)?;
+        let rows = ParquetReader::<GlobalMetadataRow>::from(uri.as_ref())?
+            .read_all()?;
+        Ok(rows.into_iter().map(|row| (row.key, row.value)).collect())
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// `None` if the key is missing or not parsable.
+    pub(crate) fn parse<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.parse().ok()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct GlobalMetadataRow {
+    #[serde(rename = "Key")]
+    key: String,
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+pub(crate) fn get_mz_bounds(metadata: &GlobalMetadata) -> Option<(f64, f64)> {
+    let mut mz_min: f64 = metadata.parse("MzAcqRangeLower")?;
+    let mut mz_max: f64 = metadata.parse("MzAcqRangeUpper")?;
+    if metadata.get("AcquisitionSoftware") == Some(OTOF_CONTROL) {
+        mz_min -= 5.0;
+        mz_max += 5.0;
+    }
+    Some((mz_min, mz_max))
+}
+
+pub(crate) fn get_im_bounds(metadata: &GlobalMetadata) -> Option<(f64, f64)> {
+    let im_min: f64 = metadata.parse("OneOverK0AcqRangeLower")?;
+    let im_max: f64 = metadata.parse("OneOverK0AcqRangeUpper")?;
+    Some((im_min, im_max))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataReaderError {
+    #[error("{0}")]
+    ParquetError(#[from] ParquetError),
+    #[error("No global metadata file found")]
+    NoGlobalMetadata,
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::Converter;
+    use timsrust_core::io::formats::parquet::ParquetWriter;
+    use timsrust_core::utils::reader::Reader;
+
+    use super::*;
+
+    fn path(name: &str) -> MiniTDFPath {
+        let path = format!("{}/../../tests/{name}", env!("CARGO_MANIFEST_DIR"));
+        MiniTDFPath::new(path).unwrap()
+    }
+
+    #[test]
+    fn resolves_prefixed_files() {
+        let path = path("test.ms2");
+        assert!(path.ms2_bin().as_ref().ends_with("converter.ms2.bin"));
+        assert!(
+            path.ms2_parquet()
+                .as_ref()
+                .ends_with("converter.ms2spectrum.parquet")
+        );
+        assert!(path.global_metadata().is_some());
+        let bin = path.ms2_bin().clone();
+        assert_eq!(MiniTDFPath::new(bin).unwrap(), path);
+    }
+
+    #[test]
+    fn global_metadata() {
+        let metadata = path("test.ms2").metadata().unwrap();
+        assert_eq!(metadata.compression_type(), 2);
+        assert_eq!(metadata.instrument_vendor(), Some("Bruker"));
+        assert_eq!(metadata.value("schemaType"), Some("miniTSF"));
+        assert_eq!(metadata.max_peaks_per_scan(), None);
+        assert_eq!(metadata.lower_mz(), None);
+        assert_eq!(f64::from(metadata.lower_rt()), 0.1);
+        assert_eq!(f64::from(metadata.upper_rt()), 0.3);
+        let spectra = path("test.ms2").spectrum_reader().unwrap();
+        let spectrum = spectra.get(0).unwrap();
+        let mz = metadata.mz_converter().convert(spectrum.tof_indices()[0]);
+        assert!((f64::from(mz) - 190.10706).abs() < 1e-4);
+        let precursor = spectrum.precursor().as_ref().unwrap();
+        assert_eq!(precursor.im(), Im::from(1.3));
+        assert_eq!(precursor.intensity(), &Some(0.0));
+    }
+
+    #[derive(serde::Serialize, Deserialize)]
+    struct Row {
+        #[serde(rename = "Key")]
+        key: String,
+        #[serde(rename = "Value")]
+        value: String,
+    }
+
+    /// Copies `tests/test.ms2` and adds acquisition ranges to its global
+    /// metadata.
+    fn with_ranges(dir: &std::path::Path) -> MiniTDFPath {
+        let source = path("test.ms2");
+        for uri in [source.ms2_bin(), source.ms2_parquet()] {
+            let from = std::path::Path::new(uri.as_ref());
+            std::fs::copy(from, dir.join(from.file_name().unwrap())).unwrap();
+        }
+        let global_metadata = source.global_metadata().unwrap().as_ref();
+        let mut rows = ParquetReader::<Row>::from(global_metadata)
+            .unwrap()
+            .read_all()
+            .unwrap();
+        for (key, value) in [
+            ("MzAcqRangeLower", "100"),
+            ("MzAcqRangeUpper", "1600"),
+            ("DigitizerNumSamples", "400000"),
+            ("OneOverK0AcqRangeLower", "0.64"),
+            ("OneOverK0AcqRangeUpper", "1.44"),
+            ("NumScans", "1000"),
+        ] {
+            rows.retain(|row| row.key != key);
+            rows.push(Row {
+                key: key.to_string(),
+                value: value.to_string(),
+            });
+        }
+        let name = std::path::Path::new(global_metadata).file_name().unwrap();
+        let mut writer =
+            ParquetWriter::new(dir.join(name).to_str().unwrap()).unwrap();
+        writer.write_batch(rows).unwrap();
+        writer.close().unwrap();
+        MiniTDFPath::new(dir.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn acquisition_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = with_ranges(dir.path());
+        let metadata = path.metadata().unwrap();
+        let offset = match metadata.acquisition_software() {
+            Some(OTOF_CONTROL) => 5.0,
+            _ => 0.0,
+        };
+        assert_eq!(metadata.lower_mz(), Some(Mz::from(100.0 - offset)));
+        assert_eq!(metadata.upper_mz(), Some(Mz::from(1600.0 + offset)));
+        assert_eq!(metadata.lower_im(), Some(Im::from(0.64)));
+        assert_eq!(metadata.upper_im(), Some(Im::from(1.44)));
+        assert!(matches!(
+            metadata.im_converter().as_ref(),
+            Scan2ImConverter::Uncalibrated(_)
+        ));
+        // The ranges do not change how m/z values are stored.
+        let spectra = path.spectrum_reader().unwrap();
+        let spectrum = spectra.get(0).unwrap();
+        let mz = metadata.mz_converter().convert(spectrum.tof_indices()[0]);
+        assert!((f64::from(mz) - 190.10706).abs() < 1e-4);
+        let precursor = spectrum.precursor().as_ref().unwrap();
+        assert_eq!(precursor.im(), Im::from(1.3));
+        let im = metadata.im_converter().convert(precursor.scan_index());
+        assert!((f64::from(im) - 1.3).abs() < 1e-3);
+    }
+}
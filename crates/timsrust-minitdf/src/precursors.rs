@@ -3,11 +3,13 @@ use std::sync::Arc;
 use serde::Deserialize;
 use timsrust_core::io::formats::parquet::{ParquetError, ParquetReader};
 use timsrust_core::utils::reader::Reader;
-use timsrust_core::{
-    Charge, Converter, FrameIndex, Im, Mz, Precursor, Rt, ScanIndex,
-};
+use timsrust_core::{Charge, Converter, FrameIndex, Im, Mz, Precursor, Rt};
 
-use crate::{MiniTDFError, MiniTDFPathError, timstof::MiniTDFPath};
+use crate::{
+    MiniTDFError, MiniTDFPathError, Scan2ImConverter,
+    metadata::{GlobalMetadata, MetadataReaderError},
+    timstof::MiniTDFPath,
+};
 
 #[derive(Debug)]
 pub struct MiniTDFPrecursorReader {
@@ -23,9 +25,16 @@ impl MiniTDFPrecursorReader {
                 .map_err(MiniTDFPrecursorReaderError::from)?
                 .read_all()
                 .map_err(MiniTDFPrecursorReaderError::from)?;
+        let global_metadata = match path.global_metadata() {
+            Some(_) => GlobalMetadata::new(path)
+                .map_err(MiniTDFPrecursorReaderError::from)?,
+            None => GlobalMetadata::default(),
+        };
+        let im_converter =
+            Scan2ImConverter::from_global_metadata(&global_metadata);
         let reader = Self {
             parquet_precursors,
-            im_converter: Arc::new(Scan2ImConverter()),
+            im_converter: Arc::new(im_converter),
         };
         Ok(reader)
     }
@@ -65,29 +74,14 @@ impl Reader<Precursor> for MiniTDFPrecursorReader {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Scan2ImConverter();
-
-impl Converter<ScanIndex, Im> for Scan2ImConverter {
-    fn convert(&self, value: ScanIndex) -> Im {
-        let bits = u32::from(value);
-        Im::from(f32::from_bits(bits))
-    }
-}
-
-impl Converter<Im, ScanIndex> for Scan2ImConverter {
-    fn convert(&self, value: Im) -> ScanIndex {
-        let bits = (f64::from(value) as f32).to_bits();
-        ScanIndex::try_from(bits).expect("ScanIndex conversion out of bounds")
-    }
-}
-
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum MiniTDFPrecursorReaderError {
     #[error("{0}")]
     ParquetError(#[from] ParquetError),
     #[error("{0}")]
     MiniTDFPathError(#[from] MiniTDFPathError),
+    #[error("{0}")]
+    MetadataReaderError(#[from] MetadataReaderError),
     #[error("No precursor found")]
     NoPrecursor,
 }
@@ -102,7 +96,7 @@ pub(crate) struct ParquetPrecursor {
     pub im: f64,
     #[serde(rename = "Charge", default)]
     pub charge: usize,
-    #[serde(rename = "Intensity", default)]
+    #[serde(rename = "Intensity", default, deserialize_with = "number")]
     pub intensity: f64,
     #[serde(rename = "Id", default)]
     pub index: usize,
@@ -112,4 +106,22 @@ pub(crate) struct ParquetPrecursor {
     pub offset: u64,
     #[serde(rename = "CollisionEnergy", default)]
     pub collision_energy: f64,
+    #[serde(rename = "ScanMode", default)]
+    pub scan_mode: u8,
+}
+
+/// Reads a column that is stored as either integers or floats.
+fn number<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Int(i64),
+        Float(f64),
+    }
+    Ok(match Number::deserialize(deserializer)? {
+        Number::Int(value) => value as f64,
+        Number::Float(value) => value,
+    })
 }
@@ -1,27 +1,48 @@
 use timsrust_core::io::Uri;
 
 use crate::{
-    MiniTDFError, precursors::MiniTDFPrecursorReader,
+    Metadata, MiniTDFError, precursors::MiniTDFPrecursorReader,
     spectrum::MiniTDFSpectrumReader,
 };
 
+/// File name suffixes of the spectrum blobs, by preference.
+const BIN_SUFFIXES: [&str; 2] = ["ms2spectrum.bin", ".ms2.bin"];
+/// File name suffixes of the precursor table, by preference.
+const PARQUET_SUFFIXES: [&str; 2] =
+    ["ms2spectrum.parquet", ".MS2Spectra.ms2.parquet"];
+const GLOBAL_METADATA_SUFFIX: &str = ".GlobalMetaData.ms2.parquet";
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MiniTDFPath {
     uri: Uri,
     bin: Uri,
     parquet: Uri,
+    global_metadata: Option<Uri>,
 }
 
 impl MiniTDFPath {
+    /// Resolves a miniTDF folder, or any file inside it.
+    ///
+    /// The folder needs a `*ms2spectrum.bin` (or `*.ms2.bin`) blob file and
+    /// a `*ms2spectrum.parquet` (or `*.MS2Spectra.ms2.parquet`) precursor
+    /// table. A `*.GlobalMetaData.ms2.parquet` table is optional.
     pub fn new(path: impl AsRef<str>) -> Result<Self, MiniTDFPathError> {
         let uri = Uri::from(path.as_ref());
-        let bin_uri = uri.join("ms2spectrum.bin");
-        let parquet_uri = uri.join("ms2spectrum.parquet");
-        if bin_uri.probe_is_file() && parquet_uri.probe_is_file() {
+        let children = uri.list_children().unwrap_or_default();
+        let find = |suffix: &str| {
+            children
+                .iter()
+                .find(|child| child.as_ref().ends_with(suffix))
+                .cloned()
+        };
+        let bin = BIN_SUFFIXES.into_iter().find_map(find);
+        let parquet = PARQUET_SUFFIXES.into_iter().find_map(find);
+        if let (Some(bin), Some(parquet)) = (bin, parquet) {
             return Ok(Self {
+                global_metadata: find(GLOBAL_METADATA_SUFFIX),
                 uri,
-                bin: bin_uri,
-                parquet: parquet_uri,
+                bin,
+                parquet,
             });
         }
         match uri.parent() {
@@ -39,10 +60,18 @@ impl MiniTDFPath {
         &self.parquet
     }
 
+    pub fn global_metadata(&self) -> Option<&Uri> {
+        self.global_metadata.as_ref()
+    }
+
     pub fn uri(&self) -> &Uri {
         &self.uri
     }
 
+    pub fn metadata(&self) -> Result<Metadata, MiniTDFError> {
+        Ok(Metadata::new(self)?)
+    }
+
     pub fn spectrum_reader(
         &self,
     ) -> Result<MiniTDFSpectrumReader, MiniTDFError> {
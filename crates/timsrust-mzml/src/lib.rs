@@ -0,0 +1,3 @@
+mod mzml;
+
+pub use mzml::{MzMLCompression, MzMLError, MzMLWriter};
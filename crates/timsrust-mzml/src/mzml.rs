@@ -0,0 +1,967 @@
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use flate2::{Compression, write::ZlibEncoder};
+use sha1_smol::Sha1;
+use timsrust_core::{Im, Mz, Rt, Spectrum};
+use timsrust_tdf::Metadata;
+
+/// Compression of the binary data arrays, before base64 encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MzMLCompression {
+    #[default]
+    None,
+    Zlib,
+}
+
+/// Writes MS1 and MS2 spectra to an indexed mzML 1.1 file.
+///
+/// Spectra are streamed to a temporary file next to the output while
+/// writing. [`finalize`](MzMLWriter::finalize) writes the header, copies
+/// the spectra and appends the spectrum offset index and the SHA-1 file
+/// checksum.
+///
+/// # Examples
+///
+/// ```
+/// use timsrust_core::{IsolationWindow, Mz, Rt, Spectrum};
+/// use timsrust_mzml::{MzMLCompression, MzMLWriter};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("run.mzML");
+/// let mut writer = MzMLWriter::new(&path).unwrap();
+/// writer.set_compression(MzMLCompression::Zlib);
+/// let spectrum = Spectrum::new(
+///     vec![10.0, 20.0],
+///     0,
+///     None,
+///     vec![Mz::from(100.0), Mz::from(200.0)],
+///     IsolationWindow::default(),
+/// );
+/// writer.write_ms1(&spectrum, Rt::from(1.5), None).unwrap();
+/// writer.finalize().unwrap();
+/// let xml = std::fs::read_to_string(&path).unwrap();
+/// assert!(xml.contains("<indexListOffset>"));
+/// ```
+pub struct MzMLWriter {
+    mzml_path: PathBuf,
+    spectra_path: PathBuf,
+    spectra: BufWriter<File>,
+    offset: u64,
+    offsets: Vec<u64>,
+    compression: MzMLCompression,
+    metadata: Option<Metadata>,
+    has_ms1: bool,
+    has_ms2: bool,
+}
+
+impl MzMLWriter {
+    /// Creates the temporary spectrum file `<output_path>.tmp`.
+    pub fn new(output_path: impl AsRef<Path>) -> Result<Self, MzMLError> {
+        let mzml_path = output_path.as_ref().to_path_buf();
+        let mut spectra_path = OsString::from(mzml_path.as_os_str());
+        spectra_path.push(".tmp");
+        let spectra_path = PathBuf::from(spectra_path);
+        let spectra = BufWriter::new(File::create(&spectra_path)?);
+        Ok(Self {
+            mzml_path,
+            spectra_path,
+            spectra,
+            offset: 0,
+            offsets: vec![],
+            compression: MzMLCompression::default(),
+            metadata: None,
+            has_ms1: false,
+            has_ms2: false,
+        })
+    }
+
+    pub fn compression(&self) -> MzMLCompression {
+        self.compression
+    }
+
+    /// Sets the compression of all subsequently written arrays.
+    pub fn set_compression(&mut self, compression: MzMLCompression) {
+        self.compression = compression;
+    }
+
+    /// Adds the source file and run-level ranges of a TDF run.
+    pub fn set_metadata(&mut self, metadata: &Metadata) {
+        self.metadata = Some(metadata.clone());
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a spectrum.
+    ///
+    /// Spectra with a precursor or an isolation window are written as MS2
+    /// spectra, with the retention time and ion mobility of the precursor
+    /// as scan attributes. All other spectra are written as MS1 spectra
+    /// without scan attributes.
+    pub fn write(&mut self, spectrum: &Spectrum<Mz>) -> Result<(), MzMLError> {
+        self.write_fragments(spectrum, None)
+    }
+
+    /// Appends an MS2 spectrum with the ion mobility of every peak.
+    ///
+    /// The ion mobility of the precursor is kept on the selected ion only.
+    pub fn write_with_ion_mobilities(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        ion_mobilities: &[Im],
+    ) -> Result<(), MzMLError> {
+        self.write_fragments(spectrum, Some(ion_mobilities))
+    }
+
+    /// Appends an MS1 spectrum, optionally with the ion mobility of every
+    /// peak.
+    pub fn write_ms1(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        rt: Rt,
+        ion_mobilities: Option<&[Im]>,
+    ) -> Result<(), MzMLError> {
+        let scan = ScanAttributes {
+            ms_level: 1,
+            rt: Some(rt),
+            im: None,
+        };
+        self.write_spectrum(spectrum, scan, ion_mobilities)
+    }
+
+    /// Writes the header, spectra, index and checksum to the output file
+    /// and removes the temporary spectrum file.
+    pub fn finalize(mut self) -> Result<(), MzMLError> {
+        self.spectra.flush()?;
+        let file = BufWriter::new(File::create(&self.mzml_path)?);
+        let mut output = ChecksumWriter::new(file);
+        output.write_all(self.header().as_bytes())?;
+        let spectra_offset = output.offset;
+        std::io::copy(&mut File::open(&self.spectra_path)?, &mut output)?;
+        output.write_all(b"    </spectrumList>\n  </run>\n</mzML>\n")?;
+        let index_offset = output.offset;
+        output.write_all(self.index(spectra_offset).as_bytes())?;
+        write!(
+            output,
+            "<indexListOffset>{index_offset}</indexListOffset>\n<fileChecksum>"
+        )?;
+        let checksum = output.sha1.digest().to_string();
+        let mut file = output.inner;
+        write!(file, "{checksum}</fileChecksum>\n</indexedmzML>\n")?;
+        file.flush()?;
+        std::fs::remove_file(&self.spectra_path)?;
+        Ok(())
+    }
+
+    fn write_fragments(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        ion_mobilities: Option<&[Im]>,
+    ) -> Result<(), MzMLError> {
+        let precursor = spectrum.precursor().as_ref();
+        let is_ms2 = precursor.is_some()
+            || f64::from(spectrum.isolation_window().width()) > 0.0;
+        let scan = ScanAttributes {
+            ms_level: if is_ms2 { 2 } else { 1 },
+            rt: precursor.map(|p| p.rt()),
+            im: precursor
+                .filter(|_| ion_mobilities.is_none())
+                .map(|p| p.im()),
+        };
+        self.write_spectrum(spectrum, scan, ion_mobilities)
+    }
+
+    fn write_spectrum(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        scan: ScanAttributes,
+        ion_mobilities: Option<&[Im]>,
+    ) -> Result<(), MzMLError> {
+        if let Some(ion_mobilities) = ion_mobilities
+            && ion_mobilities.len() != spectrum.len()
+        {
+            return Err(MzMLError::IonMobilityLengthMismatch {
+                index: spectrum.index(),
+                expected: spectrum.len(),
+                found: ion_mobilities.len(),
+            });
+        }
+        let xml = self.spectrum_xml(spectrum, &scan, ion_mobilities);
+        self.spectra.write_all(xml.as_bytes())?;
+        // The index points at the start tag, past its indentation.
+        let indentation = xml.find('<').unwrap_or(0) as u64;
+        self.offsets.push(self.offset + indentation);
+        self.offset += xml.len() as u64;
+        match scan.ms_level {
+            1 => self.has_ms1 = true,
+            _ => self.has_ms2 = true,
+        }
+        Ok(())
+    }
+
+    fn spectrum_xml(
+        &self,
+        spectrum: &Spectrum<Mz>,
+        scan: &ScanAttributes,
+        ion_mobilities: Option<&[Im]>,
+    ) -> String {
+        let index = self.offsets.len();
+        let mut xml = String::with_capacity(4096 + spectrum.len() * 24);
+        let _ = writeln!(
+            xml,
+            r#"      <spectrum index="{index}" id="index={index}" defaultArrayLength="{}">"#,
+            spectrum.len()
+        );
+        let spectrum_type = match scan.ms_level {
+            1 => cv_param("MS:1000579", "MS1 spectrum", ""),
+            _ => cv_param("MS:1000580", "MSn spectrum", ""),
+        };
+        let tic: f64 = spectrum.intensities().iter().sum();
+        let mut params = vec![
+            cv_param("MS:1000511", "ms level", &scan.ms_level.to_string()),
+            spectrum_type,
+            cv_param("MS:1000127", "centroid spectrum", ""),
+            // MS1 spectra are usually indexed by frame and MS2 spectra by
+            // spectrum, so the MS level keeps titles unique.
+            cv_param(
+                "MS:1000796",
+                "spectrum title",
+                &format!("ms{} index={}", scan.ms_level, spectrum.index()),
+            ),
+            cv_param("MS:1000285", "total ion current", &tic.to_string()),
+        ];
+        let mz_values = spectrum.mz_values();
+        if let Some((apex, intensity)) = mz_values
+            .iter()
+            .zip(spectrum.intensities())
+            .max_by(|a, b| a.1.total_cmp(b.1))
+        {
+            params.push(cv_param_with_unit(
+                "MS:1000504",
+                "base peak m/z",
+                &f64::from(*apex).to_string(),
+                MZ_UNIT,
+            ));
+            params.push(cv_param_with_unit(
+                "MS:1000505",
+                "base peak intensity",
+                &intensity.to_string(),
+                COUNTS_UNIT,
+            ));
+        }
+        if let (Some(lowest), Some(highest)) =
+            (mz_values.first(), mz_values.last())
+        {
+            params.push(cv_param_with_unit(
+                "MS:1000528",
+                "lowest observed m/z",
+                &f64::from(*lowest).to_string(),
+                MZ_UNIT,
+            ));
+            params.push(cv_param_with_unit(
+                "MS:1000527",
+                "highest observed m/z",
+                &f64::from(*highest).to_string(),
+                MZ_UNIT,
+            ));
+        }
+        for param in params {
+            let _ = writeln!(xml, "        {param}");
+        }
+        self.write_scan_list(&mut xml, scan);
+        if scan.ms_level > 1 {
+            write_precursor_list(&mut xml, spectrum);
+        }
+        self.write_binary_data_arrays(&mut xml, spectrum, ion_mobilities);
+        xml.push_str("      </spectrum>\n");
+        xml
+    }
+
+    fn write_scan_list(&self, xml: &mut String, scan: &ScanAttributes) {
+        let _ = writeln!(
+            xml,
+            "        <scanList count=\"1\">\n          {}\n          <scan>",
+            cv_param("MS:1000795", "no combination", ""),
+        );
+        if let Some(rt) = scan.rt {
+            let _ = writeln!(
+                xml,
+                "            {}",
+                cv_param_with_unit(
+                    "MS:1000016",
+                    "scan start time",
+                    &f64::from(rt).to_string(),
+                    SECOND_UNIT,
+                )
+            );
+        }
+        if let Some(im) = scan.im {
+            let _ = writeln!(xml, "            {}", ion_mobility_param(im));
+        }
+        xml.push_str("          </scan>\n        </scanList>\n");
+    }
+
+    fn write_binary_data_arrays(
+        &self,
+        xml: &mut String,
+        spectrum: &Spectrum<Mz>,
+        ion_mobilities: Option<&[Im]>,
+    ) {
+        let count = 2 + ion_mobilities.is_some() as usize;
+        let _ =
+            writeln!(xml, r#"        <binaryDataArrayList count="{count}">"#);
+        let mz_bytes: Vec<u8> = spectrum
+            .mz_values()
+            .iter()
+            .flat_map(|&mz| f64::from(mz).to_le_bytes())
+            .collect();
+        self.write_binary_data_array(
+            xml,
+            &mz_bytes,
+            cv_param("MS:1000523", "64-bit float", ""),
+            cv_param_with_unit("MS:1000514", "m/z array", "", MZ_UNIT),
+        );
+        let intensity_bytes: Vec<u8> = spectrum
+            .intensities()
+            .iter()
+            .flat_map(|&intensity| (intensity as f32).to_le_bytes())
+            .collect();
+        self.write_binary_data_array(
+            xml,
+            &intensity_bytes,
+            cv_param("MS:1000521", "32-bit float", ""),
+            cv_param_with_unit(
+                "MS:1000515",
+                "intensity array",
+                "",
+                COUNTS_UNIT,
+            ),
+        );
+        if let Some(ion_mobilities) = ion_mobilities {
+            let im_bytes: Vec<u8> = ion_mobilities
+                .iter()
+                .flat_map(|&im| (f64::from(im) as f32).to_le_bytes())
+                .collect();
+            self.write_binary_data_array(
+                xml,
+                &im_bytes,
+                cv_param("MS:1000521", "32-bit float", ""),
+                cv_param_with_unit(
+                    "MS:1003006",
+                    "mean inverse reduced ion mobility array",
+                    "",
+                    ION_MOBILITY_UNIT,
+                ),
+            );
+        }
+        xml.push_str("        </binaryDataArrayList>\n");
+    }
+
+    fn write_binary_data_array(
+        &self,
+        xml: &mut String,
+        bytes: &[u8],
+        precision: String,
+        array_type: String,
+    ) {
+        let (compression, encoded) = match self.compression {
+            MzMLCompression::None => (
+                cv_param("MS:1000576", "no compression", ""),
+                STANDARD.encode(bytes),
+            ),
+            MzMLCompression::Zlib => (
+                cv_param("MS:1000574", "zlib compression", ""),
+                STANDARD.encode(zlib(bytes)),
+            ),
+        };
+        let _ = write!(
+            xml,
+            r#"          <binaryDataArray encodedLength="{}">
+            {precision}
+            {compression}
+            {array_type}
+            <binary>{encoded}</binary>
+          </binaryDataArray>
+"#,
+            encoded.len(),
+        );
+    }
+
+    fn header(&self) -> String {
+        let mut file_content = vec![];
+        if self.has_ms1 {
+            file_content.push(cv_param("MS:1000579", "MS1 spectrum", ""));
+        }
+        if self.has_ms2 {
+            file_content.push(cv_param("MS:1000580", "MSn spectrum", ""));
+        }
+        file_content.push(cv_param("MS:1000127", "centroid spectrum", ""));
+        let file_content = file_content
+            .iter()
+            .map(|param| format!("      {param}\n"))
+            .collect::<String>();
+        let run_id = self
+            .metadata
+            .as_ref()
+            .map(|metadata| PathBuf::from(metadata.path()))
+            .unwrap_or_else(|| self.mzml_path.clone())
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "run".to_string());
+        let run_id = escape(&run_id);
+        let (source_files, source_file_ref, run_params, instrument) =
+            match &self.metadata {
+                Some(metadata) => (
+                    source_file_list(metadata),
+                    r#" defaultSourceFileRef="SF0""#,
+                    run_params(metadata),
+                    cv_param(
+                        "MS:1000122",
+                        "Bruker Daltonics instrument model",
+                        "",
+                    ),
+                ),
+                None => (
+                    String::new(),
+                    "",
+                    String::new(),
+                    cv_param("MS:1000031", "instrument model", ""),
+                ),
+            };
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<indexedmzML xmlns="http://psi.hupo.org/ms/mzml" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.2_idx.xsd">
+<mzML xmlns="http://psi.hupo.org/ms/mzml" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.0.xsd" id="{run_id}" version="1.1.0">
+  <cvList count="2">
+    <cv id="MS" fullName="Proteomics Standards Initiative Mass Spectrometry Ontology" version="4.1.0" URI="https://raw.githubusercontent.com/HUPO-PSI/psi-ms-CV/master/psi-ms.obo"/>
+    <cv id="UO" fullName="Unit Ontology" version="releases/2020-03-10" URI="http://ontologies.berkeleybop.org/uo.obo"/>
+  </cvList>
+  <fileDescription>
+    <fileContent>
+{file_content}    </fileContent>
+{source_files}  </fileDescription>
+  <softwareList count="1">
+    <software id="timsrust" version="{}">
+      {}
+    </software>
+  </softwareList>
+  <instrumentConfigurationList count="1">
+    <instrumentConfiguration id="IC0">
+      {}
+    </instrumentConfiguration>
+  </instrumentConfigurationList>
+  <dataProcessingList count="1">
+    <dataProcessing id="timsrust_export">
+      <processingMethod order="0" softwareRef="timsrust">
+        {}
+      </processingMethod>
+    </dataProcessing>
+  </dataProcessingList>
+  <run id="{run_id}" defaultInstrumentConfigurationRef="IC0"{source_file_ref}>
+{run_params}    <spectrumList count="{}" defaultDataProcessingRef="timsrust_export">
+"#,
+            env!("CARGO_PKG_VERSION"),
+            cv_param(
+                "MS:1000799",
+                "custom unreleased software tool",
+                "timsrust"
+            ),
+            instrument,
+            cv_param("MS:1000544", "Conversion to mzML", ""),
+            self.len(),
+        )
+    }
+
+    fn index(&self, spectra_offset: u64) -> String {
+        let mut xml = String::with_capacity(128 + self.len() * 48);
+        let _ = writeln!(
+            xml,
+            "<indexList count=\"1\">\n  <index name=\"spectrum\">"
+        );
+        for (index, offset) in self.offsets.iter().enumerate() {
+            let _ = writeln!(
+                xml,
+                r#"    <offset idRef="index={index}">{}</offset>"#,
+                spectra_offset + offset
+            );
+        }
+        xml.push_str("  </index>\n</indexList>\n");
+        xml
+    }
+}
+
+impl Drop for MzMLWriter {
+    /// Removes the temporary spectrum file if the writer is dropped without
+    /// being finalized.
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.spectra_path);
+    }
+}
+
+#[derive(Debug)]
+struct ScanAttributes {
+    ms_level: u8,
+    rt: Option<Rt>,
+    im: Option<Im>,
+}
+
+/// Forwards all bytes to `inner` while tracking their SHA-1 and count.
+struct ChecksumWriter<W> {
+    inner: W,
+    sha1: Sha1,
+    offset: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            sha1: Sha1::new(),
+            offset: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.sha1.update(&buf[..written]);
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_precursor_list(xml: &mut String, spectrum: &Spectrum<Mz>) {
+    let window = spectrum.isolation_window();
+    let center = f64::from(window.center());
+    xml.push_str(
+        "        <precursorList count=\"1\">\n          <precursor>\n",
+    );
+    let _ = write!(
+        xml,
+        r#"            <isolationWindow>
+              {}
+              {}
+              {}
+            </isolationWindow>
+"#,
+        cv_param_with_unit(
+            "MS:1000827",
+            "isolation window target m/z",
+            &center.to_string(),
+            MZ_UNIT,
+        ),
+        cv_param_with_unit(
+            "MS:1000828",
+            "isolation window lower offset",
+            &(center - f64::from(window.lower())).to_string(),
+            MZ_UNIT,
+        ),
+        cv_param_with_unit(
+            "MS:1000829",
+            "isolation window upper offset",
+            &(f64::from(window.upper()) - center).to_string(),
+            MZ_UNIT,
+        ),
+    );
+    if let Some(precursor) = spectrum.precursor() {
+        let mut params = vec![
+            cv_param_with_unit(
+                "MS:1000744",
+                "selected ion m/z",
+                &f64::from(precursor.mz()).to_string(),
+                MZ_UNIT,
+            ),
+            ion_mobility_param(precursor.im()),
+        ];
+        if let Some(charge) = precursor.charge() {
+            params.push(cv_param(
+                "MS:1000041",
+                "charge state",
+                &charge.to_string(),
+            ));
+        }
+        if let Some(intensity) = precursor.intensity() {
+            params.push(cv_param_with_unit(
+                "MS:1000042",
+                "peak intensity",
+                &intensity.to_string(),
+                COUNTS_UNIT,
+            ));
+        }
+        xml.push_str(
+            "            <selectedIonList count=\"1\">\n              <selectedIon>\n",
+        );
+        for param in params {
+            let _ = writeln!(xml, "                {param}");
+        }
+        xml.push_str(
+            "              </selectedIon>\n            </selectedIonList>\n",
+        );
+    }
+    let _ = write!(
+        xml,
+        r#"            <activation>
+              {}
+              {}
+            </activation>
+          </precursor>
+        </precursorList>
+"#,
+        cv_param("MS:1000133", "collision-induced dissociation", ""),
+        cv_param_with_unit(
+            "MS:1000045",
+            "collision energy",
+            &window.collision_energy().to_string(),
+            ("UO", "UO:0000266", "electronvolt"),
+        ),
+    );
+}
+
+fn source_file_list(metadata: &Metadata) -> String {
+    let path = Path::new(metadata.path());
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let location = path
+        .parent()
+        .map(|parent| parent.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!(
+        r#"    <sourceFileList count="1">
+      <sourceFile id="SF0" name="{}" location="file://{}">
+        {}
+        {}
+      </sourceFile>
+    </sourceFileList>
+"#,
+        escape(&name),
+        escape(&location),
+        cv_param("MS:1002817", "Bruker TDF format", ""),
+        cv_param("MS:1000774", "multiple peak list nativeID format", ""),
+    )
+}
+
+fn run_params(metadata: &Metadata) -> String {
+    let params = [
+        (
+            "acquisition type",
+            format!("{:?}", metadata.acquisition_type()),
+            "xsd:string",
+        ),
+        (
+            "lower rt",
+            f64::from(metadata.lower_rt()).to_string(),
+            "xsd:double",
+        ),
+        (
+            "upper rt",
+            f64::from(metadata.upper_rt()).to_string(),
+            "xsd:double",
+        ),
+        (
+            "lower im",
+            f64::from(metadata.lower_im()).to_string(),
+            "xsd:double",
+        ),
+        (
+            "upper im",
+            f64::from(metadata.upper_im()).to_string(),
+            "xsd:double",
+        ),
+        (
+            "lower mz",
+            f64::from(metadata.lower_mz()).to_string(),
+            "xsd:double",
+        ),
+        (
+            "upper mz",
+            f64::from(metadata.upper_mz()).to_string(),
+            "xsd:double",
+        ),
+    ];
+    params
+        .iter()
+        .map(|(name, value, kind)| {
+            format!(
+                "    <userParam name=\"{name}\" value=\"{value}\" type=\"{kind}\"/>\n"
+            )
+        })
+        .collect()
+}
+
+fn zlib(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(bytes)
+        .expect("Writing to a Vec cannot fail");
+    encoder.finish().expect("Writing to a Vec cannot fail")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The unit of a cvParam as (cvRef, accession, name).
+type Unit = (&'static str, &'static str, &'static str);
+
+const MZ_UNIT: Unit = ("MS", "MS:1000040", "m/z");
+const COUNTS_UNIT: Unit = ("MS", "MS:1000131", "number of detector counts");
+const SECOND_UNIT: Unit = ("UO", "UO:0000010", "second");
+const ION_MOBILITY_UNIT: Unit =
+    ("MS", "MS:1002814", "volt-second per square centimeter");
+
+fn ion_mobility_param(im: Im) -> String {
+    cv_param_with_unit(
+        "MS:1002815",
+        "inverse reduced ion mobility",
+        &f64::from(im).to_string(),
+        ION_MOBILITY_UNIT,
+    )
+}
+
+fn cv_param(accession: &str, name: &str, value: &str) -> String {
+    format!(
+        r#"<cvParam cvRef="MS" accession="{accession}" name="{name}" value="{value}"/>"#
+    )
+}
+
+fn cv_param_with_unit(
+    accession: &str,
+    name: &str,
+    value: &str,
+    (unit_cv, unit_accession, unit_name): Unit,
+) -> String {
+    format!(
+        r#"<cvParam cvRef="MS" accession="{accession}" name="{name}" value="{value}" unitCvRef="{unit_cv}" unitAccession="{unit_accession}" unitName="{unit_name}"/>"#
+    )
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MzMLError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Spectrum {index} has {expected} peaks but {found} ion mobilities")]
+    IonMobilityLengthMismatch {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+    use timsrust_core::{
+        Charge, FrameIndex, IsolationWindow, Precursor, ScanIndex,
+    };
+
+    use super::*;
+
+    fn ms1_spectrum(mz_values: &[f64], intensities: &[f64]) -> Spectrum<Mz> {
+        Spectrum::new(
+            intensities.to_vec(),
+            0,
+            None,
+            mz_values.iter().map(|&mz| Mz::from(mz)).collect(),
+            IsolationWindow::default(),
+        )
+    }
+
+    fn ms2_spectrum() -> Spectrum<Mz> {
+        let precursor = Precursor::new(
+            Mz::from(500.25),
+            Im::from(0.9),
+            Rt::from(60.0),
+            ScanIndex::try_from(100u32).unwrap(),
+            Some(Charge::try_from(3).unwrap()),
+            Some(1000.0),
+            3,
+            FrameIndex::try_from(1u32).unwrap(),
+        );
+        Spectrum::new(
+            vec![5.0, 7.0],
+            3,
+            Some(precursor),
+            vec![Mz::from(150.0), Mz::from(250.0)],
+            IsolationWindow::new_from_center(
+                Mz::from(500.0),
+                Mz::from(2.0),
+                30.0,
+            ),
+        )
+    }
+
+    fn binaries(xml: &str) -> Vec<&str> {
+        xml.split("<binary>")
+            .skip(1)
+            .map(|s| s.split("</binary>").next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn index_offsets_and_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mzML");
+        let mut writer = MzMLWriter::new(&path).unwrap();
+        writer
+            .write_ms1(
+                &ms1_spectrum(&[100.0, 200.0], &[1.0, 2.0]),
+                Rt::from(1.0),
+                None,
+            )
+            .unwrap();
+        writer.write(&ms2_spectrum()).unwrap();
+        assert_eq!(writer.len(), 2);
+        writer.finalize().unwrap();
+        assert!(!dir.path().join("run.mzML.tmp").exists());
+        let bytes = std::fs::read(&path).unwrap();
+        let xml = String::from_utf8(bytes.clone()).unwrap();
+        let offsets: Vec<usize> = xml
+            .split("\">")
+            .filter_map(|s| s.split("</offset>").next()?.parse().ok())
+            .collect();
+        assert_eq!(offsets.len(), 2);
+        assert!(xml[offsets[0]..].starts_with(r#"<spectrum index="0""#));
+        assert!(xml[offsets[1]..].starts_with(r#"<spectrum index="1""#));
+        let index_offset: usize = xml
+            .split("<indexListOffset>")
+            .nth(1)
+            .and_then(|s| s.split("</indexListOffset>").next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(xml[index_offset..].starts_with("<indexList "));
+        let checksum_end = xml.find("<fileChecksum>").unwrap() + 14;
+        let sha1 = Sha1::from(&bytes[..checksum_end]).digest().to_string();
+        assert!(xml.contains(&format!("<fileChecksum>{sha1}</fileChecksum>")));
+        assert!(xml.contains(r#"<spectrumList count="2""#));
+        assert!(xml.contains(r#"name="MSn spectrum""#));
+        assert!(xml.contains(r#"name="charge state" value="3""#));
+        assert!(xml.contains(r#"name="collision energy" value="30""#));
+        assert!(
+            xml.contains(r#"name="isolation window lower offset" value="1""#)
+        );
+        assert!(xml.contains(r#"name="scan start time" value="60""#));
+        assert!(xml.contains(r#"name="spectrum title" value="ms1 index=0""#));
+        assert!(xml.contains(r#"name="spectrum title" value="ms2 index=3""#));
+        assert!(xml.contains(r#"name="instrument model""#));
+    }
+
+    #[test]
+    fn dropped_writer_removes_temporary_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mzML");
+        let mut writer = MzMLWriter::new(&path).unwrap();
+        writer.write(&ms2_spectrum()).unwrap();
+        assert!(dir.path().join("run.mzML.tmp").exists());
+        drop(writer);
+        assert!(!dir.path().join("run.mzML.tmp").exists());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/test.d");
+        let run = dir.path().join("test.d");
+        std::fs::create_dir(&run).unwrap();
+        for file in ["analysis.tdf", "analysis.tdf_bin"] {
+            std::fs::copy(format!("{source}/{file}"), run.join(file)).unwrap();
+        }
+        // The fixture predates the `GlobalMetadata` table name.
+        rusqlite::Connection::open(run.join("analysis.tdf"))
+            .unwrap()
+            .execute_batch(
+                "ALTER TABLE GlobalMetaData RENAME TO Metadata;
+                ALTER TABLE Metadata RENAME TO GlobalMetadata;",
+            )
+            .unwrap();
+        let metadata = Metadata::new(run.to_str().unwrap()).unwrap();
+        let path = dir.path().join("run.mzML");
+        let mut writer = MzMLWriter::new(&path).unwrap();
+        writer.set_metadata(&metadata);
+        writer.write(&ms2_spectrum()).unwrap();
+        writer.finalize().unwrap();
+        let xml = std::fs::read_to_string(&path).unwrap();
+        assert!(xml.contains(r#"name="Bruker Daltonics instrument model""#));
+        assert!(!xml.contains(r#"name="instrument model""#));
+        assert!(xml.contains(r#"<run id="test""#));
+        assert!(xml.contains(r#"defaultSourceFileRef="SF0""#));
+    }
+
+    #[test]
+    fn zlib_arrays_and_ion_mobilities() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mzML");
+        let mut writer = MzMLWriter::new(&path).unwrap();
+        writer.set_compression(MzMLCompression::Zlib);
+        let spectrum = ms2_spectrum();
+        assert!(matches!(
+            writer.write_with_ion_mobilities(&spectrum, &[Im::from(1.0)]),
+            Err(MzMLError::IonMobilityLengthMismatch {
+                index: 3,
+                expected: 2,
+                found: 1
+            })
+        ));
+        writer
+            .write_with_ion_mobilities(
+                &spectrum,
+                &[Im::from(0.75), Im::from(1.25)],
+            )
+            .unwrap();
+        writer.finalize().unwrap();
+        let xml = std::fs::read_to_string(&path).unwrap();
+        assert!(xml.contains(r#"<binaryDataArrayList count="3">"#));
+        let arrays: Vec<Vec<u8>> = binaries(&xml)
+            .into_iter()
+            .map(|binary| {
+                let compressed = STANDARD.decode(binary).unwrap();
+                let mut bytes = vec![];
+                ZlibDecoder::new(compressed.as_slice())
+                    .read_to_end(&mut bytes)
+                    .unwrap();
+                bytes
+            })
+            .collect();
+        let mz_values: Vec<f64> = arrays[0]
+            .chunks(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(mz_values, vec![150.0, 250.0]);
+        let ion_mobilities: Vec<f32> = arrays[2]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(ion_mobilities, vec![0.75, 1.25]);
+        assert_eq!(
+            xml.matches(r#"name="inverse reduced ion mobility""#)
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,8 @@
+mod mzpeak;
+mod schema;
+
+pub use mzpeak::{MzPeakError, MzPeakWriter};
+pub use schema::{
+    ColumnAnnotation, Param, PeakRow, PrecursorRow, RunMetadata, SourceFile,
+    SpectrumRow,
+};
@@ -0,0 +1,514 @@
+use std::path::{Path, PathBuf};
+
+use timsrust_core::io::formats::json::{JsonError, JsonWriter};
+use timsrust_core::io::formats::parquet::{ParquetError, ParquetWriter};
+use timsrust_core::{Im, Mz, Rt, Spectrum};
+use timsrust_tdf::Metadata;
+
+use crate::schema::{
+    METADATA_FILE, PEAKS_FILE, PRECURSORS_FILE, Param, PeakRow, PrecursorRow,
+    RunMetadata, SPECTRA_FILE, SpectrumRow,
+};
+
+/// Number of peaks per row group of `spectra_data.parquet`.
+const PEAKS_PER_ROW_GROUP: usize = 1 << 20;
+/// Number of spectra or precursors per row group of the other tables.
+const ROWS_PER_ROW_GROUP: usize = 1 << 16;
+
+/// Writes MS1 and MS2 spectra to an unpacked, mzPeak-like directory.
+///
+/// The layout borrows the idea of mzPeak, with Parquet tables annotated by
+/// controlled vocabulary terms, but it does not follow the mzPeak
+/// specification and cannot be read by mzPeak readers. The directory
+/// holds one Parquet table per entity, which are streamed while writing:
+///
+/// - `spectra_metadata.parquet`: one [`SpectrumRow`] per spectrum,
+/// - `spectra_data.parquet`: one [`PeakRow`] per peak, in spectrum order,
+/// - `precursors.parquet`: one [`PrecursorRow`] per selected ion.
+///
+/// [`finalize`](MzPeakWriter::finalize) closes the tables and writes
+/// `metadata.json` with the run metadata and the controlled vocabulary
+/// terms of every column.
+///
+/// # Examples
+///
+/// ```
+/// use timsrust_core::{IsolationWindow, Mz, Rt, Spectrum};
+/// use timsrust_mzpeak::MzPeakWriter;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("run.mzpeak");
+/// let mut writer = MzPeakWriter::new(&path).unwrap();
+/// let spectrum = Spectrum::new(
+///     vec![10.0, 20.0],
+///     0,
+///     None,
+///     vec![Mz::from(100.0), Mz::from(200.0)],
+///     IsolationWindow::default(),
+/// );
+/// writer.write_ms1(&spectrum, Rt::from(1.5), None).unwrap();
+/// writer.finalize().unwrap();
+/// assert!(path.join("spectra_data.parquet").exists());
+/// assert!(path.join("metadata.json").exists());
+/// ```
+pub struct MzPeakWriter {
+    path: PathBuf,
+    spectra: ParquetWriter<SpectrumRow>,
+    peaks: ParquetWriter<PeakRow>,
+    precursors: ParquetWriter<PrecursorRow>,
+    spectrum_rows: Vec<SpectrumRow>,
+    peak_rows: Vec<PeakRow>,
+    precursor_rows: Vec<PrecursorRow>,
+    points: u64,
+    len: usize,
+    metadata: Option<Metadata>,
+    has_ms1: bool,
+    has_ms2: bool,
+}
+
+impl MzPeakWriter {
+    /// Creates the output directory and its Parquet tables.
+    pub fn new(output_path: impl AsRef<Path>) -> Result<Self, MzPeakError> {
+        let path = output_path.as_ref().to_path_buf();
+        std::fs::create_dir_all(&path)?;
+        let mut spectra = ParquetWriter::new(path.join(SPECTRA_FILE))?;
+        spectra.set_row_group_size(ROWS_PER_ROW_GROUP);
+        let mut peaks = ParquetWriter::new(path.join(PEAKS_FILE))?;
+        peaks.set_row_group_size(PEAKS_PER_ROW_GROUP);
+        let mut precursors = ParquetWriter::new(path.join(PRECURSORS_FILE))?;
+        precursors.set_row_group_size(ROWS_PER_ROW_GROUP);
+        Ok(Self {
+            path,
+            spectra,
+            peaks,
+            precursors,
+            spectrum_rows: vec![],
+            peak_rows: vec![],
+            precursor_rows: vec![],
+            points: 0,
+            len: 0,
+            metadata: None,
+            has_ms1: false,
+            has_ms2: false,
+        })
+    }
+
+    /// Adds the source file and run-level ranges of a TDF run.
+    pub fn set_metadata(&mut self, metadata: &Metadata) {
+        self.metadata = Some(metadata.clone());
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends an MS2 spectrum.
+    ///
+    /// Spectra with a precursor or an isolation window are written as MS2
+    /// spectra, with the retention time and ion mobility of the precursor.
+    /// All other spectra carry no retention time and are rejected; write
+    /// them with [`write_ms1`](MzPeakWriter::write_ms1) instead.
+    pub fn write(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+    ) -> Result<(), MzPeakError> {
+        self.write_fragments(spectrum, None)
+    }
+
+    /// Appends an MS2 spectrum with the ion mobility of every peak.
+    ///
+    /// The ion mobility of the precursor is kept in the precursor table
+    /// only.
+    pub fn write_with_ion_mobilities(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        ion_mobilities: &[Im],
+    ) -> Result<(), MzPeakError> {
+        self.write_fragments(spectrum, Some(ion_mobilities))
+    }
+
+    /// Appends an MS1 spectrum, optionally with the ion mobility of every
+    /// peak.
+    pub fn write_ms1(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        rt: Rt,
+        ion_mobilities: Option<&[Im]>,
+    ) -> Result<(), MzPeakError> {
+        let scan = ScanAttributes {
+            ms_level: 1,
+            rt: Some(rt),
+            im: None,
+        };
+        self.write_spectrum(spectrum, scan, ion_mobilities)
+    }
+
+    /// Writes the remaining rows, closes the Parquet tables and writes
+    /// `metadata.json`.
+    pub fn finalize(mut self) -> Result<(), MzPeakError> {
+        self.flush(true)?;
+        let mut file_content = vec![];
+        if self.has_ms1 {
+            file_content.push(Param::cv("MS:1000579", "MS1 spectrum"));
+        }
+        if self.has_ms2 {
+            file_content.push(Param::cv("MS:1000580", "MSn spectrum"));
+        }
+        file_content.push(Param::cv("MS:1000127", "centroid spectrum"));
+        let run_id = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let metadata = RunMetadata::new(
+            run_id,
+            self.len as u64,
+            file_content,
+            self.metadata.as_ref(),
+        );
+        self.spectra.close()?;
+        self.peaks.close()?;
+        self.precursors.close()?;
+        JsonWriter::new(self.path.join(METADATA_FILE))?.write(metadata)?;
+        Ok(())
+    }
+
+    fn write_fragments(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        ion_mobilities: Option<&[Im]>,
+    ) -> Result<(), MzPeakError> {
+        let precursor = spectrum.precursor().as_ref();
+        let is_ms2 = precursor.is_some()
+            || f64::from(spectrum.isolation_window().width()) > 0.0;
+        if !is_ms2 {
+            return Err(MzPeakError::MissingRetentionTime {
+                index: spectrum.index(),
+            });
+        }
+        let scan = ScanAttributes {
+            ms_level: 2,
+            rt: precursor.map(|p| p.rt()),
+            im: precursor
+                .filter(|_| ion_mobilities.is_none())
+                .map(|p| p.im()),
+        };
+        self.write_spectrum(spectrum, scan, ion_mobilities)
+    }
+
+    fn write_spectrum(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+        scan: ScanAttributes,
+        ion_mobilities: Option<&[Im]>,
+    ) -> Result<(), MzPeakError> {
+        if let Some(ion_mobilities) = ion_mobilities
+            && ion_mobilities.len() != spectrum.len()
+        {
+            return Err(MzPeakError::IonMobilityLengthMismatch {
+                index: spectrum.index(),
+                expected: spectrum.len(),
+                found: ion_mobilities.len(),
+            });
+        }
+        let index = self.len as u64;
+        let intensities = spectrum.intensities();
+        let mz_values = spectrum.mz_values();
+        let base_peak = intensities
+            .iter()
+            .zip(mz_values)
+            .max_by(|a, b| a.0.total_cmp(b.0));
+        let mut row = SpectrumRow {
+            index,
+            id: format!("index={index}"),
+            ms_level: scan.ms_level,
+            time: scan.rt.map(f64::from),
+            ion_mobility: scan.im.map(f64::from),
+            total_ion_current: intensities.iter().sum(),
+            base_peak_mz: base_peak.map(|(_, &mz)| f64::from(mz)),
+            base_peak_intensity: base_peak.map(|(&intensity, _)| intensity),
+            lowest_mz: mz_values
+                .iter()
+                .map(|&mz| f64::from(mz))
+                .reduce(f64::min),
+            highest_mz: mz_values
+                .iter()
+                .map(|&mz| f64::from(mz))
+                .reduce(f64::max),
+            number_of_points: spectrum.len() as u64,
+            data_start: self.points,
+            isolation_target: None,
+            isolation_lower_offset: None,
+            isolation_upper_offset: None,
+            collision_energy: None,
+        };
+        if scan.ms_level == 2 {
+            let window = spectrum.isolation_window();
+            let center = f64::from(window.center());
+            row.isolation_target = Some(center);
+            row.isolation_lower_offset =
+                Some(center - f64::from(window.lower()));
+            row.isolation_upper_offset =
+                Some(f64::from(window.upper()) - center);
+            row.collision_energy = Some(window.collision_energy());
+        }
+        if let Some(precursor) = spectrum.precursor() {
+            self.precursor_rows.push(PrecursorRow {
+                spectrum_index: index,
+                precursor_index: precursor.index() as u64,
+                selected_ion_mz: f64::from(precursor.mz()),
+                charge: precursor.charge().as_ref().map(|&c| i8::from(c)),
+                intensity: *precursor.intensity(),
+                ion_mobility: f64::from(precursor.im()),
+                time: f64::from(precursor.rt()),
+                frame_index: u32::from(precursor.frame_index()),
+                scan_index: u32::from(precursor.scan_index()),
+            });
+        }
+        self.spectrum_rows.push(row);
+        self.peak_rows.extend((0..spectrum.len()).map(|i| PeakRow {
+            spectrum_index: index,
+            mz: f64::from(mz_values[i]),
+            intensity: intensities[i],
+            ion_mobility: ion_mobilities.map(|ims| f64::from(ims[i])),
+        }));
+        self.points += spectrum.len() as u64;
+        self.len += 1;
+        match scan.ms_level {
+            1 => self.has_ms1 = true,
+            _ => self.has_ms2 = true,
+        }
+        self.flush(false)
+    }
+
+    /// Writes buffered rows once a full row group is available, or all of
+    /// them if `force` is set.
+    fn flush(&mut self, force: bool) -> Result<(), MzPeakError> {
+        if force || self.spectrum_rows.len() >= ROWS_PER_ROW_GROUP {
+            write_rows(&mut self.spectra, &mut self.spectrum_rows)?;
+        }
+        if force || self.peak_rows.len() >= PEAKS_PER_ROW_GROUP {
+            write_rows(&mut self.peaks, &mut self.peak_rows)?;
+        }
+        if force || self.precursor_rows.len() >= ROWS_PER_ROW_GROUP {
+            write_rows(&mut self.precursors, &mut self.precursor_rows)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_rows<T>(
+    writer: &mut ParquetWriter<T>,
+    rows: &mut Vec<T>,
+) -> Result<(), ParquetError>
+where
+    T: serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+    if rows.is_empty() {
+        return Ok(());
+    }
+    writer.write_batch(std::mem::take(rows))
+}
+
+/// Scan-level values that are not part of the spectrum itself.
+struct ScanAttributes {
+    ms_level: u8,
+    rt: Option<Rt>,
+    im: Option<Im>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MzPeakError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Parquet(#[from] ParquetError),
+    #[error("{0}")]
+    Json(#[from] JsonError),
+    #[error("Spectrum {index} has {expected} peaks but {found} ion mobilities")]
+    IonMobilityLengthMismatch {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Spectrum {index} is an MS1 spectrum without a retention time")]
+    MissingRetentionTime { index: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::io::formats::json::JsonReader;
+    use timsrust_core::io::formats::parquet::ParquetReader;
+    use timsrust_core::{
+        Charge, FrameIndex, IsolationWindow, Precursor, ScanIndex,
+    };
+
+    use super::*;
+
+    fn ms1_spectrum(mz_values: &[f64], intensities: &[f64]) -> Spectrum<Mz> {
+        Spectrum::new(
+            intensities.to_vec(),
+            0,
+            None,
+            mz_values.iter().map(|&mz| Mz::from(mz)).collect(),
+            IsolationWindow::default(),
+        )
+    }
+
+    fn ms2_spectrum() -> Spectrum<Mz> {
+        let precursor = Precursor::new(
+            Mz::from(500.25),
+            Im::from(0.9),
+            Rt::from(60.0),
+            ScanIndex::try_from(100u32).unwrap(),
+            Some(Charge::try_from(3).unwrap()),
+            Some(1000.0),
+            3,
+            FrameIndex::try_from(1u32).unwrap(),
+        );
+        Spectrum::new(
+            vec![5.0, 7.0],
+            3,
+            Some(precursor),
+            vec![Mz::from(150.0), Mz::from(250.0)],
+            IsolationWindow::new_from_center(
+                Mz::from(500.0),
+                Mz::from(2.0),
+                30.0,
+            ),
+        )
+    }
+
+    #[test]
+    fn tables_and_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mzpeak");
+        let mut writer = MzPeakWriter::new(&path).unwrap();
+        writer
+            .write_ms1(
+                &ms1_spectrum(&[100.0, 200.0, 300.0], &[1.0, 4.0, 2.0]),
+                Rt::from(1.0),
+                Some(&[Im::from(0.8), Im::from(0.9), Im::from(1.0)]),
+            )
+            .unwrap();
+        writer.write(&ms2_spectrum()).unwrap();
+        assert_eq!(writer.len(), 2);
+        writer.finalize().unwrap();
+
+        let spectra =
+            ParquetReader::<SpectrumRow>::from(path.join(SPECTRA_FILE))
+                .unwrap()
+                .read_all()
+                .unwrap();
+        assert_eq!(spectra.len(), 2);
+        assert_eq!(spectra[0].ms_level, 1);
+        assert_eq!(spectra[0].time, Some(1.0));
+        assert_eq!(spectra[0].total_ion_current, 7.0);
+        assert_eq!(spectra[0].base_peak_mz, Some(200.0));
+        assert_eq!(spectra[0].lowest_mz, Some(100.0));
+        assert_eq!(spectra[0].highest_mz, Some(300.0));
+        assert_eq!(spectra[0].isolation_target, None);
+        assert_eq!(spectra[1].id, "index=1");
+        assert_eq!(spectra[1].ms_level, 2);
+        assert_eq!(spectra[1].time, Some(60.0));
+        assert_eq!(spectra[1].ion_mobility, Some(0.9));
+        assert_eq!(spectra[1].data_start, 3);
+        assert_eq!(spectra[1].number_of_points, 2);
+        assert_eq!(spectra[1].isolation_lower_offset, Some(1.0));
+        assert_eq!(spectra[1].collision_energy, Some(30.0));
+
+        let peaks = ParquetReader::<PeakRow>::from(path.join(PEAKS_FILE))
+            .unwrap()
+            .read_all()
+            .unwrap();
+        assert_eq!(peaks.len(), 5);
+        assert_eq!(peaks[1].mz, 200.0);
+        assert_eq!(peaks[1].intensity, 4.0);
+        assert_eq!(peaks[1].ion_mobility, Some(0.9));
+        assert_eq!(peaks[3].spectrum_index, 1);
+        assert_eq!(peaks[3].ion_mobility, None);
+
+        let precursors =
+            ParquetReader::<PrecursorRow>::from(path.join(PRECURSORS_FILE))
+                .unwrap()
+                .read_all()
+                .unwrap();
+        assert_eq!(precursors.len(), 1);
+        assert_eq!(precursors[0].spectrum_index, 1);
+        assert_eq!(precursors[0].charge, Some(3));
+        assert_eq!(precursors[0].scan_index, 100);
+
+        let metadata =
+            JsonReader::<RunMetadata>::from(path.join(METADATA_FILE))
+                .unwrap()
+                .read_all()
+                .unwrap();
+        assert_eq!(metadata.format, "mzPeak-like");
+        assert_eq!(metadata.run_id, "run");
+        assert_eq!(metadata.spectrum_count, 2);
+        let content: Vec<_> = metadata
+            .file_content
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(
+            content,
+            ["MS1 spectrum", "MSn spectrum", "centroid spectrum"]
+        );
+        assert!(metadata.source_files.is_empty());
+        assert!(metadata.columns.iter().any(|column| {
+            column.file == PEAKS_FILE
+                && column.column == "mz"
+                && column.param.accession.as_deref() == Some("MS:1000514")
+        }));
+    }
+
+    #[test]
+    fn ion_mobility_length_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer =
+            MzPeakWriter::new(dir.path().join("run.mzpeak")).unwrap();
+        assert!(matches!(
+            writer.write_with_ion_mobilities(&ms2_spectrum(), &[Im::from(1.0)]),
+            Err(MzPeakError::IonMobilityLengthMismatch {
+                index: 3,
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(writer.is_empty());
+    }
+
+    #[test]
+    fn ms1_spectra_need_a_retention_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.mzpeak");
+        let mut writer = MzPeakWriter::new(&path).unwrap();
+        let spectrum = ms1_spectrum(&[100.0, 200.0], &[16_777_217.0, 0.1]);
+        assert!(matches!(
+            writer.write(&spectrum),
+            Err(MzPeakError::MissingRetentionTime { index: 0 })
+        ));
+        assert!(writer.is_empty());
+        writer.write_ms1(&spectrum, Rt::from(2.5), None).unwrap();
+        writer.finalize().unwrap();
+
+        let spectra =
+            ParquetReader::<SpectrumRow>::from(path.join(SPECTRA_FILE))
+                .unwrap()
+                .read_all()
+                .unwrap();
+        assert_eq!(spectra.len(), 1);
+        assert_eq!(spectra[0].time, Some(2.5));
+        let peaks = ParquetReader::<PeakRow>::from(path.join(PEAKS_FILE))
+            .unwrap()
+            .read_all()
+            .unwrap();
+        let intensities: Vec<_> = peaks.iter().map(|p| p.intensity).collect();
+        assert_eq!(intensities, [16_777_217.0, 0.1]);
+    }
+}
@@ -0,0 +1,357 @@
+use serde::{Deserialize, Serialize};
+use timsrust_tdf::Metadata;
+
+pub(crate) const SPECTRA_FILE: &str = "spectra_metadata.parquet";
+pub(crate) const PEAKS_FILE: &str = "spectra_data.parquet";
+pub(crate) const PRECURSORS_FILE: &str = "precursors.parquet";
+pub(crate) const METADATA_FILE: &str = "metadata.json";
+
+const MZ_UNIT: (&str, &str) = ("MS:1000040", "m/z");
+const COUNTS_UNIT: (&str, &str) = ("MS:1000131", "number of detector counts");
+const SECOND_UNIT: (&str, &str) = ("UO:0000010", "second");
+const ION_MOBILITY_UNIT: (&str, &str) =
+    ("MS:1002814", "volt-second per square centimeter");
+const ELECTRONVOLT_UNIT: (&str, &str) = ("UO:0000266", "electronvolt");
+
+/// One row of `spectra_metadata.parquet`, describing a single spectrum.
+///
+/// The peaks of a spectrum are the rows
+/// `data_start..data_start + number_of_points` of `spectra_data.parquet`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpectrumRow {
+    pub index: u64,
+    pub id: String,
+    pub ms_level: u8,
+    pub time: Option<f64>,
+    pub ion_mobility: Option<f64>,
+    pub total_ion_current: f64,
+    pub base_peak_mz: Option<f64>,
+    pub base_peak_intensity: Option<f64>,
+    pub lowest_mz: Option<f64>,
+    pub highest_mz: Option<f64>,
+    pub number_of_points: u64,
+    pub data_start: u64,
+    pub isolation_target: Option<f64>,
+    pub isolation_lower_offset: Option<f64>,
+    pub isolation_upper_offset: Option<f64>,
+    pub collision_energy: Option<f64>,
+}
+
+/// One row of `spectra_data.parquet`, i.e. a single peak.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PeakRow {
+    pub spectrum_index: u64,
+    pub mz: f64,
+    pub intensity: f64,
+    pub ion_mobility: Option<f64>,
+}
+
+/// One row of `precursors.parquet`, the selected ion of an MS2 spectrum.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrecursorRow {
+    pub spectrum_index: u64,
+    pub precursor_index: u64,
+    pub selected_ion_mz: f64,
+    pub charge: Option<i8>,
+    pub intensity: Option<f64>,
+    pub ion_mobility: f64,
+    pub time: f64,
+    pub frame_index: u32,
+    pub scan_index: u32,
+}
+
+/// A controlled vocabulary term, or a user parameter if it has no
+/// accession.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub accession: Option<String>,
+    pub name: String,
+    pub value: Option<String>,
+    pub unit_accession: Option<String>,
+    pub unit_name: Option<String>,
+}
+
+impl Param {
+    pub(crate) fn cv(accession: &str, name: &str) -> Self {
+        Self {
+            accession: Some(accession.to_string()),
+            name: name.to_string(),
+            value: None,
+            unit_accession: None,
+            unit_name: None,
+        }
+    }
+
+    fn user(name: &str, value: String) -> Self {
+        Self {
+            accession: None,
+            name: name.to_string(),
+            value: Some(value),
+            unit_accession: None,
+            unit_name: None,
+        }
+    }
+
+    fn with_value(mut self, value: String) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    fn with_unit(mut self, (accession, name): (&str, &str)) -> Self {
+        self.unit_accession = Some(accession.to_string());
+        self.unit_name = Some(name.to_string());
+        self
+    }
+}
+
+/// The term describing a column of one of the Parquet files.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColumnAnnotation {
+    pub file: String,
+    pub column: String,
+    pub param: Param,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourceFile {
+    pub name: String,
+    pub location: String,
+    pub format: Param,
+}
+
+/// The content of `metadata.json`.
+///
+/// Its `format` is `mzPeak-like`, as the files only resemble mzPeak.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub format: String,
+    pub software: Param,
+    pub run_id: String,
+    pub spectrum_count: u64,
+    pub file_content: Vec<Param>,
+    pub source_files: Vec<SourceFile>,
+    pub run: Vec<Param>,
+    pub columns: Vec<ColumnAnnotation>,
+}
+
+impl RunMetadata {
+    pub(crate) fn new(
+        run_id: String,
+        spectrum_count: u64,
+        file_content: Vec<Param>,
+        metadata: Option<&Metadata>,
+    ) -> Self {
+        Self {
+            format: "mzPeak-like".to_string(),
+            software: Param::cv(
+                "MS:1000799",
+                "custom unreleased software tool",
+            )
+            .with_value(format!("timsrust {}", env!("CARGO_PKG_VERSION"))),
+            run_id,
+            spectrum_count,
+            file_content,
+            source_files: metadata.map(source_file).into_iter().collect(),
+            run: metadata.map(run_params).unwrap_or_default(),
+            columns: column_annotations(),
+        }
+    }
+}
+
+fn source_file(metadata: &Metadata) -> SourceFile {
+    let path = std::path::Path::new(metadata.path());
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let location = path
+        .parent()
+        .map(|parent| format!("file://{}", parent.to_string_lossy()))
+        .unwrap_or_default();
+    SourceFile {
+        name,
+        location,
+        format: Param::cv("MS:1002817", "Bruker TDF format"),
+    }
+}
+
+fn run_params(metadata: &Metadata) -> Vec<Param> {
+    vec![
+        Param::user(
+            "acquisition type",
+            format!("{:?}", metadata.acquisition_type()),
+        ),
+        Param::user("lower rt", f64::from(metadata.lower_rt()).to_string())
+            .with_unit(SECOND_UNIT),
+        Param::user("upper rt", f64::from(metadata.upper_rt()).to_string())
+            .with_unit(SECOND_UNIT),
+        Param::user("lower im", f64::from(metadata.lower_im()).to_string())
+            .with_unit(ION_MOBILITY_UNIT),
+        Param::user("upper im", f64::from(metadata.upper_im()).to_string())
+            .with_unit(ION_MOBILITY_UNIT),
+        Param::cv("MS:1000501", "scan window lower limit")
+            .with_value(f64::from(metadata.lower_mz()).to_string())
+            .with_unit(MZ_UNIT),
+        Param::cv("MS:1000500", "scan window upper limit")
+            .with_value(f64::from(metadata.upper_mz()).to_string())
+            .with_unit(MZ_UNIT),
+    ]
+}
+
+fn column_annotations() -> Vec<ColumnAnnotation> {
+    vec![
+        column(SPECTRA_FILE, "ms_level", "MS:1000511", "ms level", None),
+        column(
+            SPECTRA_FILE,
+            "time",
+            "MS:1000016",
+            "scan start time",
+            Some(SECOND_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "ion_mobility",
+            "MS:1002815",
+            "inverse reduced ion mobility",
+            Some(ION_MOBILITY_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "total_ion_current",
+            "MS:1000285",
+            "total ion current",
+            Some(COUNTS_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "base_peak_mz",
+            "MS:1000504",
+            "base peak m/z",
+            Some(MZ_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "base_peak_intensity",
+            "MS:1000505",
+            "base peak intensity",
+            Some(COUNTS_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "lowest_mz",
+            "MS:1000528",
+            "lowest observed m/z",
+            Some(MZ_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "highest_mz",
+            "MS:1000527",
+            "highest observed m/z",
+            Some(MZ_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "number_of_points",
+            "MS:1003060",
+            "number of data points",
+            None,
+        ),
+        column(
+            SPECTRA_FILE,
+            "isolation_target",
+            "MS:1000827",
+            "isolation window target m/z",
+            Some(MZ_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "isolation_lower_offset",
+            "MS:1000828",
+            "isolation window lower offset",
+            Some(MZ_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "isolation_upper_offset",
+            "MS:1000829",
+            "isolation window upper offset",
+            Some(MZ_UNIT),
+        ),
+        column(
+            SPECTRA_FILE,
+            "collision_energy",
+            "MS:1000045",
+            "collision energy",
+            Some(ELECTRONVOLT_UNIT),
+        ),
+        column(PEAKS_FILE, "mz", "MS:1000514", "m/z array", Some(MZ_UNIT)),
+        column(
+            PEAKS_FILE,
+            "intensity",
+            "MS:1000515",
+            "intensity array",
+            Some(COUNTS_UNIT),
+        ),
+        column(
+            PEAKS_FILE,
+            "ion_mobility",
+            "MS:1003006",
+            "mean inverse reduced ion mobility array",
+            Some(ION_MOBILITY_UNIT),
+        ),
+        column(
+            PRECURSORS_FILE,
+            "selected_ion_mz",
+            "MS:1000744",
+            "selected ion m/z",
+            Some(MZ_UNIT),
+        ),
+        column(
+            PRECURSORS_FILE,
+            "charge",
+            "MS:1000041",
+            "charge state",
+            None,
+        ),
+        column(
+            PRECURSORS_FILE,
+            "intensity",
+            "MS:1000042",
+            "peak intensity",
+            Some(COUNTS_UNIT),
+        ),
+        column(
+            PRECURSORS_FILE,
+            "ion_mobility",
+            "MS:1002815",
+            "inverse reduced ion mobility",
+            Some(ION_MOBILITY_UNIT),
+        ),
+        column(
+            PRECURSORS_FILE,
+            "time",
+            "MS:1000016",
+            "scan start time",
+            Some(SECOND_UNIT),
+        ),
+    ]
+}
+
+fn column(
+    file: &str,
+    column: &str,
+    accession: &str,
+    name: &str,
+    unit: Option<(&str, &str)>,
+) -> ColumnAnnotation {
+    let param = Param::cv(accession, name);
+    ColumnAnnotation {
+        file: file.to_string(),
+        column: column.to_string(),
+        param: match unit {
+            Some(unit) => param.with_unit(unit),
+            None => param,
+        },
+    }
+}
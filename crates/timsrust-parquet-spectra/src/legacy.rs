@@ -0,0 +1,69 @@
+//! Deserializers for columns whose type changed, so older files stay
+//! readable.
+
+use serde::{Deserialize, Deserializer, de};
+
+/// A number of any primitive type, e.g. an intensity that older files
+/// store as an integer.
+struct Number(f64);
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = Number;
+
+            fn expecting(
+                &self,
+                formatter: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                formatter.write_str("a number")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Number, E> {
+                Ok(Number(value as f64))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Number, E> {
+                Ok(Number(value as f64))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Number, E> {
+                Ok(Number(value))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+pub(crate) fn f64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f64, D::Error> {
+    Number::deserialize(deserializer).map(|number| number.0)
+}
+
+pub(crate) fn option_f64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    Option::<Number>::deserialize(deserializer)
+        .map(|number| number.map(|number| number.0))
+}
+
+/// A charge, where older files store unsigned charges and 0 if the charge
+/// is unknown.
+pub(crate) fn charge<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i8>, D::Error> {
+    Option::<Number>::deserialize(deserializer)?
+        .map(|number| {
+            i8::try_from(number.0 as i64).map_err(|_| {
+                de::Error::custom(format!("invalid charge {}", number.0))
+            })
+        })
+        .transpose()
+        .map(|charge| charge.filter(|&charge| charge != 0))
+}
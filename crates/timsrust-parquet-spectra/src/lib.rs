@@ -1,8 +1,10 @@
 use timsrust_core::{Converter, Mz, TofIndex};
 
+mod legacy;
 pub mod parquet_path;
 pub mod precursor_reader;
 pub mod spectrum_reader;
+pub mod spectrum_writer;
 
 #[derive(Debug, Clone)]
 pub struct Tof2MzConverter();
@@ -1,5 +1,4 @@
-use timsrust_core::io::formats::parquet::ParquetReader;
-use timsrust_core::utils::simple_error;
+use timsrust_core::io::formats::parquet::{ParquetError, ParquetReader};
 
 #[derive(Debug)]
 pub struct ParquetPrecursorReader {
@@ -7,12 +6,12 @@ pub struct ParquetPrecursorReader {
 }
 
 impl ParquetPrecursorReader {
-    pub fn new(ms1_path: impl AsRef<str>) -> Self {
-        let precursors = ParquetReader::<Precursor>::from(ms1_path.as_ref())
-            .unwrap()
-            .read_all()
-            .unwrap();
-        Self { precursors }
+    pub fn new(
+        ms1_path: impl AsRef<str>,
+    ) -> Result<Self, ParquetPrecursorReaderError> {
+        let precursors =
+            ParquetReader::<Precursor>::from(ms1_path.as_ref())?.read_all()?;
+        Ok(Self { precursors })
     }
 
     pub fn len(&self) -> usize {
@@ -24,22 +23,33 @@ impl ParquetPrecursorReader {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Default, serde::Deserialize)]
+#[derive(
+    Clone, Debug, PartialEq, Default, serde::Serialize, serde::Deserialize,
+)]
 pub struct Precursor {
     pub frame: u32,
     pub scan: u32,
-    pub tof: u32,
-    pub apex_intensity: u64,
+    /// The TOF index of the precursor, if it is known.
+    pub tof: Option<u32>,
+    /// The precursor intensity, if it is known.
+    #[serde(deserialize_with = "crate::legacy::option_f64")]
+    pub apex_intensity: Option<f64>,
     pub rt: f64,
     pub im: f64,
     pub mz: f64,
     pub start: u64,
     pub end: u64,
-    pub charge: u8,
+    /// The precursor charge. Older files store unknown charges as 0.
+    #[serde(deserialize_with = "crate::legacy::charge")]
+    pub charge: Option<i8>,
     pub index: u32,
     pub isolation_mz: f64,
     pub isolation_width: f64,
     pub ce: f64,
+    /// The index of the spectrum of this precursor. Older files do not
+    /// store it, in which case the row is used instead.
+    #[serde(default)]
+    pub spectrum_index: Option<u64>,
 }
 
 impl From<Precursor> for timsrust_core::Precursor {
@@ -49,8 +59,10 @@ impl From<Precursor> for timsrust_core::Precursor {
             timsrust_core::Im::from(value.im),
             timsrust_core::Rt::from(value.rt),
             timsrust_core::ScanIndex::try_from(value.scan).unwrap(),
-            timsrust_core::Charge::try_from(value.charge).ok(),
-            Some(value.apex_intensity as f64),
+            value.charge.and_then(|charge| {
+                timsrust_core::Charge::try_from(charge).ok()
+            }),
+            value.apex_intensity,
             value.index as usize,
             timsrust_core::FrameIndex::try_from(value.frame).unwrap(),
         )
@@ -75,7 +87,7 @@ impl timsrust_core::utils::reader::Reader<Precursor>
         self.precursors
             .get(index)
             .cloned()
-            .ok_or(ParquetPrecursorReaderError())
+            .ok_or(ParquetPrecursorReaderError::IndexOutOfBounds(index))
     }
 }
 
@@ -93,4 +105,10 @@ impl timsrust_core::utils::reader::Reader<timsrust_core::Precursor>
     }
 }
 
-simple_error!(pub ParquetPrecursorReaderError);
+#[derive(Debug, thiserror::Error)]
+pub enum ParquetPrecursorReaderError {
+    #[error("{0}")]
+    Parquet(#[from] ParquetError),
+    #[error("Precursor index {0} is out of bounds")]
+    IndexOutOfBounds(usize),
+}
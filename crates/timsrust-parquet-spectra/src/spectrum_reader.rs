@@ -1,37 +1,55 @@
 use serde::{Deserialize, Serialize};
-use timsrust_core::io::formats::parquet::ParquetReader;
-use timsrust_core::utils::enumerated_error;
+use timsrust_core::io::formats::parquet::{ParquetError, ParquetReader};
+use timsrust_core::utils::reader::Reader;
 use timsrust_core::{ConvertibleTo, Mz, Spectrum};
 
 use crate::Tof2MzConverter;
 use crate::precursor_reader::{
-    ParquetPrecursorReader, ParquetPrecursorReaderError,
+    ParquetPrecursorReader, ParquetPrecursorReaderError, Precursor,
 };
 
+/// Reads spectra from a `precursors.parquet`/`fragments.parquet` pair.
+///
+/// Spectra read as [`Spectrum<Mz>`] have the stored m/z values and
+/// intensities. Spectra read as [`Spectrum`] encode their m/z values as f32
+/// bits in the TOF indices, see [`Tof2MzConverter`].
+///
+/// The `start` and `end` columns of every precursor index the rows of its
+/// fragments. Only the footer of the fragment file is read on creation and
+/// every spectrum only fetches the row groups of its own fragments, so
+/// large fragment files (e.g. in object storage) are cheap to open.
 #[derive(Debug)]
 pub struct ParquetSpectrumReader {
     precursor_reader: ParquetPrecursorReader,
     peak_reader: ParquetReader<CoordinatePeak>,
-    // fragments: Vec<CoordinatePeak>,
     mz_converter: Tof2MzConverter,
 }
 
 impl ParquetSpectrumReader {
-    pub fn new(ms1_path: impl AsRef<str>, ms2_path: impl AsRef<str>) -> Self {
-        let precursor_reader = ParquetPrecursorReader::new(ms1_path);
-        // let fragments =
-        //     CoordinatePeak::deserialize_vec_from_uri(ms2_path.as_ref())
-        //         .unwrap();
+    pub fn new(
+        ms1_path: impl AsRef<str>,
+        ms2_path: impl AsRef<str>,
+    ) -> Result<Self, ParquetSpectrumReaderError> {
+        let precursor_reader = ParquetPrecursorReader::new(ms1_path)?;
         let peak_reader =
-            ParquetReader::<CoordinatePeak>::from(ms2_path.as_ref()).unwrap();
-        // let fragments = reader.get(0..reader.len()).unwrap();
-        // dbg!(fragments.len());
-        Self {
+            ParquetReader::<CoordinatePeak>::from(ms2_path.as_ref())?;
+        let rows = peak_reader.shape().0 as u64;
+        for index in 0..precursor_reader.len() {
+            let precursor: Precursor = precursor_reader.get(index)?;
+            if precursor.start > precursor.end || precursor.end > rows {
+                return Err(ParquetSpectrumReaderError::InvalidFragmentRange {
+                    index,
+                    start: precursor.start,
+                    end: precursor.end,
+                    rows,
+                });
+            }
+        }
+        Ok(Self {
             precursor_reader,
-            // fragments,
             peak_reader,
             mz_converter: Tof2MzConverter(),
-        }
+        })
     }
 
     pub fn len(&self) -> usize {
@@ -52,7 +70,9 @@ pub struct CoordinatePeak {
     // pub frame: u32,
     // pub scan: u32,
     // pub tof: u32,
-    pub apex_intensity: u64,
+    /// The fragment intensity. Older files store it as an integer.
+    #[serde(deserialize_with = "crate::legacy::f64")]
+    pub apex_intensity: f64,
     // pub rt: f64,
     // pub im: f64,
     pub mz: f64,
@@ -91,12 +111,13 @@ impl timsrust_core::utils::reader::IndexedReader<Spectrum>
     }
 }
 
-impl timsrust_core::utils::reader::Reader<Spectrum> for ParquetSpectrumReader {
+impl timsrust_core::utils::reader::Reader<Spectrum<Mz>>
+    for ParquetSpectrumReader
+{
     type Error = ParquetSpectrumReaderError;
 
-    fn get(&self, index: usize) -> Result<Spectrum, Self::Error> {
-        let precursor: crate::precursor_reader::Precursor =
-            self.precursor_reader.get(index)?;
+    fn get(&self, index: usize) -> Result<Spectrum<Mz>, Self::Error> {
+        let precursor: Precursor = self.precursor_reader.get(index)?;
         let isolation_window = timsrust_core::IsolationWindow::new_from_center(
             timsrust_core::Mz::from(precursor.isolation_mz),
             timsrust_core::Mz::from(precursor.isolation_width),
@@ -104,31 +125,113 @@ impl timsrust_core::utils::reader::Reader<Spectrum> for ParquetSpectrumReader {
         );
         let start = precursor.start as usize;
         let end = precursor.end as usize;
-        let mut fragments = self
-            .peak_reader
-            .read_range(start..end)
-            .unwrap()
-            .iter()
-            .map(|frag| {
-                let mz = Mz::from(frag.mz);
-                let tof = mz.convert(&self.mz_converter);
-                (tof, frag.apex_intensity as f64)
-            })
-            .collect::<Vec<_>>();
-        fragments.sort_by_key(|(tof, _)| *tof);
-        let (tof_indices, intensities) = fragments.into_iter().unzip();
+        let mut fragments = self.peak_reader.read_range(start..end)?;
+        fragments.sort_by(|a, b| a.mz.total_cmp(&b.mz));
+        let (mz_values, intensities) = fragments
+            .into_iter()
+            .map(|frag| (Mz::from(frag.mz), frag.apex_intensity))
+            .unzip();
+        let spectrum_index = precursor
+            .spectrum_index
+            .map_or(index, |spectrum_index| spectrum_index as usize);
         let spectrum = Spectrum::new(
             intensities,
-            index,
+            spectrum_index,
             Some(precursor.into()),
-            tof_indices,
+            mz_values,
             isolation_window,
         );
         Ok(spectrum)
     }
 }
 
-enumerated_error!(
-    pub ParquetSpectrumReaderError,
-    ParquetPrecursorReader(ParquetPrecursorReaderError)
-);
+impl timsrust_core::utils::reader::Reader<Spectrum> for ParquetSpectrumReader {
+    type Error = ParquetSpectrumReaderError;
+
+    fn get(&self, index: usize) -> Result<Spectrum, Self::Error> {
+        let spectrum: Spectrum<Mz> = self.get(index)?;
+        // Positive floats have the same order as their bits, so the TOF
+        // indices stay sorted.
+        let tof_indices = spectrum
+            .mz_values()
+            .iter()
+            .map(|mz| mz.convert(&self.mz_converter))
+            .collect();
+        Ok(Spectrum::new(
+            spectrum.intensities().to_vec(),
+            spectrum.index(),
+            spectrum.precursor().clone(),
+            tof_indices,
+            spectrum.isolation_window().clone(),
+        ))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParquetSpectrumReaderError {
+    #[error("{0}")]
+    ParquetPrecursorReader(#[from] ParquetPrecursorReaderError),
+    #[error("{0}")]
+    Parquet(#[from] ParquetError),
+    #[error(
+        "Fragments {start}..{end} of precursor {index} are outside of the {rows} fragment rows"
+    )]
+    InvalidFragmentRange {
+        index: usize,
+        start: u64,
+        end: u64,
+        rows: u64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::io::formats::parquet::ParquetWriter;
+
+    use super::*;
+    use crate::spectrum_writer::{FragmentRow, PrecursorRow};
+
+    #[test]
+    fn invalid_fragment_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let precursor_path = dir.path().join("precursors.parquet");
+        let fragment_path = dir.path().join("fragments.parquet");
+        let mut writer = ParquetWriter::new(&fragment_path).unwrap();
+        writer.write_batch(vec![FragmentRow::default(); 3]).unwrap();
+        writer.close().unwrap();
+        let mut writer = ParquetWriter::new(&precursor_path).unwrap();
+        let precursor = PrecursorRow {
+            start: 2,
+            end: 4,
+            ..Default::default()
+        };
+        writer.write_batch(vec![precursor]).unwrap();
+        writer.close().unwrap();
+        let result = ParquetSpectrumReader::new(
+            precursor_path.to_str().unwrap(),
+            fragment_path.to_str().unwrap(),
+        );
+        assert!(matches!(
+            result,
+            Err(ParquetSpectrumReaderError::InvalidFragmentRange {
+                index: 0,
+                start: 2,
+                end: 4,
+                rows: 3,
+            })
+        ));
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = ParquetSpectrumReader::new(
+            dir.path().join("precursors.parquet").to_str().unwrap(),
+            dir.path().join("fragments.parquet").to_str().unwrap(),
+        );
+        assert!(matches!(
+            result,
+            Err(ParquetSpectrumReaderError::ParquetPrecursorReader(_))
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use timsrust_core::io::formats::parquet::{ParquetError, ParquetWriter};
+use timsrust_core::{Mz, Spectrum};
+
+/// Writes spectra to a `precursors.parquet`/`fragments.parquet` pair that
+/// [`ParquetSpectrumReader`](crate::spectrum_reader::ParquetSpectrumReader)
+/// reads back.
+///
+/// Every spectrum needs a precursor. Fragments are appended to the fragment
+/// file as they are written, while precursors are kept in memory until
+/// [`finalize`](ParquetSpectrumWriter::finalize).
+///
+/// The reader returns the spectra in the order they were written. m/z
+/// values, intensities, charges and spectrum indices are stored as is, so
+/// spectra read back as [`Spectrum<Mz>`] are equal to the written ones.
+/// Precursors have no TOF index, as a [`Spectrum<Mz>`] does not know it.
+///
+/// # Example
+/// ```no_run
+/// use timsrust_core::{Mz, Spectrum};
+/// use timsrust_parquet_spectra::spectrum_writer::ParquetSpectrumWriter;
+///
+/// let spectra: Vec<Spectrum<Mz>> = vec![];
+/// let mut writer = ParquetSpectrumWriter::new(
+///     "run/precursors.parquet",
+///     "run/fragments.parquet",
+/// )
+/// .unwrap();
+/// for spectrum in &spectra {
+///     writer.write(spectrum).unwrap();
+/// }
+/// writer.finalize().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ParquetSpectrumWriter {
+    precursor_writer: ParquetWriter<PrecursorRow>,
+    fragment_writer: ParquetWriter<FragmentRow>,
+    precursors: Vec<PrecursorRow>,
+}
+
+/// A row of the precursor file as it is written.
+///
+/// [`Precursor`](crate::precursor_reader::Precursor) has the same columns,
+/// but also reads the column types of older files.
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub(crate) struct PrecursorRow {
+    pub(crate) frame: u32,
+    pub(crate) scan: u32,
+    pub(crate) tof: Option<u32>,
+    pub(crate) apex_intensity: Option<f64>,
+    pub(crate) rt: f64,
+    pub(crate) im: f64,
+    pub(crate) mz: f64,
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) charge: Option<i8>,
+    pub(crate) index: u32,
+    pub(crate) isolation_mz: f64,
+    pub(crate) isolation_width: f64,
+    pub(crate) ce: f64,
+    pub(crate) spectrum_index: Option<u64>,
+}
+
+/// A row of the fragment file as it is written, see [`PrecursorRow`].
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub(crate) struct FragmentRow {
+    pub(crate) apex_intensity: f64,
+    pub(crate) mz: f64,
+}
+
+impl ParquetSpectrumWriter {
+    pub fn new(
+        precursor_path: impl AsRef<str>,
+        fragment_path: impl AsRef<str>,
+    ) -> Result<Self, ParquetSpectrumWriterError> {
+        Ok(Self {
+            precursor_writer: ParquetWriter::new(precursor_path.as_ref())?,
+            fragment_writer: ParquetWriter::new(fragment_path.as_ref())?,
+            precursors: vec![],
+        })
+    }
+
+    /// Number of spectra written so far.
+    pub fn len(&self) -> usize {
+        self.precursors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn write(
+        &mut self,
+        spectrum: &Spectrum<Mz>,
+    ) -> Result<(), ParquetSpectrumWriterError> {
+        let precursor = spectrum.precursor().as_ref().ok_or(
+            ParquetSpectrumWriterError::MissingPrecursor(spectrum.index()),
+        )?;
+        let start = self.fragment_writer.shape().0;
+        let fragments = spectrum
+            .mz_values()
+            .iter()
+            .zip(spectrum.intensities())
+            .map(|(mz, intensity)| FragmentRow {
+                apex_intensity: *intensity,
+                mz: f64::from(*mz),
+            })
+            .collect::<Vec<_>>();
+        let end = start + fragments.len();
+        self.fragment_writer.write_batch(fragments)?;
+        let isolation_window = spectrum.isolation_window();
+        self.precursors.push(PrecursorRow {
+            frame: u32::from(precursor.frame_index()),
+            scan: u32::from(precursor.scan_index()),
+            tof: None,
+            apex_intensity: *precursor.intensity(),
+            rt: f64::from(precursor.rt()),
+            im: f64::from(precursor.im()),
+            mz: f64::from(precursor.mz()),
+            start: start as u64,
+            end: end as u64,
+            charge: precursor.charge().map(i8::from),
+            index: precursor.index() as u32,
+            isolation_mz: f64::from(isolation_window.center()),
+            isolation_width: f64::from(isolation_window.width()),
+            ce: isolation_window.collision_energy(),
+            spectrum_index: Some(spectrum.index() as u64),
+        });
+        Ok(())
+    }
+
+    /// Writes the precursors and closes both files.
+    pub fn finalize(mut self) -> Result<(), ParquetSpectrumWriterError> {
+        let precursors = std::mem::take(&mut self.precursors);
+        self.precursor_writer.write_batch(precursors)?;
+        self.precursor_writer.close()?;
+        self.fragment_writer.close()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParquetSpectrumWriterError {
+    #[error("{0}")]
+    Parquet(#[from] ParquetError),
+    #[error("Spectrum {0} has no precursor")]
+    MissingPrecursor(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::utils::reader::Reader;
+    use timsrust_core::{
+        Charge, FrameIndex, Im, IsolationWindow, Rt, ScanIndex,
+    };
+
+    use super::*;
+    use crate::precursor_reader::Precursor;
+    use crate::spectrum_reader::ParquetSpectrumReader;
+
+    fn spectrum(
+        index: usize,
+        charge: Option<i8>,
+        intensity: Option<f64>,
+    ) -> Spectrum<Mz> {
+        let precursor = timsrust_core::Precursor::new(
+            Mz::from(523.2718394 + index as f64),
+            Im::from(0.9134),
+            Rt::from(1234.5678 + index as f64),
+            ScanIndex::try_from(400 + index).unwrap(),
+            charge.map(|charge| Charge::try_from(charge).unwrap()),
+            intensity,
+            10 + index,
+            FrameIndex::try_from(12 + index).unwrap(),
+        );
+        Spectrum::new(
+            vec![1523.375, 20.125 * index as f64, 0.5],
+            index,
+            Some(precursor),
+            vec![
+                Mz::from(147.1128041),
+                Mz::from(300.1234567),
+                Mz::from(1000.0000001),
+            ],
+            IsolationWindow::new_from_center(
+                Mz::from(523.5),
+                Mz::from(2.0),
+                25.0,
+            ),
+        )
+    }
+
+    fn paths(dir: &tempfile::TempDir) -> (String, String) {
+        let path = |name| dir.path().join(name).to_str().unwrap().to_string();
+        (path("precursors.parquet"), path("fragments.parquet"))
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let (precursor_path, fragment_path) = paths(&dir);
+        let spectra = vec![
+            spectrum(5, Some(2), Some(12345.678)),
+            spectrum(17, Some(-3), None),
+            spectrum(18, None, Some(0.25)),
+        ];
+        let mut writer =
+            ParquetSpectrumWriter::new(&precursor_path, &fragment_path)
+                .unwrap();
+        for spectrum in &spectra {
+            writer.write(spectrum).unwrap();
+        }
+        assert_eq!(writer.len(), 3);
+        writer.finalize().unwrap();
+        let reader =
+            ParquetSpectrumReader::new(&precursor_path, &fragment_path)
+                .unwrap();
+        assert_eq!(reader.len(), 3);
+        for (row, spectrum) in spectra.iter().enumerate() {
+            let read: Spectrum<Mz> = reader.get(row).unwrap();
+            assert_eq!(&read, spectrum);
+            let read: Spectrum = reader.get(row).unwrap();
+            assert_eq!(read.index(), spectrum.index());
+            assert_eq!(read.intensities(), spectrum.intensities());
+        }
+        let precursor: Precursor = reader.precursor_reader().get(1).unwrap();
+        assert_eq!((precursor.start, precursor.end), (3, 6));
+        assert_eq!(precursor.tof, None);
+        assert_eq!(precursor.charge, Some(-3));
+        assert_eq!(precursor.apex_intensity, None);
+    }
+
+    /// The columns of files written before intensities were stored as
+    /// floats and unknown values as nulls.
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct LegacyPrecursor {
+        frame: u32,
+        scan: u32,
+        tof: u32,
+        apex_intensity: u64,
+        rt: f64,
+        im: f64,
+        mz: f64,
+        start: u64,
+        end: u64,
+        charge: u8,
+        index: u32,
+        isolation_mz: f64,
+        isolation_width: f64,
+        ce: f64,
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct LegacyFragment {
+        apex_intensity: u64,
+        mz: f64,
+    }
+
+    #[test]
+    fn legacy_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (precursor_path, fragment_path) = paths(&dir);
+        let mut writer = ParquetWriter::new(&fragment_path).unwrap();
+        writer
+            .write_batch(vec![
+                LegacyFragment {
+                    apex_intensity: 20,
+                    mz: 300.5,
+                },
+                LegacyFragment {
+                    apex_intensity: 10,
+                    mz: 150.25,
+                },
+            ])
+            .unwrap();
+        writer.close().unwrap();
+        let mut writer = ParquetWriter::new(&precursor_path).unwrap();
+        writer
+            .write_batch(vec![LegacyPrecursor {
+                frame: 3,
+                scan: 400,
+                tof: 123456,
+                apex_intensity: 1200,
+                rt: 60.5,
+                im: 0.9,
+                mz: 500.25,
+                start: 0,
+                end: 2,
+                charge: 0,
+                index: 7,
+                isolation_mz: 500.0,
+                isolation_width: 2.0,
+                ce: 25.0,
+            }])
+            .unwrap();
+        writer.close().unwrap();
+        let reader =
+            ParquetSpectrumReader::new(&precursor_path, &fragment_path)
+                .unwrap();
+        let spectrum: Spectrum<Mz> = reader.get(0).unwrap();
+        assert_eq!(spectrum.index(), 0);
+        assert_eq!(spectrum.intensities(), &[10.0, 20.0]);
+        assert_eq!(spectrum.mz_values(), &[Mz::from(150.25), Mz::from(300.5)]);
+        let precursor = spectrum.precursor().as_ref().unwrap();
+        assert_eq!(*precursor.charge(), None);
+        assert_eq!(*precursor.intensity(), Some(1200.0));
+        let precursor: Precursor = reader.precursor_reader().get(0).unwrap();
+        assert_eq!(precursor.tof, Some(123456));
+    }
+
+    #[test]
+    fn missing_precursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ParquetSpectrumWriter::new(
+            dir.path().join("precursors.parquet").to_str().unwrap(),
+            dir.path().join("fragments.parquet").to_str().unwrap(),
+        )
+        .unwrap();
+        let spectrum = Spectrum::new(
+            vec![1.0],
+            7,
+            None,
+            vec![Mz::from(100.0)],
+            IsolationWindow::default(),
+        );
+        assert!(matches!(
+            writer.write(&spectrum),
+            Err(ParquetSpectrumWriterError::MissingPrecursor(7))
+        ));
+    }
+}
@@ -18,8 +18,10 @@ pub use quad_settings_reader::{
     QuadrupoleSettingsReader, QuadrupoleSettingsReaderError,
 };
 pub use spectrum_reader::{
+    DDACentroidingParams, DDAProcessing, DIACentroidingParams, DIADeisotoping,
+    DIAElutionCorrelation, DIAProcessing, PasefFrameMsMsInfo,
     SpectrumProcessingParams, SpectrumReaderBuilder, SpectrumReaderConfig,
-    TDFSpectrumReader, TDFSpectrumReaderError,
+    TDFSpectrumReader, TDFSpectrumReaderError, read_pasef_frame_msms_info,
 };
 pub use timstof::{TDFPath, TDFPathError, TDFPathLike};
 
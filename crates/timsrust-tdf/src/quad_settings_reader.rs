@@ -150,10 +150,19 @@ type ScanSpanStep = (usize, usize);
 ///   100 and step 80 between their in the scan number.
 ///
 #[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound = "")
+)]
 pub enum QuadWindowExpansionStrategy<ImC> {
     None,
     Even(usize),
-    UniformMobility(MobilitySpanStep, Option<Arc<ImC>>),
+    UniformMobility(
+        MobilitySpanStep,
+        // Set from the reader when the configuration is finalized.
+        #[cfg_attr(feature = "serde", serde(skip))] Option<Arc<ImC>>,
+    ),
     UniformScan(ScanSpanStep),
 }
 
@@ -192,6 +201,11 @@ impl<ImC> Clone for FrameWindowSplittingStrategy<ImC> {
 }
 
 #[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound = "")
+)]
 pub enum FrameWindowSplittingConfiguration<ImC> {
     Quadrupole(QuadWindowExpansionStrategy<ImC>),
     Window(QuadWindowExpansionStrategy<ImC>),
@@ -206,6 +220,12 @@ impl<ImC> Clone for FrameWindowSplittingConfiguration<ImC> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<ImC> timsrust_core::io::formats::json::JsonFormat
+    for FrameWindowSplittingConfiguration<ImC>
+{
+}
+
 impl<ImC> Default for FrameWindowSplittingConfiguration<ImC> {
     fn default() -> Self {
         Self::Quadrupole(QuadWindowExpansionStrategy::Even(1))
@@ -2,10 +2,15 @@ mod dda;
 mod dia;
 mod raw_spectra;
 
+pub use dda::{PasefFrameMsMsInfo, read_pasef_frame_msms_info};
+
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use raw_spectra::{RawSpectrum, RawSpectrumReader, RawSpectrumReaderError};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+#[cfg(feature = "serde")]
+use timsrust_core::io::formats::json::JsonFormat;
 use timsrust_core::utils::reader::Reader;
 use timsrust_core::{Im, InvertibleConverter, ScanIndex, Spectrum};
 
@@ -232,6 +237,11 @@ impl<ImC: InvertibleConverter<ScanIndex, Im> + Send + Sync + std::fmt::Debug>
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct SpectrumProcessingParams {
     pub smoothing_window: u32,
     pub centroiding_window: u32,
@@ -250,10 +260,167 @@ impl Default for SpectrumProcessingParams {
     }
 }
 
+/// Parameters of the 2D centroider that builds DIA pseudo-spectra.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct DIACentroidingParams {
+    pub min_ms1_ion_count: f64,
+    pub min_ms2_ion_count: f64,
+    pub min_spectrum_size: usize,
+    /// Assign MS2 peaks to deisotoped MS1 precursors. If `false`, spectra
+    /// are sliced from MS2 frames by scan only.
+    pub use_precursors: bool,
+    pub charges: Vec<u8>,
+    pub monoisotopic_only: bool,
+    pub highest_charge_state_only: bool,
+    pub deisotoping: DIADeisotoping,
+    /// Only keep fragments that co-elute with their precursor. Only used
+    /// if `use_precursors` is `true`.
+    pub elution_correlation: Option<DIAElutionCorrelation>,
+}
+
+impl Default for DIACentroidingParams {
+    fn default() -> Self {
+        Self {
+            min_ms1_ion_count: 0.5,
+            min_ms2_ion_count: 2.0,
+            min_spectrum_size: 5,
+            use_precursors: true,
+            charges: (1..6).collect(),
+            monoisotopic_only: true,
+            highest_charge_state_only: true,
+            deisotoping: DIADeisotoping::default(),
+            elution_correlation: None,
+        }
+    }
+}
+
+/// How fragments are scored on their co-elution with the precursor, by
+/// correlating their retention time and mobility profiles.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct DIAElutionCorrelation {
+    /// Number of neighbouring cycles on each side of a frame that make up
+    /// the retention time profiles.
+    pub cycles: usize,
+    /// Half-width in TOF bins over which raw intensities are summed.
+    pub tof_tolerance: u32,
+    /// Minimum score for a fragment to be kept.
+    pub min_score: f32,
+}
+
+impl Default for DIAElutionCorrelation {
+    fn default() -> Self {
+        Self {
+            cycles: 3,
+            tof_tolerance: 2,
+            min_score: 0.5,
+        }
+    }
+}
+
+/// How charge states and monoisotopic peaks are assigned to MS1 peaks of
+/// DIA precursors.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DIADeisotoping {
+    /// Accept a charge if the next isotope is present within
+    /// `max_delta_mz`, and consider a peak monoisotopic if the previous
+    /// isotope is absent.
+    Simple { max_delta_mz: f64 },
+    /// Score isotope envelopes against averagine-predicted intensities.
+    Averagine {
+        ppm_tolerance: f64,
+        max_isotopes: usize,
+        min_isotopes: usize,
+        min_score: f64,
+    },
+}
+
+impl Default for DIADeisotoping {
+    fn default() -> Self {
+        Self::Simple { max_delta_mz: 0.01 }
+    }
+}
+
+/// Parameters of the 2D centroider that builds DDA fragment spectra.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct DDACentroidingParams {
+    pub min_ms2_ion_count: f64,
+    /// A JSON file with centroiding kernels, as saved by
+    /// `timsrust_centroid::kernels::CentroidingKernels::save`. If `None`,
+    /// the kernels are learned from the MS2 frames.
+    pub kernels: Option<PathBuf>,
+}
+
+impl Default for DDACentroidingParams {
+    fn default() -> Self {
+        Self {
+            min_ms2_ion_count: 2.0,
+            kernels: None,
+        }
+    }
+}
+
+/// How spectra are read from DDA-PASEF frames.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DDAProcessing {
+    /// Sum the raw TOF spectra of all PASEF frame slices of a precursor,
+    /// then smooth and centroid them in 1D according to
+    /// [`SpectrumReaderConfig::spectrum_processing_params`].
+    #[default]
+    Raw,
+    /// Centroid every PASEF frame in scan × TOF and keep the peaks within
+    /// the scan range of each precursor.
+    Centroided(DDACentroidingParams),
+}
+
+/// How spectra are read from DIA-PASEF frames.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DIAProcessing {
+    /// One spectrum per quadrupole window slice, split according to
+    /// [`SpectrumReaderConfig::frame_splitting_params`].
+    WindowSlices,
+    /// Pseudo-spectra from 2D-centroided peaks.
+    Centroided(DIACentroidingParams),
+}
+
+impl Default for DIAProcessing {
+    fn default() -> Self {
+        Self::Centroided(DIACentroidingParams::default())
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, bound = "")
+)]
 pub struct SpectrumReaderConfig<ImC> {
     pub spectrum_processing_params: SpectrumProcessingParams,
     pub frame_splitting_params: FrameWindowSplittingConfiguration<ImC>,
+    /// Only used by readers that support centroided DIA spectra; the
+    /// [`TDFSpectrumReader`] itself always reads window slices.
+    pub dia_processing: DIAProcessing,
+    /// Only used by readers that support centroided DDA spectra; the
+    /// [`TDFSpectrumReader`] itself always reads raw spectra.
+    pub dda_processing: DDAProcessing,
 }
 
 impl<ImC> Default for SpectrumReaderConfig<ImC> {
@@ -262,6 +429,8 @@ impl<ImC> Default for SpectrumReaderConfig<ImC> {
             spectrum_processing_params: SpectrumProcessingParams::default(),
             frame_splitting_params: FrameWindowSplittingConfiguration::default(
             ),
+            dia_processing: DIAProcessing::default(),
+            dda_processing: DDAProcessing::default(),
         }
     }
 }
@@ -271,6 +440,78 @@ impl<ImC> Clone for SpectrumReaderConfig<ImC> {
         Self {
             spectrum_processing_params: self.spectrum_processing_params,
             frame_splitting_params: self.frame_splitting_params.clone(),
+            dia_processing: self.dia_processing.clone(),
+            dda_processing: self.dda_processing.clone(),
+        }
+    }
+}
+
+/// Configs can be stored next to their output and loaded again from JSON or
+/// TOML with [`JsonFormat::read_from_file`].
+#[cfg(feature = "serde")]
+impl<ImC> JsonFormat for SpectrumReaderConfig<ImC> {}
+
+#[cfg(feature = "serde")]
+impl JsonFormat for SpectrumProcessingParams {}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use timsrust_core::Im;
+
+    use super::*;
+    use crate::QuadWindowExpansionStrategy;
+
+    fn config() -> SpectrumReaderConfig<Im> {
+        SpectrumReaderConfig {
+            spectrum_processing_params: SpectrumProcessingParams {
+                smoothing_window: 3,
+                calibrate: true,
+                ..Default::default()
+            },
+            frame_splitting_params: FrameWindowSplittingConfiguration::Window(
+                QuadWindowExpansionStrategy::UniformMobility(
+                    (0.05, 0.02),
+                    None,
+                ),
+            ),
+            dia_processing: DIAProcessing::WindowSlices,
+            dda_processing: DDAProcessing::Centroided(
+                DDACentroidingParams::default(),
+            ),
         }
     }
+
+    fn assert_same(a: &SpectrumReaderConfig<Im>, b: &SpectrumReaderConfig<Im>) {
+        assert_eq!(format!("{a:?}"), format!("{b:?}"));
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["config.json", "config.toml"] {
+            let path = dir.path().join(name);
+            if name.ends_with(".toml") {
+                config().write_to_toml(&path).unwrap();
+            } else {
+                config().write_to_json(&path).unwrap();
+            }
+            let read = SpectrumReaderConfig::<Im>::read_from_file(&path);
+            assert_same(&read.unwrap(), &config());
+        }
+    }
+
+    #[test]
+    fn partial_toml_uses_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[spectrum_processing_params]\ncentroiding_window = 5\n",
+        )
+        .unwrap();
+        let read = SpectrumReaderConfig::<Im>::read_from_file(&path).unwrap();
+        let mut expected = SpectrumReaderConfig::<Im>::default();
+        expected.spectrum_processing_params.centroiding_window = 5;
+        assert_same(&read, &expected);
+    }
 }
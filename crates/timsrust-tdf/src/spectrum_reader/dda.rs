@@ -1,17 +1,62 @@
 use timsrust_core::utils::vec::{argsort, group_and_sum};
 
 use crate::{
-    FrameReaderError, TdfFrameReader,
+    FrameReaderError, TDFPathLike, TdfFrameReader,
     file_readers::sql_reader::{
         ReadableSqlTable, SqlReader, SqlReaderError,
         pasef_frame_msms::SqlPasefFrameMsMs,
     },
 };
 
-use super::raw_spectra::{
-    RawSpectrum, RawSpectrumReaderError, RawSpectrumReaderTrait,
+use super::{
+    TDFSpectrumReaderError,
+    raw_spectra::{
+        RawSpectrum, RawSpectrumReaderError, RawSpectrumReaderTrait,
+    },
 };
 
+/// A precursor that was fragmented in a scan range of a PASEF frame, as
+/// stored in the `PasefFrameMsMsInfo` table.
+///
+/// Scans are half-open: `scan_start..scan_end`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PasefFrameMsMsInfo {
+    pub frame_index: usize,
+    pub scan_start: usize,
+    pub scan_end: usize,
+    pub isolation_mz: f64,
+    pub isolation_width: f64,
+    pub collision_energy: f64,
+    /// The id of the precursor, as returned by `Precursor::index`.
+    pub precursor_id: usize,
+}
+
+impl From<SqlPasefFrameMsMs> for PasefFrameMsMsInfo {
+    fn from(pasef_frame: SqlPasefFrameMsMs) -> Self {
+        Self {
+            frame_index: pasef_frame.frame,
+            scan_start: pasef_frame.scan_start,
+            scan_end: pasef_frame.scan_end,
+            isolation_mz: pasef_frame.isolation_mz,
+            isolation_width: pasef_frame.isolation_width,
+            collision_energy: pasef_frame.collision_energy,
+            precursor_id: pasef_frame.precursor,
+        }
+    }
+}
+
+/// Read all rows of the `PasefFrameMsMsInfo` table of a DDA-PASEF run.
+pub fn read_pasef_frame_msms_info(
+    path: impl TDFPathLike,
+) -> Result<Vec<PasefFrameMsMsInfo>, TDFSpectrumReaderError> {
+    let tdf_sql_reader = SqlReader::open(&path)?;
+    let pasef_frames = SqlPasefFrameMsMs::from_sql_reader(&tdf_sql_reader)?;
+    Ok(pasef_frames
+        .into_iter()
+        .map(PasefFrameMsMsInfo::from)
+        .collect())
+}
+
 #[derive(Debug)]
 pub(crate) struct DDARawSpectrumReader {
     order: Vec<usize>,
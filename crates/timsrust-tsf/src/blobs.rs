@@ -30,27 +30,13 @@ impl TsfBlobReader {
         offset: usize,
         num_peaks: usize,
     ) -> Result<TsfSpectrumChunk, TsfBlobReaderError> {
-        let header = self.read_header(offset)?;
-        let compressed_start = offset + HEADER_BYTES;
-        let compressed_end = compressed_start + header.compressed_len;
-        let compressed = self
-            .binary_file
-            .read_range(compressed_start..compressed_end)?;
-
-        if compressed.is_empty() {
-            return Ok(TsfSpectrumChunk {
-                tof_indices: Vec::new(),
-                intensities: Vec::new(),
-            });
+        let decompressed = self.read_decompressed(offset)?;
+        if decompressed.is_empty() {
+            return Ok(TsfSpectrumChunk::default());
         }
-
-        let decompressed = decode_all(Cursor::new(compressed))
-            .map_err(TsfBlobReaderError::Decompression)?;
         // check that the number of peaks matches with the decompressed data length.
         // Each peak uses 12 bytes
-        let expected = num_peaks
-            .checked_mul(12)
-            .ok_or(TsfBlobReaderError::Overflow)?;
+        let expected = Self::line_bytes(num_peaks)?;
         if decompressed.len() < expected {
             return Err(TsfBlobReaderError::UnexpectedLength {
                 expected,
@@ -58,7 +44,7 @@ impl TsfBlobReader {
             });
         }
         let (tof_indices_bytes, intensity_bytes) =
-            decompressed.split_at(num_peaks * 8);
+            decompressed[..expected].split_at(num_peaks * 8);
         let tof_indices = tof_indices_bytes
             .chunks_exact(8)
             .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
@@ -78,6 +64,69 @@ impl TsfBlobReader {
         })
     }
 
+    /// Reads the profile spectrum of a frame.
+    ///
+    /// Profile data is stored after the `num_line_peaks` line peaks as one
+    /// u32 intensity per tof index. Only non-zero intensities are returned.
+    pub(crate) fn read_profile_chunk(
+        &self,
+        offset: usize,
+        num_line_peaks: usize,
+    ) -> Result<TsfSpectrumChunk, TsfBlobReaderError> {
+        let decompressed = self.read_decompressed(offset)?;
+        let start = Self::line_bytes(num_line_peaks)?;
+        let profile_bytes = decompressed.get(start..).ok_or(
+            TsfBlobReaderError::UnexpectedLength {
+                expected: start,
+                actual: decompressed.len(),
+            },
+        )?;
+        if !profile_bytes.len().is_multiple_of(4) {
+            return Err(TsfBlobReaderError::UnexpectedLength {
+                expected: profile_bytes.len().next_multiple_of(4),
+                actual: profile_bytes.len(),
+            });
+        }
+        let (tof_indices, intensities) = profile_bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .enumerate()
+            .filter(|&(_, intensity)| intensity > 0)
+            .map(|(tof_index, intensity)| {
+                let tof_index = TofIndex::try_from(tof_index as u32)
+                    .expect("TofIndex conversion out of bounds");
+                (tof_index, intensity as f64)
+            })
+            .unzip();
+        Ok(TsfSpectrumChunk {
+            tof_indices,
+            intensities,
+        })
+    }
+
+    fn line_bytes(num_peaks: usize) -> Result<usize, TsfBlobReaderError> {
+        num_peaks
+            .checked_mul(12)
+            .ok_or(TsfBlobReaderError::Overflow)
+    }
+
+    fn read_decompressed(
+        &self,
+        offset: usize,
+    ) -> Result<Vec<u8>, TsfBlobReaderError> {
+        let header = self.read_header(offset)?;
+        let compressed_start = offset + HEADER_BYTES;
+        let compressed_end = compressed_start + header.compressed_len;
+        let compressed = self
+            .binary_file
+            .read_range(compressed_start..compressed_end)?;
+        if compressed.is_empty() {
+            return Ok(vec![]);
+        }
+        decode_all(Cursor::new(compressed))
+            .map_err(TsfBlobReaderError::Decompression)
+    }
+
     fn read_header(
         &self,
         offset: usize,
@@ -101,7 +150,7 @@ impl TsfBlobReader {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub(crate) struct TsfSpectrumChunk {
     pub(crate) tof_indices: Vec<TofIndex>,
     pub(crate) intensities: Vec<f64>,
@@ -128,3 +177,48 @@ pub(crate) enum TsfBlobReaderError {
     #[error("Decompression failed")]
     Decompression(std::io::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TestFrame, write_run};
+
+    fn tof(index: u32) -> TofIndex {
+        TofIndex::try_from(index).unwrap()
+    }
+
+    #[test]
+    fn line_and_profile_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let frame = TestFrame {
+            line: vec![(2, 10.5), (7, 3.0)],
+            profile: vec![0, 4, 0, 0, 9, 1],
+            msms_type: 0,
+        };
+        let path = write_run(dir.path(), &[frame], "");
+        let reader = TsfBlobReader::new(path).unwrap();
+        let line = reader.read_chunk(0, 2).unwrap();
+        assert_eq!(line.tof_indices, vec![tof(2), tof(7)]);
+        assert_eq!(line.intensities, vec![10.5, 3.0]);
+        let profile = reader.read_profile_chunk(0, 2).unwrap();
+        assert_eq!(profile.tof_indices, vec![tof(1), tof(4), tof(5)]);
+        assert_eq!(profile.intensities, vec![4.0, 9.0, 1.0]);
+    }
+
+    #[test]
+    fn profile_chunk_length_is_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let frame = TestFrame {
+            line: vec![(2, 10.5)],
+            profile: vec![1, 2],
+            msms_type: 0,
+        };
+        let path = write_run(dir.path(), &[frame], "");
+        let reader = TsfBlobReader::new(path).unwrap();
+        // The blob only holds 12 + 8 bytes, less than two line peaks.
+        assert!(matches!(
+            reader.read_profile_chunk(0, 2),
+            Err(TsfBlobReaderError::UnexpectedLength { .. })
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Deserialize;
+use timsrust_core::{
+    AcquisitionType, FrameInfo, FrameIons, IntensityIndex, MSLevel,
+    QuadrupoleSettings,
+    io::formats::sql::{SqlError, SqlReader},
+    utils::reader::{IndexedReader, Reader},
+};
+
+use crate::{
+    TSFPrecursorReader, TSFPrecursorReaderError, TSFSpectrumReader,
+    TSFSpectrumReaderConfig, TSFSpectrumReaderError,
+    timstof::{TSFPathError, TSFPathLike},
+};
+
+#[derive(Deserialize)]
+struct SqlFrameInfo {
+    #[serde(rename = "Id")]
+    id: i64,
+    #[serde(rename = "Time")]
+    time: f64,
+    #[serde(rename = "MsMsType")]
+    msms_type: i64,
+}
+
+/// Serves the peaks of each TSF frame as [`FrameIons`] with a single
+/// pseudo-scan, indexed by (1-based) frame id.
+///
+/// TSF intensities are floats, while frames store integer intensities.
+/// They are rounded to the nearest integer, so fractional intensities,
+/// e.g. of profile or centroided spectra, lose precision. Use a
+/// [`TSFSpectrumReader`] to read the exact values.
+#[derive(Debug)]
+pub struct TsfIonReader {
+    spectrum_reader: TSFSpectrumReader,
+    frame_lookup: HashMap<usize, usize>,
+}
+
+impl TsfIonReader {
+    fn new(spectrum_reader: TSFSpectrumReader) -> Self {
+        let frame_lookup = (0..spectrum_reader.len())
+            .filter_map(|index| Some((spectrum_reader.frame_id(index)?, index)))
+            .collect();
+        Self {
+            spectrum_reader,
+            frame_lookup,
+        }
+    }
+
+    pub fn spectrum_reader(&self) -> &TSFSpectrumReader {
+        &self.spectrum_reader
+    }
+}
+
+impl Reader<FrameIons> for TsfIonReader {
+    type Error = TSFFrameReaderError;
+
+    fn get(&self, index: usize) -> Result<FrameIons, Self::Error> {
+        let spectrum_index = self
+            .frame_lookup
+            .get(&index)
+            .copied()
+            .ok_or(TSFFrameReaderError::IndexOutOfBounds(index))?;
+        let (tof_indices, intensities) =
+            self.spectrum_reader.read_peaks(spectrum_index)?;
+        // TSF intensities are floats; frames store integer intensity indices.
+        let intensities = intensities
+            .into_iter()
+            .map(|intensity| {
+                let intensity =
+                    intensity.round().clamp(0.0, (u32::MAX - 1) as f64);
+                IntensityIndex::try_from(intensity as u32)
+                    .expect("IntensityIndex is clamped to a valid range")
+            })
+            .collect();
+        let scan_offsets = vec![0, tof_indices.len()];
+        Ok(FrameIons::new(scan_offsets, tof_indices, intensities))
+    }
+}
+
+/// Reads [`FrameInfo`] from the `Frames` table of a TSF file, indexed by
+/// (1-based) frame id.
+///
+/// MS/MS frames carry their isolation window as quadrupole settings that
+/// span the single pseudo-scan.
+#[derive(Debug)]
+pub struct TsfFrameInfoReader {
+    frame_infos: HashMap<usize, FrameInfo>,
+}
+
+impl TsfFrameInfoReader {
+    pub fn new(path: impl TSFPathLike) -> Result<Self, TSFFrameReaderError> {
+        let precursor_reader = TSFPrecursorReader::new(&path)?;
+        Self::with_precursor_reader(path, &precursor_reader)
+    }
+
+    fn with_precursor_reader(
+        path: impl TSFPathLike,
+        precursor_reader: &TSFPrecursorReader,
+    ) -> Result<Self, TSFFrameReaderError> {
+        let timstof_path = path.to_timstof_path()?;
+        let reader = SqlReader::from(timstof_path.tsf().as_ref())?;
+        let frame_infos = reader
+            .from_table::<SqlFrameInfo>("Frames")?
+            .read_all()?
+            .into_iter()
+            .map(|sql_frame| {
+                let frame_id = sql_frame.id as usize;
+                let precursor_index = precursor_reader.index_of_frame(frame_id);
+                let ms_level = match (sql_frame.msms_type, precursor_index) {
+                    (0, _) => MSLevel::MS1,
+                    (_, Some(_)) => MSLevel::MS2,
+                    (msms_type, None) => {
+                        MSLevel::read_from_msms_type(msms_type as u8)
+                    },
+                };
+                let quadrupole_settings = precursor_index
+                    .and_then(|i| precursor_reader.isolation_window(i))
+                    .map(|isolation_window| QuadrupoleSettings {
+                        index: frame_id,
+                        scan_starts: vec![0],
+                        scan_ends: vec![1],
+                        isolation_windows: vec![isolation_window],
+                    })
+                    .unwrap_or_default();
+                let frame_info = FrameInfo::new(
+                    Arc::new(quadrupole_settings),
+                    frame_id,
+                    sql_frame.time,
+                    1.0,
+                    AcquisitionType::Unknown,
+                    ms_level,
+                    0,
+                    None,
+                );
+                (frame_id, frame_info)
+            })
+            .collect();
+        Ok(Self { frame_infos })
+    }
+}
+
+impl Reader<FrameInfo> for TsfFrameInfoReader {
+    type Error = TSFFrameReaderError;
+
+    fn get(&self, index: usize) -> Result<FrameInfo, Self::Error> {
+        self.frame_infos
+            .get(&index)
+            .cloned()
+            .ok_or(TSFFrameReaderError::IndexOutOfBounds(index))
+    }
+}
+
+impl IndexedReader<FrameInfo> for TsfFrameInfoReader {
+    type Iter = std::vec::IntoIter<usize>;
+    fn iter(&self) -> Self::Iter {
+        let mut indices: Vec<usize> =
+            self.frame_infos.keys().copied().collect();
+        indices.sort_unstable();
+        indices.into_iter()
+    }
+}
+
+/// A concrete frame reader for Bruker TSF files.
+///
+/// Every frame holds a single pseudo-scan, as TSF data has no ion mobility
+/// dimension. Its intensities are rounded to integers, see
+/// [`TsfIonReader`]. All [`timsrust_core::FrameReader`] methods are
+/// available via [`Deref`](std::ops::Deref).
+#[derive(Debug)]
+pub struct TsfFrameReader(
+    timsrust_core::FrameReader<TsfIonReader, TsfFrameInfoReader>,
+);
+
+impl std::ops::Deref for TsfFrameReader {
+    type Target = timsrust_core::FrameReader<TsfIonReader, TsfFrameInfoReader>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl TsfFrameReader {
+    pub fn new(path: impl TSFPathLike) -> Result<Self, TSFFrameReaderError> {
+        Self::with_config(path, TSFSpectrumReaderConfig::default())
+    }
+
+    /// Create a frame reader whose ions follow the spectrum type and
+    /// centroiding of `config`.
+    pub fn with_config(
+        path: impl TSFPathLike,
+        config: TSFSpectrumReaderConfig,
+    ) -> Result<Self, TSFFrameReaderError> {
+        let spectrum_reader = TSFSpectrumReader::with_config(&path, config)?;
+        let info_reader = TsfFrameInfoReader::with_precursor_reader(
+            &path,
+            spectrum_reader.precursor_reader(),
+        )?;
+        Ok(Self(timsrust_core::FrameReader::new(
+            TsfIonReader::new(spectrum_reader),
+            info_reader,
+        )))
+    }
+
+    /// Consume `self` and return the underlying generic
+    /// [`timsrust_core::FrameReader`].
+    pub fn into_inner(
+        self,
+    ) -> timsrust_core::FrameReader<TsfIonReader, TsfFrameInfoReader> {
+        self.0
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TSFFrameReaderError {
+    #[error("{0}")]
+    Sql(#[from] SqlError),
+    #[error("{0}")]
+    TSFPathError(#[from] TSFPathError),
+    #[error("{0}")]
+    TSFSpectrumReaderError(#[from] TSFSpectrumReaderError),
+    #[error("{0}")]
+    TSFPrecursorReaderError(#[from] TSFPrecursorReaderError),
+    #[error("No frame with id {0}")]
+    IndexOutOfBounds(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::{Mz, TofIndex};
+
+    use super::*;
+    use crate::testing::{TestFrame, write_run};
+
+    const MSMS_INFO: &str = "CREATE TABLE FrameMsMsInfo (
+            Frame INTEGER, Parent INTEGER, TriggerMass REAL,
+            IsolationWidth REAL, PrecursorCharge INTEGER,
+            CollisionEnergy REAL
+        );
+        INSERT INTO FrameMsMsInfo VALUES (2, 1, 500.25, 2.0, 2, 35.0);";
+
+    fn frames() -> Vec<TestFrame> {
+        vec![
+            TestFrame {
+                line: vec![(3, 7.6), (10, 2.2)],
+                profile: vec![0, 2, 5, 8, 4],
+                msms_type: 0,
+            },
+            TestFrame {
+                line: vec![(6, 1.5)],
+                profile: vec![1, 0, 0, 0, 0],
+                msms_type: 2,
+            },
+        ]
+    }
+
+    fn tof(indices: &[u32]) -> Vec<TofIndex> {
+        indices
+            .iter()
+            .map(|&index| TofIndex::try_from(index).unwrap())
+            .collect()
+    }
+
+    fn intensities(frame: &timsrust_core::Frame) -> Vec<u32> {
+        frame
+            .ions()
+            .intensities()
+            .iter()
+            .map(|&i| i.into())
+            .collect()
+    }
+
+    #[test]
+    fn frame_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &frames(), MSMS_INFO);
+        let reader = TsfFrameReader::new(&path).unwrap();
+        assert_eq!(reader.len(), 2);
+        assert_eq!(reader.iter_indices().collect::<Vec<_>>(), [1, 2]);
+        let frame = reader.get_frame(1).unwrap();
+        assert_eq!(frame.info().ms_level(), MSLevel::MS1);
+        assert_eq!(frame.info().rt_in_seconds(), 0.0);
+        assert_eq!(frame.ions().scan_count(), 1);
+        assert_eq!(frame.ions().tof_indices(), &tof(&[3, 10]));
+        // Float intensities are rounded.
+        assert_eq!(intensities(&frame), [8, 2]);
+        let frame = reader.get_frame(2).unwrap();
+        assert_eq!(frame.info().ms_level(), MSLevel::MS2);
+        assert_eq!(frame.info().rt_in_seconds(), 0.5);
+        assert_eq!(intensities(&frame), [2]);
+        let settings = frame.info().quadrupole_settings();
+        assert_eq!((settings.scan_starts[0], settings.scan_ends[0]), (0, 1));
+        assert_eq!(settings.isolation_windows[0].center(), Mz::from(500.25));
+        assert!(reader.get_frame(3).is_err());
+    }
+
+    #[test]
+    fn profile_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &frames(), "");
+        let config = TSFSpectrumReaderConfig {
+            spectrum_type: Some(crate::TSFSpectrumType::Profile),
+            centroiding_window: None,
+        };
+        let reader = TsfFrameReader::with_config(&path, config).unwrap();
+        let frame = reader.get_frame(1).unwrap();
+        assert_eq!(frame.ions().tof_indices(), &tof(&[1, 2, 3, 4]));
+        assert_eq!(intensities(&frame), [2, 5, 8, 4]);
+        // Without FrameMsMsInfo, the MsMsType decides the MS level.
+        let frame = reader.get_frame(2).unwrap();
+        assert_eq!(frame.info().ms_level(), MSLevel::Unknown);
+        assert!(
+            frame
+                .info()
+                .quadrupole_settings()
+                .isolation_windows
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn info_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &frames(), MSMS_INFO);
+        let reader = TsfFrameInfoReader::new(&path).unwrap();
+        assert_eq!(reader.iter().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(reader.get(2).unwrap().index(), 2);
+        assert!(matches!(
+            reader.get(0),
+            Err(TSFFrameReaderError::IndexOutOfBounds(0))
+        ));
+    }
+}
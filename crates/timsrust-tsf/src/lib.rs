@@ -1,8 +1,20 @@
 mod blobs;
+mod frames;
 mod mz;
+mod precursors;
 mod spectrum;
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub mod testing;
 mod timstof;
 
+pub use frames::{
+    TSFFrameReaderError, TsfFrameInfoReader, TsfFrameReader, TsfIonReader,
+};
 pub use mz::Tof2MzConverter;
-pub use spectrum::{TSFSpectrumReader, TSFSpectrumReaderError};
+pub use precursors::{TSFPrecursorReader, TSFPrecursorReaderError};
+pub use spectrum::{
+    TSFSpectrumReader, TSFSpectrumReaderConfig, TSFSpectrumReaderError,
+    TSFSpectrumType,
+};
 pub use timstof::{TSFPath, TSFPathError, TSFPathLike};
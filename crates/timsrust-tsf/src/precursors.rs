@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use timsrust_core::{
+    Charge, FrameIndex, Im, IsolationWindow, Mz, Precursor, Rt, ScanIndex,
+    io::formats::sql::{SqlError, SqlReader},
+    utils::reader::{IndexedReader, Reader},
+};
+
+use crate::timstof::{TSFPathError, TSFPathLike};
+
+#[derive(Deserialize)]
+struct SqlFrameTime {
+    #[serde(rename = "Id")]
+    id: i64,
+    #[serde(rename = "Time")]
+    time: f64,
+}
+
+#[derive(Deserialize)]
+struct SqlFrameMsMsInfo {
+    #[serde(rename = "Frame")]
+    frame: i64,
+    #[serde(rename = "Parent")]
+    parent: Option<i64>,
+    #[serde(rename = "TriggerMass")]
+    trigger_mass: f64,
+    #[serde(rename = "IsolationWidth")]
+    isolation_width: f64,
+    #[serde(rename = "PrecursorCharge")]
+    precursor_charge: Option<i64>,
+    #[serde(rename = "CollisionEnergy")]
+    collision_energy: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct TsfPrecursor {
+    frame_id: usize,
+    parent_id: usize,
+    mz: f64,
+    isolation_width: f64,
+    charge: Option<Charge>,
+    collision_energy: f64,
+    rt_seconds: f64,
+}
+
+/// Reads MS/MS precursor information from the `FrameMsMsInfo` table of a
+/// TSF file.
+///
+/// Runs without MS/MS spectra yield an empty reader.
+#[derive(Debug)]
+pub struct TSFPrecursorReader {
+    precursors: Vec<TsfPrecursor>,
+    frame_lookup: HashMap<usize, usize>,
+}
+
+impl TSFPrecursorReader {
+    pub fn new(
+        path: impl TSFPathLike,
+    ) -> Result<Self, TSFPrecursorReaderError> {
+        let timstof_path = path.to_timstof_path()?;
+        let reader = SqlReader::from(timstof_path.tsf().as_ref())?;
+        if !reader.tables()?.iter().any(|t| t == "FrameMsMsInfo") {
+            return Ok(Self {
+                precursors: vec![],
+                frame_lookup: HashMap::new(),
+            });
+        }
+        let rts: HashMap<i64, f64> = reader
+            .from_table::<SqlFrameTime>("Frames")?
+            .read_all()?
+            .into_iter()
+            .map(|f| (f.id, f.time))
+            .collect();
+        let precursors: Vec<TsfPrecursor> = reader
+            .from_table::<SqlFrameMsMsInfo>("FrameMsMsInfo")?
+            .read_all()?
+            .into_iter()
+            .map(|m| TsfPrecursor {
+                frame_id: m.frame as usize,
+                parent_id: m.parent.unwrap_or(m.frame) as usize,
+                mz: m.trigger_mass,
+                isolation_width: m.isolation_width,
+                charge: m
+                    .precursor_charge
+                    .and_then(|c| Charge::try_from(c).ok()),
+                collision_energy: m.collision_energy,
+                rt_seconds: rts.get(&m.frame).copied().unwrap_or_default(),
+            })
+            .collect();
+        let frame_lookup = precursors
+            .iter()
+            .enumerate()
+            .map(|(index, p)| (p.frame_id, index))
+            .collect();
+        Ok(Self {
+            precursors,
+            frame_lookup,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.precursors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the index of the precursor that was fragmented in the frame
+    /// with this (1-based) frame id.
+    pub fn index_of_frame(&self, frame_id: usize) -> Option<usize> {
+        self.frame_lookup.get(&frame_id).copied()
+    }
+
+    /// Returns the isolation window of the precursor at `index`.
+    pub fn isolation_window(&self, index: usize) -> Option<IsolationWindow> {
+        let precursor = self.precursors.get(index)?;
+        Some(IsolationWindow::new_from_center(
+            Mz::from(precursor.mz),
+            Mz::from(precursor.isolation_width),
+            precursor.collision_energy,
+        ))
+    }
+}
+
+impl IndexedReader<Precursor> for TSFPrecursorReader {
+    type Iter = std::ops::Range<usize>;
+    fn iter(&self) -> Self::Iter {
+        0..self.len()
+    }
+}
+
+impl Reader<Precursor> for TSFPrecursorReader {
+    type Error = TSFPrecursorReaderError;
+    fn get(&self, index: usize) -> Result<Precursor, Self::Error> {
+        let precursor = self
+            .precursors
+            .get(index)
+            .ok_or(TSFPrecursorReaderError::NoDataAtIndex(index))?;
+        let frame_index = FrameIndex::try_from(precursor.parent_id)
+            .map_err(|_| TSFPrecursorReaderError::NoDataAtIndex(index))?;
+        // TSF data has no ion mobility dimension.
+        let scan_index =
+            ScanIndex::try_from(0u32).expect("ScanIndex 0 is always valid");
+        let precursor = Precursor::new(
+            Mz::from(precursor.mz),
+            Im::from(0.0),
+            Rt::from(precursor.rt_seconds),
+            scan_index,
+            precursor.charge,
+            None,
+            index,
+            frame_index,
+        );
+        Ok(precursor)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TSFPrecursorReaderError {
+    #[error("{0}")]
+    Sql(#[from] SqlError),
+    #[error("{0}")]
+    TSFPathError(#[from] TSFPathError),
+    #[error("No data at index {0}")]
+    NoDataAtIndex(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::TofIndex;
+
+    use super::*;
+    use crate::TSFSpectrumReader;
+    use crate::testing::{TestFrame, write_run};
+
+    const MSMS_INFO: &str = "CREATE TABLE FrameMsMsInfo (
+            Frame INTEGER, Parent INTEGER, TriggerMass REAL,
+            IsolationWidth REAL, PrecursorCharge INTEGER,
+            CollisionEnergy REAL
+        );
+        INSERT INTO FrameMsMsInfo VALUES
+            (2, 1, 500.25, 2.0, 2, 35.0),
+            (3, NULL, 800.5, 4.0, NULL, 40.0);";
+
+    fn frames() -> Vec<TestFrame> {
+        let frame = |msms_type| TestFrame {
+            line: vec![(3, 8.0)],
+            profile: vec![],
+            msms_type,
+        };
+        vec![frame(0), frame(2), frame(2)]
+    }
+
+    #[test]
+    fn without_msms_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &frames(), "");
+        let reader = TSFPrecursorReader::new(&path).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(reader.index_of_frame(2), None);
+        assert!(matches!(
+            reader.get(0),
+            Err(TSFPrecursorReaderError::NoDataAtIndex(0))
+        ));
+    }
+
+    #[test]
+    fn reads_msms_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &frames(), MSMS_INFO);
+        let reader = TSFPrecursorReader::new(&path).unwrap();
+        assert_eq!(reader.len(), 2);
+        assert_eq!(reader.index_of_frame(1), None);
+        assert_eq!(reader.index_of_frame(2), Some(0));
+        assert_eq!(reader.index_of_frame(3), Some(1));
+        let precursor = reader.get(0).unwrap();
+        assert_eq!(precursor.mz(), Mz::from(500.25));
+        assert_eq!(precursor.rt(), Rt::from(0.5));
+        assert_eq!(precursor.charge(), &Some(Charge::try_from(2).unwrap()));
+        assert_eq!(precursor.frame_index(), FrameIndex::try_from(1).unwrap());
+        assert_eq!(precursor.index(), 0);
+        let window = reader.isolation_window(0).unwrap();
+        assert_eq!(window.lower(), Mz::from(499.25));
+        assert_eq!(window.upper(), Mz::from(501.25));
+        assert_eq!(window.collision_energy(), 35.0);
+        // Without a parent frame, the fragmented frame itself is used.
+        let precursor = reader.get(1).unwrap();
+        assert_eq!(precursor.charge(), &None);
+        assert_eq!(precursor.frame_index(), FrameIndex::try_from(3).unwrap());
+        assert!(reader.get(2).is_err());
+    }
+
+    #[test]
+    fn attaches_precursors_to_spectra() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &frames(), MSMS_INFO);
+        let reader = TSFSpectrumReader::new(&path).unwrap();
+        let spectrum = reader.get(0).unwrap();
+        assert!(spectrum.precursor().is_none());
+        assert_eq!(
+            spectrum.tof_indices(),
+            &vec![TofIndex::try_from(3).unwrap()]
+        );
+        let spectrum = reader.get(2).unwrap();
+        let precursor = spectrum.precursor().as_ref().unwrap();
+        assert_eq!(precursor.mz(), Mz::from(800.5));
+        assert_eq!(spectrum.isolation_window().center(), Mz::from(800.5));
+    }
+}
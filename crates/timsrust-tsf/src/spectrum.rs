@@ -2,11 +2,13 @@ use std::collections::HashMap;
 
 use serde::Deserialize;
 use timsrust_core::{
-    IsolationWindow, Spectrum,
+    IsolationWindow, Spectrum, TofIndex,
     io::formats::sql::{SqlError, SqlReader},
+    utils::vec::{filter_with_mask, find_sparse_local_maxima_mask},
 };
 
 use crate::Tof2MzConverter;
+use crate::precursors::{TSFPrecursorReader, TSFPrecursorReaderError};
 use crate::timstof::TSFPathError;
 use crate::{
     blobs::{TsfBlobReader, TsfBlobReaderError},
@@ -33,18 +35,83 @@ struct SqlFrame {
     tims_id: i64,
 }
 
+#[derive(Deserialize)]
+struct SqlMaldiFrameInfo {
+    #[serde(rename = "Frame")]
+    frame: i64,
+    #[serde(rename = "XIndexPos")]
+    x: i64,
+    #[serde(rename = "YIndexPos")]
+    y: i64,
+}
+
+impl SqlMaldiFrameInfo {
+    fn pixel(&self) -> Result<(u32, u32), TSFSpectrumReaderError> {
+        match (u32::try_from(self.x), u32::try_from(self.y)) {
+            (Ok(x), Ok(y)) => Ok((x, y)),
+            _ => Err(TSFSpectrumReaderError::InvalidPixel {
+                frame: self.frame,
+                x: self.x,
+                y: self.y,
+            }),
+        }
+    }
+}
+
+/// The kind of spectra stored in a TSF file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TSFSpectrumType {
+    /// Centroided line spectra (`HasLineSpectra`).
+    Line,
+    /// Profile spectra with one intensity per tof index
+    /// (`HasProfileSpectra`).
+    Profile,
+}
+
+impl std::fmt::Display for TSFSpectrumType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Line => write!(f, "line"),
+            Self::Profile => write!(f, "profile"),
+        }
+    }
+}
+
+/// Configuration of a [`TSFSpectrumReader`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TSFSpectrumReaderConfig {
+    /// The spectra to read. If `None`, line spectra are preferred over
+    /// profile spectra when both are present.
+    pub spectrum_type: Option<TSFSpectrumType>,
+    /// If set, profile spectra are centroided by only keeping local maxima
+    /// within this tof window. Line spectra are already centroided and are
+    /// never affected.
+    pub centroiding_window: Option<u32>,
+}
+
 #[derive(Debug)]
 pub struct TSFSpectrumReader {
     frames: Vec<TsfFrame>,
     blob_reader: TsfBlobReader,
     mz_converter: Tof2MzConverter,
+    precursor_reader: TSFPrecursorReader,
+    spectrum_type: TSFSpectrumType,
+    centroiding_window: Option<u32>,
 }
 
 impl TSFSpectrumReader {
     pub fn new(path: impl TSFPathLike) -> Result<Self, TSFSpectrumReaderError> {
+        Self::with_config(path, TSFSpectrumReaderConfig::default())
+    }
+
+    pub fn with_config(
+        path: impl TSFPathLike,
+        config: TSFSpectrumReaderConfig,
+    ) -> Result<Self, TSFSpectrumReaderError> {
         let blob_reader = TsfBlobReader::new(&path)?;
         let timstof_path = path.to_timstof_path()?;
         let mz_converter = Tof2MzConverter::new(timstof_path.as_ref());
+        let precursor_reader = TSFPrecursorReader::new(&timstof_path)?;
         let reader = SqlReader::from(timstof_path.tsf().as_ref())?;
         let metadata: HashMap<String, String> = reader
             .from_table::<KvRow>("GlobalMetadata")?
@@ -52,28 +119,61 @@ impl TSFSpectrumReader {
             .into_iter()
             .map(|r| (r.key, r.value))
             .collect();
-        let has_line_spectra = metadata
-            .get("HasLineSpectra")
-            .map(|v| v.trim() == "1")
-            .unwrap_or(false);
-        if !has_line_spectra {
-            return Err(TSFSpectrumReaderError::UnsupportedDataset);
-        }
+        let has_flag = |key: &str| {
+            metadata.get(key).map(|v| v.trim() == "1").unwrap_or(false)
+        };
+        let has_line_spectra = has_flag("HasLineSpectra");
+        let has_profile_spectra = has_flag("HasProfileSpectra");
+        let spectrum_type = match config.spectrum_type {
+            Some(TSFSpectrumType::Line) if has_line_spectra => {
+                TSFSpectrumType::Line
+            },
+            Some(TSFSpectrumType::Profile) if has_profile_spectra => {
+                TSFSpectrumType::Profile
+            },
+            Some(spectrum_type) => {
+                return Err(TSFSpectrumReaderError::UnsupportedDataset(
+                    spectrum_type,
+                ));
+            },
+            None if has_line_spectra => TSFSpectrumType::Line,
+            None if has_profile_spectra => TSFSpectrumType::Profile,
+            None => {
+                return Err(TSFSpectrumReaderError::UnsupportedDataset(
+                    TSFSpectrumType::Line,
+                ));
+            },
+        };
+        let pixels: HashMap<i64, (u32, u32)> =
+            if reader.tables()?.iter().any(|t| t == "MaldiFrameInfo") {
+                reader
+                    .from_table::<SqlMaldiFrameInfo>("MaldiFrameInfo")?
+                    .read_all()?
+                    .into_iter()
+                    .map(|m| Ok((m.frame, m.pixel()?)))
+                    .collect::<Result<_, TSFSpectrumReaderError>>()?
+            } else {
+                HashMap::new()
+            };
         let frames: Vec<TsfFrame> = reader
             .from_table::<SqlFrame>("Frames")?
             .read_all()?
             .into_iter()
             .map(|f| TsfFrame {
-                _frame_id: f.id as usize,
+                frame_id: f.id as usize,
                 num_peaks: f.num_peaks as usize,
                 _rt_seconds: f.time,
                 offset: f.tims_id as usize,
+                pixel: pixels.get(&f.id).copied(),
             })
             .collect();
         Ok(Self {
             frames,
             blob_reader,
             mz_converter,
+            precursor_reader,
+            spectrum_type,
+            centroiding_window: config.centroiding_window,
         })
     }
 
@@ -88,6 +188,60 @@ impl TSFSpectrumReader {
     pub fn mz_converter(&self) -> &Tof2MzConverter {
         &self.mz_converter
     }
+
+    pub fn precursor_reader(&self) -> &TSFPrecursorReader {
+        &self.precursor_reader
+    }
+
+    pub fn spectrum_type(&self) -> TSFSpectrumType {
+        self.spectrum_type
+    }
+
+    /// Returns `true` if the spectra of this reader are centroided.
+    pub fn is_centroided(&self) -> bool {
+        self.spectrum_type == TSFSpectrumType::Line
+            || self.centroiding_window.is_some()
+    }
+
+    /// Returns the `(x, y)` pixel position of a spectrum, as stored in the
+    /// `MaldiFrameInfo` table of imaging runs.
+    ///
+    /// Returns `None` for out-of-bounds indices and for runs without
+    /// MALDI imaging information.
+    pub fn pixel(&self, index: usize) -> Option<(u32, u32)> {
+        self.frames.get(index)?.pixel
+    }
+
+    /// Returns the (1-based) frame id of the spectrum at `index`.
+    pub(crate) fn frame_id(&self, index: usize) -> Option<usize> {
+        Some(self.frames.get(index)?.frame_id)
+    }
+
+    /// Reads the (optionally centroided) peaks of the spectrum at `index`.
+    pub(crate) fn read_peaks(
+        &self,
+        index: usize,
+    ) -> Result<(Vec<TofIndex>, Vec<f64>), TSFSpectrumReaderError> {
+        let frame = self
+            .frames
+            .get(index)
+            .ok_or(TSFSpectrumReaderError::IndexOutOfBounds)?;
+        let chunk = match self.spectrum_type {
+            TSFSpectrumType::Line => {
+                self.blob_reader.read_chunk(frame.offset, frame.num_peaks)?
+            },
+            TSFSpectrumType::Profile => self
+                .blob_reader
+                .read_profile_chunk(frame.offset, frame.num_peaks)?,
+        };
+        let peaks = match (self.spectrum_type, self.centroiding_window) {
+            (TSFSpectrumType::Profile, Some(window)) => {
+                centroid(&chunk.tof_indices, &chunk.intensities, window)
+            },
+            _ => (chunk.tof_indices, chunk.intensities),
+        };
+        Ok(peaks)
+    }
 }
 
 impl timsrust_core::utils::reader::IndexedReader<Spectrum>
@@ -106,27 +260,57 @@ impl timsrust_core::utils::reader::Reader<Spectrum> for TSFSpectrumReader {
             .frames
             .get(index)
             .ok_or(TSFSpectrumReaderError::IndexOutOfBounds)?;
-
-        let chunk =
-            self.blob_reader.read_chunk(frame.offset, frame.num_peaks)?;
-        let isolation_window = IsolationWindow::default();
+        let (tof_indices, intensities) = self.read_peaks(index)?;
+        let (precursor, isolation_window) =
+            match self.precursor_reader.index_of_frame(frame.frame_id) {
+                Some(precursor_index) => (
+                    Some(self.precursor_reader.get(precursor_index)?),
+                    self.precursor_reader
+                        .isolation_window(precursor_index)
+                        .unwrap_or_default(),
+                ),
+                None => (None, IsolationWindow::default()),
+            };
         let spectrum = Spectrum::new(
-            chunk.intensities,
+            intensities,
             index,
-            None,
-            chunk.tof_indices,
+            precursor,
+            tof_indices,
             isolation_window,
         );
         Ok(spectrum)
     }
 }
 
+fn centroid(
+    tof_indices: &[TofIndex],
+    intensities: &[f64],
+    window: u32,
+) -> (Vec<TofIndex>, Vec<f64>) {
+    let raw_tof_indices: Vec<u32> =
+        tof_indices.iter().map(|&tof| u32::from(tof)).collect();
+    let raw_intensities: Vec<u64> = intensities
+        .iter()
+        .map(|&intensity| intensity as u64)
+        .collect();
+    let local_maxima = find_sparse_local_maxima_mask(
+        &raw_tof_indices,
+        &raw_intensities,
+        window,
+    );
+    (
+        filter_with_mask(tof_indices, &local_maxima),
+        filter_with_mask(intensities, &local_maxima),
+    )
+}
+
 #[derive(Debug)]
 struct TsfFrame {
-    _frame_id: usize,
+    frame_id: usize,
     offset: usize,
     num_peaks: usize,
     _rt_seconds: f64,
+    pixel: Option<(u32, u32)>,
 }
 
 // Minimal frame data extracted from the TSF 'Frames' table.
@@ -144,8 +328,137 @@ pub enum TSFSpectrumReaderError {
     #[allow(private_interfaces)]
     #[error("{0}")]
     TsfBlobReaderError(#[from] TsfBlobReaderError),
+    #[error("{0}")]
+    TSFPrecursorReaderError(#[from] TSFPrecursorReaderError),
     #[error("Spectrum index out of bounds")]
     IndexOutOfBounds,
-    #[error("TSF dataset does not contain centroided line spectra")]
-    UnsupportedDataset,
+    #[error("TSF dataset does not contain {0} spectra")]
+    UnsupportedDataset(TSFSpectrumType),
+    #[error("Frame {frame} has an invalid pixel position ({x}, {y})")]
+    InvalidPixel { frame: i64, x: i64, y: i64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::utils::reader::Reader;
+
+    use super::*;
+    use crate::testing::{TestFrame, write_run};
+
+    fn tof(indices: &[u32]) -> Vec<TofIndex> {
+        indices
+            .iter()
+            .map(|&index| TofIndex::try_from(index).unwrap())
+            .collect()
+    }
+
+    fn frame() -> TestFrame {
+        TestFrame {
+            line: vec![(3, 8.0)],
+            profile: vec![0, 2, 5, 8, 4, 0, 0, 3, 6, 1],
+            msms_type: 0,
+        }
+    }
+
+    #[test]
+    fn prefers_line_spectra() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &[frame()], "");
+        let reader = TSFSpectrumReader::new(&path).unwrap();
+        assert_eq!(reader.spectrum_type(), TSFSpectrumType::Line);
+        assert!(reader.is_centroided());
+        let spectrum = reader.get(0).unwrap();
+        assert_eq!(spectrum.tof_indices(), &tof(&[3]));
+        assert_eq!(spectrum.intensities(), &vec![8.0]);
+    }
+
+    #[test]
+    fn profile_spectra() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_run(dir.path(), &[frame()], "");
+        let config = TSFSpectrumReaderConfig {
+            spectrum_type: Some(TSFSpectrumType::Profile),
+            centroiding_window: None,
+        };
+        let reader = TSFSpectrumReader::with_config(&path, config).unwrap();
+        assert!(!reader.is_centroided());
+        let spectrum = reader.get(0).unwrap();
+        assert_eq!(spectrum.tof_indices(), &tof(&[1, 2, 3, 4, 7, 8, 9]));
+        assert_eq!(
+            spectrum.intensities(),
+            &vec![2.0, 5.0, 8.0, 4.0, 3.0, 6.0, 1.0]
+        );
+        let config = TSFSpectrumReaderConfig {
+            centroiding_window: Some(1),
+            ..config
+        };
+        let reader = TSFSpectrumReader::with_config(&path, config).unwrap();
+        assert!(reader.is_centroided());
+        let spectrum = reader.get(0).unwrap();
+        assert_eq!(spectrum.tof_indices(), &tof(&[3, 8]));
+        assert_eq!(spectrum.intensities(), &vec![8.0, 6.0]);
+    }
+
+    #[test]
+    fn falls_back_to_profile_spectra() {
+        let dir = tempfile::tempdir().unwrap();
+        let frame = TestFrame {
+            line: vec![],
+            ..frame()
+        };
+        let path = write_run(dir.path(), &[frame], "");
+        let reader = TSFSpectrumReader::new(&path).unwrap();
+        assert_eq!(reader.spectrum_type(), TSFSpectrumType::Profile);
+        let config = TSFSpectrumReaderConfig {
+            spectrum_type: Some(TSFSpectrumType::Line),
+            centroiding_window: None,
+        };
+        assert!(matches!(
+            TSFSpectrumReader::with_config(&path, config),
+            Err(TSFSpectrumReaderError::UnsupportedDataset(
+                TSFSpectrumType::Line
+            ))
+        ));
+    }
+
+    #[test]
+    fn centroids_within_window() {
+        let tof_indices = tof(&[10, 11, 12, 20, 21, 40]);
+        let intensities = [1.0, 5.0, 2.0, 3.0, 4.0, 2.0];
+        let (tof_indices, intensities) =
+            centroid(&tof_indices, &intensities, 2);
+        assert_eq!(tof_indices, tof(&[11, 21, 40]));
+        assert_eq!(intensities, vec![5.0, 4.0, 2.0]);
+    }
+
+    #[test]
+    fn pixels() {
+        let dir = tempfile::tempdir().unwrap();
+        let sql = "CREATE TABLE MaldiFrameInfo (
+                Frame INTEGER, XIndexPos INTEGER, YIndexPos INTEGER
+            );
+            INSERT INTO MaldiFrameInfo VALUES (1, 4, 9);";
+        let path = write_run(dir.path(), &[frame(), frame()], sql);
+        let reader = TSFSpectrumReader::new(&path).unwrap();
+        assert_eq!(reader.pixel(0), Some((4, 9)));
+        assert_eq!(reader.pixel(1), None);
+    }
+
+    #[test]
+    fn invalid_pixels() {
+        let dir = tempfile::tempdir().unwrap();
+        let sql = "CREATE TABLE MaldiFrameInfo (
+                Frame INTEGER, XIndexPos INTEGER, YIndexPos INTEGER
+            );
+            INSERT INTO MaldiFrameInfo VALUES (1, -4, 9);";
+        let path = write_run(dir.path(), &[frame()], sql);
+        assert!(matches!(
+            TSFSpectrumReader::new(&path),
+            Err(TSFSpectrumReaderError::InvalidPixel {
+                frame: 1,
+                x: -4,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,82 @@
+//! Builds small synthetic TSF runs for tests, also of dependent crates
+//! via the `testing` feature.
+
+use std::path::Path;
+
+/// A single frame of a synthetic TSF run.
+#[derive(Clone, Debug, Default)]
+pub struct TestFrame {
+    /// Centroided line peaks as `(tof_index, intensity)`.
+    pub line: Vec<(u32, f32)>,
+    /// One intensity per tof index, stored after the line peaks.
+    pub profile: Vec<u32>,
+    pub msms_type: i64,
+}
+
+/// Writes an `analysis.tsf`/`analysis.tsf_bin` pair to `dir`.
+///
+/// `HasLineSpectra` and `HasProfileSpectra` are set if any frame has line
+/// or profile data. `sql` is executed afterwards to add further tables.
+pub fn write_run(dir: &Path, frames: &[TestFrame], sql: &str) -> String {
+    let mut bin = vec![];
+    let mut rows = vec![];
+    for (index, frame) in frames.iter().enumerate() {
+        rows.push(format!(
+            "({}, {}, {}, {}, {})",
+            index + 1,
+            frame.line.len(),
+            index as f64 * 0.5,
+            bin.len(),
+            frame.msms_type,
+        ));
+        bin.extend(blob(frame));
+    }
+    std::fs::write(dir.join("analysis.tsf_bin"), bin).unwrap();
+    let flag = |set: bool| if set { "1" } else { "0" };
+    let has_line = frames.iter().any(|f| !f.line.is_empty());
+    let has_profile = frames.iter().any(|f| !f.profile.is_empty());
+    let connection =
+        rusqlite::Connection::open(dir.join("analysis.tsf")).unwrap();
+    connection
+        .execute_batch(&format!(
+            "CREATE TABLE GlobalMetadata (Key TEXT, Value TEXT);
+            INSERT INTO GlobalMetadata VALUES
+                ('AcquisitionSoftware', 'Bruker otofControl'),
+                ('MzAcqRangeLower', '105'),
+                ('MzAcqRangeUpper', '1595'),
+                ('DigitizerNumSamples', '400000'),
+                ('HasLineSpectra', '{}'),
+                ('HasProfileSpectra', '{}');
+            CREATE TABLE Frames (
+                Id INTEGER, NumPeaks INTEGER, Time REAL, TimsId INTEGER,
+                MsMsType INTEGER
+            );
+            INSERT INTO Frames VALUES {};
+            {sql}",
+            flag(has_line),
+            flag(has_profile),
+            rows.join(", "),
+        ))
+        .unwrap();
+    dir.to_string_lossy().to_string()
+}
+
+/// A zstd compressed frame blob with its 8 byte header.
+pub fn blob(frame: &TestFrame) -> Vec<u8> {
+    let mut data = vec![];
+    for &(tof_index, _) in &frame.line {
+        data.extend((tof_index as f64).to_le_bytes());
+    }
+    for &(_, intensity) in &frame.line {
+        data.extend(intensity.to_le_bytes());
+    }
+    for &intensity in &frame.profile {
+        data.extend(intensity.to_le_bytes());
+    }
+    let compressed = zstd::encode_all(data.as_slice(), 0).unwrap();
+    let chunk_len = (compressed.len() + 8) as u32;
+    let mut blob = chunk_len.to_le_bytes().to_vec();
+    blob.extend((compressed.len() as u32).to_le_bytes());
+    blob.extend(compressed);
+    blob
+}
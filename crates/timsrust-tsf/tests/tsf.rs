@@ -1,7 +1,7 @@
 use std::fs;
 use std::path::PathBuf;
 use timsrust_core::utils::reader::Reader;
-use timsrust_tsf::TSFSpectrumReader;
+use timsrust_tsf::{TSFSpectrumReader, TsfFrameReader};
 
 fn get_test_folder(file_name: &str) -> String {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
@@ -32,3 +32,18 @@ fn tsf_reader() {
     assert_eq!(spectrum2.tof_indices().len(), 14304);
     assert_eq!(spectrum2.intensities().len(), 14304);
 }
+
+#[test]
+#[ignore = "TODO: This test is ignored because the test data is not included in the repository. It can be run locally if the test data is available."]
+fn tsf_frame_reader() {
+    let file_dir = get_test_folder("test_tsf.d");
+    let file_path =
+        fs::canonicalize(file_dir).expect("missing test_tsf.d folder");
+    let file_path = file_path.to_string_lossy().into_owned();
+    let reader = TsfFrameReader::new(&file_path).unwrap();
+    assert_eq!(reader.len(), 255, "TSF dataset should expose 255 frames");
+    let frame = reader.get_frame(1).expect("failed to read first TSF frame");
+    assert_eq!(frame.index(), 1);
+    assert_eq!(frame.ions().scan_count(), 1);
+    assert_eq!(frame.ions().tof_indices().len(), 15636);
+}
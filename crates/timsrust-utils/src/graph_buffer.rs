@@ -236,7 +236,8 @@ use std::{
 #[derive(Debug)]
 enum NodeState<T> {
     Loading,
-    Ready(Arc<T>),
+    /// `None` if the processor failed to load the node.
+    Ready(Option<Arc<T>>),
 }
 
 struct Node<T> {
@@ -254,17 +255,17 @@ impl<T> Node<T> {
         })
     }
 
-    fn set_ready(&self, data: Arc<T>) {
+    fn set_ready(&self, data: Option<Arc<T>>) {
         let mut state = self.state.lock().unwrap();
         *state = NodeState::Ready(data);
         self.ready.notify_all();
     }
 
-    fn wait_ready(&self) -> Arc<T> {
+    fn wait_ready(&self) -> Option<Arc<T>> {
         let mut state = self.state.lock().unwrap();
         loop {
             match &*state {
-                NodeState::Ready(data) => return Arc::clone(data),
+                NodeState::Ready(data) => return data.clone(),
                 NodeState::Loading => {
                     state = self.ready.wait(state).unwrap();
                 },
@@ -291,6 +292,10 @@ pub trait GraphProcessor<T>: Sync + Send {
 
 // ------------------ Graph ------------------
 
+/// Processes nodes in parallel, each together with its neighbors.
+///
+/// Every node is loaded once while it is in use by any thread (as node or as
+/// neighbor) and dropped as soon as it is released by all of them.
 pub struct Graph<T, P: GraphProcessor<T>> {
     buffer: Mutex<HashMap<usize, Arc<Node<T>>>>,
     processor: Arc<P>,
@@ -304,80 +309,62 @@ impl<T: Send + Sync + 'static, P: GraphProcessor<T>> Graph<T, P> {
         }
     }
 
-    fn get(&self, index: usize) -> Option<Arc<T>> {
-        // Fast path: node already exists
-        if let Some(node) = self.buffer.lock().unwrap().get(&index) {
-            node.increment();
-            return Some(node.wait_ready());
-        }
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
 
-        // Create new loading node
-        let node = Node::new_loading();
-        let mut buffer = self.buffer.lock().unwrap();
-        // Another thread might have inserted in the meantime
-        let node = match buffer.entry(index) {
-            std::collections::hash_map::Entry::Occupied(e) => {
-                let existing: &Arc<Node<T>> = e.get();
-                existing.increment();
-                existing.clone()
-            },
-            std::collections::hash_map::Entry::Vacant(e) => {
-                e.insert(node.clone());
-                node
-            },
+    /// Acquire a reference to the node at `index`, loading it if no other
+    /// thread holds it. Every call must be paired with [`Self::release`].
+    fn get(&self, index: usize) -> Option<Arc<T>> {
+        let (node, is_loader) = {
+            let mut buffer = self.buffer.lock().unwrap();
+            match buffer.entry(index) {
+                std::collections::hash_map::Entry::Occupied(e) => {
+                    let existing: &Arc<Node<T>> = e.get();
+                    existing.increment();
+                    (existing.clone(), false)
+                },
+                std::collections::hash_map::Entry::Vacant(e) => {
+                    let node = Node::new_loading();
+                    e.insert(node.clone());
+                    (node, true)
+                },
+            }
         };
-        drop(buffer);
-
-        // Load data only if we were the loader
-        let state = node.state.lock().unwrap();
-        if let NodeState::Loading = &*state {
-            drop(state); // release lock before loading
-            let data = Arc::new(self.processor.load(index)?);
+        if is_loader {
+            let data = self.processor.load(index).map(Arc::new);
             node.set_ready(data.clone());
-            Some(data)
+            data
         } else {
-            Some(node.wait_ready())
+            node.wait_ready()
         }
     }
 
-    fn get_neighbors(
-        &self,
-        index: usize,
-        data: Arc<T>,
-    ) -> HashMap<usize, Arc<T>> {
-        let mut neighbors = HashMap::new();
-        for n_idx in self.processor.neighbor_indices(index, data.clone()) {
-            if let Some(n_data) = self.get(n_idx) {
-                neighbors.insert(n_idx, n_data);
-            }
-        }
-        neighbors
-    }
-
-    pub fn process(&self, index: usize) -> Option<()> {
-        let data = self.get(index)?;
-        let neighbors = self.get_neighbors(index, data.clone());
-        self.processor.process(&data, &neighbors);
-
-        // Decrement self
+    fn release(&self, index: usize) {
+        let mut buffer = self.buffer.lock().unwrap();
         #[allow(clippy::collapsible_if)]
-        if let Some(node) = self.buffer.lock().unwrap().get(&index) {
+        if let Some(node) = buffer.get(&index) {
             if node.decrement() == 0 {
-                self.buffer.lock().unwrap().remove(&index);
+                buffer.remove(&index);
             }
         }
+    }
 
-        // Decrement neighbors
-        #[allow(clippy::collapsible_if)]
-        for (n_idx, _) in neighbors {
-            if let Some(node) = self.buffer.lock().unwrap().get(&n_idx) {
-                if node.decrement() == 0 {
-                    self.buffer.lock().unwrap().remove(&n_idx);
-                }
+    pub fn process(&self, index: usize) -> Option<()> {
+        let result = self.get(index).map(|data| {
+            let neighbor_indices =
+                self.processor.neighbor_indices(index, data.clone());
+            let neighbors = neighbor_indices
+                .iter()
+                .filter_map(|&n_idx| Some((n_idx, self.get(n_idx)?)))
+                .collect::<HashMap<_, _>>();
+            self.processor.process(&data, &neighbors);
+            for n_idx in neighbor_indices {
+                self.release(n_idx);
             }
-        }
-
-        Some(())
+        });
+        self.release(index);
+        result
     }
 
     // Optional: process all indices in parallel
@@ -390,3 +377,62 @@ impl<T: Send + Sync + 'static, P: GraphProcessor<T>> Graph<T, P> {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    struct SumProcessor {
+        len: usize,
+        loads: AtomicUsize,
+        total: AtomicU64,
+    }
+
+    impl GraphProcessor<u64> for SumProcessor {
+        fn load(&self, index: usize) -> Option<u64> {
+            self.loads.fetch_add(1, Ordering::Relaxed);
+            // Index 3 fails to load.
+            (index != 3).then_some(index as u64)
+        }
+
+        fn neighbor_indices(
+            &self,
+            index: usize,
+            _data: Arc<u64>,
+        ) -> Vec<usize> {
+            (index + 1..(index + 3).min(self.len)).collect()
+        }
+
+        fn process(
+            &self,
+            data: &Arc<u64>,
+            neighbors: &HashMap<usize, Arc<u64>>,
+        ) {
+            let sum = **data + neighbors.values().map(|n| **n).sum::<u64>();
+            self.total.fetch_add(sum, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_graph_process_all() {
+        let len = 100;
+        let graph = Graph::new(SumProcessor {
+            len,
+            loads: AtomicUsize::new(0),
+            total: AtomicU64::new(0),
+        });
+        graph.process_all(0..len);
+        let expected = (0..len as u64)
+            .filter(|&i| i != 3)
+            .map(|i| {
+                i + (i + 1..(i + 3).min(len as u64))
+                    .filter(|&n| n != 3)
+                    .sum::<u64>()
+            })
+            .sum::<u64>();
+        assert_eq!(graph.processor().total.load(Ordering::Relaxed), expected);
+        assert!(graph.processor().loads.load(Ordering::Relaxed) >= len);
+        assert!(graph.buffer.lock().unwrap().is_empty());
+    }
+}
@@ -2,6 +2,7 @@ pub mod error;
 pub mod graph_buffer;
 pub mod hash_sets;
 pub mod ndarray;
+pub mod ordered;
 pub mod reader;
 pub mod thread;
 pub mod vec;
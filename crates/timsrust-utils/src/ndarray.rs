@@ -72,6 +72,18 @@ impl<T, const N: usize> NDArray<T, N> {
         self.shape
     }
 
+    /// Returns the contiguous (row-major) data of the array.
+    ///
+    /// # Example
+    /// ```
+    /// use timsrust_utils::ndarray::NDArray;
+    /// let arr = NDArray::new([2, 2], vec![1, 2, 3, 4]).unwrap();
+    /// assert_eq!(arr.data(), &[1, 2, 3, 4]);
+    /// ```
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
     /// Computes the flat index in the data vector for the given N-dimensional indices.
     ///
     /// # Arguments
@@ -0,0 +1,218 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use rayon::prelude::*;
+
+/// Collects items that arrive out of order and releases them by index.
+///
+/// # Examples
+///
+/// ```rust
+/// use timsrust_utils::ordered::ReorderBuffer;
+///
+/// let mut buffer = ReorderBuffer::new(0);
+/// buffer.push(1, "b");
+/// assert_eq!(buffer.pop_ready(), None);
+/// buffer.push(0, "a");
+/// assert_eq!(buffer.pop_ready(), Some("a"));
+/// assert_eq!(buffer.pop_ready(), Some("b"));
+/// assert_eq!(buffer.next_index(), 2);
+/// ```
+#[derive(Debug)]
+pub struct ReorderBuffer<T> {
+    next: usize,
+    pending: BTreeMap<usize, T>,
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Creates an empty buffer that first releases the item at `start`.
+    pub fn new(start: usize) -> Self {
+        Self {
+            next: start,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// The index of the next item to be released.
+    pub fn next_index(&self) -> usize {
+        self.next
+    }
+
+    /// Stores `item` until all items before `index` have been released.
+    ///
+    /// # Panics
+    /// Panics if `index` was already released or pushed.
+    pub fn push(&mut self, index: usize, item: T) {
+        assert!(index >= self.next, "Index {index} was already released");
+        let previous = self.pending.insert(index, item);
+        assert!(previous.is_none(), "Index {index} was pushed twice");
+    }
+
+    /// Releases the item at [`next_index`](Self::next_index), if present.
+    pub fn pop_ready(&mut self) -> Option<T> {
+        let item = self.pending.remove(&self.next)?;
+        self.next += 1;
+        Some(item)
+    }
+
+    /// Number of items that wait for an earlier index.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct OrderedState<T, C, E> {
+    buffer: ReorderBuffer<T>,
+    consume: C,
+    error: Option<E>,
+}
+
+/// Produces the items `0..len` in parallel and consumes them in order.
+///
+/// Indices are processed in windows of `window` consecutive indices, so at
+/// most `window` produced items are held in memory. Items are consumed as
+/// soon as all previous items are consumed, while the rest of the window is
+/// still being produced. Consumption stops at the first error, which is
+/// returned.
+///
+/// # Examples
+///
+/// ```rust
+/// use timsrust_utils::ordered::par_for_each_ordered;
+///
+/// let mut squares = vec![];
+/// par_for_each_ordered(
+///     100,
+///     16,
+///     |index| index * index,
+///     |square| {
+///         squares.push(square);
+///         Ok::<(), ()>(())
+///     },
+/// )
+/// .unwrap();
+/// assert_eq!(squares, (0..100).map(|i| i * i).collect::<Vec<_>>());
+/// ```
+pub fn par_for_each_ordered<T, E, P, C>(
+    len: usize,
+    window: usize,
+    produce: P,
+    consume: C,
+) -> Result<(), E>
+where
+    T: Send,
+    E: Send,
+    P: Fn(usize) -> T + Sync,
+    C: FnMut(T) -> Result<(), E> + Send,
+{
+    let state = Mutex::new(OrderedState {
+        buffer: ReorderBuffer::new(0),
+        consume,
+        error: None,
+    });
+    let failed = AtomicBool::new(false);
+    for start in (0..len).step_by(window.max(1)) {
+        let end = (start + window.max(1)).min(len);
+        (start..end).into_par_iter().for_each(|index| {
+            if failed.load(Ordering::Relaxed) {
+                return;
+            }
+            let item = produce(index);
+            let mut guard = state.lock().unwrap();
+            let state = &mut *guard;
+            state.buffer.push(index, item);
+            while let Some(item) = state.buffer.pop_ready() {
+                if state.error.is_some() {
+                    continue;
+                }
+                if let Err(error) = (state.consume)(item) {
+                    state.error = Some(error);
+                    failed.store(true, Ordering::Relaxed);
+                }
+            }
+        });
+        if let Some(error) = state.lock().unwrap().error.take() {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn reorder_buffer() {
+        let mut buffer = ReorderBuffer::new(5);
+        buffer.push(7, 'c');
+        buffer.push(6, 'b');
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop_ready(), None);
+        buffer.push(5, 'a');
+        assert_eq!(buffer.pop_ready(), Some('a'));
+        assert_eq!(buffer.pop_ready(), Some('b'));
+        assert_eq!(buffer.pop_ready(), Some('c'));
+        assert_eq!(buffer.pop_ready(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn reorder_buffer_released_index() {
+        let mut buffer = ReorderBuffer::new(1);
+        buffer.push(0, ());
+    }
+
+    #[test]
+    fn ordered_with_uneven_work() {
+        let mut consumed = vec![];
+        par_for_each_ordered(
+            200,
+            32,
+            |index| {
+                // Early indices finish last.
+                std::thread::sleep(Duration::from_micros(
+                    (200 - index as u64) * 10,
+                ));
+                index
+            },
+            |index| {
+                consumed.push(index);
+                Ok::<(), ()>(())
+            },
+        )
+        .unwrap();
+        assert_eq!(consumed, (0..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn ordered_stops_at_first_error() {
+        let mut consumed = vec![];
+        let result = par_for_each_ordered(
+            100,
+            8,
+            |index| index,
+            |index| {
+                if index == 20 {
+                    return Err(index);
+                }
+                consumed.push(index);
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(20));
+        assert_eq!(consumed, (0..20).collect::<Vec<_>>());
+    }
+}
@@ -531,8 +531,13 @@ pub fn extract_kernel(kernel: &[u64], threshold: f32) -> Option<Vec<f32>> {
         .iter()
         .map(|&x| x as f32 / max_val)
         .collect::<Vec<_>>();
-    let first = kernel.iter().position(|&x| x > threshold)? - 1;
-    let last = kernel.iter().rposition(|&x| x > threshold)? + 1;
+    // Keep one value below the threshold on either side, if there is one.
+    let first = kernel
+        .iter()
+        .position(|&x| x > threshold)?
+        .saturating_sub(1);
+    let last = (kernel.iter().rposition(|&x| x > threshold)? + 1)
+        .min(kernel.len() - 1);
     let kernel = kernel[first..last + 1].to_vec();
     Some(kernel)
 }
@@ -627,4 +632,16 @@ mod tests {
         let v: Vec<_> = sv.iter().collect();
         assert!(v.is_empty());
     }
+
+    #[test]
+    fn test_extract_kernel_at_edges() {
+        assert_eq!(
+            extract_kernel(&[1, 4, 10, 4, 1], 0.5),
+            Some(vec![0.4, 1.0, 0.4])
+        );
+        assert_eq!(extract_kernel(&[10, 4, 1], 0.5), Some(vec![1.0, 0.4]));
+        assert_eq!(extract_kernel(&[1, 10], 0.5), Some(vec![0.1, 1.0]));
+        assert_eq!(extract_kernel(&[0, 0], 0.5), None);
+        assert_eq!(extract_kernel(&[], 0.5), None);
+    }
 }
@@ -26,7 +26,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let path = TimsTofPath::new(&raw_path)?;
     let reader = SpectrumReader::build().with_path(&path).finalize()?;
 
-    let writer = Mutex::new(MGFWriter::new(&out_path));
+    let writer = Mutex::new(MGFWriter::new(&out_path)?);
     let written: usize = reader
         .par_iter()
         .filter_map(|spectrum| spectrum.ok())
@@ -36,10 +36,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             writer
                 .lock()
                 .expect("MGFWriter mutex poisoned")
-                .write(&spectrum);
-            1usize
+                .write(&spectrum)
+                .map(|_| 1usize)
         })
-        .sum();
+        .sum::<Result<_, _>>()?;
+    writer
+        .into_inner()
+        .expect("MGFWriter mutex poisoned")
+        .finalize()?;
 
     println!("Wrote {written} spectra to {out_path}");
     Ok(())
@@ -1,11 +1,11 @@
-//! Read raw 2D frame data from a TDF dataset and convert indices to physical
-//! units using the matching converters.
+//! Read raw frame data from a TDF or TSF dataset and convert indices to
+//! physical units using the matching converters.
 //!
 //! Usage:
 //!     cargo run --release --example read_frames -- /path/to/data.d
 //!
-//! Frame access is only supported for TDF (`.d`) inputs; the example will
-//! report an error otherwise.
+//! Frame access is supported for TDF and TSF (`.d`) inputs; the example will
+//! report an error otherwise. TSF frames hold a single pseudo-scan.
 
 use timsrust::{TimsTofPath, core::Converter};
 
@@ -15,7 +15,7 @@ const PREVIEW_IONS_PER_FRAME: usize = 5;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let raw_path = std::env::args()
         .nth(1)
-        .expect("usage: read_frames <path-to-tdf-data>");
+        .expect("usage: read_frames <path-to-tdf-or-tsf-data>");
 
     let path = TimsTofPath::new(&raw_path)?;
     let frame_reader = path.frame_reader()?;
@@ -13,7 +13,7 @@ pub enum MzConverter {
     Bit(timsrust_core::BitConverter),
     Tdf(timsrust_tdf::Tof2MzConverter),
     TSF(timsrust_tsf::Tof2MzConverter),
-    MiniTdf,
+    MiniTdf(timsrust_minitdf::Tof2MzConverter),
 }
 
 impl MzConverter {
@@ -42,7 +42,9 @@ impl MzConverter {
                     tdf_path.tdf().as_ref(),
                 )))
             },
-            TimsTofFileType::MiniTdf(_) => Some(Self::MiniTdf),
+            TimsTofFileType::MiniTdf(_) => Some(Self::MiniTdf(
+                timsrust_minitdf::Tof2MzConverter::default(),
+            )),
             TimsTofFileType::Parquet(_) => {
                 Some(Self::Bit(timsrust_core::BitConverter()))
             },
@@ -63,7 +65,7 @@ impl Converter<TofIndex, Mz> for MzConverter {
             Self::Bit(converter) => converter.convert(tof_index),
             Self::Tdf(converter) => converter.convert(tof_index),
             Self::TSF(converter) => converter.convert(tof_index),
-            Self::MiniTdf => timsrust_core::BitConverter().convert(tof_index),
+            Self::MiniTdf(converter) => converter.convert(tof_index),
         }
     }
 }
@@ -78,7 +80,7 @@ impl Converter<Mz, TofIndex> for MzConverter {
             Self::Bit(converter) => converter.convert(mz),
             Self::Tdf(converter) => converter.convert(mz),
             Self::TSF(converter) => converter.convert(mz),
-            Self::MiniTdf => timsrust_core::BitConverter().convert(mz),
+            Self::MiniTdf(converter) => converter.convert(mz),
         }
     }
 }
@@ -91,7 +93,7 @@ pub enum ImConverter {
     Bps(timsrust_patched::Scan2ImConverter),
     Bit(timsrust_core::BitConverter),
     Tdf(timsrust_tdf::Scan2ImConverter),
-    MiniTdf,
+    MiniTdf(timsrust_minitdf::Scan2ImConverter),
 }
 
 impl ImConverter {
@@ -120,7 +122,9 @@ impl ImConverter {
                     tdf_path.tdf().as_ref(),
                 )))
             },
-            TimsTofFileType::MiniTdf(_) => Some(Self::MiniTdf),
+            TimsTofFileType::MiniTdf(minitdf_path) => Some(Self::MiniTdf(
+                timsrust_minitdf::Scan2ImConverter::new(minitdf_path),
+            )),
             TimsTofFileType::Parquet(_) => {
                 Some(Self::Bit(timsrust_core::BitConverter()))
             },
@@ -140,7 +144,7 @@ impl Converter<ScanIndex, Im> for ImConverter {
             Self::Sdk(converter) => converter.convert(scan_index),
             Self::Bit(converter) => converter.convert(scan_index),
             Self::Tdf(converter) => converter.convert(scan_index),
-            Self::MiniTdf => timsrust_core::BitConverter().convert(scan_index),
+            Self::MiniTdf(converter) => converter.convert(scan_index),
         }
     }
 }
@@ -154,7 +158,7 @@ impl Converter<Im, ScanIndex> for ImConverter {
             Self::Sdk(converter) => converter.convert(im),
             Self::Bit(converter) => converter.convert(im),
             Self::Tdf(converter) => converter.convert(im),
-            Self::MiniTdf => timsrust_core::BitConverter().convert(im),
+            Self::MiniTdf(converter) => converter.convert(im),
         }
     }
 }
@@ -163,7 +167,7 @@ impl Converter<Im, ScanIndex> for ImConverter {
 pub enum RtConverter {
     Bit(timsrust_core::BitConverter),
     Tdf(timsrust_tdf::Frame2RtConverter),
-    MiniTdf,
+    MiniTdf(timsrust_minitdf::Frame2RtConverter),
 }
 
 impl RtConverter {
@@ -177,7 +181,11 @@ impl RtConverter {
             TimsTofFileType::Tdf(tdf_path) => Some(Self::Tdf(
                 timsrust_tdf::Frame2RtConverter::new(tdf_path.tdf().as_ref()),
             )),
-            TimsTofFileType::MiniTdf(_) => Some(Self::MiniTdf),
+            TimsTofFileType::MiniTdf(minitdf_path) => {
+                timsrust_minitdf::Frame2RtConverter::new(minitdf_path)
+                    .ok()
+                    .map(Self::MiniTdf)
+            },
             TimsTofFileType::Parquet(_) => {
                 Some(Self::Bit(timsrust_core::BitConverter()))
             },
@@ -193,7 +201,7 @@ impl Converter<FrameIndex, Rt> for RtConverter {
         match self {
             Self::Bit(converter) => converter.convert(frame_index),
             Self::Tdf(converter) => converter.convert(frame_index),
-            Self::MiniTdf => timsrust_core::BitConverter().convert(frame_index),
+            Self::MiniTdf(converter) => converter.convert(frame_index),
         }
     }
 }
@@ -203,7 +211,7 @@ impl Converter<Rt, FrameIndex> for RtConverter {
         match self {
             Self::Bit(converter) => converter.convert(rt),
             Self::Tdf(converter) => converter.convert(rt),
-            Self::MiniTdf => timsrust_core::BitConverter().convert(rt),
+            Self::MiniTdf(converter) => converter.convert(rt),
         }
     }
 }
@@ -0,0 +1,146 @@
+use timsrust_core::{
+    FrameInfo, FrameIons,
+    utils::reader::{IndexedReader, Reader},
+};
+use timsrust_tdf::{TdfFrameReader, TdfIonReader};
+use timsrust_tsf::{TsfFrameInfoReader, TsfFrameReader, TsfIonReader};
+
+use crate::{
+    TimsTofFrameReaderError, TimsTofPath, TimsTofPathLike,
+    timstof::TimsTofFileType,
+};
+
+/// Serves [`FrameIons`] for any file type that supports frame access.
+#[derive(Debug)]
+pub enum FrameIonReader {
+    Tdf(TdfIonReader),
+    /// TSF frames hold a single pseudo-scan.
+    Tsf(TsfIonReader),
+}
+
+impl Reader<FrameIons> for FrameIonReader {
+    type Error = TimsTofFrameReaderError;
+
+    fn get(&self, index: usize) -> Result<FrameIons, Self::Error> {
+        match self {
+            Self::Tdf(reader) => Ok(reader.get(index)?),
+            Self::Tsf(reader) => Ok(reader.get(index)?),
+        }
+    }
+}
+
+/// Serves [`FrameInfo`] for any file type that supports frame access.
+#[derive(Debug)]
+pub enum FrameInfoReader {
+    Tdf(timsrust_tdf::FrameInfoReader),
+    Tsf(TsfFrameInfoReader),
+}
+
+impl Reader<FrameInfo> for FrameInfoReader {
+    type Error = TimsTofFrameReaderError;
+
+    fn get(&self, index: usize) -> Result<FrameInfo, Self::Error> {
+        match self {
+            Self::Tdf(reader) => Ok(reader.get(index)?),
+            Self::Tsf(reader) => Ok(reader.get(index)?),
+        }
+    }
+}
+
+impl IndexedReader<FrameInfo> for FrameInfoReader {
+    type Iter = std::vec::IntoIter<usize>;
+
+    fn iter(&self) -> Self::Iter {
+        match self {
+            Self::Tdf(reader) => reader.iter(),
+            Self::Tsf(reader) => reader.iter(),
+        }
+    }
+}
+
+/// A format-generic frame reader for TDF and TSF files.
+///
+/// Thin newtype around
+/// [`timsrust_core::FrameReader<FrameIonReader, FrameInfoReader>`]. All
+/// [`timsrust_core::FrameReader`] methods (`get_frame`, `get_info`,
+/// `iter_indices`, `parallel_filter`, …) are available via
+/// [`Deref`](std::ops::Deref), so code written against the core reader runs
+/// on both formats.
+#[derive(Debug)]
+pub struct FrameReader(
+    timsrust_core::FrameReader<FrameIonReader, FrameInfoReader>,
+);
+
+impl std::ops::Deref for FrameReader {
+    type Target = timsrust_core::FrameReader<FrameIonReader, FrameInfoReader>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FrameReader {
+    pub fn new(
+        path: impl TimsTofPathLike,
+    ) -> Result<Self, TimsTofFrameReaderError> {
+        let path: TimsTofPath = path.to_timstof_path()?;
+        let (ion_reader, info_reader) = match path.file_type() {
+            TimsTofFileType::Tdf(tdf_path) => {
+                let (ions, infos) =
+                    TdfFrameReader::new(tdf_path)?.into_inner().into_parts();
+                (FrameIonReader::Tdf(ions), FrameInfoReader::Tdf(infos))
+            },
+            TimsTofFileType::Tsf(tsf_path) => {
+                let (ions, infos) =
+                    TsfFrameReader::new(tsf_path)?.into_inner().into_parts();
+                (FrameIonReader::Tsf(ions), FrameInfoReader::Tsf(infos))
+            },
+            _ => return Err(TimsTofFrameReaderError::NotSupported),
+        };
+        Ok(Self(timsrust_core::FrameReader::new(
+            ion_reader,
+            info_reader,
+        )))
+    }
+
+    /// Consume `self` and return the underlying generic
+    /// [`timsrust_core::FrameReader`].
+    pub fn into_inner(
+        self,
+    ) -> timsrust_core::FrameReader<FrameIonReader, FrameInfoReader> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::MSLevel;
+    use timsrust_tsf::testing::{TestFrame, write_run};
+
+    use super::*;
+
+    #[test]
+    fn tsf_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let frame = |msms_type| TestFrame {
+            line: vec![(3, 8.0), (7, 2.4)],
+            profile: vec![],
+            msms_type,
+        };
+        let path = write_run(dir.path(), &[frame(0), frame(8)], "");
+        let reader = FrameReader::new(path.as_str()).unwrap();
+        assert!(matches!(reader.ion_reader(), FrameIonReader::Tsf(_)));
+        assert_eq!(reader.len(), 2);
+        let frame = reader.get_frame(2).unwrap();
+        assert_eq!(frame.info().ms_level(), MSLevel::MS2);
+        assert_eq!(frame.info().rt_in_seconds(), 0.5);
+        assert_eq!(frame.ions().scan_count(), 1);
+        let intensities: Vec<u32> = frame
+            .ions()
+            .intensities()
+            .iter()
+            .map(|&i| i.into())
+            .collect();
+        assert_eq!(intensities, [8, 2]);
+    }
+}
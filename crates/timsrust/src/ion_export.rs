@@ -0,0 +1,388 @@
+use std::ops::{Range, RangeInclusive};
+
+use serde::{Deserialize, Serialize};
+use timsrust_core::io::formats::parquet::{ParquetError, ParquetWriter};
+use timsrust_core::utils::ordered::par_for_each_ordered;
+use timsrust_core::{
+    Converter, FrameInfo, FrameIons, FrameReaderError as CoreFrameReaderError,
+    MSLevel, ScanIndex,
+};
+
+use crate::{FrameReader, ImConverter, MzConverter, TimsTofFrameReaderError};
+
+/// Number of frames that are decoded in parallel before being written.
+const EXPORT_WINDOW: usize = 64;
+
+/// Selects the frames and optional columns written by [`export_ions`].
+#[derive(Debug, Clone)]
+pub struct IonExportConfig {
+    /// Frame indices to export. All frames if `None`.
+    pub frames: Option<Range<usize>>,
+    /// Retention times in seconds to export. All frames if `None`.
+    pub rt_range: Option<RangeInclusive<f64>>,
+    /// Fill the `rt` column with the frame retention time.
+    pub include_rt: bool,
+    /// Fill the `im` column with the 1/K0 of each scan.
+    pub include_im: bool,
+    /// Fill the `mz` column with the m/z of each TOF index.
+    pub include_mz: bool,
+    /// Number of ions per Parquet row group.
+    pub row_group_size: usize,
+}
+
+impl Default for IonExportConfig {
+    fn default() -> Self {
+        Self {
+            frames: None,
+            rt_range: None,
+            include_rt: true,
+            include_im: true,
+            include_mz: true,
+            row_group_size: 1_000_000,
+        }
+    }
+}
+
+impl IonExportConfig {
+    fn accepts(&self, info: &FrameInfo) -> bool {
+        let frame_ok = self
+            .frames
+            .as_ref()
+            .is_none_or(|frames| frames.contains(&info.index()));
+        let rt_ok = self
+            .rt_range
+            .as_ref()
+            .is_none_or(|rt| rt.contains(&info.rt_in_seconds()));
+        frame_ok && rt_ok
+    }
+}
+
+/// A single ion, i.e. one row of the table written by [`export_ions`].
+///
+/// `rt`, `im` and `mz` are null unless enabled in the [`IonExportConfig`].
+/// `ms_level` is 1 or 2, or 0 if unknown.
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct IonRecord {
+    pub frame: u32,
+    pub scan: u32,
+    pub tof: u32,
+    pub intensity: u32,
+    pub rt: Option<f64>,
+    pub im: Option<f64>,
+    pub mz: Option<f64>,
+    pub ms_level: u8,
+    pub window_group: u8,
+}
+
+/// Converts scans and TOF indices for the optional columns.
+#[derive(Debug)]
+struct IonConverters {
+    include_rt: bool,
+    im: Option<ImConverter>,
+    mz: Option<MzConverter>,
+}
+
+impl IonConverters {
+    fn records(&self, info: &FrameInfo, ions: &FrameIons) -> Vec<IonRecord> {
+        let ms_level = match info.ms_level() {
+            MSLevel::MS1 => 1,
+            MSLevel::MS2 => 2,
+            MSLevel::Unknown => 0,
+        };
+        let rt = self.include_rt.then(|| info.rt_in_seconds());
+        let mut records = Vec::with_capacity(ions.tof_indices().len());
+        for scan in 0..ions.scan_count() {
+            let im = self.im.as_ref().map(|converter| {
+                let scan = ScanIndex::try_from(scan).unwrap();
+                f64::from(converter.convert(scan))
+            });
+            records.extend(ions.read_scan(scan).map(|(tof, intensity)| {
+                IonRecord {
+                    frame: info.index() as u32,
+                    scan: scan as u32,
+                    tof: u32::from(tof),
+                    intensity: u32::from(intensity),
+                    rt,
+                    im,
+                    mz: self
+                        .mz
+                        .as_ref()
+                        .map(|converter| f64::from(converter.convert(tof))),
+                    ms_level,
+                    window_group: info.window_group(),
+                }
+            }));
+        }
+        records
+    }
+}
+
+/// Writes every ion of a TDF or TSF file as a row of a Parquet table.
+///
+/// Frames are written in ascending order, ions by scan and then as stored.
+/// Only frames matching both [`IonExportConfig::frames`] and
+/// [`IonExportConfig::rt_range`] are exported. Returns the number of ions
+/// written.
+///
+/// # Example
+/// ```no_run
+/// use timsrust::{IonExportConfig, export_ions};
+///
+/// let config = IonExportConfig {
+///     rt_range: Some(600.0..=660.0),
+///     ..Default::default()
+/// };
+/// let ions = export_ions("run.d", "ions.parquet", &config).unwrap();
+/// println!("Exported {ions} ions");
+/// ```
+pub fn export_ions(
+    in_path: impl AsRef<str>,
+    out_path: impl AsRef<str>,
+    config: &IonExportConfig,
+) -> Result<usize, IonExportError> {
+    let in_path = in_path.as_ref();
+    let reader = FrameReader::new(in_path)?;
+    let converters = IonConverters {
+        include_rt: config.include_rt,
+        im: match config.include_im {
+            true => Some(
+                ImConverter::new(in_path)
+                    .ok_or(IonExportError::MissingConverter("1/K0"))?,
+            ),
+            false => None,
+        },
+        mz: match config.include_mz {
+            true => Some(
+                MzConverter::new(in_path)
+                    .ok_or(IonExportError::MissingConverter("m/z"))?,
+            ),
+            false => None,
+        },
+    };
+    let mut indices = reader.iter_indices().collect::<Vec<_>>();
+    indices.sort_unstable();
+    let mut infos = vec![];
+    for index in indices {
+        let info = reader.get_info(index)?;
+        if config.accepts(&info) {
+            infos.push(info);
+        }
+    }
+    let row_group_size = config.row_group_size.max(1);
+    let mut writer = ParquetWriter::<IonRecord>::new(out_path.as_ref())?;
+    writer.set_row_group_size(row_group_size);
+    let mut buffer = Vec::with_capacity(row_group_size);
+    par_for_each_ordered(
+        infos.len(),
+        EXPORT_WINDOW,
+        |i| {
+            let info = &infos[i];
+            let ions = reader.get_ions(info.index())?;
+            Ok(converters.records(info, &ions))
+        },
+        |records: Result<Vec<IonRecord>, CoreFrameReaderError>| {
+            for record in records? {
+                buffer.push(record);
+                if buffer.len() == row_group_size {
+                    let batch = std::mem::replace(
+                        &mut buffer,
+                        Vec::with_capacity(row_group_size),
+                    );
+                    writer.write_batch(batch)?;
+                }
+            }
+            Ok::<(), IonExportError>(())
+        },
+    )?;
+    if !buffer.is_empty() {
+        writer.write_batch(buffer)?;
+    }
+    let count = writer.shape().0;
+    writer.close()?;
+    Ok(count)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IonExportError {
+    #[error("{0}")]
+    TimsTofFrameReaderError(#[from] TimsTofFrameReaderError),
+    #[error("{0}")]
+    FrameReaderError(#[from] CoreFrameReaderError),
+    #[error("{0}")]
+    ParquetError(#[from] ParquetError),
+    #[error("No {0} converter available for this file")]
+    MissingConverter(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use timsrust_core::io::formats::parquet::ParquetReader;
+    use timsrust_core::{
+        AcquisitionType, BitConverter, IntensityIndex, QuadrupoleSettings,
+        TofIndex,
+    };
+
+    use super::*;
+
+    fn info(index: usize, rt: f64) -> FrameInfo {
+        FrameInfo::new(
+            Arc::new(QuadrupoleSettings::default()),
+            index,
+            rt,
+            1.0,
+            AcquisitionType::DIAPASEF,
+            MSLevel::MS2,
+            3,
+            None,
+        )
+    }
+
+    #[test]
+    fn records() {
+        let ions = FrameIons::new(
+            vec![0, 2, 2, 3],
+            [10, 20, 30]
+                .map(|tof| TofIndex::try_from(tof).unwrap())
+                .into(),
+            [1, 2, 3]
+                .map(|intensity| IntensityIndex::try_from(intensity).unwrap())
+                .into(),
+        );
+        let converters = IonConverters {
+            include_rt: true,
+            im: Some(ImConverter::Bit(BitConverter())),
+            mz: None,
+        };
+        let records = converters.records(&info(5, 12.5), &ions);
+        let im = |scan: usize| {
+            let scan = ScanIndex::try_from(scan).unwrap();
+            Some(f64::from(BitConverter().convert(scan)))
+        };
+        let record = |scan: u32, tof, intensity| IonRecord {
+            frame: 5,
+            scan,
+            tof,
+            intensity,
+            rt: Some(12.5),
+            im: im(scan as usize),
+            mz: None,
+            ms_level: 2,
+            window_group: 3,
+        };
+        assert_eq!(
+            records,
+            vec![record(0, 10, 1), record(0, 20, 2), record(2, 30, 3)]
+        );
+    }
+
+    #[test]
+    fn frame_and_rt_filters() {
+        let config = IonExportConfig {
+            frames: Some(2..4),
+            rt_range: Some(1.0..=2.0),
+            ..Default::default()
+        };
+        assert!(config.accepts(&info(2, 1.0)));
+        assert!(config.accepts(&info(3, 2.0)));
+        assert!(!config.accepts(&info(4, 1.5)));
+        assert!(!config.accepts(&info(3, 2.5)));
+        assert!(IonExportConfig::default().accepts(&info(100, 1e4)));
+    }
+
+    /// Exports `tests/test.d`, whose frames store the TOF indices 0 to 135
+    /// in order, with intensity `2 * (tof + 1)`. Returns the number of
+    /// exported ions, and the row groups and rows of the written file.
+    fn export(config: &IonExportConfig) -> (usize, usize, Vec<IonRecord>) {
+        let source = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/test.d");
+        let dir = tempfile::tempdir().unwrap();
+        let run = dir.path().join("test.d");
+        std::fs::create_dir(&run).unwrap();
+        for file in ["analysis.tdf", "analysis.tdf_bin"] {
+            std::fs::copy(format!("{source}/{file}"), run.join(file)).unwrap();
+        }
+        // The fixture predates the `GlobalMetadata` table name.
+        rusqlite::Connection::open(run.join("analysis.tdf"))
+            .unwrap()
+            .execute_batch(
+                "ALTER TABLE GlobalMetaData RENAME TO Metadata;
+                ALTER TABLE Metadata RENAME TO GlobalMetadata;",
+            )
+            .unwrap();
+        let out_path = dir.path().join("ions.parquet");
+        let count = export_ions(
+            run.to_str().unwrap(),
+            out_path.to_str().unwrap(),
+            config,
+        )
+        .unwrap();
+        let reader = ParquetReader::<IonRecord>::from(&out_path).unwrap();
+        (count, reader.row_group_count(), reader.read_all().unwrap())
+    }
+
+    #[test]
+    fn export_test_run() {
+        let config = IonExportConfig {
+            row_group_size: 50,
+            ..Default::default()
+        };
+        let (count, row_groups, records) = export(&config);
+        assert_eq!(count, 136);
+        assert_eq!(row_groups, 3);
+        assert_eq!(records.len(), 136);
+        for (tof, record) in records.iter().enumerate() {
+            assert_eq!(record.tof, tof as u32);
+            assert_eq!(record.intensity, 2 * (tof as u32 + 1));
+            assert!(record.im.is_some() && record.mz.is_some());
+        }
+        let frames = [
+            (1, 0.1, 1, 10),
+            (2, 0.2, 2, 26),
+            (3, 0.3, 1, 42),
+            (4, 0.4, 2, 58),
+        ];
+        let mut start = 0;
+        for (frame, rt, ms_level, ions) in frames {
+            let frame_records = &records[start..start + ions];
+            assert!(frame_records.iter().all(|record| {
+                (record.frame, record.rt, record.ms_level)
+                    == (frame, Some(rt), ms_level)
+            }));
+            start += ions;
+        }
+        // The second scan of the first frame holds the second and third ion.
+        assert_eq!(
+            records[..4]
+                .iter()
+                .map(|record| record.scan)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 1, 2]
+        );
+        assert!(records[0].mz < records[1].mz);
+        assert!(records[0].im > records[1].im);
+    }
+
+    #[test]
+    fn export_filtered_test_run() {
+        let config = IonExportConfig {
+            frames: Some(2..4),
+            include_rt: false,
+            include_im: false,
+            include_mz: false,
+            ..Default::default()
+        };
+        let (count, _, records) = export(&config);
+        assert_eq!(count, 26 + 42);
+        assert_eq!(records.first().unwrap().tof, 10);
+        assert_eq!(records.last().unwrap().tof, 77);
+        assert!(records.iter().all(|record| {
+            record.rt.is_none() && record.im.is_none() && record.mz.is_none()
+        }));
+        let config = IonExportConfig {
+            rt_range: Some(0.35..=1.0),
+            ..Default::default()
+        };
+        assert_eq!(export(&config).0, 58);
+    }
+}
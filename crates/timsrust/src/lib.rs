@@ -1,11 +1,15 @@
 mod converters;
 mod errors;
+mod frame_reader;
+mod ion_export;
 mod precursor_reader;
 mod spectrum_reader;
 mod timstof;
 
 pub use converters::{ImConverter, MzConverter, RtConverter};
 pub use errors::TimsRustError;
+pub use frame_reader::{FrameInfoReader, FrameIonReader, FrameReader};
+pub use ion_export::{IonExportConfig, IonExportError, IonRecord, export_ions};
 pub use precursor_reader::{
     PrecursorReader, PrecursorReaderBuilder, PrecursorReaderError,
 };
@@ -11,6 +11,7 @@ use timsrust_tdf::{
     FrameWindowSplittingConfiguration, TDFPrecursorReader,
     TDFPrecursorReaderError,
 };
+use timsrust_tsf::{TSFPrecursorReader, TSFPrecursorReaderError};
 
 use crate::{
     ImConverter, TimsTofPath, TimsTofPathError, TimsTofPathLike,
@@ -20,7 +21,7 @@ use crate::{
 enum Inner {
     MiniTDF(MiniTDFPrecursorReader),
     Tdf(TDFPrecursorReader<ImConverter>),
-
+    Tsf(TSFPrecursorReader),
     ParquetSpectra(ParquetPrecursorReader),
 }
 
@@ -29,7 +30,7 @@ impl Inner {
         match self {
             Inner::MiniTDF(reader) => Ok(reader.get(index)?),
             Inner::Tdf(reader) => Ok(reader.get(index)?),
-
+            Inner::Tsf(reader) => Ok(reader.get(index)?),
             Inner::ParquetSpectra(reader) => Ok(reader.get(index)?),
         }
     }
@@ -38,7 +39,7 @@ impl Inner {
         match self {
             Inner::MiniTDF(reader) => reader.len(),
             Inner::Tdf(reader) => reader.len(),
-
+            Inner::Tsf(reader) => reader.len(),
             Inner::ParquetSpectra(reader) => reader.len(),
         }
     }
@@ -120,10 +121,10 @@ impl PrecursorReaderBuilder {
             },
 
             TimsTofFileType::Parquet(parquet_path) => Inner::ParquetSpectra(
-                ParquetPrecursorReader::new(parquet_path.precursor_path()),
+                ParquetPrecursorReader::new(parquet_path.precursor_path())?,
             ),
-            TimsTofFileType::Tsf(_) => {
-                return Err(PrecursorReaderError::TsfNotSupported);
+            TimsTofFileType::Tsf(tsf_path) => {
+                Inner::Tsf(TSFPrecursorReader::new(tsf_path)?)
             },
         };
         let reader = PrecursorReader { precursor_reader };
@@ -140,10 +141,16 @@ pub enum PrecursorReaderError {
     ParquetPrecursorReader(#[from] ParquetPrecursorReaderError),
     #[error("{0}")]
     TDFPrecursorReaderError(#[from] TDFPrecursorReaderError),
+    #[error("{0}")]
+    TSFPrecursorReaderError(#[from] TSFPrecursorReaderError),
     #[error("No path provided")]
     NoPath,
     #[error("{0}")]
     TimsTofPathError(#[from] TimsTofPathError),
+    #[deprecated(
+        since = "0.6.4",
+        note = "TSF precursors are read with `TSFPrecursorReader`, this error is never returned"
+    )]
     #[error("TSF datasets do not provide precursor information")]
     TsfNotSupported,
     #[cfg(feature = "patched")]
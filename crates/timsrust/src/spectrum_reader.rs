@@ -1,10 +1,22 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use rayon::prelude::*;
+use timsrust_centroid::correlation::ElutionCorrelationParams;
+use timsrust_centroid::kernels::{
+    CentroidingKernels, KernelConfig, KernelSource,
+};
+use timsrust_centroid::spectrum_reader::{
+    DDASpectrumReader, PasefPrecursor, PasefSelection,
+};
 use timsrust_core::AcquisitionType;
-use timsrust_core::{Mz, Spectrum};
+use timsrust_core::{IsolationWindow, Mz, Precursor, Spectrum};
 use timsrust_minitdf::{MiniTDFError, MiniTDFSpectrumReader};
-use timsrust_tdf::{FrameInfoReader, Metadata, TdfIonReader};
+use timsrust_tdf::{
+    DDAProcessing, DIADeisotoping, DIAProcessing, FrameInfoReader, Metadata,
+    PasefFrameMsMsInfo, TDFPath, TDFPrecursorReader, TdfIonReader,
+    read_pasef_frame_msms_info,
+};
 use timsrust_tdf::{
     SpectrumReaderConfig, TDFSpectrumReader, TDFSpectrumReaderError,
 };
@@ -26,6 +38,7 @@ enum Inner {
             MzConverter,
         >,
     ),
+    DdaCentroider(DDASpectrumReader<TdfIonReader, FrameInfoReader>),
     Tdf(TDFSpectrumReader<ImConverter>),
     MiniTdf(MiniTDFSpectrumReader),
     ParquetSpectra(
@@ -42,9 +55,8 @@ impl Inner {
         index: usize,
     ) -> Result<timsrust_core::Spectrum, SpectrumReaderError> {
         match self {
-            Inner::Centroider(_) => {
-                Err(SpectrumReaderError::CentroiderNotSupported)
-            },
+            Inner::Centroider(reader) => Ok(reader.get(index)?),
+            Inner::DdaCentroider(reader) => Ok(reader.get(index)?),
             Inner::Tdf(reader) => Ok(reader.get(index)?),
             Inner::MiniTdf(reader) => Ok(reader.get(index)?),
             Inner::ParquetSpectra(reader) => Ok(reader.get(index)?),
@@ -61,6 +73,7 @@ impl Inner {
             Inner::Tdf(reader) => reader.len(),
             Inner::MiniTdf(reader) => reader.len(),
             Inner::Centroider(reader) => reader.len(),
+            Inner::DdaCentroider(reader) => reader.len(),
             Inner::ParquetSpectra(reader) => reader.len(),
             Inner::Tsf(reader) => reader.len(),
             #[cfg(feature = "patched")]
@@ -79,6 +92,7 @@ impl Inner {
     ) -> impl ParallelIterator<Item = timsrust_core::Spectrum> + '_ {
         match self {
             Inner::Centroider(reader) => A::Centroider(reader),
+            Inner::DdaCentroider(reader) => A::DdaCentroider(reader),
             Inner::Tdf(reader) => A::Tdf(reader),
             Inner::MiniTdf(reader) => A::MiniTdf(reader),
             Inner::ParquetSpectra(reader) => A::ParquetSpectra(reader),
@@ -98,6 +112,7 @@ enum A<'a> {
             MzConverter,
         >,
     ),
+    DdaCentroider(&'a DDASpectrumReader<TdfIonReader, FrameInfoReader>),
     Tdf(&'a TDFSpectrumReader<ImConverter>),
     MiniTdf(&'a MiniTDFSpectrumReader),
     ParquetSpectra(
@@ -120,6 +135,10 @@ impl<'a> ParallelIterator for A<'a> {
                 .par_iter()
                 .filter_map(|s| s.ok())
                 .drive_unindexed(consumer),
+            Self::DdaCentroider(reader) => reader
+                ._par_iter()
+                .filter_map(|s| s.ok())
+                .drive_unindexed(consumer),
             Self::Tdf(reader) => rayon::iter::ParallelIterator::filter_map(
                 reader.par_iter(),
                 |s| s.ok(),
@@ -193,6 +212,44 @@ impl SpectrumReader {
         self.len() == 0
     }
 
+    /// The number of chunks that [`get_chunk`](Self::get_chunk) reads.
+    pub fn chunk_count(&self) -> usize {
+        match &self.spectrum_reader {
+            Inner::Centroider(reader) => reader.frame_indices().len(),
+            inner => inner.len(),
+        }
+    }
+
+    /// The spectra of chunk `chunk`, sorted by index.
+    ///
+    /// A chunk of centroided DIA data holds all spectra of one frame, so
+    /// reading all chunks in order processes every frame once. Any other
+    /// chunk is the single spectrum at index `chunk`. Prefer this over
+    /// [`get`](Self::get) for sequential exports.
+    pub fn get_chunk(
+        &self,
+        chunk: usize,
+    ) -> Result<Vec<Spectrum<Mz>>, SpectrumReaderError> {
+        let spectra = match &self.spectrum_reader {
+            Inner::Centroider(reader) => {
+                let frame_index =
+                    *reader.frame_indices().get(chunk).ok_or_else(|| {
+                        timsrust_centroid::TimsError::new(format!(
+                            "No chunk at index {chunk}"
+                        ))
+                    })?;
+                let mut spectra = reader.get_spectra_from_frame(frame_index);
+                spectra.sort_unstable_by_key(|spectrum| spectrum.index());
+                spectra
+            },
+            inner => vec![inner.get(chunk)?],
+        };
+        Ok(spectra
+            .into_iter()
+            .map(|spectrum| spectrum.convert_to(self.mz_converter.as_ref()))
+            .collect())
+    }
+
     pub fn get_all(&self) -> Vec<Result<Spectrum<Mz>, SpectrumReaderError>> {
         let mut spectra: Vec<Result<Spectrum<Mz>, SpectrumReaderError>> = (0
             ..self.len())
@@ -267,8 +324,18 @@ pub enum SpectrumReaderError {
     TSFSpectrumReaderError(#[from] TSFSpectrumReaderError),
     #[error("No path provided")]
     NoPath,
-    #[error("Centroider is not supported")]
-    CentroiderNotSupported,
+    #[error("{0}")]
+    MetadataReaderError(#[from] timsrust_tdf::MetadataReaderError),
+    #[error("{0}")]
+    FrameReaderError(#[from] timsrust_tdf::FrameReaderError),
+    #[error("No {0} converter available for this file")]
+    MissingConverter(&'static str),
+    #[error("{0}")]
+    TimsCentroidError(
+        #[from] timsrust_centroid::spectrum_reader::TimsCentroidError,
+    ),
+    #[error("{0}")]
+    TimsError(#[from] timsrust_centroid::TimsError),
     #[cfg(feature = "patched")]
     #[error("Random access is not supported for patched datasets")]
     PatchedRandomAccessNotSupported,
@@ -300,7 +367,31 @@ impl SpectrumReaderBuilder {
         }
     }
 
+    /// Choose how DIA-PASEF data is read: window slices or 2D-centroided
+    /// pseudo-spectra. Has no effect on other acquisition types.
+    pub fn with_dia_processing(&self, dia_processing: DIAProcessing) -> Self {
+        let mut config = self.config.clone();
+        config.dia_processing = dia_processing;
+        Self {
+            config,
+            ..self.clone()
+        }
+    }
+
+    /// Choose how DDA-PASEF data is read: 1D-centroided raw spectra or
+    /// slices of 2D-centroided frames. Has no effect on other acquisition
+    /// types.
+    pub fn with_dda_processing(&self, dda_processing: DDAProcessing) -> Self {
+        let mut config = self.config.clone();
+        config.dda_processing = dda_processing;
+        Self {
+            config,
+            ..self.clone()
+        }
+    }
+
     pub fn finalize(self) -> Result<SpectrumReader, SpectrumReaderError> {
+        let missing = SpectrumReaderError::MissingConverter;
         let path = match self.path {
             None => return Err(SpectrumReaderError::NoPath),
             Some(path) => path,
@@ -321,40 +412,105 @@ impl SpectrumReaderBuilder {
                 }
             },
             TimsTofFileType::Tdf(tdf_path) => {
-                if Metadata::new(tdf_path.as_ref()).unwrap().acquisition_type()
-                    == AcquisitionType::DIAPASEF
+                let acquisition_type =
+                    Metadata::new(tdf_path.as_ref())?.acquisition_type();
+                if let (
+                    AcquisitionType::DIAPASEF,
+                    DIAProcessing::Centroided(params),
+                ) = (acquisition_type, &self.config.dia_processing)
                 {
                     use timsrust_tdf::TdfFrameReader;
 
-                    let im_converter = ImConverter::new(&path).unwrap();
-                    let mz_converter = MzConverter::new(&path).unwrap();
+                    let im_converter = ImConverter::new(&path)
+                        .ok_or(missing("1/K0"))?;
+                    let mz_converter = MzConverter::new(&path)
+                        .ok_or(missing("m/z"))?;
                     let frame_reader =
-                        TdfFrameReader::new(tdf_path.as_ref())
-                            .unwrap()
-                            .into_inner();
-                    let spectrum_reader = Inner::Centroider(
+                        TdfFrameReader::new(tdf_path.as_ref())?.into_inner();
+                    let mut centroider =
                         timsrust_centroid::spectrum_reader::SpectrumReader::new(
                             frame_reader,
-                            0.5,
-                            // 15,
-                            // 5,
-                            2.0,
-                            5,
-                            true,
+                            params.min_ms1_ion_count,
+                            params.min_ms2_ion_count,
+                            params.min_spectrum_size,
+                            params.use_precursors,
                             im_converter,
                             mz_converter,
-                        )
-                        .unwrap(),
+                        )?;
+                    centroider.set_charges(params.charges.clone());
+                    centroider.set_monoisotopic_only(params.monoisotopic_only);
+                    centroider.set_highest_charge_state_only(
+                        params.highest_charge_state_only,
+                    );
+                    let deisotoping = deisotoping_strategy(&params.deisotoping);
+                    centroider.set_deisotoping(deisotoping);
+                    centroider.set_elution_correlation(
+                        params.elution_correlation.as_ref().map(|params| {
+                            ElutionCorrelationParams {
+                                cycles: params.cycles,
+                                tof_tolerance: params.tof_tolerance,
+                                min_score: params.min_score,
+                            }
+                        }),
+                    );
+                    let spectrum_reader = Inner::Centroider(centroider);
+                    let mz_converter = Arc::new(
+                        MzConverter::new(&path)
+                            .ok_or(missing("m/z"))?,
                     );
-                    let mz_converter =
-                        Arc::new(MzConverter::new(&path).unwrap());
                     return Ok(SpectrumReader {
                         spectrum_reader,
                         mz_converter,
                     });
                 }
-                let im_converter =
-                    Arc::new(ImConverter::new(&path).unwrap());
+                if let (
+                    AcquisitionType::DDAPASEF,
+                    DDAProcessing::Centroided(params),
+                ) = (acquisition_type, &self.config.dda_processing)
+                {
+                    use timsrust_tdf::TdfFrameReader;
+
+                    let im_converter = Arc::new(
+                        ImConverter::new(&path)
+                            .ok_or(missing("1/K0"))?,
+                    );
+                    let precursors = read_pasef_precursors(
+                        tdf_path,
+                        &self.config,
+                        im_converter,
+                    )?;
+                    let frame_reader =
+                        TdfFrameReader::new(tdf_path.as_ref())?.into_inner();
+                    let kernels = match &params.kernels {
+                        Some(path) => KernelSource::Explicit(
+                            CentroidingKernels::load(path)?,
+                        ),
+                        None => KernelSource::Learned,
+                    };
+                    let peak_reader = timsrust_centroid::PeakReader::ms2_only(
+                        frame_reader,
+                        params.min_ms2_ion_count,
+                        KernelConfig {
+                            ms2: Some(kernels),
+                            ..Default::default()
+                        },
+                    )?;
+                    let spectrum_reader = Inner::DdaCentroider(
+                        DDASpectrumReader::new(peak_reader, precursors),
+                    );
+                    let mz_converter = Arc::new(
+                        MzConverter::new(&path)
+                            .ok_or(missing("m/z"))?,
+                    );
+                    return Ok(SpectrumReader {
+                        spectrum_reader,
+                        mz_converter,
+                    });
+                }
+                let im_converter = Arc::new(
+                    ImConverter::new(&path)
+                        .ok_or(missing("1/K0"))?,
+                );
                 Inner::Tdf(TDFSpectrumReader::new(
                     tdf_path,
                     self.config.clone(),
@@ -372,14 +528,15 @@ impl SpectrumReaderBuilder {
                     timsrust_parquet_spectra::spectrum_reader::ParquetSpectrumReader::new(
                         parquet_path.precursor_path(),
                         parquet_path.fragment_path(),
-                    ),
+                    )?,
                 )
             },
             TimsTofFileType::Tsf(tsf_path) => {
                 Inner::Tsf(TSFSpectrumReader::new(tsf_path)?)
             },
         };
-        let mz_converter = Arc::new(MzConverter::new(&path).unwrap());
+        let mz_converter =
+            Arc::new(MzConverter::new(&path).ok_or(missing("m/z"))?);
         let mut reader = SpectrumReader {
             spectrum_reader,
             mz_converter,
@@ -390,3 +547,225 @@ impl SpectrumReaderBuilder {
         Ok(reader)
     }
 }
+
+fn deisotoping_strategy(
+    deisotoping: &DIADeisotoping,
+) -> timsrust_centroid::isotopes::DeisotopingStrategy {
+    use timsrust_centroid::isotopes::{AveragineParams, DeisotopingStrategy};
+
+    match *deisotoping {
+        DIADeisotoping::Simple { max_delta_mz } => {
+            DeisotopingStrategy::Simple { max_delta_mz }
+        },
+        DIADeisotoping::Averagine {
+            ppm_tolerance,
+            max_isotopes,
+            min_isotopes,
+            min_score,
+        } => DeisotopingStrategy::Averagine(AveragineParams {
+            ppm_tolerance,
+            max_isotopes,
+            min_isotopes,
+            min_score,
+        }),
+    }
+}
+
+/// Group the PASEF frame slices of a DDA run per precursor, ordered like
+/// the spectra of a [`TDFSpectrumReader`].
+fn read_pasef_precursors(
+    tdf_path: &TDFPath,
+    config: &SpectrumReaderConfig<ImConverter>,
+    im_converter: Arc<ImConverter>,
+) -> Result<Vec<PasefPrecursor>, TDFSpectrumReaderError> {
+    let precursor_reader = TDFPrecursorReader::new(
+        tdf_path,
+        config.frame_splitting_params.clone(),
+        im_converter,
+    )?;
+    let precursors = (0..precursor_reader.len())
+        .map(|index| precursor_reader.get(index))
+        .collect::<Result<Vec<_>, _>>()?;
+    let pasef_frames = read_pasef_frame_msms_info(tdf_path)?;
+    Ok(group_pasef_frames(precursors, pasef_frames))
+}
+
+/// Attach the PASEF frame slices to their precursors.
+///
+/// Like the raw DDA spectra, precursors without PASEF frame slices are
+/// kept, with an empty isolation window and no selections.
+fn group_pasef_frames(
+    precursors: Vec<Precursor>,
+    pasef_frames: Vec<PasefFrameMsMsInfo>,
+) -> Vec<PasefPrecursor> {
+    let mut grouped: HashMap<usize, Vec<_>> = HashMap::new();
+    for pasef_frame in pasef_frames {
+        grouped
+            .entry(pasef_frame.precursor_id)
+            .or_default()
+            .push(pasef_frame);
+    }
+    precursors
+        .into_iter()
+        .map(|precursor| {
+            let pasef_frames =
+                grouped.remove(&precursor.index()).unwrap_or_default();
+            let isolation_window = match pasef_frames.last() {
+                Some(last) => IsolationWindow::new_from_center(
+                    Mz::from(last.isolation_mz),
+                    Mz::from(last.isolation_width),
+                    last.collision_energy,
+                ),
+                None => IsolationWindow::new_from_center(
+                    Mz::from(0.0),
+                    Mz::from(0.0),
+                    0.0,
+                ),
+            };
+            let selections = pasef_frames
+                .iter()
+                .map(|pasef_frame| PasefSelection {
+                    frame_index: pasef_frame.frame_index,
+                    scan_start: pasef_frame.scan_start,
+                    scan_end: pasef_frame.scan_end,
+                })
+                .collect();
+            PasefPrecursor {
+                precursor,
+                isolation_window,
+                selections,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use timsrust_core::{FrameIndex, Im, Rt, ScanIndex};
+    use timsrust_tdf::DIACentroidingParams;
+
+    use super::*;
+
+    fn precursor(index: usize) -> Precursor {
+        Precursor::new(
+            Mz::from(500.0),
+            Im::from(1.0),
+            Rt::from(10.0),
+            ScanIndex::try_from(100_u32).unwrap(),
+            None,
+            None,
+            index,
+            FrameIndex::try_from(1_u32).unwrap(),
+        )
+    }
+
+    fn pasef_frame(
+        precursor_id: usize,
+        frame_index: usize,
+    ) -> PasefFrameMsMsInfo {
+        PasefFrameMsMsInfo {
+            frame_index,
+            scan_start: 10,
+            scan_end: 20,
+            isolation_mz: 500.0,
+            isolation_width: 2.0,
+            collision_energy: 30.0,
+            precursor_id,
+        }
+    }
+
+    #[test]
+    fn precursors_without_pasef_frames_are_kept() {
+        let precursors = (1..4).map(precursor).collect();
+        let pasef_frames =
+            vec![pasef_frame(3, 2), pasef_frame(1, 2), pasef_frame(3, 4)];
+        let grouped = group_pasef_frames(precursors, pasef_frames);
+        let indices = grouped
+            .iter()
+            .map(|p| p.precursor.index())
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![1, 2, 3]);
+        let frames = grouped
+            .iter()
+            .map(|p| p.selections.iter().map(|s| s.frame_index).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(frames, vec![vec![2], vec![], vec![2, 4]]);
+        assert_eq!(grouped[0].isolation_window.center(), Mz::from(500.0));
+        assert_eq!(grouped[1].isolation_window.width(), Mz::from(0.0));
+    }
+
+    /// Copies `tests/dia_test.d`, which has 4 MS2 frames with 2 windows.
+    fn dia_run(dir: &std::path::Path) -> String {
+        let source =
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/dia_test.d");
+        let run = dir.join("dia_test.d");
+        std::fs::create_dir(&run).unwrap();
+        for file in ["analysis.tdf", "analysis.tdf_bin"] {
+            std::fs::copy(format!("{source}/{file}"), run.join(file)).unwrap();
+        }
+        // The fixture predates the `GlobalMetadata` table name.
+        rusqlite::Connection::open(run.join("analysis.tdf"))
+            .unwrap()
+            .execute_batch(
+                "ALTER TABLE GlobalMetaData RENAME TO Metadata;
+                ALTER TABLE Metadata RENAME TO GlobalMetadata;",
+            )
+            .unwrap();
+        run.to_string_lossy().to_string()
+    }
+
+    fn dia_reader(
+        run: &str,
+        dia_processing: DIAProcessing,
+    ) -> Result<SpectrumReader, SpectrumReaderError> {
+        SpectrumReader::build()
+            .with_path(run)
+            .with_config(SpectrumReaderConfig {
+                dia_processing,
+                ..Default::default()
+            })
+            .finalize()
+    }
+
+    #[test]
+    fn dia_processing() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = dia_run(dir.path());
+        let reader = dia_reader(&run, DIAProcessing::WindowSlices).unwrap();
+        assert!(matches!(reader.spectrum_reader, Inner::Tdf(_)));
+        assert_eq!(reader.len(), 4 * 2);
+        assert!(reader.get_all().iter().all(Result::is_ok));
+        assert_eq!(reader.chunk_count(), reader.len());
+        assert_eq!(reader.get_chunk(3).unwrap(), [reader.get(3).unwrap()]);
+
+        let params = DIACentroidingParams {
+            use_precursors: false,
+            min_spectrum_size: 1,
+            ..Default::default()
+        };
+        let reader =
+            dia_reader(&run, DIAProcessing::Centroided(params.clone()))
+                .unwrap();
+        assert!(matches!(reader.spectrum_reader, Inner::Centroider(_)));
+        assert!(!reader.is_empty());
+        assert!(reader.get_all().iter().all(Result::is_ok));
+        // The chunks hold every spectrum once, one frame at a time.
+        assert_eq!(reader.chunk_count(), 6);
+        let mut indices = (0..reader.chunk_count())
+            .flat_map(|chunk| reader.get_chunk(chunk).unwrap())
+            .map(|spectrum| spectrum.index())
+            .collect::<Vec<_>>();
+        assert_eq!(indices.len(), reader.len());
+        indices.sort_unstable();
+        indices.dedup();
+        assert_eq!(indices.len(), reader.len());
+        assert!(reader.get_chunk(reader.chunk_count()).is_err());
+
+        // Invalid parameters are reported instead of panicking.
+        let params = DIACentroidingParams {
+            min_ms2_ion_count: -1.0,
+            ..params
+        };
+        assert!(dia_reader(&run, DIAProcessing::Centroided(params)).is_err());
+    }
+}
@@ -3,11 +3,11 @@ use std::path::PathBuf;
 use timsrust_core::io::Uri;
 use timsrust_minitdf::MiniTDFPath;
 use timsrust_parquet_spectra::parquet_path::ParquetSpectrumPath;
-use timsrust_tdf::{FrameReaderError, TDFPath, TdfFrameReader};
-use timsrust_tsf::TSFPath;
+use timsrust_tdf::{FrameReaderError, FrameReaderErrorInternal, TDFPath};
+use timsrust_tsf::{TSFFrameReaderError, TSFPath};
 
 use crate::{
-    ImConverter, MzConverter, RtConverter,
+    FrameReader, ImConverter, MzConverter, RtConverter,
     precursor_reader::{
         PrecursorReader, PrecursorReaderBuilder, PrecursorReaderError,
     },
@@ -92,19 +92,12 @@ impl TimsTofPath {
         PrecursorReaderBuilder::default().with_path(self).finalize()
     }
 
-    /// Create a [`TdfFrameReader`] for this path.
+    /// Create a [`FrameReader`] for this path.
     ///
-    /// Only supported for `.d` (TDF) files; returns
+    /// Supported for TDF and TSF files; returns
     /// [`TimsTofFrameReaderError::NotSupported`] for all other file types.
-    pub fn frame_reader(
-        &self,
-    ) -> Result<TdfFrameReader, TimsTofFrameReaderError> {
-        match &self.file_type {
-            TimsTofFileType::Tdf(tdf_path) => {
-                Ok(TdfFrameReader::new(tdf_path)?)
-            },
-            _ => Err(TimsTofFrameReaderError::NotSupported),
-        }
+    pub fn frame_reader(&self) -> Result<FrameReader, TimsTofFrameReaderError> {
+        FrameReader::new(self)
     }
 
     /// Create an [`MzConverter`] (TOF index → m/z) for this path.
@@ -147,11 +140,17 @@ pub enum TimsTofPathError {
     UnknownType(PathBuf),
 }
 
-/// Error returned by [`TimsTofPath::frame_reader`].
+/// Error returned by [`FrameReader`] and [`TimsTofPath::frame_reader`].
 #[derive(Debug, thiserror::Error)]
 pub enum TimsTofFrameReaderError {
     #[error("{0}")]
     FrameReaderError(#[from] FrameReaderError),
+    #[error("{0}")]
+    FrameInfoReaderError(#[from] FrameReaderErrorInternal),
+    #[error("{0}")]
+    TSFFrameReaderError(#[from] TSFFrameReaderError),
+    #[error("{0}")]
+    TimsTofPathError(#[from] TimsTofPathError),
     #[error("Frame reading is not supported for this file type")]
     NotSupported,
 }
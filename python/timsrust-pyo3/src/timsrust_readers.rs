@@ -143,6 +143,7 @@ impl PySpectrumReader {
                     ),
                 ),
             spectrum_processing_params: SpectrumProcessingParams::default(),
+            ..Default::default()
         };
 
         let builder = SpectrumReader::build()